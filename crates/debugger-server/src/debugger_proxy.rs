@@ -10,7 +10,8 @@ use wasmparser::FuncType;
 use crate::rpc::{self, WasmExport};
 use crate::serialization;
 use wasminspect_debugger::{
-    try_load_dwarf, CommandContext, CommandResult, Debugger, Interactive, MainDebugger, Process,
+    try_load_dwarf, CommandContext, CommandResult, Debugger, Interactive, MainDebugger,
+    OutputTarget, Process,
 };
 use wasminspect_vm::{HostFuncBody, HostValue, MemoryAddr, NumVal, Trap, WasmValue};
 
@@ -211,6 +212,22 @@ where
     })
 }
 
+/// Sends the output of the process to the client as `Output` messages
+pub fn forward_output<S: futures::Sink<Message> + Unpin + Send + 'static>(
+    stream: &'static str,
+    tx: Arc<Mutex<S>>,
+) -> OutputTarget {
+    OutputTarget::Forward(Arc::new(move |bytes: &[u8]| {
+        let response = rpc::TextResponse::Output {
+            stream: stream.to_string(),
+            bytes: bytes.to_vec(),
+        };
+        if let Err(err) = blocking_send_response(response.into(), tx.clone()) {
+            log::error!("Failed to forward {}: {:?}", stream, err);
+        }
+    }))
+}
+
 type ImportModule = HashMap<String, HostValue>;
 
 fn remote_import_module<S: futures::Sink<Message> + Unpin + Send + 'static>(
@@ -339,10 +356,13 @@ fn call_exported(
             let values = values.iter().map(from_vm_wasm_value).collect();
             Ok(TextResponse::CallResult { values }.into())
         }
-        Ok(RunResult::Breakpoint) => {
+        Ok(RunResult::Exit(status)) => {
+            Err(anyhow::anyhow!("Process exited with status {}", status))
+        }
+        Ok(RunResult::Breakpoint) | Ok(RunResult::Exiting(_)) => {
             // use std::borrow::{Borrow, BorrowMut};
             let mut interactive = Interactive::new_with_loading_history().unwrap();
-            let mut result = { interactive.run_loop(&*context.borrow(), process.clone())? };
+            let mut result = { interactive.run_loop(&mut *context.borrow_mut(), process.clone())? };
             loop {
                 match result {
                     CommandResult::ProcessFinish(values) => {
@@ -353,15 +373,15 @@ fn call_exported(
                         let cmd_result = {
                             process
                                 .borrow_mut()
-                                .dispatch_command("process continue", &*context.borrow())?
+                                .dispatch_command("process continue", &mut *context.borrow_mut())?
                         };
                         match cmd_result {
                             Some(r) => {
                                 result = r;
                             }
                             None => {
-                                result =
-                                    interactive.run_loop(&*context.borrow(), process.clone())?;
+                                result = interactive
+                                    .run_loop(&mut *context.borrow_mut(), process.clone())?;
                             }
                         }
                     }
@@ -373,7 +393,7 @@ fn call_exported(
             {
                 let err = format!("Error while calling exported function: {}", msg);
                 context.borrow().printer.eprintln(&err);
-                interactive.run_loop(&*context.borrow(), process)?
+                interactive.run_loop(&mut *context.borrow_mut(), process)?
             };
             Err(msg)
         }
@@ -465,3 +485,28 @@ fn memory_addr_by_name(name: &str, debugger: &MainDebugger) -> Result<MemoryAddr
         .ok_or_else(|| anyhow::anyhow!("no exported memory"))?;
     Ok(addr)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_forward_output() {
+        let tx = Arc::new(Mutex::new(Vec::<Message>::new()));
+        let forward = match forward_output("stderr", tx.clone()) {
+            OutputTarget::Forward(forward) => forward,
+            _ => panic!("output is not forwarded"),
+        };
+        forward(b"Hi\n");
+        forward(b"!");
+        assert_eq!(
+            *tx.lock().unwrap(),
+            vec![
+                Message::Text(
+                    r#"{"type":"Output","stream":"stderr","bytes":[72,105,10]}"#.to_string()
+                ),
+                Message::Text(r#"{"type":"Output","stream":"stderr","bytes":[33]}"#.to_string()),
+            ]
+        );
+    }
+}
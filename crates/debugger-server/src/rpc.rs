@@ -139,6 +139,11 @@ pub enum TextResponse {
         bytes: Vec<u8>,
     },
     StoreMemoryResult,
+    /// Output written by the process to stdout or stderr
+    Output {
+        stream: String,
+        bytes: Vec<u8>,
+    },
     Error {
         message: String,
     },
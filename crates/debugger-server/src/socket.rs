@@ -12,7 +12,7 @@ use std::{
 use anyhow::anyhow;
 use futures::{Sink, SinkExt, StreamExt};
 use lazy_static::lazy_static;
-use wasminspect_debugger::Interactive;
+use wasminspect_debugger::{Debugger, Interactive};
 
 use crate::{debugger_proxy, serialization};
 use crate::{debugger_proxy::ProcessRef, rpc};
@@ -152,9 +152,17 @@ async fn _establish_connection(upgraded: Upgraded) -> Result<(), anyhow::Error>
         let rt = tokio::runtime::Runtime::new().unwrap();
         rt.block_on(async move {
             log::debug!("Start debugger thread");
-            let (process, dbg_context) =
+            let (process, mut dbg_context) =
                 wasminspect_debugger::start_debugger(None, vec![], vec![]).unwrap();
             let process = Rc::new(RefCell::new(process));
+            let tx = Arc::new(Mutex::new(tx));
+            {
+                let debugger = &mut process.borrow_mut().debugger;
+                let mut opts = debugger.get_opts();
+                opts.stdio.stdout = debugger_proxy::forward_output("stdout", tx.clone());
+                opts.stdio.stderr = debugger_proxy::forward_output("stderr", tx.clone());
+                debugger.set_opts(opts);
+            }
 
             let mut last_line: Option<String> = None;
             let step_timeout = Duration::from_millis(500);
@@ -167,7 +175,7 @@ async fn _establish_connection(upgraded: Upgraded) -> Result<(), anyhow::Error>
                         break;
                     }
                     let result = interactive.run_step(
-                        &dbg_context,
+                        &mut dbg_context,
                         process.clone(),
                         &mut last_line,
                         Some(step_timeout),
@@ -187,7 +195,6 @@ async fn _establish_connection(upgraded: Upgraded) -> Result<(), anyhow::Error>
             }
             log::debug!("Start receiving messages");
 
-            let tx = Arc::new(Mutex::new(tx));
             let request_rx = Arc::new(request_rx);
             let dbg_context = Rc::new(RefCell::new(dbg_context));
             loop {
@@ -0,0 +1,21 @@
+//! Parsers of command line options shared by the binaries
+
+use anyhow::{anyhow, Result};
+
+/// Parses `NAME=VAL` passed by `--env`
+pub fn parse_env_var(s: &str) -> Result<(String, String)> {
+    let parts: Vec<_> = s.splitn(2, '=').collect();
+    if parts.len() != 2 {
+        return Err(anyhow!("must be of the form `key=value"));
+    }
+    Ok((parts[0].to_owned(), parts[1].to_owned()))
+}
+
+/// Parses `GUEST_DIR::HOST_DIR` passed by `--mapdir`
+pub fn parse_map_dirs(s: &str) -> Result<(String, String)> {
+    let parts: Vec<&str> = s.split("::").collect();
+    if parts.len() != 2 {
+        return Err(anyhow!("must contain exactly one double colon ('::')"));
+    }
+    Ok((parts[0].into(), parts[1].into()))
+}
@@ -24,6 +24,9 @@ struct SetOpts {
     name: Option<String>,
     #[structopt(short, long)]
     address: Option<String>,
+    /// Stops before the process exits
+    #[structopt(long)]
+    exit: bool,
 }
 
 impl SetOpts {
@@ -40,6 +43,8 @@ impl SetOpts {
             Ok(Breakpoint::Instruction {
                 inst_offset: address,
             })
+        } else if self.exit {
+            Ok(Breakpoint::Exit)
         } else {
             Err(anyhow!("no breakpoint option"))
         }
@@ -58,7 +63,7 @@ impl<D: Debugger> Command<D> for BreakpointCommand {
     fn run(
         &self,
         debugger: &mut D,
-        _context: &CommandContext,
+        _context: &mut CommandContext,
         args: Vec<&str>,
     ) -> Result<Option<CommandResult>> {
         let opts = Opts::from_iter_safe(args)?;
@@ -2,11 +2,12 @@ use super::debugger::{Debugger, OutputPrinter};
 use super::sourcemap::SourceMap;
 use super::subroutine::SubroutineMap;
 use anyhow::Result;
+use std::rc::Rc;
 use wasminspect_vm::WasmValue;
 
 pub struct CommandContext {
     pub sourcemap: Box<dyn SourceMap>,
-    pub subroutine: Box<dyn SubroutineMap>,
+    pub subroutine: Rc<dyn SubroutineMap>,
     pub printer: Box<dyn OutputPrinter>,
 }
 
@@ -24,7 +25,7 @@ pub trait Command<D: Debugger> {
     fn run(
         &self,
         debugger: &mut D,
-        context: &CommandContext,
+        context: &mut CommandContext,
         args: Vec<&str>,
     ) -> Result<Option<CommandResult>>;
 }
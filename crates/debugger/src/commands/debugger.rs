@@ -1,19 +1,72 @@
+use super::sourcemap::SourceMap;
+use super::subroutine::SubroutineMap;
+use crate::profile::ProfileReport;
+use crate::trace::TraceOptions;
 use anyhow::Result;
 use wasminspect_vm::{HostValue, Instruction, ModuleIndex, Signal, Store, WasmValue};
 
-#[derive(Default, Clone)]
+#[derive(Clone)]
 pub struct DebuggerOpts {
     pub watch_memory: bool,
+    /// Display demangled symbol names
+    pub demangle_symbols: bool,
+    /// Record the execution history for reverse execution
+    pub record: bool,
+    /// Memory limit of the recorded execution history in bytes
+    pub record_max_memory: usize,
+    /// Record or replay the calls of host functions on the next launch
+    pub host_calls: Option<crate::host_calls::HostCallMode>,
+    /// Log every WASI call with the decoded arguments and errno
+    pub trace_wasi: bool,
+    /// Replace clocks, randomness and polling of WASI with deterministic ones
+    pub wasi_deterministic: Option<wasminspect_wasi::DeterministicOptions>,
+    /// Preopen in-memory directories instead of host directories
+    pub wasi_vfs: Option<wasminspect_wasi::VfsOptions>,
+    /// Listeners given to the guest as socket file descriptors
+    pub wasi_listen: Vec<wasminspect_wasi::ListenAddress>,
+    /// Errors injected into WASI calls on every launch
+    pub wasi_faults: Vec<wasminspect_wasi::FaultOptions>,
+    /// Redirect or capture the standard streams of the process
+    pub stdio: wasminspect_wasi::StdioOptions,
+}
+
+impl Default for DebuggerOpts {
+    fn default() -> Self {
+        Self {
+            watch_memory: false,
+            demangle_symbols: true,
+            record: false,
+            record_max_memory: crate::record::DEFAULT_MAX_MEMORY,
+            host_calls: None,
+            trace_wasi: false,
+            wasi_deterministic: None,
+            wasi_vfs: None,
+            wasi_listen: Vec::new(),
+            wasi_faults: Vec::new(),
+            stdio: Default::default(),
+        }
+    }
 }
 
 pub enum Breakpoint {
     Function { name: String },
     Instruction { inst_offset: usize },
+    Exit,
+}
+
+/// Stops when any byte in the memory range is written
+pub struct Watchpoint {
+    pub address: usize,
+    pub size: usize,
 }
 
 pub enum RunResult {
     Finish(Vec<WasmValue>),
     Breakpoint,
+    /// The process is stopped by the exit breakpoint before exiting with the status
+    Exiting(i32),
+    /// The process exited by `proc_exit` with the status
+    Exit(i32),
 }
 
 #[derive(Clone, Copy)]
@@ -23,7 +76,16 @@ pub enum StepStyle {
     Out,
 }
 
+pub struct StackFrame {
+    pub name: String,
+    /// Offset of the instruction being executed in this frame.
+    /// For callers, this is the offset of the call instruction.
+    pub code_offset: Option<usize>,
+    pub arguments: Vec<WasmValue>,
+}
+
 pub struct FunctionFrame {
+    pub name: String,
     pub module_index: ModuleIndex,
     pub argument_count: usize,
 }
@@ -31,6 +93,8 @@ pub struct FunctionFrame {
 pub trait OutputPrinter {
     fn println(&self, _: &str);
     fn eprintln(&self, _: &str);
+    /// Asks the user the yes/no question
+    fn confirm(&self, prompt: &str) -> bool;
 }
 pub type RawHostModule = std::collections::HashMap<String, HostValue>;
 
@@ -44,15 +108,41 @@ pub trait Debugger {
     ) -> Result<()>;
     fn run(&mut self, name: Option<&str>, args: Vec<WasmValue>) -> Result<RunResult>;
     fn is_running(&self) -> bool;
-    fn frame(&self) -> Vec<String>;
+    fn main_module_bytes(&self) -> Option<&[u8]>;
+    /// Returns true if the main module is a core module of a component
+    fn is_component(&self) -> bool;
+    fn frame(&self) -> Vec<StackFrame>;
     fn current_frame(&self) -> Option<FunctionFrame>;
     fn locals(&self) -> Vec<WasmValue>;
     fn memory(&self) -> Result<Vec<u8>>;
+    /// Serializes the execution state of the main module
+    fn snapshot(&self) -> Result<Vec<u8>>;
     fn store(&self) -> Result<&Store>;
     fn set_breakpoint(&mut self, breakpoint: Breakpoint);
+    fn clear_breakpoints(&mut self);
+    fn set_watchpoint(&mut self, watchpoint: Watchpoint);
     fn stack_values(&self) -> Vec<WasmValue>;
     fn selected_instructions(&self) -> Result<(&[Instruction], usize)>;
     fn step(&self, style: StepStyle) -> Result<Signal>;
     fn process(&mut self) -> Result<RunResult>;
-    fn select_frame(&mut self, frame_index: Option<usize>) -> Result<()>;
+    /// Rewinds the recorded execution by an instruction, or over calls with `InstOver`.
+    /// Returns `Signal::End` at the beginning of the recorded history.
+    fn step_back(&mut self, style: StepStyle) -> Result<Signal>;
+    /// Rewinds the recorded execution until a breakpoint or watchpoint hits.
+    /// Returns `Signal::End` at the beginning of the recorded history.
+    fn reverse_process(&mut self) -> Result<Signal>;
+    /// Starts logging calls and returns of functions. Parameters are shown with the
+    /// names and types from DWARF if `subroutine` is given.
+    fn start_trace(
+        &mut self,
+        options: TraceOptions,
+        subroutine: Option<std::rc::Rc<dyn SubroutineMap>>,
+    ) -> Result<()>;
+    fn stop_trace(&mut self);
+    /// Starts counting executed instructions by call stack from scratch
+    fn start_profiling(&mut self);
+    fn stop_profiling(&mut self);
+    fn profile(&self, sourcemap: &dyn SourceMap) -> Result<ProfileReport>;
+    fn select_frame(&mut self, frame_index: Option<usize>, inline_depth: usize) -> Result<()>;
+    fn selected_inline_depth(&self) -> usize;
 }
@@ -1,5 +1,6 @@
 use super::command::{Command, CommandContext, CommandResult};
 use super::debugger::{Debugger, OutputPrinter};
+use super::symbol::format_symbol;
 use anyhow::Result;
 use structopt::StructOpt;
 
@@ -17,6 +18,9 @@ struct Opts {
     count: Option<usize>,
     #[structopt(short, long)]
     pc: bool,
+    /// Display mangled symbol names
+    #[structopt(long)]
+    mangled: bool,
 }
 
 impl<D: Debugger> Command<D> for DisassembleCommand {
@@ -31,7 +35,7 @@ impl<D: Debugger> Command<D> for DisassembleCommand {
     fn run(
         &self,
         debugger: &mut D,
-        context: &CommandContext,
+        context: &mut CommandContext,
         args: Vec<&str>,
     ) -> Result<Option<CommandResult>> {
         let opts: Opts = Opts::from_iter_safe(args)?;
@@ -40,6 +44,11 @@ impl<D: Debugger> Command<D> for DisassembleCommand {
         } else {
             opts.count
         };
+        if let Some(frame) = debugger.current_frame() {
+            let demangle = debugger.get_opts().demangle_symbols && !opts.mangled;
+            let output = format!("{}:", format_symbol(&frame.name, demangle));
+            context.printer.println(&output);
+        }
         display_asm(debugger, context.printer.as_ref(), count, opts.pc)?;
         Ok(None)
     }
@@ -30,64 +30,75 @@ impl<D: Debugger> Command<D> for ExpressionCommand {
     fn run(
         &self,
         debugger: &mut D,
-        context: &CommandContext,
+        context: &mut CommandContext,
         args: Vec<&str>,
     ) -> Result<Option<CommandResult>> {
         let opts = Opts::from_iter_safe(args)?;
-        let (insts, next_index) = debugger.selected_instructions()?;
-        let current_index = if next_index == 0 { 0 } else { next_index - 1 };
-        let current_inst = insts[current_index].clone();
-        let locals = debugger.locals();
-        use wasminspect_vm::*;
-        let store: &Store = debugger.store()?;
-        let mod_index = match debugger.current_frame() {
-            Some(frame) => frame.module_index,
-            None => return Err(anyhow!("function frame not found")),
-        };
-        let frame_base = match context.subroutine.get_frame_base(current_inst.offset)? {
-            Some(loc) => {
-                let offset = match loc {
-                    WasmLoc::Global(idx) => store
-                        .global(GlobalAddr::new_unsafe(mod_index, idx as usize))
-                        .borrow()
-                        .value(),
-                    WasmLoc::Local(idx) => *locals
-                        .get(idx as usize)
-                        .with_context(|| "failed to get base local".to_string())?,
-                    WasmLoc::Stack(idx) => *debugger
-                        .stack_values()
-                        .get(idx as usize)
-                        .with_context(|| "failed to get base local".to_string())?,
-                };
-                let offset = match offset {
-                    WasmValue::Num(NumVal::I32(v)) => v as u64,
-                    WasmValue::Num(NumVal::I64(v)) => v as u64,
-                    _ => return Err(anyhow!("unexpected frame base value: {:?}", offset)),
-                };
-                FrameBase::WasmFrameBase(offset)
-            }
-            None => {
-                let argument_count = debugger
-                    .current_frame()
-                    .with_context(|| "function frame not found".to_string())?
-                    .argument_count;
-                let offset = *locals
-                    .get(argument_count + 2)
-                    .with_context(|| "failed to get rbp".to_string())?;
-                let offset = match offset {
-                    WasmValue::Num(NumVal::I32(v)) => v as u64,
-                    _ => return Err(anyhow!("unexpected frame base value: {:?}", offset)),
-                };
-                FrameBase::Rbp(offset)
-            }
-        };
-        log::debug!("frame_base is {:?}", frame_base);
-        context.subroutine.display_variable(
-            current_inst.offset,
-            frame_base,
-            &debugger.memory()?,
-            opts.symbol,
-        )?;
+        let output = evaluate_variable(debugger, context, opts.symbol)?;
+        context.printer.println(&output);
         Ok(None)
     }
 }
+
+/// Formats the value of the variable visible from the selected frame
+pub fn evaluate_variable<D: Debugger>(
+    debugger: &D,
+    context: &CommandContext,
+    name: String,
+) -> Result<String> {
+    let (insts, next_index) = debugger.selected_instructions()?;
+    let current_index = if next_index == 0 { 0 } else { next_index - 1 };
+    let current_inst = insts[current_index].clone();
+    let locals = debugger.locals();
+    use wasminspect_vm::*;
+    let store: &Store = debugger.store()?;
+    let mod_index = match debugger.current_frame() {
+        Some(frame) => frame.module_index,
+        None => return Err(anyhow!("function frame not found")),
+    };
+    let frame_base = match context.subroutine.get_frame_base(current_inst.offset)? {
+        Some(loc) => {
+            let offset = match loc {
+                WasmLoc::Global(idx) => store
+                    .global(GlobalAddr::new_unsafe(mod_index, idx as usize))
+                    .borrow()
+                    .value(),
+                WasmLoc::Local(idx) => *locals
+                    .get(idx as usize)
+                    .with_context(|| "failed to get base local".to_string())?,
+                WasmLoc::Stack(idx) => *debugger
+                    .stack_values()
+                    .get(idx as usize)
+                    .with_context(|| "failed to get base local".to_string())?,
+            };
+            let offset = match offset {
+                WasmValue::Num(NumVal::I32(v)) => v as u64,
+                WasmValue::Num(NumVal::I64(v)) => v as u64,
+                _ => return Err(anyhow!("unexpected frame base value: {:?}", offset)),
+            };
+            FrameBase::WasmFrameBase(offset)
+        }
+        None => {
+            let argument_count = debugger
+                .current_frame()
+                .with_context(|| "function frame not found".to_string())?
+                .argument_count;
+            let offset = *locals
+                .get(argument_count + 2)
+                .with_context(|| "failed to get rbp".to_string())?;
+            let offset = match offset {
+                WasmValue::Num(NumVal::I32(v)) => v as u64,
+                _ => return Err(anyhow!("unexpected frame base value: {:?}", offset)),
+            };
+            FrameBase::Rbp(offset)
+        }
+    };
+    log::debug!("frame_base is {:?}", frame_base);
+    context.subroutine.format_variable(
+        current_inst.offset,
+        debugger.selected_inline_depth(),
+        frame_base,
+        &debugger.memory()?,
+        name,
+    )
+}
@@ -1,6 +1,8 @@
 use super::command::{Command, CommandContext, CommandResult};
 use super::debugger::Debugger;
-use anyhow::Result;
+use super::sourcemap::LineInfo;
+use anyhow::{anyhow, Result};
+use wasminspect_vm::WasmValue;
 
 use structopt::StructOpt;
 
@@ -12,6 +14,86 @@ impl FrameCommand {
     }
 }
 
+/// A stack frame including subroutines inlined into a physical frame
+pub struct VirtualFrame {
+    pub name: String,
+    /// Index of the physical frame counted from the innermost frame
+    pub physical_index: usize,
+    /// Number of inlined frames inside of this frame in the physical frame
+    pub inline_depth: usize,
+    pub is_inlined: bool,
+    pub code_offset: Option<usize>,
+    /// The location where this frame called the next inner frame
+    pub call_site: Option<LineInfo>,
+    /// Arguments of the physical frame. Empty for inlined frames
+    pub arguments: Vec<WasmValue>,
+}
+
+impl VirtualFrame {
+    /// Returns the source location currently executed in this frame
+    pub fn line_info(&self, context: &CommandContext) -> Option<LineInfo> {
+        match &self.call_site {
+            Some(call_site) => Some(call_site.clone()),
+            None => context.sourcemap.find_line_info(self.code_offset?),
+        }
+    }
+}
+
+/// Returns virtual frames ordered from the innermost frame
+pub fn virtual_frames<D: Debugger>(debugger: &D, context: &CommandContext) -> Vec<VirtualFrame> {
+    let mut frames = vec![];
+    for (physical_index, frame) in debugger.frame().into_iter().rev().enumerate() {
+        let inlined = match frame.code_offset {
+            Some(offset) => context
+                .subroutine
+                .inlined_frames(offset)
+                .unwrap_or_default(),
+            None => vec![],
+        };
+        let count = inlined.len();
+        let mut call_site = None;
+        for (index, inlined_frame) in inlined.into_iter().enumerate() {
+            frames.push(VirtualFrame {
+                name: inlined_frame
+                    .name
+                    .unwrap_or_else(|| "<inlined>".to_string()),
+                physical_index,
+                inline_depth: index,
+                is_inlined: true,
+                code_offset: frame.code_offset,
+                call_site,
+                arguments: vec![],
+            });
+            call_site = inlined_frame.call_site;
+        }
+        frames.push(VirtualFrame {
+            name: frame.name,
+            physical_index,
+            inline_depth: count,
+            is_inlined: false,
+            code_offset: frame.code_offset,
+            call_site,
+            arguments: frame.arguments,
+        });
+    }
+    frames
+}
+
+/// Runs the closure with the frame selected, and restores the selection.
+/// `debugger` gets the debugger out of the state passed to the closure.
+pub fn with_selected_frame<S, D: Debugger, T>(
+    state: &mut S,
+    debugger: fn(&mut S) -> &mut D,
+    physical_index: usize,
+    inline_depth: usize,
+    f: impl FnOnce(&mut S) -> Result<T>,
+) -> Result<T> {
+    debugger(state).select_frame(Some(physical_index), inline_depth)?;
+    let result = f(state);
+    debugger(state).select_frame(None, 0)?;
+    result
+}
+
 #[derive(StructOpt)]
 enum Opts {
     #[structopt(name = "variable")]
@@ -35,7 +117,7 @@ impl<D: Debugger> Command<D> for FrameCommand {
     fn run(
         &self,
         debugger: &mut D,
-        context: &CommandContext,
+        context: &mut CommandContext,
         args: Vec<&str>,
     ) -> Result<Option<CommandResult>> {
         let opts = Opts::from_iter_safe(args)?;
@@ -44,7 +126,9 @@ impl<D: Debugger> Command<D> for FrameCommand {
                 let (insts, next_index) = debugger.selected_instructions()?;
                 let current_index = if next_index == 0 { 0 } else { next_index - 1 };
                 let current_inst = insts[current_index].clone();
-                let variable_names = context.subroutine.variable_name_list(current_inst.offset)?;
+                let variable_names = context
+                    .subroutine
+                    .variable_name_list(current_inst.offset, debugger.selected_inline_depth())?;
                 for variable in variable_names {
                     let output = format!("{}: {}", variable.name, variable.type_name);
                     context.printer.println(&output);
@@ -52,7 +136,11 @@ impl<D: Debugger> Command<D> for FrameCommand {
                 Ok(None)
             }
             Opts::Select { frame_index } => {
-                debugger.select_frame(Some(frame_index))?;
+                let frames = virtual_frames(debugger, context);
+                let frame = frames
+                    .get(frame_index)
+                    .ok_or_else(|| anyhow!("Frame index {} is out of range", frame_index))?;
+                debugger.select_frame(Some(frame.physical_index), frame.inline_depth)?;
                 Ok(None)
             }
         }
@@ -33,7 +33,7 @@ impl<D: Debugger> Command<D> for GlobalCommand {
     fn run(
         &self,
         debugger: &mut D,
-        context: &CommandContext,
+        context: &mut CommandContext,
         args: Vec<&str>,
     ) -> Result<Option<CommandResult>> {
         let opts = Opts::from_iter_safe(args)?;
@@ -23,7 +23,7 @@ impl<D: Debugger> Command<D> for ListCommand {
     fn run(
         &self,
         debugger: &mut D,
-        context: &CommandContext,
+        context: &mut CommandContext,
         _args: Vec<&str>,
     ) -> Result<Option<CommandResult>> {
         let line_info = next_line_info(debugger, context.sourcemap.as_ref())?;
@@ -15,7 +15,11 @@ pub mod list;
 pub mod local;
 pub mod memory;
 pub mod process;
+pub mod profile;
 pub mod run;
 pub mod settings;
 pub mod stack;
+pub mod target;
 pub mod thread;
+pub mod wasi;
+pub mod watchpoint;
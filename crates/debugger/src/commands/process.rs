@@ -2,7 +2,12 @@ use crate::RunResult;
 
 use super::command::{Command, CommandContext, CommandResult};
 use super::debugger::Debugger;
-use anyhow::Result;
+use crate::trace::TraceOptions;
+use anyhow::{anyhow, Result};
+use regex::Regex;
+use std::path::PathBuf;
+use wasminspect_vm::Signal;
+use wasminspect_wasi::{OutputBuffer, OutputTarget, StdioOptions};
 
 use structopt::StructOpt;
 
@@ -19,18 +24,77 @@ enum Opts {
     #[structopt(name = "continue")]
     Continue,
 
+    /// Rewind the recorded execution until a breakpoint or watchpoint
+    #[structopt(name = "reverse-continue")]
+    ReverseContinue,
+
+    /// Save the execution state to the file to resume it by `wasminspect --restore`
+    #[structopt(name = "save")]
+    Save {
+        #[structopt(name = "FILE", parse(from_os_str))]
+        file: std::path::PathBuf,
+    },
+
+    /// Log calls and returns of functions without stopping the process
+    #[structopt(name = "trace")]
+    Trace(TraceOpts),
+
+    /// Show the output captured by `process launch --capture`
+    #[structopt(name = "output")]
+    Output {
+        /// Discard the output after showing it
+        #[structopt(long)]
+        clear: bool,
+    },
+
     /// Start WASI entry point
+    ///
+    /// The stdio redirection is kept for later launches until it's given again.
     #[structopt(name = "launch")]
     Launch {
         /// Entry point to start
         start: Option<String>,
 
+        /// Read the standard input from the file
+        #[structopt(long, value_name = "FILE", parse(from_os_str))]
+        stdin: Option<PathBuf>,
+
+        /// Write the standard output to the file
+        #[structopt(long, value_name = "FILE", parse(from_os_str))]
+        stdout: Option<PathBuf>,
+
+        /// Write the standard error to the file
+        #[structopt(long, value_name = "FILE", parse(from_os_str))]
+        stderr: Option<PathBuf>,
+
+        /// Keep the output in a buffer shown by `process output` instead of printing it
+        #[structopt(long)]
+        capture: bool,
+
         /// Arguments to pass to the WASI entry point
         #[structopt(name = "ARGS", last = true)]
         args: Vec<String>,
     },
 }
 
+#[derive(StructOpt)]
+enum TraceOpts {
+    #[structopt(name = "on")]
+    On {
+        /// Only log functions whose name matches the regex
+        #[structopt(short, long, value_name = "REGEX")]
+        filter: Option<Regex>,
+        /// Write the log to the file instead of stdout
+        #[structopt(short, long, value_name = "FILE", parse(from_os_str))]
+        output: Option<std::path::PathBuf>,
+        /// Show the names and types of parameters from DWARF
+        #[structopt(long)]
+        dwarf: bool,
+    },
+    #[structopt(name = "off")]
+    Off,
+}
+
 impl<D: Debugger> Command<D> for ProcessCommand {
     fn name(&self) -> &'static str {
         "process"
@@ -43,7 +107,7 @@ impl<D: Debugger> Command<D> for ProcessCommand {
     fn run(
         &self,
         debugger: &mut D,
-        context: &CommandContext,
+        context: &mut CommandContext,
         args: Vec<&str>,
     ) -> Result<Option<CommandResult>> {
         let opts = Opts::from_iter_safe(args)?;
@@ -55,8 +119,81 @@ impl<D: Debugger> Command<D> for ProcessCommand {
                 RunResult::Breakpoint => {
                     context.printer.println("Hit breakpoint");
                 }
+                RunResult::Exiting(status) => {
+                    let output = format!("Process is exiting with status {}", status);
+                    context.printer.println(&output);
+                }
+                RunResult::Exit(status) => {
+                    let output = format!("Process exited with status {}", status);
+                    context.printer.println(&output);
+                }
+            },
+            Opts::ReverseContinue => match debugger.reverse_process()? {
+                Signal::End => {
+                    context
+                        .printer
+                        .println("Reached the beginning of the recorded history");
+                }
+                _ => {
+                    context.printer.println("Hit breakpoint");
+                }
             },
-            Opts::Launch { start, args } => {
+            Opts::Save { file } => {
+                let bytes = debugger.snapshot()?;
+                std::fs::write(&file, bytes)?;
+                let output = format!("Saved the process to {}", file.display());
+                context.printer.println(&output);
+            }
+            Opts::Trace(TraceOpts::On {
+                filter,
+                output,
+                dwarf,
+            }) => {
+                let subroutine = if dwarf {
+                    Some(context.subroutine.clone())
+                } else {
+                    None
+                };
+                debugger.start_trace(TraceOptions { filter, output }, subroutine)?;
+            }
+            Opts::Trace(TraceOpts::Off) => debugger.stop_trace(),
+            Opts::Output { clear } => {
+                let opts = debugger.get_opts();
+                let buffer = opts.stdio.captured().ok_or_else(|| {
+                    anyhow!("Output is not captured, launch with `process launch --capture`")
+                })?;
+                let output = String::from_utf8_lossy(&buffer.contents()).to_string();
+                context
+                    .printer
+                    .println(output.strip_suffix('\n').unwrap_or(&output));
+                if clear {
+                    buffer.clear();
+                }
+            }
+            Opts::Launch {
+                start,
+                stdin,
+                stdout,
+                stderr,
+                capture,
+                args,
+            } => {
+                if stdin.is_some() || stdout.is_some() || stderr.is_some() || capture {
+                    let buffer = OutputBuffer::default();
+                    let target = |path: Option<PathBuf>| match path {
+                        Some(path) => OutputTarget::File(path),
+                        None if capture => OutputTarget::Capture(buffer.clone()),
+                        None => OutputTarget::Inherit,
+                    };
+                    let mut opts = debugger.get_opts();
+                    opts.stdio = StdioOptions {
+                        stdin,
+                        null_stdin: opts.stdio.null_stdin,
+                        stdout: target(stdout),
+                        stderr: target(stderr),
+                    };
+                    debugger.set_opts(opts);
+                }
                 return self.start_debugger(debugger, context, start, args);
             }
         }
@@ -71,16 +208,12 @@ impl ProcessCommand {
         start: Option<String>,
         wasi_args: Vec<String>,
     ) -> Result<Option<CommandResult>> {
-        use std::io::Write;
-        if debugger.is_running() {
-            print!("There is a running process, kill it and restart?: [Y/n] ");
-            std::io::stdout().flush().unwrap();
-            let stdin = std::io::stdin();
-            let mut input = String::new();
-            stdin.read_line(&mut input).unwrap();
-            if input != "Y\n" && input != "y\n" {
-                return Ok(None);
-            }
+        if debugger.is_running()
+            && !context
+                .printer
+                .confirm("There is a running process, kill it and restart?:")
+        {
+            return Ok(None);
         }
         debugger.instantiate(std::collections::HashMap::new(), Some(&wasi_args))?;
 
@@ -93,6 +226,14 @@ impl ProcessCommand {
             Ok(RunResult::Breakpoint) => {
                 context.printer.println("Hit breakpoint");
             }
+            Ok(RunResult::Exiting(status)) => {
+                let output = format!("Process is exiting with status {}", status);
+                context.printer.println(&output);
+            }
+            Ok(RunResult::Exit(status)) => {
+                let output = format!("Process exited with status {}", status);
+                context.printer.println(&output);
+            }
             Err(msg) => {
                 let output = format!("{}", msg);
                 context.printer.eprintln(&output);
@@ -0,0 +1,88 @@
+use super::command::{Command, CommandContext, CommandResult};
+use super::debugger::Debugger;
+use anyhow::Result;
+use std::path::PathBuf;
+use structopt::StructOpt;
+
+pub struct ProfileCommand {}
+
+impl ProfileCommand {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+#[derive(StructOpt)]
+enum Opts {
+    /// Starts counting executed instructions by call stack
+    #[structopt(name = "start")]
+    Start,
+    /// Stops counting, and keeps the profile to report
+    #[structopt(name = "stop")]
+    Stop,
+    /// Shows the functions sorted by self cost
+    #[structopt(name = "report")]
+    Report {
+        /// Number of functions to show
+        #[structopt(short = "n", long, default_value = "20")]
+        top: usize,
+    },
+    /// Shows the source lines which execute the most instructions
+    #[structopt(name = "lines")]
+    Lines {
+        /// Number of lines to show
+        #[structopt(short = "n", long, default_value = "20")]
+        top: usize,
+    },
+    /// Writes the folded stacks for flamegraph tools to the file
+    #[structopt(name = "folded")]
+    Folded {
+        #[structopt(name = "FILE", parse(from_os_str))]
+        file: PathBuf,
+    },
+}
+
+impl<D: Debugger> Command<D> for ProfileCommand {
+    fn name(&self) -> &'static str {
+        "profile"
+    }
+
+    fn description(&self) -> &'static str {
+        "Commands for counting executed instructions by function."
+    }
+
+    fn run(
+        &self,
+        debugger: &mut D,
+        context: &mut CommandContext,
+        args: Vec<&str>,
+    ) -> Result<Option<CommandResult>> {
+        let opts = Opts::from_iter_safe(args)?;
+        let demangle = debugger.get_opts().demangle_symbols;
+        let mut output = vec![];
+        match opts {
+            Opts::Start => debugger.start_profiling(),
+            Opts::Stop => debugger.stop_profiling(),
+            Opts::Report { top } => {
+                let report = debugger.profile(context.sourcemap.as_ref())?;
+                report.write_top(&mut output, top, demangle)?;
+            }
+            Opts::Lines { top } => {
+                let report = debugger.profile(context.sourcemap.as_ref())?;
+                if report.lines.is_empty() {
+                    context.printer.eprintln("No line info is available");
+                }
+                report.write_lines(&mut output, top)?;
+            }
+            Opts::Folded { file } => {
+                let report = debugger.profile(context.sourcemap.as_ref())?;
+                let mut file = std::fs::File::create(file)?;
+                report.write_folded(&mut file, demangle)?;
+            }
+        }
+        for line in String::from_utf8_lossy(&output).lines() {
+            context.printer.println(line);
+        }
+        Ok(None)
+    }
+}
@@ -1,6 +1,7 @@
 use super::command::{Command, CommandContext, CommandResult};
 use super::debugger::Debugger;
-use anyhow::Result;
+use crate::host_calls::HostCallMode;
+use anyhow::{anyhow, Result};
 
 use structopt::StructOpt;
 
@@ -18,7 +19,7 @@ enum Opts {
     Set {
         key: String,
         operand1: String,
-        operand2: String,
+        operand2: Option<String>,
     },
 }
 
@@ -33,8 +34,8 @@ impl<D: Debugger> Command<D> for SettingsCommand {
 
     fn run(
         &self,
-        _debugger: &mut D,
-        context: &CommandContext,
+        debugger: &mut D,
+        context: &mut CommandContext,
         args: Vec<&str>,
     ) -> Result<Option<CommandResult>> {
         let opts = Opts::from_iter_safe(args)?;
@@ -45,8 +46,50 @@ impl<D: Debugger> Command<D> for SettingsCommand {
                 operand2,
             } => match key.as_str() {
                 "directory.map" => {
+                    let operand2 = operand2.ok_or_else(|| anyhow!("'{}' takes 2 values", key))?;
                     context.sourcemap.set_directory_map(operand1, operand2);
                 }
+                "symbols.demangle" => {
+                    let mut opts = debugger.get_opts();
+                    opts.demangle_symbols = operand1
+                        .parse()
+                        .map_err(|_| anyhow!("'{}' is not a boolean value", operand1))?;
+                    debugger.set_opts(opts);
+                }
+                "record.enabled" => {
+                    let mut opts = debugger.get_opts();
+                    opts.record = operand1
+                        .parse()
+                        .map_err(|_| anyhow!("'{}' is not a boolean value", operand1))?;
+                    debugger.set_opts(opts);
+                }
+                "record.max-memory" => {
+                    let mut opts = debugger.get_opts();
+                    opts.record_max_memory = parse_byte_size(&operand1)?;
+                    debugger.set_opts(opts);
+                }
+                "wasi.trace" => {
+                    let mut opts = debugger.get_opts();
+                    opts.trace_wasi = operand1
+                        .parse()
+                        .map_err(|_| anyhow!("'{}' is not a boolean value", operand1))?;
+                    debugger.set_opts(opts);
+                }
+                "host-calls" => {
+                    let mut opts = debugger.get_opts();
+                    opts.host_calls = match (operand1.as_str(), operand2) {
+                        ("record", Some(path)) => Some(HostCallMode::Record(path.into())),
+                        ("replay", Some(path)) => Some(HostCallMode::Replay(path.into())),
+                        ("off", None) => None,
+                        _ => {
+                            return Err(anyhow!(
+                                "'{}' takes 'record <FILE>', 'replay <FILE>' or 'off'",
+                                key
+                            ))
+                        }
+                    };
+                    debugger.set_opts(opts);
+                }
                 _ => {
                     let output = format!("'{}' is not valid key", key);
                     context.printer.eprintln(&output);
@@ -56,3 +99,17 @@ impl<D: Debugger> Command<D> for SettingsCommand {
         Ok(None)
     }
 }
+
+/// Parses a size in bytes with an optional `K`, `M` or `G` suffix
+fn parse_byte_size(value: &str) -> Result<usize> {
+    let (digits, unit) = match value.chars().last() {
+        Some('K') | Some('k') => (&value[..value.len() - 1], 1 << 10),
+        Some('M') | Some('m') => (&value[..value.len() - 1], 1 << 20),
+        Some('G') | Some('g') => (&value[..value.len() - 1], 1 << 30),
+        _ => (value, 1),
+    };
+    let size = digits
+        .parse::<usize>()
+        .map_err(|_| anyhow!("'{}' is not a valid size", value))?;
+    Ok(size * unit)
+}
@@ -1,3 +1,5 @@
+use std::path::Path;
+
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum ColumnType {
     LeftEdge,
@@ -13,7 +15,7 @@ impl From<ColumnType> for u64 {
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug)]
 pub struct LineInfo {
     pub filepath: String,
     pub line: Option<u64>,
@@ -22,9 +24,51 @@ pub struct LineInfo {
 
 pub trait SourceMap {
     fn find_line_info(&self, offset: usize) -> Option<LineInfo>;
+    /// Returns offsets of instructions where the line begins.
+    /// If the line has no code, the nearest following line is used.
+    fn find_line_offsets(&self, filepath: &str, line: u64) -> Vec<usize>;
     fn set_directory_map(&self, from: String, to: String);
 }
 
+fn is_same_file(lhs: &str, rhs: &str) -> bool {
+    let (lhs, rhs) = (Path::new(lhs), Path::new(rhs));
+    lhs == rhs || lhs.ends_with(rhs) || rhs.ends_with(lhs)
+}
+
+/// Finds offsets of the line from rows sorted by offset
+pub fn line_offsets(
+    rows: impl Iterator<Item = (usize, Option<LineInfo>)>,
+    filepath: &str,
+    line: u64,
+) -> Vec<usize> {
+    let rows: Vec<_> = rows
+        .map(|(offset, info)| {
+            let line = info
+                .filter(|info| is_same_file(&info.filepath, filepath))
+                .and_then(|info| info.line);
+            (offset, line)
+        })
+        .collect();
+    let target = match rows
+        .iter()
+        .filter_map(|row| row.1)
+        .filter(|l| *l >= line)
+        .min()
+    {
+        Some(target) => target,
+        None => return vec![],
+    };
+    let mut offsets = vec![];
+    let mut last_line = None;
+    for (offset, line) in rows {
+        if line == Some(target) && last_line != line {
+            offsets.push(offset);
+        }
+        last_line = line;
+    }
+    offsets
+}
+
 pub struct EmptySourceMap {}
 
 impl EmptySourceMap {
@@ -36,5 +80,8 @@ impl SourceMap for EmptySourceMap {
     fn find_line_info(&self, _: usize) -> Option<LineInfo> {
         None
     }
+    fn find_line_offsets(&self, _: &str, _: u64) -> Vec<usize> {
+        vec![]
+    }
     fn set_directory_map(&self, _: String, _: String) {}
 }
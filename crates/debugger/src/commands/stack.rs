@@ -22,7 +22,7 @@ impl<D: Debugger> Command<D> for StackCommand {
     fn run(
         &self,
         debugger: &mut D,
-        context: &CommandContext,
+        context: &mut CommandContext,
         _args: Vec<&str>,
     ) -> Result<Option<CommandResult>> {
         for (index, value) in debugger.stack_values().iter().enumerate() {
@@ -1,21 +1,33 @@
+use super::sourcemap::LineInfo;
 use crate::dwarf::{FrameBase, WasmLoc};
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 
 pub struct Variable {
     pub name: String,
     pub type_name: String,
+    /// The variable is a formal parameter of the subroutine
+    pub is_parameter: bool,
+}
+
+pub struct InlinedFrame {
+    pub name: Option<String>,
+    /// The location in the caller where this subroutine was inlined
+    pub call_site: Option<LineInfo>,
 }
 
 pub trait SubroutineMap {
-    fn variable_name_list(&self, code_offset: usize) -> Result<Vec<Variable>>;
+    fn variable_name_list(&self, code_offset: usize, inline_depth: usize) -> Result<Vec<Variable>>;
     fn get_frame_base(&self, code_offset: usize) -> Result<Option<WasmLoc>>;
-    fn display_variable(
+    /// Returns inlined subroutines containing the offset, innermost first
+    fn inlined_frames(&self, code_offset: usize) -> Result<Vec<InlinedFrame>>;
+    fn format_variable(
         &self,
         code_offset: usize,
+        inline_depth: usize,
         frame_base: FrameBase,
         memory: &[u8],
         name: String,
-    ) -> Result<()>;
+    ) -> Result<String>;
 }
 
 pub struct EmptySubroutineMap {}
@@ -26,13 +38,23 @@ impl EmptySubroutineMap {
     }
 }
 impl SubroutineMap for EmptySubroutineMap {
-    fn variable_name_list(&self, _code_offset: usize) -> Result<Vec<Variable>> {
+    fn variable_name_list(&self, _code_offset: usize, _: usize) -> Result<Vec<Variable>> {
         Ok(vec![])
     }
     fn get_frame_base(&self, _: usize) -> Result<Option<WasmLoc>> {
         Ok(Some(WasmLoc::Global(0)))
     }
-    fn display_variable(&self, _: usize, _: FrameBase, _: &[u8], _: String) -> Result<()> {
-        Ok(())
+    fn inlined_frames(&self, _: usize) -> Result<Vec<InlinedFrame>> {
+        Ok(vec![])
+    }
+    fn format_variable(
+        &self,
+        _: usize,
+        _: usize,
+        _: FrameBase,
+        _: &[u8],
+        name: String,
+    ) -> Result<String> {
+        Err(anyhow!("'{}' is not valid variable name", name))
     }
 }
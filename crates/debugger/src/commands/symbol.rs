@@ -1,11 +1,22 @@
-#[cfg(feature = "swift-extension")]
-use wasminspect_swift_runtime::demangle;
+/// Returns the symbol name to be displayed. Mangled names are demangled
+/// unless `demangle` is false.
+pub fn format_symbol(symbol: &str, demangle: bool) -> String {
+    if demangle {
+        demangle_symbol(symbol)
+    } else {
+        symbol.to_string()
+    }
+}
 
-pub fn demangle_symbol(symbol: &str) -> &str {
+pub fn demangle_symbol(symbol: &str) -> String {
     if is_swift_symbol(symbol) {
         demangle_swift_symbol(symbol)
+    } else if let Some(demangled) = demangle_rust_symbol(symbol) {
+        demangled
+    } else if let Some(demangled) = demangle_cpp_symbol(symbol) {
+        demangled
     } else {
-        symbol
+        symbol.to_string()
     }
 }
 
@@ -13,11 +24,49 @@ fn is_swift_symbol(symbol: &str) -> bool {
     symbol.starts_with("$s")
 }
 
-#[cfg(feature = "swift-extension")]
-fn demangle_swift_symbol(symbol: &str) -> &str {
-    demangle(symbol).unwrap_or(symbol)
+/// Demangles both legacy (`_ZN...E`) and v0 (`_R...`) Rust symbols
+fn demangle_rust_symbol(symbol: &str) -> Option<String> {
+    let demangled = rustc_demangle::try_demangle(symbol).ok()?;
+    // Alternate format omits the hash suffix of legacy symbols
+    Some(format!("{:#}", demangled))
 }
-#[cfg(not(feature = "swift-extension"))]
-fn demangle_swift_symbol(symbol: &str) -> &str {
+
+/// Demangles Itanium C++ ABI symbols
+fn demangle_cpp_symbol(symbol: &str) -> Option<String> {
+    if !symbol.starts_with("_Z") {
+        return None;
+    }
+    let symbol = cpp_demangle::Symbol::new(symbol.as_bytes()).ok()?;
     symbol
+        .demangle(&cpp_demangle::DemangleOptions::default())
+        .ok()
+}
+
+/// Demangles Swift symbols with the runtime library if `swift-extension`
+/// is enabled, or with the pure Rust demangler otherwise
+fn demangle_swift_symbol(symbol: &str) -> String {
+    wasminspect_swift_runtime::demangle(symbol).unwrap_or_else(|_| symbol.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_symbol() {
+        // Rust legacy symbols lose their hash suffix
+        let legacy = "_ZN4demo3add17h0123456789abcdefE";
+        assert_eq!(format_symbol(legacy, true), "demo::add");
+        let v0 = "_RNvCs4fqI2P2rA04_4demo3mul";
+        assert_eq!(format_symbol(v0, true), "demo::mul");
+        let cpp = "_ZN4demo5twiceEi";
+        assert_eq!(format_symbol(cpp, true), "demo::twice(int)");
+        // Names which are not mangled are kept as they are
+        assert_eq!(format_symbol("_start", true), "_start");
+        assert_eq!(format_symbol("_Zinvalid", true), "_Zinvalid");
+
+        for symbol in [legacy, v0, cpp].iter() {
+            assert_eq!(format_symbol(symbol, false), *symbol);
+        }
+    }
 }
@@ -0,0 +1,66 @@
+use super::command::{Command, CommandContext, CommandResult};
+use super::debugger::Debugger;
+use anyhow::{anyhow, Context, Result};
+
+use structopt::StructOpt;
+
+pub struct TargetCommand {}
+
+impl TargetCommand {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+#[derive(StructOpt)]
+enum Opts {
+    #[structopt(name = "symbols")]
+    Symbols(SymbolsOpts),
+}
+
+#[derive(StructOpt)]
+enum SymbolsOpts {
+    /// Load debug info from a separate debug file
+    #[structopt(name = "add")]
+    Add {
+        #[structopt(name = "PATH")]
+        path: String,
+    },
+}
+
+impl<D: Debugger> Command<D> for TargetCommand {
+    fn name(&self) -> &'static str {
+        "target"
+    }
+
+    fn description(&self) -> &'static str {
+        "Commands for operating debugger target."
+    }
+
+    fn run(
+        &self,
+        debugger: &mut D,
+        context: &mut CommandContext,
+        args: Vec<&str>,
+    ) -> Result<Option<CommandResult>> {
+        let opts = Opts::from_iter_safe(args)?;
+        match opts {
+            Opts::Symbols(SymbolsOpts::Add { path }) => {
+                if debugger.is_component() {
+                    return Err(anyhow!(
+                        "Symbol files of components are unsupported. Launch with --debug-file instead"
+                    ));
+                }
+                let module = debugger
+                    .main_module_bytes()
+                    .ok_or_else(|| anyhow!("No module loaded"))?;
+                let debug_file = std::fs::read(&path)
+                    .with_context(|| format!("failed to read debug file {}", path))?;
+                crate::try_load_separate_dwarf(module, &debug_file, context)?;
+                let output = format!("symbol file '{}' has been added", path);
+                context.printer.println(&output);
+            }
+        }
+        Ok(None)
+    }
+}
@@ -1,8 +1,12 @@
 use super::command::{Command, CommandContext, CommandResult};
 use super::debugger::{Debugger, StepStyle};
 use super::disassemble::display_asm;
+use super::frame::virtual_frames;
 use super::list::{display_source, next_line_info};
-use super::symbol::demangle_symbol;
+use super::sourcemap::LineInfo;
+use super::subroutine::SubroutineMap;
+use super::symbol::format_symbol;
+use wasminspect_vm::Signal;
 
 pub struct ThreadCommand {}
 
@@ -15,12 +19,128 @@ impl ThreadCommand {
 use anyhow::Result;
 use structopt::StructOpt;
 
+/// Returns the number of inlined subroutines containing the next instruction
+fn next_inline_depth<D: Debugger>(debugger: &D, subroutine: &dyn SubroutineMap) -> Result<usize> {
+    let (insts, next_index) = debugger.selected_instructions()?;
+    Ok(subroutine.inlined_frames(insts[next_index].offset)?.len())
+}
+
+fn format_frame(code_offset: usize, frame_name: &str, line_info: Option<LineInfo>) -> String {
+    if let Some(line_info) = line_info {
+        format!(
+            "0x{:x} `{} at {}:{}:{}`",
+            code_offset,
+            frame_name,
+            line_info.filepath,
+            line_info
+                .line
+                .map(|l| format!("{}", l))
+                .unwrap_or_else(|| "".to_string()),
+            Into::<u64>::into(line_info.column)
+        )
+    } else {
+        format!("0x{:x} `{}`", code_offset, frame_name)
+    }
+}
+
+/// Steps until the next source line. Stops also at breakpoints or at the end of the process
+pub fn step_line<D: Debugger>(
+    debugger: &D,
+    context: &CommandContext,
+    step_in: bool,
+) -> Result<Signal> {
+    let style = if step_in {
+        StepStyle::InstIn
+    } else {
+        StepStyle::InstOver
+    };
+    let subroutine = context.subroutine.as_ref();
+    let initial_line_info = next_line_info(debugger, context.sourcemap.as_ref())?;
+    let initial_frame_depth = debugger.frame().len();
+    let initial_inline_depth = next_inline_depth(debugger, subroutine)?;
+    loop {
+        let signal = debugger.step(style)?;
+        if let Signal::End | Signal::Breakpoint = signal {
+            return Ok(signal);
+        }
+        let line_info = next_line_info(debugger, context.sourcemap.as_ref())?;
+        let is_same_frame = initial_frame_depth == debugger.frame().len();
+        let inline_depth = next_inline_depth(debugger, subroutine)?;
+        let is_same_line = initial_line_info.filepath == line_info.filepath
+            && initial_line_info.line == line_info.line;
+        let should_continue = if step_in {
+            // Stop at the entry of an inlined subroutine even if the line is not changed
+            is_same_line && !(is_same_frame && inline_depth > initial_inline_depth)
+        } else {
+            // Skip over subroutines inlined into the current frame
+            is_same_line || (is_same_frame && inline_depth > initial_inline_depth)
+        };
+        if !should_continue {
+            return Ok(signal);
+        }
+    }
+}
+
+/// Steps out of the current (possibly inlined) frame
+pub fn step_out<D: Debugger>(debugger: &D, context: &CommandContext) -> Result<Signal> {
+    let subroutine = context.subroutine.as_ref();
+    let initial_inline_depth = next_inline_depth(debugger, subroutine)?;
+    if initial_inline_depth == 0 {
+        return debugger.step(StepStyle::Out);
+    }
+    // Step out of the inlined subroutine within the physical frame
+    let initial_frame_depth = debugger.frame().len();
+    loop {
+        let signal = debugger.step(StepStyle::InstOver)?;
+        if let Signal::End | Signal::Breakpoint = signal {
+            return Ok(signal);
+        }
+        if initial_frame_depth != debugger.frame().len()
+            || next_inline_depth(debugger, subroutine)? < initial_inline_depth
+        {
+            return Ok(signal);
+        }
+    }
+}
+
+/// Steps back until the previous source line in the current frame.
+/// Stops also at breakpoints or at the beginning of the recorded history
+pub fn step_back_line<D: Debugger>(debugger: &mut D, context: &CommandContext) -> Result<Signal> {
+    let initial_line_info = next_line_info(debugger, context.sourcemap.as_ref())?;
+    loop {
+        let signal = debugger.step_back(StepStyle::InstOver)?;
+        if let Signal::End | Signal::Breakpoint = signal {
+            return Ok(signal);
+        }
+        let line_info = next_line_info(debugger, context.sourcemap.as_ref())?;
+        if initial_line_info.filepath != line_info.filepath
+            || initial_line_info.line != line_info.line
+        {
+            return Ok(signal);
+        }
+    }
+}
+
 #[derive(StructOpt)]
 enum Opts {
     #[structopt(name = "info")]
-    Info,
+    Info {
+        /// Display mangled symbol names
+        #[structopt(long)]
+        mangled: bool,
+    },
     #[structopt(name = "backtrace")]
-    Backtrace,
+    Backtrace {
+        /// How many frames to display
+        #[structopt(short = "c", long = "count")]
+        count: Option<usize>,
+        /// Display arguments of each frame
+        #[structopt(long)]
+        full: bool,
+        /// Display mangled symbol names
+        #[structopt(long)]
+        mangled: bool,
+    },
     #[structopt(name = "step-in")]
     StepIn,
     #[structopt(name = "step-over")]
@@ -31,6 +151,12 @@ enum Opts {
     StepInstIn,
     #[structopt(name = "step-inst-over")]
     StepInstOver,
+    /// Step back to the previous source line of the recorded execution
+    #[structopt(name = "step-back")]
+    StepBack,
+    /// Step back a single instruction of the recorded execution
+    #[structopt(name = "step-inst-back")]
+    StepInstBack,
 }
 
 impl<D: Debugger> Command<D> for ThreadCommand {
@@ -45,60 +171,65 @@ impl<D: Debugger> Command<D> for ThreadCommand {
     fn run(
         &self,
         debugger: &mut D,
-        context: &CommandContext,
+        context: &mut CommandContext,
         args: Vec<&str>,
     ) -> Result<Option<CommandResult>> {
         let opts = Opts::from_iter_safe(args.clone())?;
         match opts {
-            Opts::Info => {
+            Opts::Info { mangled } => {
                 let frames = debugger.frame();
-                let frame_name = frames.last().unwrap();
+                let demangle = debugger.get_opts().demangle_symbols && !mangled;
+                let frame_name = format_symbol(&frames.last().unwrap().name, demangle);
                 let (insts, next_index) = debugger.selected_instructions()?;
                 let current_index = if next_index == 0 { 0 } else { next_index - 1 };
                 let current_inst = insts[current_index].clone();
                 let code_offset = current_inst.offset;
-                let output = if let Some(line_info) = context.sourcemap.find_line_info(code_offset)
-                {
-                    format!(
-                        "0x{:x} `{} at {}:{}:{}`",
-                        code_offset,
-                        frame_name,
-                        line_info.filepath,
-                        line_info
-                            .line
-                            .map(|l| format!("{}", l))
-                            .unwrap_or_else(|| "".to_string()),
-                        Into::<u64>::into(line_info.column)
-                    )
-                } else {
-                    format!("0x{:x} `{}`", code_offset, frame_name)
-                };
+                let line_info = context.sourcemap.find_line_info(code_offset);
+                let output = format_frame(code_offset, &frame_name, line_info);
                 context.printer.println(&output);
             }
-            Opts::Backtrace => {
-                for (index, frame) in debugger.frame().iter().rev().enumerate() {
-                    let output = format!("{}: {}", index, demangle_symbol(frame));
+            Opts::Backtrace {
+                count,
+                full,
+                mangled,
+            } => {
+                let demangle = debugger.get_opts().demangle_symbols && !mangled;
+                let frames = virtual_frames(debugger, context);
+                let count = count.unwrap_or(frames.len());
+                for (index, frame) in frames.iter().take(count).enumerate() {
+                    let frame_name = format_symbol(&frame.name, demangle);
+                    let mut output = match frame.code_offset {
+                        Some(code_offset) => format!(
+                            "{}: {}",
+                            index,
+                            format_frame(code_offset, &frame_name, frame.line_info(context))
+                        ),
+                        None => format!("{}: `{}`", index, frame_name),
+                    };
+                    if frame.is_inlined {
+                        output.push_str(" [inlined]");
+                    }
                     context.printer.println(&output);
+                    if full {
+                        for (index, value) in frame.arguments.iter().enumerate() {
+                            let output = format!("    arg{}: {:?}", index, value);
+                            context.printer.println(&output);
+                        }
+                    }
                 }
             }
             Opts::StepIn | Opts::StepOver => {
-                let style = match opts {
-                    Opts::StepIn => StepStyle::InstIn,
-                    Opts::StepOver => StepStyle::InstOver,
-                    _ => panic!(),
-                };
-                let initial_line_info = next_line_info(debugger, context.sourcemap.as_ref())?;
-                while {
-                    debugger.step(style)?;
-                    let line_info = next_line_info(debugger, context.sourcemap.as_ref())?;
-                    initial_line_info.filepath == line_info.filepath
-                        && initial_line_info.line == line_info.line
-                } {}
+                let signal = step_line(debugger, context, matches!(opts, Opts::StepIn))?;
+                if let Signal::End = signal {
+                    return Ok(None);
+                }
                 let line_info = next_line_info(debugger, context.sourcemap.as_ref())?;
                 display_source(line_info, context.printer.as_ref())?;
             }
             Opts::StepOut => {
-                debugger.step(StepStyle::Out)?;
+                if let Signal::End = step_out(debugger, context)? {
+                    return Ok(None);
+                }
                 let line_info = next_line_info(debugger, context.sourcemap.as_ref())?;
                 display_source(line_info, context.printer.as_ref())?;
             }
@@ -111,6 +242,23 @@ impl<D: Debugger> Command<D> for ThreadCommand {
                 debugger.step(style)?;
                 display_asm(debugger, context.printer.as_ref(), Some(4), true)?;
             }
+            Opts::StepBack => {
+                if let Signal::End = step_back_line(debugger, context)? {
+                    context
+                        .printer
+                        .println("Reached the beginning of the recorded history");
+                }
+                let line_info = next_line_info(debugger, context.sourcemap.as_ref())?;
+                display_source(line_info, context.printer.as_ref())?;
+            }
+            Opts::StepInstBack => {
+                if let Signal::End = debugger.step_back(StepStyle::InstIn)? {
+                    context
+                        .printer
+                        .println("Reached the beginning of the recorded history");
+                }
+                display_asm(debugger, context.printer.as_ref(), Some(4), true)?;
+            }
         }
         Ok(None)
     }
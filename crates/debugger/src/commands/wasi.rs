@@ -0,0 +1,206 @@
+use super::command::{Command, CommandContext, CommandResult};
+use super::debugger::Debugger;
+use anyhow::{anyhow, Result};
+use regex::Regex;
+use wasminspect_wasi::{is_wasi_function, parse_errno, FaultOptions, VirtualFs, WasiContext};
+
+use structopt::StructOpt;
+
+pub struct WasiCommand {}
+
+impl WasiCommand {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+#[derive(StructOpt)]
+enum Opts {
+    /// Inspect and modify the in-memory filesystem seen by the process
+    #[structopt(name = "fs")]
+    Fs(FsOpts),
+    /// Make the WASI function fail with the errno instead of calling it.
+    /// The fault also applies to the next launches.
+    #[structopt(name = "fail")]
+    Fail(FailOpts),
+    /// List the faults with the number of injected failures
+    #[structopt(name = "faults")]
+    Faults {
+        /// Remove all faults
+        #[structopt(long)]
+        clear: bool,
+    },
+}
+
+#[derive(StructOpt)]
+struct FailOpts {
+    function: String,
+    /// The errno name like `io`, `EIO` or the number
+    #[structopt(long)]
+    errno: String,
+    /// Number of matching calls to let through before failing
+    #[structopt(long, default_value = "0")]
+    skip: u32,
+    /// Number of calls to fail. All matching calls fail if not given
+    #[structopt(long)]
+    count: Option<u32>,
+    /// Only fail the calls with a path matching the regex.
+    /// Paths relative to a preopened directory are joined to the directory name.
+    #[structopt(long)]
+    path_regex: Option<String>,
+}
+
+impl FailOpts {
+    fn into_fault(self) -> Result<FaultOptions> {
+        if !is_wasi_function(&self.function) {
+            return Err(anyhow!("unknown WASI function '{}'", self.function));
+        }
+        Ok(FaultOptions {
+            function: self.function,
+            errno: parse_errno(&self.errno)?,
+            skip: self.skip,
+            count: self.count,
+            path_regex: self
+                .path_regex
+                .map(|regex| Regex::new(&regex))
+                .transpose()?,
+        })
+    }
+}
+
+/// Parses the fault given in the same form as the arguments of `wasi fail`
+pub fn parse_fault(spec: &str) -> Result<FaultOptions> {
+    let args = std::iter::once("fail").chain(spec.split_whitespace());
+    FailOpts::from_iter_safe(args)?.into_fault()
+}
+
+#[derive(StructOpt)]
+enum FsOpts {
+    /// List the directory, or the mounts if no path is given
+    #[structopt(name = "ls")]
+    Ls { path: Option<String> },
+    /// Print the contents of the file
+    #[structopt(name = "cat")]
+    Cat { path: String },
+    /// Replace the contents of the file, creating it if it doesn't exist.
+    /// `\n`, `\t` and `\\` in the contents are unescaped.
+    #[structopt(name = "write")]
+    Write { path: String, contents: Vec<String> },
+}
+
+impl<D: Debugger> Command<D> for WasiCommand {
+    fn name(&self) -> &'static str {
+        "wasi"
+    }
+
+    fn description(&self) -> &'static str {
+        "Commands for inspecting the WASI environment."
+    }
+
+    fn run(
+        &self,
+        debugger: &mut D,
+        context: &mut CommandContext,
+        args: Vec<&str>,
+    ) -> Result<Option<CommandResult>> {
+        let opts = Opts::from_iter_safe(args)?;
+        match opts {
+            Opts::Fs(opts) => {
+                let vfs = vfs(debugger)?;
+                match opts {
+                    FsOpts::Ls { path: None } => {
+                        for mount in vfs.mounts() {
+                            context.printer.println(mount);
+                        }
+                    }
+                    FsOpts::Ls { path: Some(path) } => {
+                        for entry in vfs.list(&path)? {
+                            let output = if entry.is_dir {
+                                format!("{:>10} {}/", "", entry.name)
+                            } else {
+                                format!("{:>10} {}", entry.size, entry.name)
+                            };
+                            context.printer.println(&output);
+                        }
+                    }
+                    FsOpts::Cat { path } => {
+                        let contents = vfs.read(&path)?;
+                        let contents = String::from_utf8_lossy(&contents);
+                        context
+                            .printer
+                            .println(contents.strip_suffix('\n').unwrap_or(&contents));
+                    }
+                    FsOpts::Write { path, contents } => {
+                        vfs.write(&path, unescape(&contents.join(" ")).as_bytes())?;
+                    }
+                }
+            }
+            Opts::Fail(opts) => {
+                if debugger.is_component() {
+                    return Err(anyhow!("WASI faults are unsupported in components"));
+                }
+                let fault = opts.into_fault()?;
+                if let Some(wasi_ctx) = wasi_context(debugger) {
+                    wasi_ctx.add_fault(fault.clone());
+                }
+                let mut opts = debugger.get_opts();
+                opts.wasi_faults.push(fault);
+                debugger.set_opts(opts);
+            }
+            Opts::Faults { clear: true } => {
+                if let Some(wasi_ctx) = wasi_context(debugger) {
+                    wasi_ctx.clear_faults();
+                }
+                let mut opts = debugger.get_opts();
+                opts.wasi_faults.clear();
+                debugger.set_opts(opts);
+            }
+            Opts::Faults { clear: false } => match wasi_context(debugger) {
+                Some(wasi_ctx) => {
+                    for (fault, injected) in wasi_ctx.faults() {
+                        let output = format!("{} (injected {} times)", fault, injected);
+                        context.printer.println(&output);
+                    }
+                }
+                None => {
+                    for fault in debugger.get_opts().wasi_faults {
+                        context.printer.println(&fault.to_string());
+                    }
+                }
+            },
+        }
+        Ok(None)
+    }
+}
+
+fn wasi_context<D: Debugger>(debugger: &D) -> Option<&WasiContext> {
+    debugger.store().ok()?.get_embed_context::<WasiContext>()
+}
+
+fn vfs<D: Debugger>(debugger: &D) -> Result<&VirtualFs> {
+    debugger
+        .store()?
+        .get_embed_context::<WasiContext>()
+        .and_then(|ctx| ctx.vfs())
+        .ok_or_else(|| {
+            anyhow!("No in-memory filesystem. Launch with --vfs-dir, --vfs-tar, --vfs-overlay or --vfs-file")
+        })
+}
+
+fn unescape(text: &str) -> String {
+    let mut output = String::new();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            output.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => output.push('\n'),
+            Some('t') => output.push('\t'),
+            Some(c) => output.push(c),
+            None => output.push('\\'),
+        }
+    }
+    output
+}
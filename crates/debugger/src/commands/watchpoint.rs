@@ -0,0 +1,56 @@
+use super::command::{Command, CommandContext, CommandResult};
+use super::debugger::{Debugger, Watchpoint};
+use anyhow::Result;
+use structopt::StructOpt;
+
+pub struct WatchpointCommand {}
+
+impl WatchpointCommand {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+#[derive(StructOpt)]
+enum Opts {
+    /// Sets a watchpoint for writes to the memory range
+    #[structopt(name = "set")]
+    Set {
+        #[structopt(name = "ADDRESS")]
+        address: String,
+        /// Number of bytes to watch
+        #[structopt(short, long, default_value = "4")]
+        size: usize,
+    },
+}
+
+impl<D: Debugger> Command<D> for WatchpointCommand {
+    fn name(&self) -> &'static str {
+        "watchpoint"
+    }
+
+    fn description(&self) -> &'static str {
+        "Commands for operating on watchpoints."
+    }
+
+    fn run(
+        &self,
+        debugger: &mut D,
+        _context: &mut CommandContext,
+        args: Vec<&str>,
+    ) -> Result<Option<CommandResult>> {
+        let opts = Opts::from_iter_safe(args)?;
+        match opts {
+            Opts::Set { address, size } => {
+                let address = if address.starts_with("0x") {
+                    let raw = address.trim_start_matches("0x");
+                    usize::from_str_radix(raw, 16)?
+                } else {
+                    address.parse::<usize>()?
+                };
+                debugger.set_watchpoint(Watchpoint { address, size });
+                Ok(None)
+            }
+        }
+    }
+}
@@ -0,0 +1,142 @@
+//! Debug info of the core modules in a component.
+//!
+//! The instruction offsets of a core module in a component are shifted by the offset of
+//! the module in the component, so the debug info of each module is looked up by the
+//! module containing the offset.
+
+use crate::commands::sourcemap::{LineInfo, SourceMap};
+use crate::commands::subroutine::{InlinedFrame, SubroutineMap, Variable};
+use crate::dwarf::{FrameBase, WasmLoc};
+use anyhow::{anyhow, Result};
+use std::rc::Rc;
+
+/// Returns the debug info of the module containing the offset, and the offset relative
+/// to the module. `modules` are sorted by the offsets of the modules.
+fn find_module<T>(modules: &[(usize, T)], offset: usize) -> Option<(&T, usize)> {
+    let index = modules.partition_point(|(module_offset, _)| *module_offset <= offset);
+    let (module_offset, debug_info) = modules.get(index.checked_sub(1)?)?;
+    Some((debug_info, offset - module_offset))
+}
+
+pub struct ComponentSourceMap {
+    modules: Vec<(usize, Box<dyn SourceMap>)>,
+}
+
+impl ComponentSourceMap {
+    pub fn new(mut modules: Vec<(usize, Box<dyn SourceMap>)>) -> Self {
+        modules.sort_by_key(|(offset, _)| *offset);
+        Self { modules }
+    }
+}
+
+impl SourceMap for ComponentSourceMap {
+    fn find_line_info(&self, offset: usize) -> Option<LineInfo> {
+        let (sourcemap, offset) = find_module(&self.modules, offset)?;
+        sourcemap.find_line_info(offset)
+    }
+    fn find_line_offsets(&self, filepath: &str, line: u64) -> Vec<usize> {
+        self.modules
+            .iter()
+            .flat_map(|(module_offset, sourcemap)| {
+                sourcemap
+                    .find_line_offsets(filepath, line)
+                    .into_iter()
+                    .map(move |offset| module_offset + offset)
+            })
+            .collect()
+    }
+    fn set_directory_map(&self, from: String, to: String) {
+        for (_, sourcemap) in self.modules.iter() {
+            sourcemap.set_directory_map(from.clone(), to.clone());
+        }
+    }
+}
+
+pub struct ComponentSubroutineMap {
+    modules: Vec<(usize, Rc<dyn SubroutineMap>)>,
+}
+
+impl ComponentSubroutineMap {
+    pub fn new(mut modules: Vec<(usize, Rc<dyn SubroutineMap>)>) -> Self {
+        modules.sort_by_key(|(offset, _)| *offset);
+        Self { modules }
+    }
+
+    fn find_module(&self, code_offset: usize) -> Result<(&dyn SubroutineMap, usize)> {
+        find_module(&self.modules, code_offset)
+            .map(|(subroutine, offset)| (subroutine.as_ref(), offset))
+            .ok_or_else(|| anyhow!("No module contains the offset {:#x}", code_offset))
+    }
+}
+
+impl SubroutineMap for ComponentSubroutineMap {
+    fn variable_name_list(&self, code_offset: usize, inline_depth: usize) -> Result<Vec<Variable>> {
+        let (subroutine, offset) = self.find_module(code_offset)?;
+        subroutine.variable_name_list(offset, inline_depth)
+    }
+    fn get_frame_base(&self, code_offset: usize) -> Result<Option<WasmLoc>> {
+        let (subroutine, offset) = self.find_module(code_offset)?;
+        subroutine.get_frame_base(offset)
+    }
+    fn inlined_frames(&self, code_offset: usize) -> Result<Vec<InlinedFrame>> {
+        let (subroutine, offset) = self.find_module(code_offset)?;
+        subroutine.inlined_frames(offset)
+    }
+    fn format_variable(
+        &self,
+        code_offset: usize,
+        inline_depth: usize,
+        frame_base: FrameBase,
+        memory: &[u8],
+        name: String,
+    ) -> Result<String> {
+        let (subroutine, offset) = self.find_module(code_offset)?;
+        subroutine.format_variable(offset, inline_depth, frame_base, memory, name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::sourcemap::ColumnType;
+
+    struct LineSourceMap(&'static str);
+
+    impl SourceMap for LineSourceMap {
+        fn find_line_info(&self, offset: usize) -> Option<LineInfo> {
+            Some(LineInfo {
+                filepath: self.0.to_string(),
+                line: Some(offset as u64),
+                column: ColumnType::LeftEdge,
+            })
+        }
+        fn find_line_offsets(&self, filepath: &str, line: u64) -> Vec<usize> {
+            if filepath == self.0 {
+                vec![line as usize]
+            } else {
+                vec![]
+            }
+        }
+        fn set_directory_map(&self, _: String, _: String) {}
+    }
+
+    #[test]
+    fn test_component_source_map() {
+        let sourcemap = ComponentSourceMap::new(vec![
+            (0x100, Box::new(LineSourceMap("main.c"))),
+            (0x10, Box::new(LineSourceMap("adapter.c"))),
+        ]);
+        let line_info = sourcemap.find_line_info(0x105).unwrap();
+        assert_eq!(
+            (line_info.filepath.as_str(), line_info.line),
+            ("main.c", Some(5))
+        );
+        let line_info = sourcemap.find_line_info(0x15).unwrap();
+        assert_eq!(
+            (line_info.filepath.as_str(), line_info.line),
+            ("adapter.c", Some(5))
+        );
+        assert!(sourcemap.find_line_info(0x5).is_none());
+        assert_eq!(sourcemap.find_line_offsets("main.c", 3), vec![0x103]);
+    }
+}
@@ -0,0 +1,407 @@
+//! WebAssembly coredump format
+//! See also https://github.com/WebAssembly/tool-conventions/blob/main/Coredump.md
+
+use anyhow::{anyhow, Result};
+use wasminspect_vm::{NumVal, WasmValue};
+use wasmparser::{BinaryReader, DataKind, Operator, Payload, TypeRef};
+
+const WASM_PAGE_SIZE: usize = 0x10000;
+/// Zero bytes shorter than this are included in data segments to avoid tiny segments
+const MIN_ZERO_GAP: usize = 16;
+
+pub struct CoreFrame {
+    pub funcidx: u32,
+    /// Offset of the instruction relative to the start of the function body
+    pub codeoffset: u32,
+    /// `None` for values which can't be represented in the coredump
+    pub locals: Vec<Option<WasmValue>>,
+    pub stack: Vec<Option<WasmValue>>,
+}
+
+pub struct CoreDump {
+    pub executable_name: String,
+    /// Frames of the main thread from the innermost
+    pub frames: Vec<CoreFrame>,
+    pub memories: Vec<Vec<u8>>,
+    pub globals: Vec<Option<WasmValue>>,
+}
+
+fn write_u32(sink: &mut Vec<u8>, mut value: u32) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            sink.push(byte);
+            return;
+        }
+        sink.push(byte | 0x80);
+    }
+}
+
+fn write_i64(sink: &mut Vec<u8>, mut value: i64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        let is_done = (value == 0 && byte & 0x40 == 0) || (value == -1 && byte & 0x40 != 0);
+        if is_done {
+            sink.push(byte);
+            return;
+        }
+        sink.push(byte | 0x80);
+    }
+}
+
+fn write_name(sink: &mut Vec<u8>, name: &str) {
+    write_u32(sink, name.len() as u32);
+    sink.extend_from_slice(name.as_bytes());
+}
+
+fn write_section(sink: &mut Vec<u8>, id: u8, contents: &[u8]) {
+    sink.push(id);
+    write_u32(sink, contents.len() as u32);
+    sink.extend_from_slice(contents);
+}
+
+fn write_custom_section(sink: &mut Vec<u8>, name: &str, contents: &[u8]) {
+    let mut section = vec![];
+    write_name(&mut section, name);
+    section.extend_from_slice(contents);
+    write_section(sink, 0, &section);
+}
+
+fn write_value(sink: &mut Vec<u8>, value: &Option<WasmValue>) {
+    match value {
+        Some(WasmValue::Num(NumVal::I32(v))) => {
+            sink.push(0x7f);
+            write_i64(sink, *v as i64);
+        }
+        Some(WasmValue::Num(NumVal::I64(v))) => {
+            sink.push(0x7e);
+            write_i64(sink, *v);
+        }
+        Some(WasmValue::Num(NumVal::F32(v))) => {
+            sink.push(0x7d);
+            sink.extend_from_slice(&v.to_bits().to_le_bytes());
+        }
+        Some(WasmValue::Num(NumVal::F64(v))) => {
+            sink.push(0x7c);
+            sink.extend_from_slice(&v.to_bits().to_le_bytes());
+        }
+        Some(WasmValue::Ref(_)) | None => sink.push(0x01),
+    }
+}
+
+fn read_value(reader: &mut BinaryReader) -> Result<Option<WasmValue>> {
+    let value = match reader.read_u8()? {
+        0x01 => return Ok(None),
+        0x7f => WasmValue::I32(reader.read_var_i32()?),
+        0x7e => WasmValue::I64(reader.read_var_i64()?),
+        0x7d => WasmValue::F32(reader.read_f32()?.bits()),
+        0x7c => WasmValue::F64(reader.read_f64()?.bits()),
+        ty => return Err(anyhow!("unknown value type 0x{:x} in coredump", ty)),
+    };
+    Ok(Some(value))
+}
+
+fn read_values(reader: &mut BinaryReader) -> Result<Vec<Option<WasmValue>>> {
+    let count = reader.read_var_u32()?;
+    (0..count).map(|_| read_value(reader)).collect()
+}
+
+/// Splits the memory into segments of non-zero bytes
+fn data_segments(memory: &[u8]) -> Vec<(usize, &[u8])> {
+    let mut segments = vec![];
+    let mut start = None;
+    let mut zeros = 0;
+    for (offset, byte) in memory.iter().enumerate() {
+        if *byte != 0 {
+            if start.is_none() {
+                start = Some(offset);
+            }
+            zeros = 0;
+            continue;
+        }
+        zeros += 1;
+        if let Some(segment_start) = start {
+            if zeros >= MIN_ZERO_GAP {
+                let end = offset + 1 - zeros;
+                segments.push((segment_start, &memory[segment_start..end]));
+                start = None;
+            }
+        }
+    }
+    if let Some(segment_start) = start {
+        let end = memory.len() - zeros;
+        segments.push((segment_start, &memory[segment_start..end]));
+    }
+    segments
+}
+
+impl CoreDump {
+    /// Encodes the coredump as a wasm module
+    pub fn encode(&self) -> Vec<u8> {
+        let mut module = b"\0asm\x01\0\0\0".to_vec();
+
+        let mut process_info = vec![0x00];
+        write_name(&mut process_info, &self.executable_name);
+        write_custom_section(&mut module, "core", &process_info);
+
+        let mut core_modules = vec![];
+        write_u32(&mut core_modules, 1);
+        core_modules.push(0x00);
+        write_name(&mut core_modules, &self.executable_name);
+        write_custom_section(&mut module, "coremodules", &core_modules);
+
+        let mut core_instances = vec![];
+        write_u32(&mut core_instances, 1);
+        core_instances.push(0x00);
+        write_u32(&mut core_instances, 0);
+        write_u32(&mut core_instances, self.memories.len() as u32);
+        for index in 0..self.memories.len() {
+            write_u32(&mut core_instances, index as u32);
+        }
+        write_u32(&mut core_instances, self.globals.len() as u32);
+        for index in 0..self.globals.len() {
+            write_u32(&mut core_instances, index as u32);
+        }
+        write_custom_section(&mut module, "coreinstances", &core_instances);
+
+        let mut core_stack = vec![0x00];
+        write_name(&mut core_stack, "main");
+        write_u32(&mut core_stack, self.frames.len() as u32);
+        for frame in &self.frames {
+            core_stack.push(0x00);
+            // The main module is the only instance
+            write_u32(&mut core_stack, 0);
+            write_u32(&mut core_stack, frame.funcidx);
+            write_u32(&mut core_stack, frame.codeoffset);
+            write_u32(&mut core_stack, frame.locals.len() as u32);
+            for value in &frame.locals {
+                write_value(&mut core_stack, value);
+            }
+            write_u32(&mut core_stack, frame.stack.len() as u32);
+            for value in &frame.stack {
+                write_value(&mut core_stack, value);
+            }
+        }
+        write_custom_section(&mut module, "corestack", &core_stack);
+
+        if !self.memories.is_empty() {
+            let mut section = vec![];
+            write_u32(&mut section, self.memories.len() as u32);
+            for memory in &self.memories {
+                section.push(0x00);
+                write_u32(&mut section, (memory.len() / WASM_PAGE_SIZE) as u32);
+            }
+            write_section(&mut module, 5, &section);
+        }
+
+        if !self.globals.is_empty() {
+            let mut section = vec![];
+            write_u32(&mut section, self.globals.len() as u32);
+            for global in &self.globals {
+                match global {
+                    Some(WasmValue::Num(num)) => {
+                        let (ty, opcode) = match num {
+                            NumVal::I32(_) => (0x7f, 0x41),
+                            NumVal::I64(_) => (0x7e, 0x42),
+                            NumVal::F32(_) => (0x7d, 0x43),
+                            NumVal::F64(_) => (0x7c, 0x44),
+                        };
+                        section.extend_from_slice(&[ty, 0x01, opcode]);
+                        match num {
+                            NumVal::I32(v) => write_i64(&mut section, *v as i64),
+                            NumVal::I64(v) => write_i64(&mut section, *v),
+                            NumVal::F32(v) => section.extend_from_slice(&v.to_bits().to_le_bytes()),
+                            NumVal::F64(v) => section.extend_from_slice(&v.to_bits().to_le_bytes()),
+                        }
+                    }
+                    // References can't be restored, so they are recorded as null
+                    Some(WasmValue::Ref(_)) | None => {
+                        section.extend_from_slice(&[0x70, 0x01, 0xd0, 0x70])
+                    }
+                }
+                section.push(0x0b);
+            }
+            write_section(&mut module, 6, &section);
+        }
+
+        let segments = self
+            .memories
+            .iter()
+            .enumerate()
+            .flat_map(|(index, memory)| {
+                data_segments(memory)
+                    .into_iter()
+                    .map(move |(offset, bytes)| (index, offset, bytes))
+            })
+            .collect::<Vec<_>>();
+        if !segments.is_empty() {
+            let mut section = vec![];
+            write_u32(&mut section, segments.len() as u32);
+            for (index, offset, bytes) in segments {
+                if index == 0 {
+                    section.push(0x00);
+                } else {
+                    section.push(0x02);
+                    write_u32(&mut section, index as u32);
+                }
+                section.push(0x41);
+                write_i64(&mut section, offset as u32 as i32 as i64);
+                section.push(0x0b);
+                write_u32(&mut section, bytes.len() as u32);
+                section.extend_from_slice(bytes);
+            }
+            write_section(&mut module, 11, &section);
+        }
+        module
+    }
+
+    pub fn parse(bytes: &[u8]) -> Result<Self> {
+        let mut executable_name = None;
+        let mut frames = None;
+        let mut memories = vec![];
+        let mut globals = vec![];
+        for payload in wasmparser::Parser::new(0).parse_all(bytes) {
+            match payload? {
+                Payload::CustomSection(section) if section.name() == "core" => {
+                    let mut reader = BinaryReader::new(section.data());
+                    if reader.read_u8()? != 0x00 {
+                        return Err(anyhow!("unsupported process-info in coredump"));
+                    }
+                    executable_name = Some(reader.read_string()?.to_string());
+                }
+                Payload::CustomSection(section) if section.name() == "corestack" => {
+                    let mut reader = BinaryReader::new(section.data());
+                    if reader.read_u8()? != 0x00 {
+                        return Err(anyhow!("unsupported thread-info in coredump"));
+                    }
+                    let _thread_name = reader.read_string()?;
+                    let count = reader.read_var_u32()?;
+                    let mut stack = vec![];
+                    for _ in 0..count {
+                        if reader.read_u8()? != 0x00 {
+                            return Err(anyhow!("unsupported frame in coredump"));
+                        }
+                        let _instanceidx = reader.read_var_u32()?;
+                        stack.push(CoreFrame {
+                            funcidx: reader.read_var_u32()?,
+                            codeoffset: reader.read_var_u32()?,
+                            locals: read_values(&mut reader)?,
+                            stack: read_values(&mut reader)?,
+                        });
+                    }
+                    frames = Some(stack);
+                }
+                Payload::MemorySection(reader) => {
+                    for memory in reader {
+                        memories.push(vec![0; memory?.initial as usize * WASM_PAGE_SIZE]);
+                    }
+                }
+                Payload::GlobalSection(reader) => {
+                    for global in reader {
+                        let mut ops = global?.init_expr.get_operators_reader();
+                        let value = match ops.read()? {
+                            Operator::I32Const { value } => Some(WasmValue::I32(value)),
+                            Operator::I64Const { value } => Some(WasmValue::I64(value)),
+                            Operator::F32Const { value } => Some(WasmValue::F32(value.bits())),
+                            Operator::F64Const { value } => Some(WasmValue::F64(value.bits())),
+                            _ => None,
+                        };
+                        globals.push(value);
+                    }
+                }
+                Payload::DataSection(reader) => {
+                    for data in reader {
+                        let data = data?;
+                        let (memory_index, offset_expr) = match data.kind {
+                            DataKind::Active {
+                                memory_index,
+                                offset_expr,
+                            } => (memory_index as usize, offset_expr),
+                            DataKind::Passive => continue,
+                        };
+                        let offset = match offset_expr.get_operators_reader().read()? {
+                            Operator::I32Const { value } => value as u32 as usize,
+                            _ => return Err(anyhow!("unsupported data offset in coredump")),
+                        };
+                        let memory = memories.get_mut(memory_index).ok_or_else(|| {
+                            anyhow!("unknown memory {} in coredump", memory_index)
+                        })?;
+                        if offset + data.data.len() > memory.len() {
+                            return Err(anyhow!("data segment out of memory in coredump"));
+                        }
+                        memory[offset..offset + data.data.len()].copy_from_slice(data.data);
+                    }
+                }
+                _ => continue,
+            }
+        }
+        Ok(Self {
+            executable_name: executable_name.ok_or_else(|| anyhow!("not a coredump"))?,
+            frames: frames.ok_or_else(|| anyhow!("no corestack section in coredump"))?,
+            memories,
+            globals,
+        })
+    }
+}
+
+/// Returns the number of imported functions and offsets of function bodies
+/// relative to the code section
+pub fn function_offsets(module: &[u8]) -> Result<(usize, Vec<usize>)> {
+    let mut imported_funcs = 0;
+    let mut code_section_offset = 0;
+    let mut offsets = vec![];
+    for payload in wasmparser::Parser::new(0).parse_all(module) {
+        match payload? {
+            Payload::ImportSection(reader) => {
+                for import in reader {
+                    if let TypeRef::Func(_) = import?.ty {
+                        imported_funcs += 1;
+                    }
+                }
+            }
+            Payload::CodeSectionStart { range, .. } => code_section_offset = range.start,
+            Payload::CodeSectionEntry(body) => {
+                offsets.push(body.range().start - code_section_offset)
+            }
+            _ => continue,
+        }
+    }
+    Ok((imported_funcs, offsets))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_coredump_roundtrip() {
+        let mut memory = vec![0; WASM_PAGE_SIZE];
+        memory[8..12].copy_from_slice(b"core");
+        memory[0x1000] = 0xff;
+        let coredump = CoreDump {
+            executable_name: "main.wasm".to_string(),
+            frames: vec![CoreFrame {
+                funcidx: 3,
+                codeoffset: 12,
+                locals: vec![Some(WasmValue::I32(-1)), None],
+                stack: vec![Some(WasmValue::I64(1 << 40))],
+            }],
+            memories: vec![memory.clone()],
+            globals: vec![Some(WasmValue::I32(42)), None],
+        };
+        let bytes = coredump.encode();
+        wasmparser::validate(&bytes).unwrap();
+        let parsed = CoreDump::parse(&bytes).unwrap();
+        assert_eq!(parsed.executable_name, "main.wasm");
+        assert_eq!(parsed.frames.len(), 1);
+        assert_eq!(parsed.frames[0].funcidx, 3);
+        assert_eq!(parsed.frames[0].codeoffset, 12);
+        assert_eq!(parsed.frames[0].locals.len(), 2);
+        assert!(parsed.frames[0].locals[1].is_none());
+        assert_eq!(parsed.memories, vec![memory]);
+        assert_eq!(parsed.globals.len(), 2);
+        assert!(parsed.globals[1].is_none());
+    }
+}
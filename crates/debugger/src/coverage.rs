@@ -0,0 +1,173 @@
+//! Line coverage collected from the executed instructions.
+//!
+//! The debugger counts executions of each instruction by its code offset, and the
+//! counts are mapped to source lines through the source map when the report is made.
+
+use crate::commands::sourcemap::SourceMap;
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, Write};
+use wasminspect_vm::{FuncAddr, ModuleIndex, Store};
+
+#[derive(Default)]
+pub struct Coverage {
+    /// Execution counts by code offset of the instruction
+    counts: HashMap<usize, u64>,
+}
+
+impl Coverage {
+    pub fn hit(&mut self, offset: usize) {
+        *self.counts.entry(offset).or_insert(0) += 1;
+    }
+
+    pub fn report(
+        &self,
+        store: &Store,
+        module_index: ModuleIndex,
+        sourcemap: &dyn SourceMap,
+    ) -> CoverageReport {
+        let mut report = CoverageReport::default();
+        let mut func_index = 0;
+        while let Some((func, _)) = store.func(FuncAddr::new_unsafe(module_index, func_index)) {
+            func_index += 1;
+            let func = match func.defined() {
+                Some(func) if func.module_index() == module_index => func,
+                _ => continue,
+            };
+            let mut function = FunctionCoverage {
+                name: func.name().clone(),
+                instructions: func.instructions().len(),
+                ..Default::default()
+            };
+            for inst in func.instructions() {
+                let count = self.counts.get(&inst.offset).copied().unwrap_or(0);
+                if count > 0 {
+                    function.executed += 1;
+                }
+                let info = match sourcemap.find_line_info(inst.offset) {
+                    Some(info) => info,
+                    None => continue,
+                };
+                let line = match info.line {
+                    Some(line) if line > 0 => line,
+                    _ => continue,
+                };
+                if function.location.is_none() {
+                    function.location = Some((info.filepath.clone(), line));
+                }
+                let hits = report
+                    .lines
+                    .entry(info.filepath)
+                    .or_default()
+                    .entry(line)
+                    .or_insert(0);
+                *hits = (*hits).max(count);
+            }
+            if let Some(entry) = func.instructions().first() {
+                function.calls = self.counts.get(&entry.offset).copied().unwrap_or(0);
+            }
+            report.functions.push(function);
+        }
+        report
+    }
+}
+
+#[derive(Default)]
+pub struct FunctionCoverage {
+    pub name: String,
+    /// Number of times the function is entered
+    pub calls: u64,
+    /// Number of instructions executed at least once
+    pub executed: usize,
+    pub instructions: usize,
+    /// The source file and line where the function begins
+    pub location: Option<(String, u64)>,
+}
+
+#[derive(Default)]
+pub struct CoverageReport {
+    pub functions: Vec<FunctionCoverage>,
+    /// Hit counts of source lines by file
+    pub lines: BTreeMap<String, BTreeMap<u64, u64>>,
+}
+
+impl CoverageReport {
+    /// Writes LCOV if the module has line info, otherwise the instruction summary
+    pub fn write<W: Write>(&self, output: &mut W) -> io::Result<()> {
+        if self.lines.is_empty() {
+            self.write_summary(output)
+        } else {
+            self.write_lcov(output)
+        }
+    }
+
+    pub fn write_lcov<W: Write>(&self, output: &mut W) -> io::Result<()> {
+        for (file, lines) in &self.lines {
+            writeln!(output, "TN:")?;
+            writeln!(output, "SF:{}", file)?;
+            let functions = self
+                .functions
+                .iter()
+                .filter(|f| matches!(f.location, Some((ref path, _)) if path == file))
+                .collect::<Vec<_>>();
+            for function in &functions {
+                if let Some((_, line)) = function.location {
+                    writeln!(output, "FN:{},{}", line, function.name)?;
+                }
+            }
+            for function in &functions {
+                writeln!(output, "FNDA:{},{}", function.calls, function.name)?;
+            }
+            writeln!(output, "FNF:{}", functions.len())?;
+            let hit_functions = functions.iter().filter(|f| f.calls > 0).count();
+            writeln!(output, "FNH:{}", hit_functions)?;
+            for (line, hits) in lines {
+                writeln!(output, "DA:{},{}", line, hits)?;
+            }
+            writeln!(output, "LF:{}", lines.len())?;
+            let hit_lines = lines.values().filter(|hits| **hits > 0).count();
+            writeln!(output, "LH:{}", hit_lines)?;
+            writeln!(output, "end_of_record")?;
+        }
+        Ok(())
+    }
+
+    /// Writes the number of executed wasm instructions of each function
+    pub fn write_summary<W: Write>(&self, output: &mut W) -> io::Result<()> {
+        let (mut executed, mut instructions) = (0, 0);
+        for function in &self.functions {
+            writeln!(
+                output,
+                "{}: {}/{} instructions, {} calls",
+                function.name, function.executed, function.instructions, function.calls
+            )?;
+            executed += function.executed;
+            instructions += function.instructions;
+        }
+        writeln!(output, "total: {}/{} instructions", executed, instructions)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_lcov() {
+        let mut report = CoverageReport::default();
+        report.functions.push(FunctionCoverage {
+            name: "main".to_string(),
+            calls: 1,
+            executed: 3,
+            instructions: 4,
+            location: Some(("main.c".to_string(), 2)),
+        });
+        let lines = report.lines.entry("main.c".to_string()).or_default();
+        lines.insert(2, 1);
+        lines.insert(3, 0);
+        let mut output = vec![];
+        report.write(&mut output).unwrap();
+        let expected = "TN:\nSF:main.c\nFN:2,main\nFNDA:1,main\nFNF:1\nFNH:1\n\
+                        DA:2,1\nDA:3,0\nLF:2\nLH:1\nend_of_record\n";
+        assert_eq!(String::from_utf8(output).unwrap(), expected);
+    }
+}
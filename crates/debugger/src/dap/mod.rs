@@ -0,0 +1,822 @@
+//! Debug Adapter Protocol server built on top of `MainDebugger`
+//! See also https://microsoft.github.io/debug-adapter-protocol/specification
+
+mod protocol;
+
+use crate::commands::command::CommandContext;
+use crate::commands::debugger::{Breakpoint, Debugger, OutputPrinter, RunResult};
+use crate::commands::expression::evaluate_variable;
+use crate::commands::frame::{virtual_frames, with_selected_frame, VirtualFrame};
+use crate::commands::symbol::format_symbol;
+use crate::commands::thread::{step_line, step_out};
+use crate::debugger::MainDebugger;
+use crate::process::Process;
+use crate::ModuleInput;
+use anyhow::{anyhow, Context, Result};
+use log::warn;
+use protocol::{Request, Sender};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, ToSocketAddrs};
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
+use wasminspect_vm::{GlobalAddr, NumVal, Signal, WasmValue};
+use wasminspect_wasi::OutputTarget;
+
+/// The process has only one thread
+const THREAD_ID: usize = 1;
+
+/// Subcommands moving the process. The client follows the process only when it's moved by
+/// `continue` and the step requests, so they are rejected in the debug console.
+const RESUMING_COMMANDS: [(&str, &[&str]); 2] = [
+    ("process", &["continue", "reverse-continue", "launch"]),
+    (
+        "thread",
+        &[
+            "step-in",
+            "step-over",
+            "step-out",
+            "step-inst-in",
+            "step-inst-over",
+            "step-back",
+            "step-inst-back",
+        ],
+    ),
+];
+
+fn is_resuming_command(line: &str) -> bool {
+    let mut words = line.split_whitespace();
+    match (words.next(), words.next()) {
+        (Some(command), Some(subcommand)) => RESUMING_COMMANDS
+            .iter()
+            .any(|(name, subcommands)| *name == command && subcommands.contains(&subcommand)),
+        _ => false,
+    }
+}
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "camelCase", default)]
+struct LaunchArguments {
+    /// Path to the module. The module given from the command line is used if not specified
+    program: Option<PathBuf>,
+    /// Arguments to pass to the WASI entry point
+    args: Vec<String>,
+    /// Exported function to start instead of the start function or `_start`
+    entry_point: Option<String>,
+    stop_on_entry: bool,
+}
+
+#[derive(Deserialize)]
+struct Source {
+    path: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct SourceBreakpoint {
+    line: u64,
+}
+
+#[derive(Deserialize)]
+struct SetBreakpointsArguments {
+    source: Source,
+    #[serde(default)]
+    breakpoints: Vec<SourceBreakpoint>,
+}
+
+#[derive(Deserialize)]
+struct FunctionBreakpoint {
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct SetFunctionBreakpointsArguments {
+    breakpoints: Vec<FunctionBreakpoint>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "camelCase", default)]
+struct StackTraceArguments {
+    start_frame: usize,
+    /// All frames are returned if zero
+    levels: usize,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ScopesArguments {
+    frame_id: usize,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct VariablesArguments {
+    variables_reference: usize,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct EvaluateArguments {
+    expression: String,
+    frame_id: Option<usize>,
+    context: Option<String>,
+}
+
+#[derive(Clone, Copy)]
+enum Scope {
+    /// Variables described in DWARF
+    Variables,
+    /// Wasm locals of the physical frame
+    Locals,
+    Globals,
+}
+
+const SCOPES: [Scope; 3] = [Scope::Variables, Scope::Locals, Scope::Globals];
+
+impl Scope {
+    fn name(&self) -> &'static str {
+        match self {
+            Scope::Variables => "Variables",
+            Scope::Locals => "Wasm Locals",
+            Scope::Globals => "Wasm Globals",
+        }
+    }
+
+    /// Encodes the frame and the scope into `variablesReference`, which must be non-zero
+    fn reference(self, frame_id: usize) -> usize {
+        frame_id * SCOPES.len() + self as usize + 1
+    }
+
+    fn from_reference(reference: usize) -> Option<(usize, Scope)> {
+        let index = reference.checked_sub(1)?;
+        Some((index / SCOPES.len(), SCOPES[index % SCOPES.len()]))
+    }
+}
+
+enum Execution {
+    Launch,
+    Continue,
+    Next,
+    StepIn,
+    StepOut,
+}
+
+enum Stop {
+    Stopped(&'static str),
+    /// Stopped by the exit breakpoint before exiting with the status
+    Exiting(i32),
+    Finish(Vec<WasmValue>),
+    Exit(i32),
+}
+
+/// Collects outputs of commands to send them to the client
+struct BufferPrinter {
+    lines: Rc<RefCell<Vec<(&'static str, String)>>>,
+}
+
+impl OutputPrinter for BufferPrinter {
+    fn println(&self, output: &str) {
+        self.lines
+            .borrow_mut()
+            .push(("stdout", format!("{}\n", output)));
+    }
+    fn eprintln(&self, output: &str) {
+        self.lines
+            .borrow_mut()
+            .push(("stderr", format!("{}\n", output)));
+    }
+    /// The client can't answer questions because evaluating a command is a single request
+    fn confirm(&self, prompt: &str) -> bool {
+        let output = format!("{} n (Questions can't be answered over DAP)", prompt);
+        self.eprintln(&output);
+        false
+    }
+}
+
+fn format_value(value: &WasmValue) -> (String, &'static str) {
+    match value {
+        WasmValue::Num(NumVal::I32(v)) => (v.to_string(), "i32"),
+        WasmValue::Num(NumVal::I64(v)) => (v.to_string(), "i64"),
+        WasmValue::Num(NumVal::F32(v)) => (v.to_float().to_string(), "f32"),
+        WasmValue::Num(NumVal::F64(v)) => (v.to_float().to_string(), "f64"),
+        WasmValue::Ref(v) => (format!("{:?}", v), "ref"),
+    }
+}
+
+fn wasm_variable(name: String, value: &WasmValue) -> Value {
+    let (value, ty) = format_value(value);
+    json!({ "name": name, "value": value, "type": ty, "variablesReference": 0 })
+}
+
+struct Server<W: Write> {
+    process: Process<MainDebugger>,
+    context: CommandContext,
+    output: Rc<RefCell<Vec<(&'static str, String)>>>,
+    sender: Sender<W>,
+    /// Set by the reader thread when the client asks to pause the process
+    pause_requested: Arc<AtomicBool>,
+    launch: Option<LaunchArguments>,
+    source_breakpoints: HashMap<String, Vec<usize>>,
+    function_breakpoints: Vec<String>,
+    next_breakpoint_id: usize,
+}
+
+impl<W: Write> Server<W> {
+    fn debugger(&mut self) -> &mut MainDebugger {
+        &mut self.process.debugger
+    }
+
+    fn run(&mut self, requests: mpsc::Receiver<Request>) -> Result<()> {
+        for request in requests {
+            let (body, execution) = match self.handle(&request) {
+                Ok((body, execution)) => (Ok(body), execution),
+                Err(err) => (Err(err), None),
+            };
+            self.flush_output()?;
+            let is_launched = request.command == "launch" && body.is_ok();
+            self.sender.response(&request, body)?;
+            match request.command.as_str() {
+                "launch" if is_launched => self.sender.event("initialized", json!({}))?,
+                "disconnect" => break,
+                _ => (),
+            }
+            if let Some(execution) = execution {
+                self.execute(execution)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn handle(&mut self, request: &Request) -> Result<(Value, Option<Execution>)> {
+        let body = match request.command.as_str() {
+            "initialize" => json!({
+                "supportsConfigurationDoneRequest": true,
+                "supportsFunctionBreakpoints": true,
+                "supportsEvaluateForHovers": true,
+            }),
+            "launch" => {
+                self.launch(request.arguments()?)?;
+                json!({})
+            }
+            "setBreakpoints" => self.set_breakpoints(request.arguments()?)?,
+            "setFunctionBreakpoints" => self.set_function_breakpoints(request.arguments()?)?,
+            "configurationDone" => return Ok((json!({}), Some(Execution::Launch))),
+            "threads" => json!({ "threads": [{ "id": THREAD_ID, "name": "main" }] }),
+            "stackTrace" => self.stack_trace(request.arguments()?)?,
+            "scopes" => self.scopes(request.arguments()?)?,
+            "variables" => self.variables(request.arguments()?)?,
+            "evaluate" => self.evaluate(request.arguments()?)?,
+            "continue" => {
+                let body = json!({ "allThreadsContinued": true });
+                return Ok((body, Some(Execution::Continue)));
+            }
+            "next" => return Ok((json!({}), Some(Execution::Next))),
+            "stepIn" => return Ok((json!({}), Some(Execution::StepIn))),
+            "stepOut" => return Ok((json!({}), Some(Execution::StepOut))),
+            "pause" => {
+                // The process is already stopped if the request is handled here
+                self.debugger()
+                    .interrupt_handle()
+                    .store(false, Ordering::SeqCst);
+                self.pause_requested.store(false, Ordering::SeqCst);
+                json!({})
+            }
+            "disconnect" => json!({}),
+            command => return Err(anyhow!("Unsupported request '{}'", command)),
+        };
+        Ok((body, None))
+    }
+
+    fn launch(&mut self, args: LaunchArguments) -> Result<()> {
+        if let Some(ref program) = args.program {
+            let bytes = std::fs::read(program)
+                .with_context(|| format!("failed to read {}", program.display()))?;
+            let module_input = ModuleInput {
+                basename: program
+                    .file_name()
+                    .map(|name| name.to_string_lossy().to_string())
+                    .unwrap_or_default(),
+                dirname: program.parent().map(|dir| dir.to_path_buf()),
+                bytes,
+                debug_file: None,
+                source_map: None,
+            };
+            self.process
+                .debugger
+                .load_main_module(&module_input.bytes, module_input.basename.clone())?;
+            if let Err(err) = crate::load_debug_info(&module_input, &mut self.context) {
+                warn!("Failed to load dwarf info: {}", err);
+            }
+        }
+        self.debugger()
+            .instantiate(HashMap::new(), Some(&args.args))?;
+        self.launch = Some(args);
+        Ok(())
+    }
+
+    fn install_breakpoints(&mut self) {
+        let debugger = &mut self.process.debugger;
+        debugger.clear_breakpoints();
+        for offsets in self.source_breakpoints.values() {
+            for offset in offsets {
+                debugger.set_breakpoint(Breakpoint::Instruction {
+                    inst_offset: *offset,
+                });
+            }
+        }
+        for name in &self.function_breakpoints {
+            debugger.set_breakpoint(Breakpoint::Function { name: name.clone() });
+        }
+    }
+
+    fn set_breakpoints(&mut self, args: SetBreakpointsArguments) -> Result<Value> {
+        let path = args
+            .source
+            .path
+            .ok_or_else(|| anyhow!("Source without path is not supported"))?;
+        let mut breakpoints = vec![];
+        let mut all_offsets = vec![];
+        for breakpoint in args.breakpoints {
+            let offsets = self
+                .context
+                .sourcemap
+                .find_line_offsets(&path, breakpoint.line);
+            self.next_breakpoint_id += 1;
+            let line_info = offsets
+                .first()
+                .and_then(|offset| self.context.sourcemap.find_line_info(*offset));
+            breakpoints.push(match line_info {
+                Some(line_info) => json!({
+                    "id": self.next_breakpoint_id,
+                    "verified": true,
+                    "line": line_info.line.unwrap_or(breakpoint.line),
+                    "source": { "path": path },
+                }),
+                None => json!({
+                    "id": self.next_breakpoint_id,
+                    "verified": false,
+                    "message": "No code found for the line",
+                }),
+            });
+            all_offsets.extend(offsets);
+        }
+        self.source_breakpoints.insert(path, all_offsets);
+        self.install_breakpoints();
+        Ok(json!({ "breakpoints": breakpoints }))
+    }
+
+    fn set_function_breakpoints(&mut self, args: SetFunctionBreakpointsArguments) -> Result<Value> {
+        self.function_breakpoints = args.breakpoints.into_iter().map(|b| b.name).collect();
+        self.install_breakpoints();
+        let mut breakpoints = vec![];
+        for _ in &self.function_breakpoints {
+            self.next_breakpoint_id += 1;
+            breakpoints.push(json!({ "id": self.next_breakpoint_id, "verified": true }));
+        }
+        Ok(json!({ "breakpoints": breakpoints }))
+    }
+
+    fn frames(&self) -> Vec<VirtualFrame> {
+        virtual_frames(&self.process.debugger, &self.context)
+    }
+
+    /// Returns the offset of the instruction executed next in the innermost frame
+    fn next_offset(&self) -> Option<usize> {
+        let (insts, next_index) = self.process.debugger.selected_instructions().ok()?;
+        insts.get(next_index).map(|inst| inst.offset)
+    }
+
+    fn stack_trace(&self, args: StackTraceArguments) -> Result<Value> {
+        let demangle = self.process.debugger.get_opts().demangle_symbols;
+        let frames = self.frames();
+        let levels = if args.levels == 0 {
+            frames.len()
+        } else {
+            args.levels
+        };
+        let stack_frames = frames
+            .iter()
+            .enumerate()
+            .skip(args.start_frame)
+            .take(levels)
+            .map(|(id, frame)| {
+                let mut stack_frame = json!({
+                    "id": id,
+                    "name": format_symbol(&frame.name, demangle),
+                    "line": 0,
+                    "column": 0,
+                });
+                // Clients show the location to be executed next for the innermost frame
+                let (code_offset, line_info) = if id == 0 {
+                    let offset = self.next_offset().or(frame.code_offset);
+                    let line_info = offset.and_then(|o| self.context.sourcemap.find_line_info(o));
+                    (offset, line_info)
+                } else {
+                    (frame.code_offset, frame.line_info(&self.context))
+                };
+                if let Some(line_info) = line_info {
+                    let name = std::path::Path::new(&line_info.filepath)
+                        .file_name()
+                        .map(|name| name.to_string_lossy().to_string());
+                    stack_frame["source"] = json!({ "name": name, "path": line_info.filepath });
+                    stack_frame["line"] = json!(line_info.line.unwrap_or(0));
+                    stack_frame["column"] = json!(Into::<u64>::into(line_info.column));
+                }
+                if let Some(offset) = code_offset {
+                    stack_frame["instructionPointerReference"] = json!(format!("0x{:x}", offset));
+                }
+                stack_frame
+            })
+            .collect::<Vec<_>>();
+        Ok(json!({ "stackFrames": stack_frames, "totalFrames": frames.len() }))
+    }
+
+    fn with_frame<T>(
+        &mut self,
+        frame_id: usize,
+        f: impl FnOnce(&mut Self) -> Result<T>,
+    ) -> Result<T> {
+        let frame = self
+            .frames()
+            .into_iter()
+            .nth(frame_id)
+            .ok_or_else(|| anyhow!("Frame {} is not found", frame_id))?;
+        with_selected_frame(
+            self,
+            Self::debugger,
+            frame.physical_index,
+            frame.inline_depth,
+            f,
+        )
+    }
+
+    fn scopes(&self, args: ScopesArguments) -> Result<Value> {
+        let scopes = SCOPES
+            .iter()
+            .map(|scope| {
+                json!({
+                    "name": scope.name(),
+                    "variablesReference": scope.reference(args.frame_id),
+                    "expensive": false,
+                })
+            })
+            .collect::<Vec<_>>();
+        Ok(json!({ "scopes": scopes }))
+    }
+
+    fn variables(&mut self, args: VariablesArguments) -> Result<Value> {
+        let (frame_id, scope) = Scope::from_reference(args.variables_reference)
+            .ok_or_else(|| anyhow!("Invalid variables reference"))?;
+        let variables = self.with_frame(frame_id, |server| match scope {
+            Scope::Variables => server.dwarf_variables(),
+            Scope::Locals => Ok(server
+                .process
+                .debugger
+                .locals()
+                .iter()
+                .enumerate()
+                .map(|(index, value)| wasm_variable(format!("local{}", index), value))
+                .collect()),
+            Scope::Globals => server.globals(),
+        })?;
+        Ok(json!({ "variables": variables }))
+    }
+
+    fn dwarf_variables(&self) -> Result<Vec<Value>> {
+        let debugger = &self.process.debugger;
+        let (insts, next_index) = debugger.selected_instructions()?;
+        let current_index = if next_index == 0 { 0 } else { next_index - 1 };
+        let variables = self.context.subroutine.variable_name_list(
+            insts[current_index].offset,
+            debugger.selected_inline_depth(),
+        )?;
+        Ok(variables
+            .into_iter()
+            .map(|variable| {
+                let value = evaluate_variable(debugger, &self.context, variable.name.clone())
+                    .unwrap_or_else(|err| format!("<{}>", err));
+                json!({
+                    "name": variable.name,
+                    "value": value,
+                    "type": variable.type_name,
+                    "variablesReference": 0,
+                })
+            })
+            .collect())
+    }
+
+    fn globals(&self) -> Result<Vec<Value>> {
+        let debugger = &self.process.debugger;
+        let module_index = debugger
+            .current_frame()
+            .ok_or_else(|| anyhow!("function frame not found"))?
+            .module_index;
+        let store = debugger.store()?;
+        Ok((0..store.global_count(module_index))
+            .map(|index| {
+                let global = store.global(GlobalAddr::new_unsafe(module_index, index));
+                let value = global.borrow().value();
+                wasm_variable(format!("global{}", index), &value)
+            })
+            .collect())
+    }
+
+    fn evaluate(&mut self, args: EvaluateArguments) -> Result<Value> {
+        let expression = args.expression.trim().to_string();
+        let is_repl = args.context.as_deref() == Some("repl");
+        let result = match args.frame_id {
+            Some(frame_id) => self.with_frame(frame_id, |server| {
+                server.evaluate_expression(&expression, is_repl)
+            })?,
+            None => self.evaluate_expression(&expression, is_repl)?,
+        };
+        Ok(json!({ "result": result, "variablesReference": 0 }))
+    }
+
+    /// Evaluates a variable name, or a debugger command in REPL
+    fn evaluate_expression(&mut self, expression: &str, is_repl: bool) -> Result<String> {
+        let debugger = &self.process.debugger;
+        if let Some(index) = expression.strip_prefix("local") {
+            if let Ok(index) = index.parse::<usize>() {
+                let locals = debugger.locals();
+                let value = locals
+                    .get(index)
+                    .ok_or_else(|| anyhow!("local{} is out of range", index))?;
+                return Ok(format_value(value).0);
+            }
+        }
+        if let Some(index) = expression.strip_prefix("global") {
+            if let Ok(index) = index.parse::<usize>() {
+                let module_index = debugger
+                    .current_frame()
+                    .ok_or_else(|| anyhow!("function frame not found"))?
+                    .module_index;
+                let store = debugger.store()?;
+                if index >= store.global_count(module_index) {
+                    return Err(anyhow!("global{} is out of range", index));
+                }
+                let global = store.global(GlobalAddr::new_unsafe(module_index, index));
+                let value = global.borrow().value();
+                return Ok(format_value(&value).0);
+            }
+        }
+        match evaluate_variable(debugger, &self.context, expression.to_string()) {
+            Ok(value) => return Ok(value),
+            Err(err) if !is_repl => return Err(err),
+            // Evaluate as a debugger command in REPL
+            Err(_) => (),
+        }
+        if is_resuming_command(&self.process.expand_alias(expression)?) {
+            return Err(anyhow!(
+                "'{}' can't be run in the debug console. Use continue and step instead",
+                expression
+            ));
+        }
+        if self
+            .process
+            .dispatch_command(expression, &mut self.context)?
+            .is_some()
+        {
+            // Like `start-server`, which leaves the command loop
+            return Err(anyhow!(
+                "'{}' can't be run in the debug console",
+                expression
+            ));
+        }
+        let lines = self.output.borrow_mut().drain(..).collect::<Vec<_>>();
+        let output = lines.into_iter().map(|(_, line)| line).collect::<String>();
+        Ok(output.trim_end().to_string())
+    }
+
+    fn flush_output(&mut self) -> Result<()> {
+        let lines = self.output.borrow_mut().drain(..).collect::<Vec<_>>();
+        for (category, output) in lines {
+            self.sender
+                .event("output", json!({ "category": category, "output": output }))?;
+        }
+        Ok(())
+    }
+
+    fn terminated(&mut self, exit_code: i32) -> Result<()> {
+        self.sender
+            .event("exited", json!({ "exitCode": exit_code }))?;
+        self.sender.event("terminated", json!({}))
+    }
+
+    fn breakpoint_reason(&self) -> &'static str {
+        if self.pause_requested.swap(false, Ordering::SeqCst) {
+            "pause"
+        } else {
+            "breakpoint"
+        }
+    }
+
+    fn execute(&mut self, execution: Execution) -> Result<()> {
+        self.debugger().select_frame(None, 0)?;
+        let debugger = &mut self.process.debugger;
+        let result = match execution {
+            Execution::Launch => match self.launch.take() {
+                Some(launch) => {
+                    let entry_point = launch.entry_point.as_deref();
+                    if launch.stop_on_entry {
+                        debugger
+                            .start(entry_point, vec![])
+                            .map(|_| Stop::Stopped("entry"))
+                    } else {
+                        debugger
+                            .run(entry_point, vec![])
+                            .map(|result| match result {
+                                RunResult::Finish(values) => Stop::Finish(values),
+                                RunResult::Breakpoint => Stop::Stopped(self.breakpoint_reason()),
+                                RunResult::Exiting(status) => Stop::Exiting(status),
+                                RunResult::Exit(status) => Stop::Exit(status),
+                            })
+                    }
+                }
+                None => Err(anyhow!("The process is not launched")),
+            },
+            Execution::Continue => debugger.process().map(|result| match result {
+                RunResult::Finish(values) => Stop::Finish(values),
+                RunResult::Breakpoint => Stop::Stopped(self.breakpoint_reason()),
+                RunResult::Exiting(status) => Stop::Exiting(status),
+                RunResult::Exit(status) => Stop::Exit(status),
+            }),
+            Execution::Next | Execution::StepIn | Execution::StepOut => {
+                let debugger = &self.process.debugger;
+                let signal = match execution {
+                    Execution::Next => step_line(debugger, &self.context, false),
+                    Execution::StepIn => step_line(debugger, &self.context, true),
+                    _ => step_out(debugger, &self.context),
+                };
+                signal.map(|signal| match signal {
+                    Signal::Next => Stop::Stopped("step"),
+                    Signal::Breakpoint => Stop::Stopped(self.breakpoint_reason()),
+                    Signal::End => Stop::Finish(vec![]),
+                })
+            }
+        };
+        self.flush_output()?;
+        match result {
+            Ok(Stop::Stopped(reason)) => self.sender.event(
+                "stopped",
+                json!({ "reason": reason, "threadId": THREAD_ID, "allThreadsStopped": true }),
+            ),
+            Ok(Stop::Exiting(status)) => {
+                let output = format!("Process is exiting with status {}\n", status);
+                self.sender
+                    .event("output", json!({ "category": "console", "output": output }))?;
+                self.sender.event(
+                    "stopped",
+                    json!({ "reason": "breakpoint", "threadId": THREAD_ID, "allThreadsStopped": true }),
+                )
+            }
+            Ok(Stop::Finish(values)) => {
+                if !values.is_empty() {
+                    let values = values.iter().map(|v| format_value(v).0).collect::<Vec<_>>();
+                    let output = format!("Process finished with [{}]\n", values.join(", "));
+                    self.sender
+                        .event("output", json!({ "category": "console", "output": output }))?;
+                }
+                self.terminated(0)
+            }
+            Ok(Stop::Exit(status)) => {
+                let output = format!("Process exited with status {}\n", status);
+                self.sender
+                    .event("output", json!({ "category": "console", "output": output }))?;
+                self.terminated(status)
+            }
+            Err(err) => {
+                let output = format!("{}\n", err);
+                self.sender
+                    .event("output", json!({ "category": "stderr", "output": output }))?;
+                self.terminated(1)
+            }
+        }
+    }
+}
+
+/// Reads requests on another thread to handle `pause` while the process is running
+fn spawn_reader<R: BufRead + Send + 'static>(
+    mut reader: R,
+    interrupt: Arc<AtomicBool>,
+    pause_requested: Arc<AtomicBool>,
+) -> mpsc::Receiver<Request> {
+    let (tx, rx) = mpsc::channel();
+    std::thread::spawn(move || {
+        loop {
+            let request = match protocol::read_request(&mut reader) {
+                Ok(Some(request)) => request,
+                Ok(None) => break,
+                Err(err) => {
+                    warn!("Failed to read DAP request: {}", err);
+                    break;
+                }
+            };
+            if request.command == "pause" || request.command == "disconnect" {
+                pause_requested.store(true, Ordering::SeqCst);
+                interrupt.store(true, Ordering::SeqCst);
+            }
+            if tx.send(request).is_err() {
+                return;
+            }
+        }
+        // Stop the running process when the client is gone
+        interrupt.store(true, Ordering::SeqCst);
+    });
+    rx
+}
+
+/// Sends the output of the guest to the client as `output` events
+fn forward_output<W: Write + Send + 'static>(
+    category: &'static str,
+    sender: &Sender<W>,
+) -> OutputTarget {
+    let sender = sender.clone();
+    OutputTarget::Forward(Arc::new(move |bytes: &[u8]| {
+        let output = String::from_utf8_lossy(bytes);
+        let body = json!({ "category": category, "output": output });
+        if let Err(err) = sender.event("output", body) {
+            warn!("Failed to forward {}: {}", category, err);
+        }
+    }))
+}
+
+/// Serves a DAP client on the reader and the writer until the client disconnects
+pub fn serve_dap<R: BufRead + Send + 'static, W: Write + Send + 'static>(
+    module_input: Option<ModuleInput>,
+    preopen_dirs: Vec<(String, String)>,
+    envs: Vec<(String, String)>,
+    reader: R,
+    writer: W,
+) -> Result<()> {
+    serve(module_input, preopen_dirs, envs, reader, writer, false)
+}
+
+fn serve<R: BufRead + Send + 'static, W: Write + Send + 'static>(
+    module_input: Option<ModuleInput>,
+    preopen_dirs: Vec<(String, String)>,
+    envs: Vec<(String, String)>,
+    reader: R,
+    writer: W,
+    null_stdin: bool,
+) -> Result<()> {
+    let (mut process, mut context) = crate::start_debugger(module_input, preopen_dirs, envs)?;
+    let sender = Sender::new(writer);
+    let mut opts = process.debugger.get_opts();
+    opts.stdio.null_stdin = null_stdin;
+    opts.stdio.stdout = forward_output("stdout", &sender);
+    opts.stdio.stderr = forward_output("stderr", &sender);
+    process.debugger.set_opts(opts);
+    let output = Rc::new(RefCell::new(vec![]));
+    context.printer = Box::new(BufferPrinter {
+        lines: output.clone(),
+    });
+    let pause_requested = Arc::new(AtomicBool::new(false));
+    let requests = spawn_reader(
+        reader,
+        process.debugger.interrupt_handle(),
+        pause_requested.clone(),
+    );
+    let mut server = Server {
+        process,
+        context,
+        output,
+        sender,
+        pause_requested,
+        launch: None,
+        source_breakpoints: HashMap::new(),
+        function_breakpoints: vec![],
+        next_breakpoint_id: 0,
+    };
+    server.run(requests)
+}
+
+/// Serves a DAP client on stdin and stdout. The guest gets an empty stdin not to read
+/// the requests.
+pub fn serve_dap_stdio(
+    module_input: Option<ModuleInput>,
+    preopen_dirs: Vec<(String, String)>,
+    envs: Vec<(String, String)>,
+) -> Result<()> {
+    let reader = BufReader::new(std::io::stdin());
+    let writer = std::io::stdout();
+    serve(module_input, preopen_dirs, envs, reader, writer, true)
+}
+
+/// Waits for a DAP client on the address, and serves it
+pub fn serve_dap_tcp<A: ToSocketAddrs>(
+    module_input: Option<ModuleInput>,
+    preopen_dirs: Vec<(String, String)>,
+    envs: Vec<(String, String)>,
+    addr: A,
+) -> Result<()> {
+    let listener = TcpListener::bind(addr)?;
+    eprintln!("Listening for DAP client on {}", listener.local_addr()?);
+    let (stream, _) = listener.accept()?;
+    let reader = BufReader::new(stream.try_clone()?);
+    serve_dap(module_input, preopen_dirs, envs, reader, stream)
+}
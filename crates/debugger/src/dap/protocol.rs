@@ -0,0 +1,115 @@
+//! Base protocol of Debug Adapter Protocol
+//! See also https://microsoft.github.io/debug-adapter-protocol/overview#base-protocol
+
+use anyhow::{anyhow, Result};
+use serde::{de::DeserializeOwned, Deserialize};
+use serde_json::{json, Value};
+use std::io::{BufRead, Write};
+use std::sync::{Arc, Mutex};
+
+#[derive(Deserialize, Debug)]
+pub struct Request {
+    pub seq: i64,
+    pub command: String,
+    #[serde(default)]
+    pub arguments: Value,
+}
+
+impl Request {
+    pub fn arguments<T: DeserializeOwned>(&self) -> Result<T> {
+        Ok(serde_json::from_value(self.arguments.clone())?)
+    }
+}
+
+/// Reads a request from the stream. Returns `None` at the end of the stream.
+pub fn read_request<R: BufRead>(reader: &mut R) -> Result<Option<Request>> {
+    let mut content_length = None;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            if content_length.is_some() {
+                break;
+            }
+            continue;
+        }
+        if let Some(value) = line.strip_prefix("Content-Length:") {
+            content_length = Some(value.trim().parse::<usize>()?);
+        }
+    }
+    let mut content = vec![0; content_length.unwrap()];
+    reader.read_exact(&mut content)?;
+    let message: Value = serde_json::from_slice(&content)?;
+    if message["type"] != "request" {
+        return Err(anyhow!("unexpected message: {}", message));
+    }
+    Ok(Some(serde_json::from_value(message)?))
+}
+
+/// Writes messages with sequence numbers. Clones share the writer to send events
+/// while the process is running.
+pub struct Sender<W: Write> {
+    writer: Arc<Mutex<(W, i64)>>,
+}
+
+impl<W: Write> Clone for Sender<W> {
+    fn clone(&self) -> Self {
+        Self {
+            writer: self.writer.clone(),
+        }
+    }
+}
+
+impl<W: Write> Sender<W> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer: Arc::new(Mutex::new((writer, 0))),
+        }
+    }
+
+    fn send(&self, mut message: Value) -> Result<()> {
+        let mut writer = self.writer.lock().unwrap();
+        let (writer, seq) = &mut *writer;
+        *seq += 1;
+        message["seq"] = json!(*seq);
+        let content = serde_json::to_string(&message)?;
+        write!(
+            writer,
+            "Content-Length: {}\r\n\r\n{}",
+            content.len(),
+            content
+        )?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    pub fn response(&self, request: &Request, body: Result<Value>) -> Result<()> {
+        let mut message = json!({
+            "type": "response",
+            "request_seq": request.seq,
+            "command": request.command,
+        });
+        match body {
+            Ok(body) => {
+                message["success"] = json!(true);
+                message["body"] = body;
+            }
+            Err(err) => {
+                message["success"] = json!(false);
+                message["message"] = json!(err.to_string());
+            }
+        }
+        self.send(message)
+    }
+
+    pub fn event(&self, event: &str, body: Value) -> Result<()> {
+        self.send(json!({
+            "type": "event",
+            "event": event,
+            "body": body,
+        }))
+    }
+}
@@ -1,22 +1,38 @@
 use crate::commands::debugger::{self, Debugger, DebuggerOpts, RawHostModule, RunResult};
+use crate::commands::sourcemap::SourceMap;
+use crate::commands::subroutine::SubroutineMap;
+use crate::coredump::{self, CoreDump, CoreFrame};
+use crate::coverage::{Coverage, CoverageReport};
+use crate::host_calls;
+use crate::profile::{ProfileReport, Profiler};
+use crate::record::History;
+use crate::trace::{TraceOptions, Tracer};
 use anyhow::{anyhow, Context, Result};
 use log::{trace, warn};
 use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
-use std::{cell::RefCell, usize};
+use std::{
+    cell::{Cell, RefCell},
+    usize,
+};
 use wasminspect_vm::{
-    CallFrame, DefinedModuleInstance, Executor, FuncAddr, FunctionInstance, InstIndex, Instruction,
-    Interceptor, MemoryAddr, ModuleIndex, ProgramCounter, Signal, Store, Trap, WasmValue,
+    CallFrame, ComponentHost, ComponentInstance, DefinedModuleInstance, Executor, FuncAddr,
+    FunctionInstance, GlobalAddr, InstIndex, Instruction, Interceptor, MemoryAddr, ModuleIndex,
+    ProgramCounter, Signal, Store, Trap, WasmValue, WASM_PAGE_SIZE,
 };
-use wasminspect_wasi::instantiate_wasi;
+use wasminspect_wasi::{instantiate_preview2, instantiate_wasi, WasiContext};
 use wasmparser::WasmFeatures;
 
 type RawModule = Vec<u8>;
 
 pub struct Instance {
     main_module_index: ModuleIndex,
+    /// Instances of all the core modules, which are more than the main one in components
+    module_indices: Vec<ModuleIndex>,
+    component: Option<ComponentInstance>,
     pub store: Store,
     pub executor: Option<Rc<RefCell<Executor>>>,
 }
@@ -25,6 +41,9 @@ pub struct MainDebugger {
     pub instance: Option<Instance>,
 
     main_module: Option<(RawModule, String)>,
+    /// The component, and the ordinal and the offset of the main module in it if a
+    /// component is loaded
+    component: Option<(RawModule, usize, usize)>,
 
     opts: DebuggerOpts,
     preopen_dirs: Vec<(String, String)>,
@@ -34,12 +53,31 @@ pub struct MainDebugger {
     breakpoints: Breakpoints,
     is_interrupted: Arc<AtomicBool>,
     selected_frame: Option<usize>,
+    selected_inline_depth: usize,
+
+    /// The file to write a coredump when the process traps
+    coredump_path: Option<PathBuf>,
+    /// The process is restored from a coredump, and can't be resumed
+    is_coredump: bool,
+
+    watchpoints: Vec<debugger::Watchpoint>,
+    /// Execution history for reverse execution, if recording is enabled
+    history: RefCell<Option<History>>,
+    /// Execution counts of instructions, if coverage is enabled
+    coverage: RefCell<Option<Coverage>>,
+    profiler: RefCell<Option<Profiler>>,
+    /// Keep the profile after profiling is stopped to report it
+    is_profiling: bool,
+    tracer: RefCell<Option<Tracer>>,
+    /// The exit status when the process is stopped by the exit breakpoint
+    pending_exit: Cell<Option<i32>>,
 }
 
 #[derive(Default)]
 struct Breakpoints {
     function_map: HashMap<String, debugger::Breakpoint>,
     inst_map: HashMap<usize, debugger::Breakpoint>,
+    exit: bool,
 }
 
 impl Breakpoints {
@@ -62,17 +100,72 @@ impl Breakpoints {
             debugger::Breakpoint::Instruction { inst_offset } => {
                 self.inst_map.insert(*inst_offset, breakpoint);
             }
+            debugger::Breakpoint::Exit => self.exit = true,
         }
     }
 }
 
+/// Returns the ordinal of the main module among the core modules of a component. It is
+/// the module providing `wasi:cli/run`, which is exported as `wasi:cli/run@<version>#run`
+/// by wit-component, or the module exporting `_start` if the export is provided by the
+/// adapter of a preview1 command. The largest module is chosen if no module exports them.
+pub(crate) fn main_core_module(modules: &[(usize, &[u8])]) -> Option<usize> {
+    let find = |is_target: fn(&str) -> bool| {
+        modules
+            .iter()
+            .position(|(_, module)| has_export(module, is_target))
+    };
+    find(|name| name == "_start")
+        .or_else(|| find(|name| name.starts_with("wasi:cli/run@") && name.ends_with("#run")))
+        .or_else(|| {
+            modules
+                .iter()
+                .enumerate()
+                .max_by_key(|(_, (_, module))| module.len())
+                .map(|(ordinal, _)| ordinal)
+        })
+}
+
+fn has_export(module: &[u8], is_target: fn(&str) -> bool) -> bool {
+    for payload in wasmparser::Parser::new(0).parse_all(module) {
+        if let Ok(wasmparser::Payload::ExportSection(section)) = payload {
+            return section
+                .into_iter()
+                .any(|export| matches!(export, Ok(export) if is_target(export.name)));
+        }
+    }
+    false
+}
+
 impl MainDebugger {
     pub fn load_main_module(&mut self, module: &[u8], name: String) -> Result<()> {
+        if wasminspect_vm::is_component(module) {
+            return self.load_component(module, name);
+        }
         if let Err(err) = wasmparser::validate(module) {
             warn!("{}", err);
             return Err(err.into());
         }
         self.main_module = Some((module.to_vec(), name));
+        self.component = None;
+        Ok(())
+    }
+
+    /// Loads a component, whose main core module is debugged as the main module. The
+    /// others are usually adapters and shims generated by the toolchain.
+    fn load_component(&mut self, component: &[u8], name: String) -> Result<()> {
+        let modules = wasminspect_vm::core_modules(component)?;
+        for (_, module) in modules.iter() {
+            if let Err(err) = wasmparser::validate(module) {
+                warn!("{}", err);
+                return Err(err.into());
+            }
+        }
+        let ordinal =
+            main_core_module(&modules).ok_or_else(|| anyhow!("No core module in the component"))?;
+        let (offset, module) = modules[ordinal];
+        self.main_module = Some((module.to_vec(), name));
+        self.component = Some((component.to_vec(), ordinal, offset));
         Ok(())
     }
 
@@ -82,6 +175,7 @@ impl MainDebugger {
         Ok(Self {
             instance: None,
             main_module: None,
+            component: None,
             opts: DebuggerOpts::default(),
             config: wasminspect_vm::Config {
                 features: WasmFeatures::default(),
@@ -91,9 +185,336 @@ impl MainDebugger {
             preopen_dirs,
             envs,
             selected_frame: None,
+            selected_inline_depth: 0,
+            coredump_path: None,
+            is_coredump: false,
+            watchpoints: vec![],
+            history: RefCell::new(None),
+            coverage: RefCell::new(None),
+            profiler: RefCell::new(None),
+            is_profiling: false,
+            tracer: RefCell::new(None),
+            pending_exit: Cell::new(None),
+        })
+    }
+
+    /// Returns the flag to interrupt the running process, which is also set by SIGINT
+    pub fn interrupt_handle(&self) -> Arc<AtomicBool> {
+        self.is_interrupted.clone()
+    }
+
+    /// Write a coredump to the file when the process traps
+    pub fn set_coredump_path(&mut self, path: Option<PathBuf>) {
+        self.coredump_path = path;
+    }
+
+    /// Start counting executed instructions from scratch
+    pub fn enable_coverage(&mut self) {
+        self.coverage = RefCell::new(Some(Coverage::default()));
+    }
+
+    /// Map the executed instructions of the main module to source lines
+    pub fn coverage_report(&self, sourcemap: &dyn SourceMap) -> Result<CoverageReport> {
+        let coverage = self.coverage.borrow();
+        let coverage = coverage
+            .as_ref()
+            .ok_or_else(|| anyhow!("Coverage is not enabled"))?;
+        let instance = self.instance()?;
+        Ok(coverage.report(&instance.store, instance.main_module_index, sourcemap))
+    }
+
+    fn ensure_resumable(&self) -> Result<()> {
+        if self.is_coredump {
+            return Err(anyhow!(
+                "The process is restored from a coredump and can't be resumed"
+            ));
+        }
+        Ok(())
+    }
+
+    /// Writes a coredump if requested. The executor must not be borrowed.
+    fn on_trap(&self, trap: Trap) -> Trap {
+        if let Some(ref path) = self.coredump_path {
+            match self.write_coredump(path) {
+                Ok(()) => eprintln!("Wrote coredump to {}", path.display()),
+                Err(err) => warn!("Failed to write coredump: {}", err),
+            }
+        }
+        trap
+    }
+
+    /// Clears the recorded history when the execution context is replaced
+    fn reset_history(&self) {
+        let mut history = self.history.borrow_mut();
+        if history.is_some() {
+            *history = Some(History::new(self.opts.record_max_memory));
+        }
+    }
+
+    /// Executes an instruction, and records or profiles it if enabled
+    fn execute_step(&self, executor: &RefCell<Executor>, store: &Store) -> Result<Signal, Trap> {
+        let instance = match self.instance {
+            Some(ref instance) => instance,
+            None => return Err(Trap::NoMoreInstruction),
+        };
+        if let Some(history) = self.history.borrow_mut().as_mut() {
+            history.begin_step(&executor.borrow(), store, &instance.module_indices);
+        }
+        if self.is_profiling {
+            if let Some(profiler) = self.profiler.borrow_mut().as_mut() {
+                profiler.step(&executor.borrow(), store);
+            }
+        }
+        let result = executor
+            .borrow_mut()
+            .execute_step(store, self, &self.config);
+        if let Some(history) = self.history.borrow_mut().as_mut() {
+            history.end_step(&executor.borrow(), store);
+        }
+        result
+    }
+
+    /// Returns true if the recorded step hit a breakpoint or watchpoint
+    fn hits_breakpoint(&self, history: &History, index: usize, store: &Store) -> bool {
+        let executor = history.executor(index);
+        let next = history.executor(index + 1);
+        let inst = store
+            .func_global(executor.pc.exec_addr())
+            .defined()
+            .and_then(|func| func.instructions().get(executor.pc.inst_index().0 as usize));
+        if let Some(inst) = inst {
+            if self.breakpoints.should_break_inst(inst) {
+                return true;
+            }
+        }
+        if next.stack.peek_frames().len() > executor.stack.peek_frames().len() {
+            let callee = store.func_global(next.pc.exec_addr());
+            if self.breakpoints.should_break_func(callee.name()) {
+                return true;
+            }
+        }
+        self.watchpoints
+            .iter()
+            .any(|watchpoint| history.writes_memory(index, watchpoint.address, watchpoint.size))
+    }
+
+    /// Rewinds the recorded execution step by step until `should_stop` returns a signal.
+    /// It takes the executor of each state and the signal of the step reaching the state.
+    fn rewind_until<F>(&mut self, should_stop: F) -> Result<Signal>
+    where
+        F: Fn(&Executor, Signal) -> Option<Signal>,
+    {
+        self.ensure_resumable()?;
+        let instance = self.instance()?;
+        let store = &instance.store;
+        let executor = self.executor()?;
+        let mut history = self.history.borrow_mut();
+        let history = history.as_mut().ok_or_else(|| {
+            anyhow!("Execution is not recorded. Enable it by `settings set record.enabled true`")
+        })?;
+        let mut position = history.len();
+        let signal = loop {
+            if position == 0 {
+                break Signal::End;
+            }
+            position -= 1;
+            let signal = if position > 0 && self.hits_breakpoint(history, position - 1, store) {
+                Signal::Breakpoint
+            } else {
+                Signal::Next
+            };
+            if let Some(signal) = should_stop(history.executor(position), signal) {
+                break signal;
+            }
+        };
+        if position < history.len() {
+            let restored = history.rewind(position, store);
+            *executor.borrow_mut() = restored;
+        }
+        Ok(signal)
+    }
+
+    fn coredump(&self) -> Result<CoreDump> {
+        let instance = self.instance()?;
+        let store = &instance.store;
+        let main_module_index = instance.main_module_index;
+        let (module, executable_name) = self
+            .main_module
+            .as_ref()
+            .ok_or_else(|| anyhow!("No main module registered"))?;
+        let (imported_funcs, func_offsets) = coredump::function_offsets(module)?;
+        // Instructions of the main module of a component are shifted by its offset
+        let module_offset = self.component.as_ref().map_or(0, |(_, _, offset)| *offset);
+        let executor = self.executor()?;
+        let executor = executor.borrow();
+        let frames = executor.stack.peek_frames();
+        let mut core_frames = vec![];
+        for (index, frame) in frames.iter().enumerate().rev() {
+            // The callee frame holds the return address of the caller
+            let inst_index = match frames.get(index + 1) {
+                Some(callee) => callee.ret_pc.map(|pc| pc.inst_index().0 as usize),
+                None => Some(executor.pc.inst_index().0 as usize),
+            }
+            .ok_or_else(|| anyhow!("No return address"))?;
+            let funcidx = store
+                .func_index(main_module_index, frame.exec_addr)
+                .ok_or_else(|| anyhow!("Function not found in the main module"))?;
+            let func = store
+                .func_global(frame.exec_addr)
+                .defined()
+                .ok_or_else(|| anyhow!("Function not found"))?;
+            let inst = func
+                .instructions()
+                .get(inst_index.saturating_sub(1))
+                .ok_or_else(|| anyhow!("Instruction not found"))?;
+            let body_offset = funcidx
+                .checked_sub(imported_funcs)
+                .and_then(|index| func_offsets.get(index))
+                .ok_or_else(|| anyhow!("Function body not found"))?;
+            core_frames.push(CoreFrame {
+                funcidx: funcidx as u32,
+                codeoffset: (inst.offset - module_offset - body_offset) as u32,
+                locals: frame.locals.iter().map(|value| Some(*value)).collect(),
+                stack: vec![],
+            });
+        }
+        let memories = (0..store.memory_count(main_module_index))
+            .map(|index| {
+                let addr = MemoryAddr::new_unsafe(main_module_index, index);
+                store.memory(addr).borrow().raw_data().to_vec()
+            })
+            .collect();
+        let globals = (0..store.global_count(main_module_index))
+            .map(|index| {
+                let global = store.global(GlobalAddr::new_unsafe(main_module_index, index));
+                let value = global.borrow().value();
+                Some(value)
+            })
+            .collect();
+        Ok(CoreDump {
+            executable_name: executable_name.clone(),
+            frames: core_frames,
+            memories,
+            globals,
         })
     }
 
+    /// Writes a coredump of the current process state
+    pub fn write_coredump(&self, path: &Path) -> Result<()> {
+        let bytes = self.coredump()?.encode();
+        std::fs::write(path, bytes)
+            .with_context(|| format!("failed to write coredump {}", path.display()))
+    }
+
+    /// Restores the process state from a coredump of the main module to inspect it.
+    /// Returns the executable name recorded in the coredump.
+    pub fn load_coredump(&mut self, bytes: &[u8]) -> Result<String> {
+        let coredump = CoreDump::parse(bytes)?;
+        let (imported_funcs, func_offsets) = match self.main_module {
+            Some((ref module, _)) => coredump::function_offsets(module)?,
+            None => return Err(anyhow!("No main module registered")),
+        };
+        self.instantiate(HashMap::new(), Some(&[]))?;
+        let instance = self.instance.as_mut().unwrap();
+        let store = &instance.store;
+        let main_module_index = instance.main_module_index;
+
+        for (index, data) in coredump.memories.iter().enumerate() {
+            if index >= store.memory_count(main_module_index) {
+                return Err(anyhow!("Memory {} is not defined in the module", index));
+            }
+            let memory = store.memory(MemoryAddr::new_unsafe(main_module_index, index));
+            let mut memory = memory.borrow_mut();
+            let pages = data.len() / WASM_PAGE_SIZE;
+            if memory.page_count() < pages {
+                let delta = pages - memory.page_count();
+                memory
+                    .grow(delta)
+                    .map_err(|err| anyhow!("Failed to restore memory: {:?}", err))?;
+            }
+            memory.raw_data_mut()[..data.len()].copy_from_slice(data);
+        }
+        for (index, value) in coredump.globals.iter().enumerate() {
+            if index >= store.global_count(main_module_index) {
+                break;
+            }
+            let global = store.global(GlobalAddr::new_unsafe(main_module_index, index));
+            let mut global = global.borrow_mut();
+            match value {
+                Some(value) if global.is_mutable() => global.set_value(*value),
+                _ => (),
+            }
+        }
+
+        let mut executor: Option<Executor> = None;
+        for core_frame in coredump.frames.iter().rev() {
+            let funcidx = core_frame.funcidx as usize;
+            let (func, exec_addr) = store
+                .func(FuncAddr::new_unsafe(main_module_index, funcidx))
+                .ok_or_else(|| anyhow!("Function {} not found", funcidx))?;
+            let func = func
+                .defined()
+                .ok_or_else(|| anyhow!("Function {} is not defined in the module", funcidx))?;
+            let body_offset = funcidx
+                .checked_sub(imported_funcs)
+                .and_then(|index| func_offsets.get(index))
+                .ok_or_else(|| anyhow!("Function body {} not found", funcidx))?;
+            let offset = body_offset + core_frame.codeoffset as usize;
+            let inst_index = func
+                .instructions()
+                .iter()
+                .position(|inst| inst.offset == offset)
+                .ok_or_else(|| anyhow!("No instruction at offset 0x{:x}", offset))?;
+            // The pc points the next instruction as well as the running process
+            let pc = ProgramCounter::new(
+                main_module_index,
+                exec_addr,
+                InstIndex(inst_index as u32 + 1),
+            );
+            let ret_pc = executor.as_ref().map(|executor| executor.pc);
+            let mut frame = CallFrame::new_from_func(exec_addr, func, vec![], ret_pc);
+            for (index, value) in core_frame.locals.iter().enumerate() {
+                if let (Some(value), true) = (value, index < frame.locals.len()) {
+                    frame.set_local(index, *value);
+                }
+            }
+            let arity = func.ty().results().len();
+            match executor {
+                Some(ref mut executor) => executor.push_frame(frame, arity, pc)?,
+                None => executor = Some(Executor::new(frame, arity, pc)),
+            }
+        }
+        let executor = executor.ok_or_else(|| anyhow!("No frame in coredump"))?;
+        instance.executor = Some(Rc::new(RefCell::new(executor)));
+        self.reset_history();
+        self.selected_frame = None;
+        self.selected_inline_depth = 0;
+        self.is_coredump = true;
+        Ok(coredump.executable_name)
+    }
+
+    /// Instantiates the main module with host modules and restores the execution state
+    /// from a snapshot of it
+    pub fn restore_snapshot(&mut self, bytes: &[u8]) -> Result<()> {
+        self.instantiate(HashMap::new(), Some(&[]))?;
+        let instance = self.instance.as_mut().unwrap();
+        let module = match self.main_module {
+            Some((ref module, _)) => module,
+            None => return Err(anyhow!("No main module registered")),
+        };
+        let executor = wasminspect_vm::restore_snapshot(
+            &instance.store,
+            instance.main_module_index,
+            module,
+            bytes,
+        )?;
+        instance.executor = Some(Rc::new(RefCell::new(executor)));
+        self.selected_frame = None;
+        self.selected_inline_depth = 0;
+        self.reset_history();
+        Ok(())
+    }
+
     pub fn main_module(&self) -> Result<&DefinedModuleInstance> {
         if let Some(ref instance) = self.instance {
             let module = match instance.store.module(instance.main_module_index).defined() {
@@ -174,17 +595,87 @@ impl MainDebugger {
                     Err(_) => Err(anyhow!("Failed to execute host func")),
                 }
             }
-            (FunctionInstance::Defined(func), exec_addr) => {
-                let ret_types = &func.ty().results();
-                let frame = CallFrame::new_from_func(exec_addr, func, args, None);
-                let pc = ProgramCounter::new(func.module_index(), exec_addr, InstIndex::zero());
-                let executor = Rc::new(RefCell::new(Executor::new(frame, ret_types.len(), pc)));
-                instance.executor = Some(executor);
+            (FunctionInstance::Defined(_), _) => {
+                self.prepare_executor(func_addr, args)?;
                 Ok(self.process()?)
             }
         }
     }
 
+    /// Set up the execution context of the entry function without executing any instruction
+    pub fn start(&mut self, name: Option<&str>, args: Vec<WasmValue>) -> Result<()> {
+        let func_addr = self.entry_func_addr(name)?;
+        self.prepare_executor(func_addr, args)
+    }
+
+    fn prepare_executor(&mut self, func_addr: FuncAddr, args: Vec<WasmValue>) -> Result<()> {
+        self.ensure_resumable()?;
+        let instance = self
+            .instance
+            .as_mut()
+            .with_context(|| "No instance".to_string())?;
+        let (func, exec_addr) = instance
+            .store
+            .func(func_addr)
+            .with_context(|| "Function not found".to_string())?;
+        let func = func
+            .defined()
+            .with_context(|| "Host function can't be started".to_string())?;
+        let ret_types = &func.ty().results();
+        let frame = CallFrame::new_from_func(exec_addr, func, args, None);
+        let pc = ProgramCounter::new(func.module_index(), exec_addr, InstIndex::zero());
+        let executor = Rc::new(RefCell::new(Executor::new(frame, ret_types.len(), pc)));
+        // The entry function is entered without invoke_func
+        if let Some(tracer) = self.tracer.borrow_mut().as_mut() {
+            tracer.call(func.name(), &executor.borrow(), &instance.store);
+        }
+        instance.executor = Some(executor);
+        self.pending_exit.set(None);
+        self.selected_frame = None;
+        self.selected_inline_depth = 0;
+        self.reset_history();
+        Ok(())
+    }
+
+    fn entry_func_addr(&self, name: Option<&str>) -> Result<FuncAddr> {
+        if let Some(component) = &self.instance()?.component {
+            // Functions exported by the component take precedence over the main module's
+            let func_addr = match name {
+                Some(name) => component.lifted_func(None, name),
+                None => component.lifted_func(Some("wasi:cli/run"), "run"),
+            };
+            if let Some(func_addr) = func_addr {
+                return Ok(func_addr);
+            }
+        }
+        let main_module = self.main_module()?;
+        let start_func_addr = *main_module.start_func_addr();
+        if let Some(name) = name {
+            self.lookup_func(name)
+        } else if let Some(start_func_addr) = start_func_addr {
+            Ok(start_func_addr)
+        } else {
+            self.lookup_func("_start")
+        }
+    }
+
+    /// Discards the execution context of the exited process
+    fn exit(&mut self, status: i32) -> RunResult {
+        if let Some(instance) = self.instance.as_mut() {
+            instance.executor = None;
+        }
+        RunResult::Exit(status)
+    }
+
+    /// Returns the module of the selected frame, which may not be the main module in
+    /// components, or the main module if no process is running
+    fn current_module_index(&self) -> Result<ModuleIndex> {
+        match self.selected_frame() {
+            Ok(pc) => Ok(pc.module_index()),
+            Err(_) => Ok(self.instance()?.main_module_index),
+        }
+    }
+
     fn selected_frame(&self) -> Result<ProgramCounter> {
         let executor = self.executor()?;
         let executor = executor.borrow();
@@ -210,14 +701,61 @@ impl debugger::Debugger for MainDebugger {
         self.opts.clone()
     }
     fn set_opts(&mut self, opts: DebuggerOpts) {
+        let history = self.history.get_mut();
+        match history {
+            Some(history) if opts.record => history.set_max_memory(opts.record_max_memory),
+            None if opts.record => *history = Some(History::new(opts.record_max_memory)),
+            _ => *history = None,
+        }
+        if let Some(instance) = &self.instance {
+            if let Some(wasi_ctx) = instance.store.get_embed_context::<WasiContext>() {
+                wasi_ctx.set_trace(opts.trace_wasi);
+            }
+        }
         self.opts = opts
     }
 
-    fn select_frame(&mut self, frame_index: Option<usize>) -> Result<()> {
+    fn start_profiling(&mut self) {
+        self.profiler = RefCell::new(Some(Profiler::new()));
+        self.is_profiling = true;
+    }
+
+    fn stop_profiling(&mut self) {
+        self.is_profiling = false;
+    }
+
+    fn start_trace(
+        &mut self,
+        options: TraceOptions,
+        subroutine: Option<Rc<dyn SubroutineMap>>,
+    ) -> Result<()> {
+        let tracer = Tracer::new(options, subroutine, self.opts.demangle_symbols)?;
+        self.tracer = RefCell::new(Some(tracer));
+        Ok(())
+    }
+
+    fn stop_trace(&mut self) {
+        self.tracer = RefCell::new(None);
+    }
+
+    fn profile(&self, sourcemap: &dyn SourceMap) -> Result<ProfileReport> {
+        let profiler = self.profiler.borrow();
+        let profiler = profiler
+            .as_ref()
+            .ok_or_else(|| anyhow!("Profiling is not started"))?;
+        Ok(profiler.report(self.store()?, sourcemap))
+    }
+
+    fn select_frame(&mut self, frame_index: Option<usize>, inline_depth: usize) -> Result<()> {
         self.selected_frame = frame_index;
+        self.selected_inline_depth = inline_depth;
         Ok(())
     }
 
+    fn selected_inline_depth(&self) -> usize {
+        self.selected_inline_depth
+    }
+
     fn selected_instructions(&self) -> Result<(&[Instruction], usize)> {
         let pc = self.selected_frame()?;
         let func = self.store()?.func_global(pc.exec_addr());
@@ -230,6 +768,14 @@ impl debugger::Debugger for MainDebugger {
         self.breakpoints.insert(breakpoint)
     }
 
+    fn clear_breakpoints(&mut self) {
+        self.breakpoints = Default::default();
+    }
+
+    fn set_watchpoint(&mut self, watchpoint: debugger::Watchpoint) {
+        self.watchpoints.push(watchpoint);
+    }
+
     fn stack_values(&self) -> Vec<WasmValue> {
         if let Ok(ref executor) = self.executor() {
             let executor = executor.borrow();
@@ -267,11 +813,12 @@ impl debugger::Debugger for MainDebugger {
         };
 
         Some(debugger::FunctionFrame {
+            name: func.name().clone(),
             module_index: frame.module_index(),
             argument_count: func.ty().params().len(),
         })
     }
-    fn frame(&self) -> Vec<String> {
+    fn frame(&self) -> Vec<debugger::StackFrame> {
         let instance = if let Ok(instance) = self.instance() {
             instance
         } else {
@@ -286,47 +833,100 @@ impl debugger::Debugger for MainDebugger {
         let frames = executor.stack.peek_frames();
         return frames
             .iter()
-            .map(|frame| instance.store.func_global(frame.exec_addr).name().clone())
+            .enumerate()
+            .map(|(index, frame)| {
+                let func = instance.store.func_global(frame.exec_addr);
+                // The callee frame holds the return address of the caller
+                let inst_index = match frames.get(index + 1) {
+                    Some(callee) => callee.ret_pc.map(|pc| pc.inst_index().0 as usize),
+                    None => Some(executor.pc.inst_index().0 as usize),
+                };
+                let code_offset = inst_index.and_then(|inst_index| {
+                    let insts = func.defined()?.instructions();
+                    insts
+                        .get(inst_index.saturating_sub(1))
+                        .map(|inst| inst.offset)
+                });
+                let argument_count = func.ty().params().len();
+                debugger::StackFrame {
+                    name: func.name().clone(),
+                    code_offset,
+                    arguments: frame.locals.iter().take(argument_count).cloned().collect(),
+                }
+            })
             .collect();
     }
     fn memory(&self) -> Result<Vec<u8>> {
-        let instance = self.instance()?;
-        let store = &instance.store;
-        if store.memory_count(instance.main_module_index) == 0 {
+        let store = self.store()?;
+        let module_index = self.current_module_index()?;
+        if store.memory_count(module_index) == 0 {
             return Ok(vec![]);
         }
-        let addr = MemoryAddr::new_unsafe(instance.main_module_index, 0);
+        let addr = MemoryAddr::new_unsafe(module_index, 0);
         Ok(store.memory(addr).borrow().raw_data().to_vec())
     }
 
+    fn snapshot(&self) -> Result<Vec<u8>> {
+        let instance = self.instance()?;
+        let (module, _) = self
+            .main_module
+            .as_ref()
+            .ok_or_else(|| anyhow!("No main module registered"))?;
+        let executor = self.executor()?;
+        let executor = executor.borrow();
+        wasminspect_vm::save_snapshot(
+            &instance.store,
+            instance.main_module_index,
+            module,
+            &executor,
+        )
+    }
+
     fn is_running(&self) -> bool {
         self.executor().is_ok()
     }
 
+    fn main_module_bytes(&self) -> Option<&[u8]> {
+        self.main_module.as_ref().map(|(bytes, _)| bytes.as_slice())
+    }
+
+    fn is_component(&self) -> bool {
+        self.component.is_some()
+    }
+
     fn step(&self, style: debugger::StepStyle) -> Result<Signal> {
+        self.ensure_resumable()?;
         let store = self.store()?;
         let executor = self.executor()?;
         use debugger::StepStyle::*;
+        if let Some(status) = self.pending_exit.get() {
+            return Err(Trap::Exit(status).into());
+        }
+        let execute_step = || -> Result<Signal> {
+            let result = self.execute_step(&executor, store);
+            result.map_err(|trap| {
+                let trap = match trap {
+                    // Keep the state at the exit until the process is resumed or relaunched
+                    Trap::Exit(status) => {
+                        self.pending_exit.set(Some(status));
+                        trap
+                    }
+                    trap => self.on_trap(trap),
+                };
+                trap.into()
+            })
+        };
 
         fn frame_depth(executor: &Executor) -> usize {
             executor.stack.peek_frames().len()
         }
         match style {
-            InstIn => {
-                return Ok(executor
-                    .borrow_mut()
-                    .execute_step(store, self, &self.config)?)
-            }
+            InstIn => execute_step(),
             InstOver => {
                 let initial_frame_depth = frame_depth(&executor.borrow());
-                let mut last_signal =
-                    executor
-                        .borrow_mut()
-                        .execute_step(store, self, &self.config)?;
+                let mut last_signal = execute_step()?;
                 while initial_frame_depth < frame_depth(&executor.borrow()) {
-                    last_signal = executor
-                        .borrow_mut()
-                        .execute_step(store, self, &self.config)?;
+                    last_signal = execute_step()?;
                     if let Signal::Breakpoint = last_signal {
                         return Ok(last_signal);
                     }
@@ -335,14 +935,9 @@ impl debugger::Debugger for MainDebugger {
             }
             Out => {
                 let initial_frame_depth = frame_depth(&executor.borrow());
-                let mut last_signal =
-                    executor
-                        .borrow_mut()
-                        .execute_step(store, self, &self.config)?;
+                let mut last_signal = execute_step()?;
                 while initial_frame_depth <= frame_depth(&executor.borrow()) {
-                    last_signal = executor
-                        .borrow_mut()
-                        .execute_step(store, self, &self.config)?;
+                    last_signal = execute_step()?;
                     if let Signal::Breakpoint = last_signal {
                         return Ok(last_signal);
                     }
@@ -353,13 +948,16 @@ impl debugger::Debugger for MainDebugger {
     }
 
     fn process(&mut self) -> Result<RunResult> {
+        self.ensure_resumable()?;
         self.selected_frame = None;
+        self.selected_inline_depth = 0;
+        if let Some(status) = self.pending_exit.take() {
+            return Ok(self.exit(status));
+        }
         let store = self.store()?;
         let executor = self.executor()?;
-        loop {
-            let result = executor
-                .borrow_mut()
-                .execute_step(store, self, &self.config);
+        let status = loop {
+            let result = self.execute_step(&executor, store);
             match result {
                 Ok(Signal::Next) => continue,
                 Ok(Signal::Breakpoint) => return Ok(RunResult::Breakpoint),
@@ -371,24 +969,47 @@ impl debugger::Debugger for MainDebugger {
                         .pop_result(func.ty().results().to_vec())?;
                     return Ok(RunResult::Finish(results));
                 }
-                Err(err) => return Err(anyhow!("Function exec failure {}", err)),
+                Err(Trap::Exit(status)) if self.breakpoints.exit => {
+                    self.pending_exit.set(Some(status));
+                    return Ok(RunResult::Exiting(status));
+                }
+                Err(Trap::Exit(status)) => break status,
+                Err(err) => return Err(anyhow!("Function exec failure {}", self.on_trap(err))),
             }
-        }
+        };
+        Ok(self.exit(status))
     }
 
-    fn run(&mut self, name: Option<&str>, args: Vec<WasmValue>) -> Result<debugger::RunResult> {
-        let main_module = self.main_module()?;
-        let start_func_addr = *main_module.start_func_addr();
-        let func_addr = {
-            if let Some(name) = name {
-                self.lookup_func(name)?
-            } else if let Some(start_func_addr) = start_func_addr {
-                start_func_addr
-            } else {
-                self.lookup_func("_start")?
+    fn step_back(&mut self, style: debugger::StepStyle) -> Result<Signal> {
+        use debugger::StepStyle::*;
+        self.selected_frame = None;
+        self.selected_inline_depth = 0;
+        let initial_frame_depth = self.executor()?.borrow().stack.peek_frames().len();
+        self.rewind_until(|executor, signal| {
+            let frame_depth = executor.stack.peek_frames().len();
+            match style {
+                InstIn => Some(signal),
+                InstOver if frame_depth <= initial_frame_depth => Some(signal),
+                Out if frame_depth < initial_frame_depth => Some(signal),
+                _ => match signal {
+                    Signal::Breakpoint => Some(signal),
+                    _ => None,
+                },
             }
-        };
+        })
+    }
 
+    fn reverse_process(&mut self) -> Result<Signal> {
+        self.selected_frame = None;
+        self.selected_inline_depth = 0;
+        self.rewind_until(|_, signal| match signal {
+            Signal::Breakpoint => Some(signal),
+            _ => None,
+        })
+    }
+
+    fn run(&mut self, name: Option<&str>, args: Vec<WasmValue>) -> Result<debugger::RunResult> {
+        let func_addr = self.entry_func_addr(name)?;
         self.execute_func(func_addr, args)
     }
 
@@ -397,10 +1018,9 @@ impl debugger::Debugger for MainDebugger {
         host_modules: HashMap<String, RawHostModule>,
         wasi_args: Option<&[String]>,
     ) -> Result<()> {
+        self.ensure_resumable()?;
         let mut store = Store::new();
-        for (name, host_module) in host_modules {
-            store.load_host_module(name, host_module);
-        }
+        let mut host_modules = host_modules;
 
         let (main_module, basename) = if let Some((main_module, basename)) = &self.main_module {
             (main_module, basename.clone())
@@ -408,41 +1028,113 @@ impl debugger::Debugger for MainDebugger {
             return Err(anyhow::anyhow!("No main module registered"));
         };
 
-        if let Some(wasi_args) = wasi_args {
+        let wasi_args = wasi_args.map(|wasi_args| {
             let mut wasi_args = wasi_args.to_vec();
             wasi_args.insert(0, basename);
+            wasi_args
+        });
+
+        fn collect_preopen_dirs(
+            preopen_dirs: &[(String, String)],
+        ) -> anyhow::Result<Vec<(String, cap_std::fs::Dir)>> {
+            preopen_dirs
+                .iter()
+                .map(|(guest, host)| {
+                    let dir = unsafe { cap_std::fs::Dir::open_ambient_dir(host) }?;
+                    Ok((guest.clone(), dir))
+                })
+                .collect::<anyhow::Result<Vec<_>>>()
+        }
 
-            fn collect_preopen_dirs(
-                preopen_dirs: &[(String, String)],
-            ) -> anyhow::Result<Vec<(String, cap_std::fs::Dir)>> {
-                preopen_dirs
-                    .iter()
-                    .map(|(guest, host)| {
-                        let dir = unsafe { cap_std::fs::Dir::open_ambient_dir(host) }?;
-                        Ok((guest.clone(), dir))
-                    })
-                    .collect::<anyhow::Result<Vec<_>>>()
+        if let Some((component, ordinal, _)) = &self.component {
+            // They are implemented for the host modules of core modules and WASI preview1
+            if self.opts.host_calls.is_some() {
+                return Err(anyhow!(
+                    "Host calls of components can't be recorded or replayed"
+                ));
             }
+            let host = match wasi_args {
+                Some(wasi_args) => {
+                    if self.opts.wasi_vfs.is_some() {
+                        return Err(anyhow!(
+                            "The in-memory filesystem is unsupported in components"
+                        ));
+                    }
+                    if !self.opts.wasi_listen.is_empty() {
+                        return Err(anyhow!("Listeners are unsupported in components"));
+                    }
+                    if !self.opts.wasi_faults.is_empty() {
+                        return Err(anyhow!("WASI faults are unsupported in components"));
+                    }
+                    instantiate_preview2(
+                        &wasi_args,
+                        collect_preopen_dirs(&self.preopen_dirs)?,
+                        &self.envs,
+                        &self.opts.stdio,
+                        self.opts.wasi_deterministic.as_ref(),
+                    )?
+                }
+                None => ComponentHost::new(),
+            };
+            for (name, host_module) in host_modules {
+                store.load_host_module(name, host_module);
+            }
+            let component = store.load_component(component, &host, &self.config)?;
+            let main_module_index = component
+                .modules
+                .iter()
+                .find(|(_, module_ordinal)| module_ordinal == ordinal)
+                .map(|(module_index, _)| *module_index)
+                .ok_or_else(|| anyhow!("The main module is not instantiated by the component"))?;
+            let module_indices = component.modules.iter().map(|(index, _)| *index).collect();
+            self.reset_history();
 
-            let (ctx, wasi_snapshot_preview) = instantiate_wasi(
-                &wasi_args,
-                collect_preopen_dirs(&self.preopen_dirs)?,
-                &self.envs,
-            )?;
-            let (_, wasi_unstable) = instantiate_wasi(
+            self.instance = Some(Instance {
+                main_module_index,
+                module_indices,
+                component: Some(component),
+                store,
+                executor: None,
+            });
+            return Ok(());
+        }
+
+        if let Some(wasi_args) = wasi_args {
+            let (ctx, wasi_modules) = instantiate_wasi(
                 &wasi_args,
                 collect_preopen_dirs(&self.preopen_dirs)?,
                 &self.envs,
+                &self.opts.stdio,
+                self.opts.wasi_deterministic.as_ref(),
+                self.opts.wasi_vfs.as_ref(),
+                &self.opts.wasi_listen,
             )?;
+            ctx.set_trace(self.opts.trace_wasi);
+            for fault in &self.opts.wasi_faults {
+                ctx.add_fault(fault.clone());
+            }
             store.add_embed_context(Box::new(ctx));
-            store.load_host_module("wasi_snapshot_preview1".to_string(), wasi_snapshot_preview);
-            store.load_host_module("wasi_unstable".to_string(), wasi_unstable);
+            host_modules.extend(wasi_modules);
+        }
+
+        if let Some(ref mode) = self.opts.host_calls {
+            host_modules = host_calls::wrap_host_modules(
+                host_modules,
+                mode,
+                wasminspect_vm::module_hash(main_module),
+            )?;
+        }
+        for (name, host_module) in host_modules {
+            store.load_host_module(name, host_module);
         }
 
         let main_module_index = store.load_module(None, main_module)?;
+        self.reset_history();
 
         self.instance = Some(Instance {
             main_module_index,
+            module_indices: vec![main_module_index],
+            component: None,
             store,
             executor: None,
         });
@@ -451,13 +1143,11 @@ impl debugger::Debugger for MainDebugger {
 }
 
 impl Interceptor for MainDebugger {
-    fn invoke_func(
-        &self,
-        name: &str,
-        _executor: &Executor,
-        _store: &Store,
-    ) -> Result<Signal, Trap> {
+    fn invoke_func(&self, name: &str, executor: &Executor, store: &Store) -> Result<Signal, Trap> {
         trace!("Invoke function '{}'", name);
+        if let Some(tracer) = self.tracer.borrow_mut().as_mut() {
+            tracer.call(name, executor, store);
+        }
         if self.breakpoints.should_break_func(name) {
             Ok(Signal::Breakpoint)
         } else {
@@ -465,18 +1155,44 @@ impl Interceptor for MainDebugger {
         }
     }
 
+    fn return_func(
+        &self,
+        name: &str,
+        results: &[WasmValue],
+        executor: &Executor,
+        _store: &Store,
+    ) -> Result<Signal, Trap> {
+        if let Some(tracer) = self.tracer.borrow_mut().as_mut() {
+            tracer.ret(name, results, executor);
+        }
+        Ok(Signal::Next)
+    }
+
     fn execute_inst(&self, inst: &Instruction) -> Result<Signal, Trap> {
+        if let Some(coverage) = self.coverage.borrow_mut().as_mut() {
+            coverage.hit(inst.offset);
+        }
         if self.breakpoints.should_break_inst(inst) {
             Ok(Signal::Breakpoint)
         } else if self.is_interrupted.swap(false, Ordering::Relaxed) {
-            println!("Interrupted by signal");
+            eprintln!("Interrupted by signal");
             Ok(Signal::Breakpoint)
         } else {
             Ok(Signal::Next)
         }
     }
 
-    fn after_store(&self, _addr: usize, _bytes: &[u8]) -> Result<Signal, Trap> {
-        Ok(Signal::Next)
+    fn after_store(&self, addr: usize, bytes: &[u8]) -> Result<Signal, Trap> {
+        if let Some(history) = self.history.borrow_mut().as_mut() {
+            history.log_store(addr, bytes);
+        }
+        let hits_watchpoint = self.watchpoints.iter().any(|watchpoint| {
+            addr < watchpoint.address + watchpoint.size && watchpoint.address < addr + bytes.len()
+        });
+        if hits_watchpoint {
+            Ok(Signal::Breakpoint)
+        } else {
+            Ok(Signal::Next)
+        }
     }
 }
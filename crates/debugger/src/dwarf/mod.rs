@@ -35,25 +35,19 @@ pub fn parse_dwarf(module: &[u8]) -> Result<Dwarf> {
     let debug_abbrev = DebugAbbrev::new(try_get(".debug_abbrev")?, endian);
     let debug_info = DebugInfo::new(try_get(".debug_info")?, endian);
     let debug_line = DebugLine::new(try_get(".debug_line")?, endian);
-    let debug_addr = DebugAddr::from(EndianSlice::new(EMPTY_SECTION, endian));
-    let debug_line_str = match sections.get(".debug_line_str") {
-        Some(section) => DebugLineStr::from(EndianSlice::new(section, endian)),
-        None => DebugLineStr::from(EndianSlice::new(EMPTY_SECTION, endian)),
-    };
+    let get_or_empty = |key: &str| sections.get(key).copied().unwrap_or(EMPTY_SECTION);
+    let debug_addr = DebugAddr::from(EndianSlice::new(get_or_empty(".debug_addr"), endian));
+    let debug_line_str =
+        DebugLineStr::from(EndianSlice::new(get_or_empty(".debug_line_str"), endian));
     let debug_str_sup = DebugStr::from(EndianSlice::new(EMPTY_SECTION, endian));
-    let debug_ranges = match sections.get(".debug_ranges") {
-        Some(section) => DebugRanges::new(section, endian),
-        None => DebugRanges::new(EMPTY_SECTION, endian),
-    };
-    let debug_rnglists = DebugRngLists::new(EMPTY_SECTION, endian);
+    let debug_ranges = DebugRanges::new(get_or_empty(".debug_ranges"), endian);
+    let debug_rnglists = DebugRngLists::new(get_or_empty(".debug_rnglists"), endian);
     let ranges = RangeLists::new(debug_ranges, debug_rnglists);
-    let debug_loc = match sections.get(".debug_loc") {
-        Some(section) => DebugLoc::new(section, endian),
-        None => DebugLoc::new(EMPTY_SECTION, endian),
-    };
-    let debug_loclists = DebugLocLists::new(EMPTY_SECTION, endian);
+    let debug_loc = DebugLoc::new(get_or_empty(".debug_loc"), endian);
+    let debug_loclists = DebugLocLists::new(get_or_empty(".debug_loclists"), endian);
     let locations = LocationLists::new(debug_loc, debug_loclists);
-    let debug_str_offsets = DebugStrOffsets::from(EndianSlice::new(EMPTY_SECTION, endian));
+    let debug_str_offsets =
+        DebugStrOffsets::from(EndianSlice::new(get_or_empty(".debug_str_offsets"), endian));
     let debug_types = DebugTypes::from(EndianSlice::new(EMPTY_SECTION, endian));
 
     Ok(Dwarf {
@@ -71,6 +65,62 @@ pub fn parse_dwarf(module: &[u8]) -> Result<Dwarf> {
     })
 }
 
+/// Returns the path of the separate debug file recorded in
+/// `external_debug_info` custom section
+pub fn external_debug_info(module: &[u8]) -> Result<Option<String>> {
+    let parser = wasmparser::Parser::new(0);
+    for payload in parser.parse_all(module) {
+        match payload? {
+            wasmparser::Payload::CustomSection(section)
+                if section.name() == "external_debug_info" =>
+            {
+                let mut reader = wasmparser::BinaryReader::new(section.data());
+                return Ok(Some(reader.read_string()?.to_string()));
+            }
+            _ => continue,
+        }
+    }
+    Ok(None)
+}
+
+/// Returns offsets of function bodies relative to the code section
+fn function_body_offsets(module: &[u8]) -> Result<Option<Vec<usize>>> {
+    let parser = wasmparser::Parser::new(0);
+    let mut base_offset = None;
+    let mut offsets = Vec::new();
+    for payload in parser.parse_all(module) {
+        match payload? {
+            wasmparser::Payload::CodeSectionStart { range, .. } => {
+                base_offset = Some(range.start);
+            }
+            wasmparser::Payload::CodeSectionEntry(body) => {
+                let base_offset = base_offset.with_context(|| "no code section start")?;
+                offsets.push(body.range().start - base_offset);
+            }
+            _ => continue,
+        }
+    }
+    Ok(base_offset.map(|_| offsets))
+}
+
+/// Checks that DWARF addresses in the separate debug file are valid for the module.
+/// Addresses are relative to the code section, so function bodies must be at the same offsets.
+pub fn validate_debug_file(module: &[u8], debug_file: &[u8]) -> Result<()> {
+    let expected = function_body_offsets(module)?;
+    let actual = match function_body_offsets(debug_file)? {
+        Some(offsets) => offsets,
+        // Some tools emit debug files without code section
+        None => return Ok(()),
+    };
+    match expected {
+        Some(expected) if expected == actual => Ok(()),
+        Some(_) => Err(anyhow!(
+            "code section offsets of the debug file don't match the module"
+        )),
+        None => Err(anyhow!("the module doesn't have code section")),
+    }
+}
+
 pub struct DwarfDebugInfo {
     pub sourcemap: DwarfSourceMap,
     pub subroutine: DwarfSubroutineMap,
@@ -88,13 +138,14 @@ pub fn transform_dwarf(buffer: &[u8]) -> Result<DwarfDebugInfo> {
             Some((_, entry)) => entry,
             None => continue,
         };
-        sourcemaps.push(transform_debug_line(
-            &unit,
-            root,
+        let unit_sourcemap = transform_debug_line(&unit, root, &dwarf, &dwarf.debug_line)?;
+        subroutines.append(&mut transform_subprogram(
             &dwarf,
-            &dwarf.debug_line,
+            &unit,
+            header.offset(),
+            &unit_sourcemap,
         )?);
-        subroutines.append(&mut transform_subprogram(&dwarf, &unit, header.offset())?);
+        sourcemaps.push(unit_sourcemap);
     }
     Ok(DwarfDebugInfo {
         sourcemap: DwarfSourceMap::new(sourcemaps),
@@ -113,6 +164,7 @@ where
     name: Option<String>,
     content: VariableContent<R>,
     ty_offset: Option<R::Offset>,
+    is_parameter: bool,
 }
 
 #[derive(Clone)]
@@ -122,25 +174,53 @@ enum VariableContent<R: gimli::Reader> {
     Unknown { debug_info: String },
 }
 
+#[derive(Debug)]
+pub enum SubroutineKind {
+    Subprogram,
+    LexicalBlock,
+    /// An instance of `DW_TAG_inlined_subroutine`. `call_site` is the location
+    /// in the caller where the subroutine was inlined.
+    Inlined {
+        call_site: Option<sourcemap::LineInfo>,
+    },
+}
+
 #[derive(Debug)]
 pub struct Subroutine<Offset> {
     pub name: Option<String>,
-    pub pc: std::ops::Range<u64>,
+    /// Code ranges of the subroutine. Non-contiguous subroutines described by
+    /// `DW_AT_ranges` have multiple ranges.
+    pub pc: Vec<std::ops::Range<u64>>,
     pub entry_offset: UnitOffset<Offset>,
     pub unit_offset: DebugInfoOffset<Offset>,
     pub encoding: gimli::Encoding,
     pub frame_base: Option<WasmLoc>,
+    pub kind: SubroutineKind,
+}
+
+impl<Offset> Subroutine<Offset> {
+    pub fn contains(&self, address: u64) -> bool {
+        self.pc.iter().any(|range| range.contains(&address))
+    }
 }
 
 pub fn transform_subprogram<R: gimli::Reader>(
     dwarf: &gimli::Dwarf<R>,
     unit: &Unit<R, R::Offset>,
     unit_offset: DebugInfoOffset<R::Offset>,
+    unit_sourcemap: &DwarfUnitSourceMap,
 ) -> Result<Vec<Subroutine<R::Offset>>> {
     let mut tree = unit.entries_tree(None)?;
     let root = tree.root()?;
     let mut subroutines = vec![];
-    transform_subprogram_rec(root, dwarf, unit, unit_offset, &mut subroutines)?;
+    transform_subprogram_rec(
+        root,
+        dwarf,
+        unit,
+        unit_offset,
+        unit_sourcemap,
+        &mut subroutines,
+    )?;
     Ok(subroutines)
 }
 
@@ -181,52 +261,148 @@ fn read_wasm_location<R: gimli::Reader>(attr_value: AttributeValue<R>) -> Result
     Ok(loc)
 }
 
+/// Reads an attribute of the entry, falling back to the entry referenced by
+/// `DW_AT_abstract_origin`. Inlined instances only carry the attributes that
+/// differ from their abstract origin, such as locations and pc ranges.
+fn attr_value_with_origin<R: gimli::Reader>(
+    unit: &Unit<R, R::Offset>,
+    entry: &DebuggingInformationEntry<R>,
+    name: gimli::DwAt,
+) -> Result<Option<AttributeValue<R>>> {
+    if let Some(value) = entry.attr_value(name)? {
+        return Ok(Some(value));
+    }
+    match entry.attr_value(gimli::DW_AT_abstract_origin)? {
+        Some(AttributeValue::UnitRef(offset)) => {
+            let origin = unit.entry(offset)?;
+            attr_value_with_origin(unit, &origin, name)
+        }
+        _ => Ok(None),
+    }
+}
+
+fn read_call_site<R: gimli::Reader>(
+    entry: &DebuggingInformationEntry<R>,
+    unit_sourcemap: &DwarfUnitSourceMap,
+) -> Result<Option<sourcemap::LineInfo>> {
+    let file_index = match entry.attr_value(gimli::DW_AT_call_file)? {
+        Some(AttributeValue::FileIndex(index)) => index,
+        Some(attr) => match attr.udata_value() {
+            Some(index) => index,
+            None => return Ok(None),
+        },
+        None => return Ok(None),
+    };
+    let filepath = match unit_sourcemap.filepath(file_index) {
+        Some(filepath) => filepath,
+        None => return Ok(None),
+    };
+    let line = entry
+        .attr_value(gimli::DW_AT_call_line)?
+        .and_then(|attr| attr.udata_value());
+    let column = match entry
+        .attr_value(gimli::DW_AT_call_column)?
+        .and_then(|attr| attr.udata_value())
+    {
+        Some(column) if column > 0 => sourcemap::ColumnType::Column(column),
+        _ => sourcemap::ColumnType::LeftEdge,
+    };
+    Ok(Some(sourcemap::LineInfo {
+        filepath,
+        line,
+        column,
+    }))
+}
+
 fn read_subprogram_header<R: gimli::Reader>(
     node: &gimli::EntriesTreeNode<R>,
     dwarf: &gimli::Dwarf<R>,
     unit: &Unit<R, R::Offset>,
     unit_offset: DebugInfoOffset<R::Offset>,
+    unit_sourcemap: &DwarfUnitSourceMap,
 ) -> Result<Option<Subroutine<R::Offset>>> {
-    match node.entry().tag() {
-        gimli::DW_TAG_subprogram | gimli::DW_TAG_lexical_block => (),
+    let kind = match node.entry().tag() {
+        gimli::DW_TAG_subprogram => SubroutineKind::Subprogram,
+        gimli::DW_TAG_lexical_block => SubroutineKind::LexicalBlock,
+        gimli::DW_TAG_inlined_subroutine => SubroutineKind::Inlined {
+            call_site: read_call_site(node.entry(), unit_sourcemap)?,
+        },
         _ => return Ok(None),
     };
 
-    let name = match node.entry().attr_value(gimli::DW_AT_name)? {
+    let name = match attr_value_with_origin(unit, node.entry(), gimli::DW_AT_name)? {
         Some(attr) => Some(clone_string_attribute(dwarf, unit, attr)?),
         None => None,
     };
 
-    let low_pc_attr = node.entry().attr_value(gimli::DW_AT_low_pc)?;
-    trace!("low_pc_attr: {:?}", low_pc_attr);
-    let high_pc_attr = node.entry().attr_value(gimli::DW_AT_high_pc)?;
-    trace!("high_pc_attr: {:?}", high_pc_attr);
-    let frame_base_attr = node.entry().attr_value(gimli::DW_AT_frame_base)?;
+    let pc = read_pc_ranges(node.entry(), dwarf, unit)?;
+    if pc.is_empty() {
+        return Ok(None);
+    }
+    let frame_base = match node.entry().attr_value(gimli::DW_AT_frame_base)? {
+        Some(attr) => Some(read_wasm_location(attr)?),
+        None => None,
+    };
+    Ok(Some(Subroutine {
+        pc,
+        name,
+        encoding: unit.encoding(),
+        entry_offset: node.entry().offset(),
+        unit_offset,
+        frame_base,
+        kind,
+    }))
+}
 
-    let subroutine = if let Some(AttributeValue::Addr(low_pc)) = low_pc_attr {
-        let high_pc = match high_pc_attr {
-            Some(AttributeValue::Udata(size)) => low_pc + size,
-            Some(AttributeValue::Addr(high_pc)) => high_pc,
-            Some(x) => unreachable!("high_pc can't be {:?}", x),
-            None => return Ok(None),
-        };
-        let frame_base = if let Some(attr) = frame_base_attr {
-            Some(read_wasm_location(attr)?)
-        } else {
-            None
-        };
-        Subroutine {
-            pc: low_pc..high_pc,
-            name,
-            encoding: unit.encoding(),
-            entry_offset: node.entry().offset(),
-            unit_offset,
-            frame_base,
+fn read_address<R: gimli::Reader>(
+    attr: AttributeValue<R>,
+    dwarf: &gimli::Dwarf<R>,
+    unit: &Unit<R, R::Offset>,
+) -> Result<Option<u64>> {
+    match attr {
+        AttributeValue::Addr(address) => Ok(Some(address)),
+        // DW_FORM_addrx in DWARF 5
+        AttributeValue::DebugAddrIndex(index) => Ok(Some(dwarf.address(unit, index)?)),
+        _ => Ok(None),
+    }
+}
+
+/// Reads code ranges from `DW_AT_low_pc`/`DW_AT_high_pc` or `DW_AT_ranges`
+fn read_pc_ranges<R: gimli::Reader>(
+    entry: &DebuggingInformationEntry<R>,
+    dwarf: &gimli::Dwarf<R>,
+    unit: &Unit<R, R::Offset>,
+) -> Result<Vec<std::ops::Range<u64>>> {
+    if let Some(ranges_attr) = entry.attr_value(gimli::DW_AT_ranges)? {
+        let mut ranges = vec![];
+        if let Some(mut iter) = dwarf.attr_ranges(unit, ranges_attr)? {
+            while let Some(range) = iter.next()? {
+                ranges.push(range.begin..range.end);
+            }
         }
-    } else {
-        return Ok(None);
+        return Ok(ranges);
+    }
+
+    let low_pc_attr = entry.attr_value(gimli::DW_AT_low_pc)?;
+    trace!("low_pc_attr: {:?}", low_pc_attr);
+    let high_pc_attr = entry.attr_value(gimli::DW_AT_high_pc)?;
+    trace!("high_pc_attr: {:?}", high_pc_attr);
+    let low_pc = match low_pc_attr {
+        Some(attr) => match read_address(attr, dwarf, unit)? {
+            Some(low_pc) => low_pc,
+            None => return Ok(vec![]),
+        },
+        None => return Ok(vec![]),
+    };
+    let high_pc = match high_pc_attr {
+        Some(AttributeValue::Udata(size)) => low_pc + size,
+        Some(attr) => match read_address(attr.clone(), dwarf, unit)? {
+            Some(high_pc) => high_pc,
+            None => return Err(anyhow!("high_pc can't be {:?}", attr)),
+        },
+        None => return Ok(vec![]),
     };
-    Ok(Some(subroutine))
+    Ok(vec![low_pc..high_pc])
 }
 
 pub fn transform_subprogram_rec<R: gimli::Reader>(
@@ -234,9 +410,10 @@ pub fn transform_subprogram_rec<R: gimli::Reader>(
     dwarf: &gimli::Dwarf<R>,
     unit: &Unit<R, R::Offset>,
     unit_offset: DebugInfoOffset<R::Offset>,
+    unit_sourcemap: &DwarfUnitSourceMap,
     out_subroutines: &mut Vec<Subroutine<R::Offset>>,
 ) -> Result<()> {
-    let mut subroutine = read_subprogram_header(&node, dwarf, unit, unit_offset)?;
+    let mut subroutine = read_subprogram_header(&node, dwarf, unit, unit_offset, unit_sourcemap)?;
     let mut children = node.children();
     while let Some(child) = children.next()? {
         match child.entry().tag() {
@@ -244,7 +421,14 @@ pub fn transform_subprogram_rec<R: gimli::Reader>(
                 continue;
             }
             _ => {
-                transform_subprogram_rec(child, dwarf, unit, unit_offset, out_subroutines)?;
+                transform_subprogram_rec(
+                    child,
+                    dwarf,
+                    unit,
+                    unit_offset,
+                    unit_sourcemap,
+                    out_subroutines,
+                )?;
             }
         }
     }
@@ -286,12 +470,12 @@ fn transform_variable<R: gimli::Reader>(
             content = VariableContent::ConstValue(bytes);
         }
     }
-    let name = match entry.attr_value(gimli::DW_AT_name)? {
+    let name = match attr_value_with_origin(unit, entry, gimli::DW_AT_name)? {
         Some(name_attr) => Some(clone_string_attribute(dwarf, unit, name_attr)?),
         None => None,
     };
 
-    let ty = match entry.attr_value(gimli::DW_AT_type)? {
+    let ty = match attr_value_with_origin(unit, entry, gimli::DW_AT_type)? {
         Some(AttributeValue::UnitRef(ref offset)) => Some(offset.0),
         _ => None,
     };
@@ -299,6 +483,7 @@ fn transform_variable<R: gimli::Reader>(
         name,
         content,
         ty_offset: ty,
+        is_parameter: entry.tag() == gimli::DW_TAG_formal_parameter,
     })
 }
 
@@ -337,6 +522,25 @@ fn evaluate_variable_location<R: gimli::Reader>(
     }
 }
 
+/// Finds the location description valid at the address from the location list
+fn find_location_expression<R: gimli::Reader>(
+    dwarf: &gimli::Dwarf<R>,
+    unit: &Unit<R, R::Offset>,
+    location: AttributeValue<R>,
+    address: u64,
+) -> Result<Expression<R>> {
+    let mut locations = match dwarf.attr_locations(unit, location)? {
+        Some(locations) => locations,
+        None => return Err(anyhow!("unexpected location attribute")),
+    };
+    while let Some(entry) = locations.next()? {
+        if entry.range.begin <= address && address < entry.range.end {
+            return Ok(entry.data);
+        }
+    }
+    Err(anyhow!("variable is not available at 0x{:x}", address))
+}
+
 use std::path::Path;
 
 pub fn transform_debug_line<R: gimli::Reader>(
@@ -405,6 +609,14 @@ pub struct DwarfUnitSourceMap {
 
 use crate::commands::sourcemap;
 impl DwarfUnitSourceMap {
+    fn filepath(&self, file_index: u64) -> Option<String> {
+        let index = (file_index as usize).checked_sub(self.sequence_base_index)?;
+        self.paths
+            .get(index)
+            .and_then(|path| path.to_str())
+            .map(|path| path.to_string())
+    }
+
     fn transform_lineinfo(&self, row: &LineRow) -> sourcemap::LineInfo {
         let filepath = self.paths[row.file_index() as usize - self.sequence_base_index].clone();
         sourcemap::LineInfo {
@@ -438,6 +650,13 @@ impl DwarfSourceMap {
             directory_map: RefCell::new(HashMap::new()),
         }
     }
+
+    fn map_directory(&self, mut line_info: sourcemap::LineInfo) -> sourcemap::LineInfo {
+        for (from, to) in self.directory_map.borrow().iter() {
+            line_info.filepath = line_info.filepath.replace(from, to);
+        }
+        line_info
+    }
 }
 
 impl sourcemap::SourceMap for DwarfSourceMap {
@@ -445,7 +664,7 @@ impl sourcemap::SourceMap for DwarfSourceMap {
         self.directory_map.borrow_mut().insert(from, to);
     }
     fn find_line_info(&self, offset: usize) -> Option<sourcemap::LineInfo> {
-        let mut line_info = match self
+        let line_info = match self
             .address_sorted_rows
             .binary_search_by_key(&(offset as u64), |i| i.0)
         {
@@ -458,10 +677,14 @@ impl sourcemap::SourceMap for DwarfSourceMap {
                 }
             }
         };
-        for (from, to) in self.directory_map.borrow().iter() {
-            line_info.filepath = line_info.filepath.replace(from, to);
-        }
-        Some(line_info)
+        Some(self.map_directory(line_info))
+    }
+    fn find_line_offsets(&self, filepath: &str, line: u64) -> Vec<usize> {
+        let rows = self
+            .address_sorted_rows
+            .iter()
+            .map(|(addr, line_info)| (*addr as usize, Some(self.map_directory(line_info.clone()))));
+        sourcemap::line_offsets(rows, filepath, line)
     }
 }
 
@@ -472,6 +695,33 @@ pub struct DwarfSubroutineMap {
     buffer: Vec<u8>,
 }
 
+impl DwarfSubroutineMap {
+    /// Returns scopes containing the offset, innermost first.
+    fn scopes_at(&self, code_offset: usize) -> impl Iterator<Item = &Subroutine<usize>> {
+        let offset = code_offset as u64;
+        // Subroutines are stored in post-order, so nested scopes come before their parents
+        self.subroutines.iter().filter(move |s| s.contains(offset))
+    }
+
+    /// Returns scopes that belong to the (possibly inlined) frame at `inline_depth`.
+    /// Depth 0 is the innermost inlined subroutine, or the physical function
+    /// itself when nothing is inlined at the offset.
+    fn frame_scopes(&self, code_offset: usize, inline_depth: usize) -> Vec<&Subroutine<usize>> {
+        let mut depth = 0;
+        let mut scopes = vec![];
+        for scope in self.scopes_at(code_offset) {
+            if depth == inline_depth {
+                scopes.push(scope);
+            }
+            match scope.kind {
+                SubroutineKind::LexicalBlock => (),
+                SubroutineKind::Subprogram | SubroutineKind::Inlined { .. } => depth += 1,
+            }
+        }
+        scopes
+    }
+}
+
 fn header_from_offset<R: gimli::Reader>(
     dwarf: &gimli::Dwarf<R>,
     offset: DebugInfoOffset<R::Offset>,
@@ -529,9 +779,13 @@ fn unit_type_name<R: gimli::Reader>(
 }
 
 impl subroutine::SubroutineMap for DwarfSubroutineMap {
-    fn variable_name_list(&self, code_offset: usize) -> Result<Vec<subroutine::Variable>> {
-        let offset = &(code_offset as u64);
-        let subroutine = match self.subroutines.iter().find(|s| s.pc.contains(offset)) {
+    fn variable_name_list(
+        &self,
+        code_offset: usize,
+        inline_depth: usize,
+    ) -> Result<Vec<subroutine::Variable>> {
+        let scopes = self.frame_scopes(code_offset, inline_depth);
+        let subroutine = match scopes.first() {
             Some(s) => s,
             None => return Err(anyhow!("failed to determine subroutine")),
         };
@@ -544,7 +798,10 @@ impl subroutine::SubroutineMap for DwarfSubroutineMap {
         };
 
         let unit = dwarf.unit(header)?;
-        let variables = subroutine_variables(&dwarf, &unit, subroutine)?;
+        let mut variables = vec![];
+        for scope in scopes {
+            variables.append(&mut subroutine_variables(&dwarf, &unit, scope)?);
+        }
 
         Ok(variables
             .iter()
@@ -552,6 +809,7 @@ impl subroutine::SubroutineMap for DwarfSubroutineMap {
                 let mut v = subroutine::Variable {
                     name: "<<not parsed yet>>".to_string(),
                     type_name: "<<not parsed yet>>".to_string(),
+                    is_parameter: var.is_parameter,
                 };
                 if let Some(name) = var.name.clone() {
                     v.name = name;
@@ -565,22 +823,41 @@ impl subroutine::SubroutineMap for DwarfSubroutineMap {
     }
 
     fn get_frame_base(&self, code_offset: usize) -> Result<Option<WasmLoc>> {
-        let offset = &(code_offset as u64);
-        let subroutine = match self.subroutines.iter().find(|s| s.pc.contains(offset)) {
+        // Lexical blocks and inlined subroutines share the frame base of
+        // the physical function that contains them
+        let subroutine = match self
+            .scopes_at(code_offset)
+            .find(|s| matches!(s.kind, SubroutineKind::Subprogram))
+        {
             Some(s) => s,
             None => return Err(anyhow!("failed to determine subroutine")),
         };
         Ok(subroutine.frame_base)
     }
-    fn display_variable(
+
+    fn inlined_frames(&self, code_offset: usize) -> Result<Vec<subroutine::InlinedFrame>> {
+        Ok(self
+            .scopes_at(code_offset)
+            .filter_map(|s| match &s.kind {
+                SubroutineKind::Inlined { call_site } => Some(subroutine::InlinedFrame {
+                    name: s.name.clone(),
+                    call_site: call_site.clone(),
+                }),
+                _ => None,
+            })
+            .collect())
+    }
+
+    fn format_variable(
         &self,
         code_offset: usize,
+        inline_depth: usize,
         frame_base: FrameBase,
         memory: &[u8],
         name: String,
-    ) -> Result<()> {
-        let offset = &(code_offset as u64);
-        let subroutine = match self.subroutines.iter().find(|s| s.pc.contains(offset)) {
+    ) -> Result<String> {
+        let scopes = self.frame_scopes(code_offset, inline_depth);
+        let subroutine = match scopes.first() {
             Some(s) => s,
             None => return Err(anyhow!("failed to determine subroutine")),
         };
@@ -588,12 +865,15 @@ impl subroutine::SubroutineMap for DwarfSubroutineMap {
         let header = match header_from_offset(&dwarf, subroutine.unit_offset)? {
             Some(header) => header,
             None => {
-                return Ok(());
+                return Err(anyhow!("failed to find compilation unit"));
             }
         };
 
         let unit = dwarf.unit(header)?;
-        let variables = subroutine_variables(&dwarf, &unit, subroutine)?;
+        let mut variables = vec![];
+        for scope in scopes.iter() {
+            variables.append(&mut subroutine_variables(&dwarf, &unit, scope)?);
+        }
 
         let var = match variables.iter().find(|v| {
             if let Some(vname) = v.name.clone() {
@@ -612,8 +892,12 @@ impl subroutine::SubroutineMap for DwarfSubroutineMap {
                 AttributeValue::Exprloc(expr) => {
                     evaluate_variable_location(subroutine.encoding, frame_base, expr)?
                 }
-                AttributeValue::LocationListsRef(_listsref) => unimplemented!("listsref"),
-                _ => panic!(),
+                // DW_FORM_sec_offset or DW_FORM_loclistx in DWARF 5
+                location => {
+                    let expr =
+                        find_location_expression(&dwarf, &unit, location, code_offset as u64)?;
+                    evaluate_variable_location(subroutine.encoding, frame_base, expr)?
+                }
             },
             VariableContent::ConstValue(ref _bytes) => unimplemented!(),
             VariableContent::Unknown { ref debug_info } => {
@@ -624,8 +908,7 @@ impl subroutine::SubroutineMap for DwarfSubroutineMap {
         let piece = match piece.get(0) {
             Some(p) => p,
             None => {
-                println!("failed to get piece of variable");
-                return Ok(());
+                return Err(anyhow!("failed to get piece of variable"));
             }
         };
 
@@ -635,22 +918,18 @@ impl subroutine::SubroutineMap for DwarfSubroutineMap {
                 gimli::Location::Address { address } => {
                     let mut tree = unit.entries_tree(Some(UnitOffset(offset)))?;
                     let root = tree.root()?;
-                    println!(
-                        "{}",
-                        format_object(
-                            root,
-                            &memory[(address as usize)..],
-                            subroutine.encoding,
-                            &dwarf,
-                            &unit
-                        )?
-                    );
+                    format_object(
+                        root,
+                        &memory[(address as usize)..],
+                        subroutine.encoding,
+                        &dwarf,
+                        &unit,
+                    )
                 }
-                _ => unimplemented!(),
+                location => Err(anyhow!("unsupported variable location {:?}", location)),
             }
         } else {
-            println!("no explicit type");
+            Ok("no explicit type".to_string())
         }
-        Ok(())
     }
 }
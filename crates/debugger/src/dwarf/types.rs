@@ -251,13 +251,23 @@ fn parse_member<R: gimli::Reader>(
     let mut member_location = MemberLocation::ConstOffset(0);
     if let Some(loc_attr) = node.entry().attr_value(gimli::DW_AT_data_member_location)? {
         match loc_attr {
-            gimli::AttributeValue::Udata(offset) => {
-                member_location = MemberLocation::ConstOffset(offset);
-            }
             gimli::AttributeValue::Exprloc(expr) => {
                 member_location = MemberLocation::LocationDescription(expr);
             }
-            _ => unimplemented!(),
+            // DWARF 2 and 3 producers encode the location expression in a block form
+            gimli::AttributeValue::Block(data) => {
+                member_location = MemberLocation::LocationDescription(gimli::Expression(data));
+            }
+            // DWARF 5 producers may encode the offset in any constant form
+            attr => match attr.udata_value() {
+                Some(offset) => member_location = MemberLocation::ConstOffset(offset),
+                None => {
+                    return Err(anyhow!(
+                        "Unsupported DW_AT_data_member_location form: {:?}",
+                        attr
+                    ))
+                }
+            },
         }
     }
     Ok(Member {
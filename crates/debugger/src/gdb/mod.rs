@@ -0,0 +1,437 @@
+//! GDB remote stub for LLDB's WebAssembly support
+//! LLDB reads the call stack, locals, globals and memory of Wasm processes
+//! through `qWasmCallStack`, `qWasmLocal`, `qWasmGlobal` and `qWasmMem` packets.
+//! See also https://lldb.llvm.org/resources/lldbgdbremote.html
+
+mod packet;
+
+use crate::commands::debugger::{Debugger, StepStyle};
+use crate::commands::frame::with_selected_frame;
+use crate::debugger::MainDebugger;
+use crate::ModuleInput;
+use anyhow::{anyhow, Result};
+use log::{trace, warn};
+use packet::{hex_encode, read_packet, write_packet, Packet};
+use std::collections::{HashMap, HashSet};
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpListener;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
+use wasminspect_vm::{GlobalAddr, NumVal, Signal, Trap, WasmValue};
+
+const THREAD_ID: usize = 1;
+const SIGINT: u8 = 2;
+const SIGTRAP: u8 = 5;
+const SIGILL: u8 = 4;
+
+/// Address spaces of LLDB's wasm addresses. An address is composed of
+/// the space in the top 2 bits, the module id in the next 30 bits and the offset.
+const MEMORY_SPACE: u64 = 0;
+const OBJECT_SPACE: u64 = 1;
+/// The main module is the only module loaded in the process
+const MAIN_MODULE_ID: u64 = 0;
+
+fn wasm_address(space: u64, module_id: u64, offset: u64) -> u64 {
+    (space << 62) | (module_id << 32) | offset
+}
+
+/// Returns (space, module id, offset) of the address
+fn split_wasm_address(addr: u64) -> (u64, u64, u64) {
+    (addr >> 62, (addr >> 32) & 0x3fff_ffff, addr & 0xffff_ffff)
+}
+
+fn value_bytes(value: &WasmValue) -> Result<Vec<u8>> {
+    let bytes = match value {
+        WasmValue::Num(NumVal::I32(v)) => v.to_le_bytes().to_vec(),
+        WasmValue::Num(NumVal::I64(v)) => v.to_le_bytes().to_vec(),
+        WasmValue::Num(NumVal::F32(v)) => v.to_bits().to_le_bytes().to_vec(),
+        WasmValue::Num(NumVal::F64(v)) => v.to_bits().to_le_bytes().to_vec(),
+        WasmValue::Ref(_) => return Err(anyhow!("Reference values are not supported")),
+    };
+    Ok(bytes)
+}
+
+fn parse_hex(value: &str) -> Result<u64> {
+    Ok(u64::from_str_radix(value, 16)?)
+}
+
+fn parse_decimal(value: &str) -> Result<usize> {
+    Ok(value.parse::<usize>()?)
+}
+
+enum Stop {
+    Signal(u8, &'static str),
+    Exception(String),
+    Exited(i32),
+}
+
+struct Stub<W: Write> {
+    debugger: MainDebugger,
+    writer: W,
+    no_ack_mode: bool,
+    /// Offsets of instructions in the code section
+    breakpoints: HashSet<usize>,
+    code_section_offset: usize,
+    module_name: String,
+    last_stop: Stop,
+}
+
+impl<W: Write> Stub<W> {
+    fn run(&mut self, packets: mpsc::Receiver<Packet>) -> Result<()> {
+        for packet in packets {
+            let data = match packet {
+                Packet::Command(data) => data,
+                Packet::Interrupt => {
+                    // The process is already stopped if the interrupt is handled here
+                    self.debugger
+                        .interrupt_handle()
+                        .store(false, Ordering::SeqCst);
+                    continue;
+                }
+            };
+            if !self.no_ack_mode {
+                self.writer.write_all(b"+")?;
+            }
+            let data = String::from_utf8_lossy(&data).to_string();
+            trace!("gdb-remote <- {}", data);
+            let (reply, is_finished) = match self.handle(&data) {
+                Ok(reply) => (reply, data == "k" || data.starts_with('D')),
+                Err(err) => {
+                    warn!("Failed to handle '{}': {}", data, err);
+                    ("E01".to_string(), false)
+                }
+            };
+            trace!("gdb-remote -> {}", reply);
+            write_packet(&mut self.writer, reply.as_bytes())?;
+            if data == "QStartNoAckMode" {
+                self.no_ack_mode = true;
+            }
+            if is_finished {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    fn handle(&mut self, data: &str) -> Result<String> {
+        let reply = match data {
+            "QStartNoAckMode" | "QThreadSuffixSupported" | "QListThreadsInStopReply" => {
+                "OK".to_string()
+            }
+            "qHostInfo" | "qProcessInfo" => format!(
+                "pid:1;triple:{};endian:little;ptrsize:4;",
+                hex_encode(b"wasm32-unknown-unknown-wasm")
+            ),
+            "qAttached" => "1".to_string(),
+            "qC" => format!("QC{:x}", THREAD_ID),
+            "qfThreadInfo" => format!("m{:x}", THREAD_ID),
+            "qsThreadInfo" => "l".to_string(),
+            "vCont?" => "vCont;c;C;s;S".to_string(),
+            "?" => self.stop_reply(),
+            "g" | "p0" => hex_encode(&self.pc().to_le_bytes()),
+            "qRegisterInfo0" => {
+                "name:pc;alt-name:pc;bitsize:64;offset:0;encoding:uint;format:hex;set:General Purpose Registers;gcc:16;dwarf:16;generic:pc;"
+                    .to_string()
+            }
+            "c" | "vCont;c" => self.resume(false),
+            "s" | "vCont;s" => self.resume(true),
+            "k" => "OK".to_string(),
+            _ => {
+                if data.starts_with("qSupported") {
+                    "PacketSize=1000;qXfer:libraries:read+".to_string()
+                } else if data.starts_with("qRegisterInfo") {
+                    "E45".to_string()
+                } else if data.starts_with('H') || data.starts_with("qSymbol") {
+                    "OK".to_string()
+                } else if data.starts_with("qThreadStopInfo") {
+                    self.stop_reply()
+                } else if data.starts_with("vCont;c") || data.starts_with('C') {
+                    self.resume(false)
+                } else if data.starts_with("vCont;s") || data.starts_with('S') {
+                    self.resume(true)
+                } else if data.starts_with('D') {
+                    "OK".to_string()
+                } else if let Some(args) = data.strip_prefix("qXfer:libraries:read::") {
+                    self.read_libraries(args)?
+                } else if let Some(args) = data.strip_prefix('m') {
+                    self.read_memory(args)?
+                } else if let Some(args) = data.strip_prefix("Z0,") {
+                    self.breakpoints.insert(self.breakpoint_offset(args)?);
+                    "OK".to_string()
+                } else if let Some(args) = data.strip_prefix("z0,") {
+                    self.breakpoints.remove(&self.breakpoint_offset(args)?);
+                    "OK".to_string()
+                } else if data.starts_with("qWasmCallStack") {
+                    let pcs = self.call_stack()?;
+                    pcs.iter()
+                        .map(|pc| hex_encode(&pc.to_le_bytes()))
+                        .collect()
+                } else if let Some(args) = data.strip_prefix("qWasmLocal:") {
+                    let (frame, index) = self.frame_and_index(args)?;
+                    let locals = self.with_frame(frame, |debugger| Ok(debugger.locals()))?;
+                    let value = locals
+                        .get(index)
+                        .ok_or_else(|| anyhow!("local{} is out of range", index))?;
+                    hex_encode(&value_bytes(value)?)
+                } else if let Some(args) = data.strip_prefix("qWasmGlobal:") {
+                    let (frame, index) = self.frame_and_index(args)?;
+                    let value = self.with_frame(frame, |debugger| global_value(debugger, index))?;
+                    hex_encode(&value_bytes(&value)?)
+                } else if let Some(args) = data.strip_prefix("qWasmMem:") {
+                    let mut args = args.split(';');
+                    let _frame = args.next();
+                    let addr = parse_hex(args.next().unwrap_or_default())? as usize;
+                    let len = parse_hex(args.next().unwrap_or_default())? as usize;
+                    self.read_linear_memory(addr, len)?
+                } else {
+                    // Empty response for unsupported packets
+                    String::new()
+                }
+            }
+        };
+        Ok(reply)
+    }
+
+    /// Returns the address of the instruction executed next in the frame
+    fn frame_pc(debugger: &MainDebugger, code_section_offset: usize) -> Option<u64> {
+        let (insts, next_index) = debugger.selected_instructions().ok()?;
+        let inst = insts.get(next_index).or_else(|| insts.last())?;
+        let offset = (code_section_offset + inst.offset) as u64;
+        Some(wasm_address(OBJECT_SPACE, MAIN_MODULE_ID, offset))
+    }
+
+    fn pc(&self) -> u64 {
+        Self::frame_pc(&self.debugger, self.code_section_offset).unwrap_or(0)
+    }
+
+    fn call_stack(&mut self) -> Result<Vec<u64>> {
+        let code_section_offset = self.code_section_offset;
+        let depth = self.debugger.frame().len();
+        let mut pcs = vec![];
+        for frame in 0..depth {
+            let pc = self.with_frame(frame, |debugger| {
+                Ok(Self::frame_pc(debugger, code_section_offset))
+            })?;
+            pcs.extend(pc);
+        }
+        Ok(pcs)
+    }
+
+    fn with_frame<T>(
+        &mut self,
+        frame: usize,
+        f: impl FnOnce(&MainDebugger) -> Result<T>,
+    ) -> Result<T> {
+        with_selected_frame(
+            self,
+            |stub| &mut stub.debugger,
+            frame,
+            0,
+            |stub| f(&stub.debugger),
+        )
+    }
+
+    fn frame_and_index(&self, args: &str) -> Result<(usize, usize)> {
+        let mut args = args.split(';');
+        let frame = parse_decimal(args.next().unwrap_or_default())?;
+        let index = parse_decimal(args.next().unwrap_or_default())?;
+        Ok((frame, index))
+    }
+
+    /// Converts `addr,kind` of `Z0` packet into the offset in the code section
+    fn breakpoint_offset(&self, args: &str) -> Result<usize> {
+        let addr = parse_hex(args.split(',').next().unwrap_or_default())?;
+        let (space, module_id, offset) = split_wasm_address(addr);
+        if space != OBJECT_SPACE || module_id != MAIN_MODULE_ID {
+            return Err(anyhow!("Invalid code address 0x{:x}", addr));
+        }
+        (offset as usize)
+            .checked_sub(self.code_section_offset)
+            .ok_or_else(|| anyhow!("Address 0x{:x} is not in code section", addr))
+    }
+
+    fn read_libraries(&self, args: &str) -> Result<String> {
+        let mut args = args.split(',');
+        let offset = parse_hex(args.next().unwrap_or_default())? as usize;
+        let length = parse_hex(args.next().unwrap_or_default())? as usize;
+        let xml = format!(
+            "<library-list><library name=\"{}\"><section address=\"0x{:x}\"/></library></library-list>",
+            self.module_name,
+            wasm_address(OBJECT_SPACE, MAIN_MODULE_ID, 0)
+        );
+        let start = offset.min(xml.len());
+        let end = start
+            .checked_add(length)
+            .ok_or_else(|| anyhow!("Invalid length 0x{:x}", length))?
+            .min(xml.len());
+        let prefix = if end == xml.len() { 'l' } else { 'm' };
+        Ok(format!("{}{}", prefix, &xml[start..end]))
+    }
+
+    fn read_memory(&self, args: &str) -> Result<String> {
+        let mut args = args.split(',');
+        let addr = parse_hex(args.next().unwrap_or_default())?;
+        let len = parse_hex(args.next().unwrap_or_default())? as usize;
+        let (space, module_id, offset) = split_wasm_address(addr);
+        match space {
+            OBJECT_SPACE if module_id == MAIN_MODULE_ID => {
+                let bytes = self
+                    .debugger
+                    .main_module_bytes()
+                    .ok_or_else(|| anyhow!("No module loaded"))?;
+                let start = (offset as usize).min(bytes.len());
+                let end = start
+                    .checked_add(len)
+                    .ok_or_else(|| anyhow!("Invalid length 0x{:x}", len))?
+                    .min(bytes.len());
+                Ok(hex_encode(&bytes[start..end]))
+            }
+            MEMORY_SPACE => self.read_linear_memory(offset as usize, len),
+            _ => Err(anyhow!("Invalid address 0x{:x}", addr)),
+        }
+    }
+
+    fn read_linear_memory(&self, addr: usize, len: usize) -> Result<String> {
+        let memory = self.debugger.memory()?;
+        let end = addr
+            .checked_add(len)
+            .filter(|end| *end <= memory.len())
+            .ok_or_else(|| anyhow!("Memory access out of bounds 0x{:x}", addr))?;
+        Ok(hex_encode(&memory[addr..end]))
+    }
+
+    /// Runs the process by instructions, and stops at breakpoints managed by the stub
+    fn execute(&self, single_step: bool) -> Result<Stop> {
+        loop {
+            let signal = match self.debugger.step(StepStyle::InstIn) {
+                Ok(signal) => signal,
+                Err(err) => match err.downcast_ref::<Trap>() {
+                    Some(Trap::Exit(status)) => return Ok(Stop::Exited(*status)),
+                    _ => return Err(err),
+                },
+            };
+            match signal {
+                Signal::Next => (),
+                Signal::Breakpoint => return Ok(Stop::Signal(SIGINT, "signal")),
+                Signal::End => return Ok(Stop::Exited(0)),
+            }
+            if single_step {
+                return Ok(Stop::Signal(SIGTRAP, "trace"));
+            }
+            let (insts, next_index) = self.debugger.selected_instructions()?;
+            if let Some(inst) = insts.get(next_index) {
+                if self.breakpoints.contains(&inst.offset) {
+                    return Ok(Stop::Signal(SIGTRAP, "breakpoint"));
+                }
+            }
+        }
+    }
+
+    fn resume(&mut self, single_step: bool) -> String {
+        if let Stop::Exited(_) = self.last_stop {
+            return self.stop_reply();
+        }
+        self.last_stop = match self.execute(single_step) {
+            Ok(stop) => stop,
+            Err(err) => Stop::Exception(err.to_string()),
+        };
+        self.stop_reply()
+    }
+
+    fn stop_reply(&self) -> String {
+        let (signal, reason) = match &self.last_stop {
+            Stop::Signal(signal, reason) => (*signal, reason.to_string()),
+            Stop::Exception(description) => (
+                SIGILL,
+                format!(
+                    "exception;description:{}",
+                    hex_encode(description.as_bytes())
+                ),
+            ),
+            // The status is truncated to 8 bits as the exit status of a process
+            Stop::Exited(status) => return format!("W{:02x}", *status as u8),
+        };
+        let pc = self.pc();
+        format!(
+            "T{:02x}thread:{:x};thread-pcs:{:x};00:{};reason:{};",
+            signal,
+            THREAD_ID,
+            pc,
+            hex_encode(&pc.to_le_bytes()),
+            reason
+        )
+    }
+}
+
+fn global_value(debugger: &MainDebugger, index: usize) -> Result<WasmValue> {
+    let module_index = debugger
+        .current_frame()
+        .ok_or_else(|| anyhow!("function frame not found"))?
+        .module_index;
+    let store = debugger.store()?;
+    if index >= store.global_count(module_index) {
+        return Err(anyhow!("global{} is out of range", index));
+    }
+    let global = store.global(GlobalAddr::new_unsafe(module_index, index));
+    let value = global.borrow().value();
+    Ok(value)
+}
+
+/// Reads packets on another thread to handle `^C` while the process is running
+fn spawn_reader<R: BufRead + Send + 'static>(
+    mut reader: R,
+    interrupt: Arc<AtomicBool>,
+) -> mpsc::Receiver<Packet> {
+    let (tx, rx) = mpsc::channel();
+    std::thread::spawn(move || {
+        loop {
+            let packet = match read_packet(&mut reader) {
+                Ok(Some(packet)) => packet,
+                Ok(None) => break,
+                Err(err) => {
+                    warn!("Failed to read gdb-remote packet: {}", err);
+                    break;
+                }
+            };
+            if let Packet::Interrupt = packet {
+                interrupt.store(true, Ordering::SeqCst);
+            }
+            if tx.send(packet).is_err() {
+                return;
+            }
+        }
+        // Stop the running process when the client is gone
+        interrupt.store(true, Ordering::SeqCst);
+    });
+    rx
+}
+
+/// Accepts a LLDB connection on the listener, and serves the module stopped at its entry point
+pub fn serve_gdb_remote(
+    listener: TcpListener,
+    module_input: ModuleInput,
+    preopen_dirs: Vec<(String, String)>,
+    envs: Vec<(String, String)>,
+    wasi_args: Vec<String>,
+    entry_point: Option<String>,
+) -> Result<()> {
+    let mut debugger = MainDebugger::new(preopen_dirs, envs)?;
+    debugger.load_main_module(&module_input.bytes, module_input.basename.clone())?;
+    debugger.instantiate(HashMap::new(), Some(&wasi_args))?;
+    debugger.start(entry_point.as_deref(), vec![])?;
+    let code_section_offset = crate::jsmap::code_section_offset(&module_input.bytes)?;
+
+    let (stream, _) = listener.accept()?;
+    let reader = BufReader::new(stream.try_clone()?);
+    let packets = spawn_reader(reader, debugger.interrupt_handle());
+    let mut stub = Stub {
+        debugger,
+        writer: stream,
+        no_ack_mode: false,
+        breakpoints: HashSet::new(),
+        code_section_offset,
+        module_name: module_input.basename,
+        last_stop: Stop::Signal(SIGTRAP, "trace"),
+    };
+    stub.run(packets)
+}
@@ -0,0 +1,96 @@
+//! Packet framing of GDB Remote Serial Protocol
+//! See also https://sourceware.org/gdb/onlinedocs/gdb/Overview.html
+
+use anyhow::{anyhow, Result};
+use std::io::{BufRead, Write};
+
+pub enum Packet {
+    Command(Vec<u8>),
+    /// `^C` sent out of band to stop the running process
+    Interrupt,
+}
+
+fn checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |sum, b| sum.wrapping_add(*b))
+}
+
+/// Reads a packet from the stream. Acknowledgments are skipped.
+/// Returns `None` at the end of the stream.
+pub fn read_packet<R: BufRead>(reader: &mut R) -> Result<Option<Packet>> {
+    let mut byte = [0; 1];
+    loop {
+        if reader.read(&mut byte)? == 0 {
+            return Ok(None);
+        }
+        match byte[0] {
+            b'$' => break,
+            0x03 => return Ok(Some(Packet::Interrupt)),
+            _ => continue,
+        }
+    }
+    let mut data = vec![];
+    reader.read_until(b'#', &mut data)?;
+    if data.pop() != Some(b'#') {
+        return Ok(None);
+    }
+    let mut sum = [0; 2];
+    reader.read_exact(&mut sum)?;
+    let sum = u8::from_str_radix(std::str::from_utf8(&sum)?, 16)?;
+    if sum != checksum(&data) {
+        return Err(anyhow!("checksum mismatch"));
+    }
+    // Unescape `}` sequences
+    let mut packet = Vec::with_capacity(data.len());
+    let mut bytes = data.into_iter();
+    while let Some(byte) = bytes.next() {
+        match byte {
+            b'}' => packet.push(bytes.next().unwrap_or(0) ^ 0x20),
+            byte => packet.push(byte),
+        }
+    }
+    Ok(Some(Packet::Command(packet)))
+}
+
+pub fn write_packet<W: Write>(writer: &mut W, data: &[u8]) -> Result<()> {
+    let mut packet = Vec::with_capacity(data.len() + 4);
+    packet.push(b'$');
+    for byte in data {
+        match byte {
+            b'#' | b'$' | b'}' | b'*' => packet.extend_from_slice(&[b'}', byte ^ 0x20]),
+            byte => packet.push(*byte),
+        }
+    }
+    let sum = checksum(&packet[1..]);
+    write!(packet, "#{:02x}", sum)?;
+    writer.write_all(&packet)?;
+    writer.flush()?;
+    Ok(())
+}
+
+pub fn hex_encode(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_packet_roundtrip() {
+        let mut buffer = vec![];
+        write_packet(&mut buffer, b"qWasmLocal:0;1").unwrap();
+        assert_eq!(buffer, b"$qWasmLocal:0;1#ca");
+        let mut buffer = vec![];
+        write_packet(&mut buffer, b"a#b").unwrap();
+        assert_eq!(buffer, b"$a}\x03b#43");
+        match read_packet(&mut &buffer[..]).unwrap() {
+            Some(Packet::Command(data)) => assert_eq!(data, b"a#b"),
+            _ => panic!("failed to read packet"),
+        }
+        let mut input = &b"+\x03"[..];
+        assert!(matches!(
+            read_packet(&mut input).unwrap(),
+            Some(Packet::Interrupt)
+        ));
+    }
+}
@@ -0,0 +1,392 @@
+//! Record and replay of host function calls.
+//!
+//! Every imported host function is wrapped before instantiation. In record mode the
+//! wrapper calls the original function and logs its arguments, results, trap and the bytes
+//! it changed in the linear memory as a JSON line. In replay mode the original function is
+//! never called: the wrapper checks that the guest makes the same call as recorded,
+//! writes the recorded bytes back to the memory and returns the recorded results or trap.
+
+use crate::commands::debugger::RawHostModule;
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::PathBuf;
+use std::rc::Rc;
+use wasminspect_vm::{HostFuncBody, HostValue, NumVal, RefVal, Trap, WasmValue};
+
+const VERSION: u32 = 1;
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum HostCallMode {
+    /// Log every host call to the file
+    Record(PathBuf),
+    /// Feed the host calls logged in the file back instead of calling the host
+    Replay(PathBuf),
+}
+
+#[derive(Serialize, Deserialize)]
+struct Header {
+    version: u32,
+    module_hash: u64,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+enum LoggedValue {
+    I32(i32),
+    I64(i64),
+    /// Bit pattern of the float
+    F32(u32),
+    F64(u64),
+    NullRef,
+    ExternRef(u32),
+}
+
+impl LoggedValue {
+    fn new(value: &WasmValue) -> Result<Self> {
+        Ok(match value {
+            WasmValue::Num(NumVal::I32(v)) => Self::I32(*v),
+            WasmValue::Num(NumVal::I64(v)) => Self::I64(*v),
+            WasmValue::Num(NumVal::F32(v)) => Self::F32(v.to_bits()),
+            WasmValue::Num(NumVal::F64(v)) => Self::F64(v.to_bits()),
+            WasmValue::Ref(RefVal::NullRef(_)) => Self::NullRef,
+            WasmValue::Ref(RefVal::ExternRef(v)) => Self::ExternRef(*v),
+            WasmValue::Ref(RefVal::FuncRef(_)) => {
+                return Err(anyhow!("funcref values can't be recorded"))
+            }
+        })
+    }
+
+    fn to_value(&self, ty: wasmparser::ValType) -> Option<WasmValue> {
+        match self {
+            Self::I32(v) => Some(WasmValue::I32(*v)),
+            Self::I64(v) => Some(WasmValue::I64(*v)),
+            Self::F32(v) => Some(WasmValue::F32(*v)),
+            Self::F64(v) => Some(WasmValue::F64(*v)),
+            Self::NullRef => WasmValue::null_ref(ty),
+            Self::ExternRef(v) => Some(WasmValue::Ref(RefVal::ExternRef(*v))),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct MemoryWrite {
+    offset: usize,
+    /// Hex encoded bytes
+    bytes: String,
+}
+
+/// Failure of a host call raised again on replay
+#[derive(Serialize, Deserialize)]
+enum HostCallError {
+    /// The host function exited the process with the status
+    Exit(i32),
+    /// The host function trapped with the message
+    Trap(String),
+}
+
+impl HostCallError {
+    fn new(trap: &Trap) -> Self {
+        match trap {
+            Trap::Exit(status) => Self::Exit(*status),
+            trap => Self::Trap(trap.to_string()),
+        }
+    }
+
+    fn to_trap(&self) -> Trap {
+        match self {
+            Self::Exit(status) => Trap::Exit(*status),
+            Self::Trap(message) => host_error(anyhow!("{}", message)),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct HostCall {
+    module: String,
+    field: String,
+    args: Vec<LoggedValue>,
+    results: Vec<LoggedValue>,
+    writes: Vec<MemoryWrite>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    error: Option<HostCallError>,
+}
+
+impl HostCall {
+    fn signature(&self) -> String {
+        format!("{}.{}{:?}", self.module, self.field, self.args)
+    }
+}
+
+enum HostCallLog {
+    Recorder {
+        writer: BufWriter<File>,
+    },
+    Replayer {
+        calls: std::vec::IntoIter<HostCall>,
+        /// Number of calls replayed so far
+        count: usize,
+    },
+}
+
+impl HostCallLog {
+    fn open(mode: &HostCallMode, module_hash: u64) -> Result<Self> {
+        match mode {
+            HostCallMode::Record(path) => {
+                let file = File::create(path)
+                    .with_context(|| format!("failed to create {}", path.display()))?;
+                let mut writer = BufWriter::new(file);
+                let header = Header {
+                    version: VERSION,
+                    module_hash,
+                };
+                serde_json::to_writer(&mut writer, &header)?;
+                writeln!(writer)?;
+                writer.flush()?;
+                Ok(Self::Recorder { writer })
+            }
+            HostCallMode::Replay(path) => {
+                let file = File::open(path)
+                    .with_context(|| format!("failed to open {}", path.display()))?;
+                let mut lines = BufReader::new(file).lines();
+                let header = lines
+                    .next()
+                    .ok_or_else(|| anyhow!("{} is empty", path.display()))??;
+                let header: Header = serde_json::from_str(&header)
+                    .with_context(|| format!("{} is not a host call log", path.display()))?;
+                if header.version != VERSION {
+                    return Err(anyhow!(
+                        "Unsupported host call log version {}",
+                        header.version
+                    ));
+                }
+                if header.module_hash != module_hash {
+                    return Err(anyhow!(
+                        "The host calls in {} were recorded with another module",
+                        path.display()
+                    ));
+                }
+                let mut calls = vec![];
+                for (index, line) in lines.enumerate() {
+                    let call = serde_json::from_str(&line?).with_context(|| {
+                        format!("malformed host call #{} in {}", index, path.display())
+                    })?;
+                    calls.push(call);
+                }
+                Ok(Self::Replayer {
+                    calls: calls.into_iter(),
+                    count: 0,
+                })
+            }
+        }
+    }
+
+    fn record(&mut self, call: &HostCall) -> Result<()> {
+        if let Self::Recorder { writer } = self {
+            serde_json::to_writer(&mut *writer, call)?;
+            writeln!(writer)?;
+            // Flush each call to keep the log even if the process exits in a host function
+            writer.flush()?;
+        }
+        Ok(())
+    }
+}
+
+/// Wraps all host functions of the modules to record or replay their calls
+pub fn wrap_host_modules(
+    host_modules: HashMap<String, RawHostModule>,
+    mode: &HostCallMode,
+    module_hash: u64,
+) -> Result<HashMap<String, RawHostModule>> {
+    let log = Rc::new(RefCell::new(HostCallLog::open(mode, module_hash)?));
+    Ok(host_modules
+        .into_iter()
+        .map(|(name, module)| {
+            let module = wrap_host_module(&name, module, &log);
+            (name, module)
+        })
+        .collect())
+}
+
+fn wrap_host_module(
+    module_name: &str,
+    host_module: RawHostModule,
+    log: &Rc<RefCell<HostCallLog>>,
+) -> RawHostModule {
+    host_module
+        .into_iter()
+        .map(|(field, value)| {
+            let value = match value {
+                HostValue::Func(body) => {
+                    HostValue::Func(wrap_func(module_name.to_string(), field.clone(), body, log))
+                }
+                value => value,
+            };
+            (field, value)
+        })
+        .collect()
+}
+
+fn host_error(err: anyhow::Error) -> Trap {
+    Trap::HostFunctionError(err.into())
+}
+
+fn wrap_func(
+    module: String,
+    field: String,
+    body: HostFuncBody,
+    log: &Rc<RefCell<HostCallLog>>,
+) -> HostFuncBody {
+    let log = log.clone();
+    let ty = body.ty().clone();
+    let result_types = ty.results().to_vec();
+    HostFuncBody::new(ty, move |args, results, ctx, store| {
+        let logged_args = args
+            .iter()
+            .map(LoggedValue::new)
+            .collect::<Result<Vec<_>>>()
+            .map_err(host_error)?;
+        let is_recording = matches!(*log.borrow(), HostCallLog::Recorder { .. });
+        if is_recording {
+            // Don't keep the log borrowed while the host function runs
+            let before = ctx.mem.to_vec();
+            let output = body.call_with_context(args, results, ctx, store);
+            let call = HostCall {
+                module: module.clone(),
+                field: field.clone(),
+                args: logged_args,
+                results: results
+                    .iter()
+                    .map(LoggedValue::new)
+                    .collect::<Result<Vec<_>>>()
+                    .map_err(host_error)?,
+                writes: diff_memory(&before, ctx.mem),
+                error: output.as_ref().err().map(HostCallError::new),
+            };
+            log.borrow_mut().record(&call).map_err(host_error)?;
+            return output;
+        }
+        let (expected, index) = match &mut *log.borrow_mut() {
+            HostCallLog::Replayer { calls, count } => {
+                *count += 1;
+                (calls.next(), *count - 1)
+            }
+            HostCallLog::Recorder { .. } => unreachable!(),
+        };
+        let actual = HostCall {
+            module: module.clone(),
+            field: field.clone(),
+            args: logged_args,
+            results: vec![],
+            writes: vec![],
+            error: None,
+        };
+        let expected = expected.ok_or_else(|| {
+            host_error(anyhow!(
+                "Host call #{} {} diverged: the recording has no more calls",
+                index,
+                actual.signature()
+            ))
+        })?;
+        if expected.module != actual.module
+            || expected.field != actual.field
+            || expected.args != actual.args
+        {
+            return Err(host_error(anyhow!(
+                "Host call #{} diverged: expected {} but got {}",
+                index,
+                expected.signature(),
+                actual.signature()
+            )));
+        }
+        for write in expected.writes {
+            let bytes = decode_hex(&write.bytes).map_err(host_error)?;
+            let dst = ctx
+                .mem
+                .get_mut(write.offset..write.offset + bytes.len())
+                .ok_or_else(|| {
+                    host_error(anyhow!(
+                        "Host call #{} writes out of the memory at {:#x}",
+                        index,
+                        write.offset
+                    ))
+                })?;
+            dst.copy_from_slice(&bytes);
+        }
+        if let Some(error) = expected.error {
+            return Err(error.to_trap());
+        }
+        if expected.results.len() != result_types.len() {
+            return Err(host_error(anyhow!(
+                "Host call #{} has {} results but {} are recorded",
+                index,
+                result_types.len(),
+                expected.results.len()
+            )));
+        }
+        for (value, ty) in expected.results.iter().zip(result_types.iter()) {
+            let value = value
+                .to_value(*ty)
+                .ok_or_else(|| host_error(anyhow!("Host call #{} has an invalid result", index)))?;
+            results.push(value);
+        }
+        Ok(())
+    })
+}
+
+/// Collects the byte ranges changed from `before` to `after`
+fn diff_memory(before: &[u8], after: &[u8]) -> Vec<MemoryWrite> {
+    let mut writes = vec![];
+    let mut offset = 0;
+    while offset < after.len() {
+        if before.get(offset) == Some(&after[offset]) {
+            offset += 1;
+            continue;
+        }
+        let start = offset;
+        while offset < after.len() && before.get(offset) != Some(&after[offset]) {
+            offset += 1;
+        }
+        writes.push(MemoryWrite {
+            offset: start,
+            bytes: encode_hex(&after[start..offset]),
+        });
+    }
+    writes
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn decode_hex(hex: &str) -> Result<Vec<u8>> {
+    hex.as_bytes()
+        .chunks(2)
+        .map(|digits| {
+            std::str::from_utf8(digits)
+                .ok()
+                .filter(|digits| digits.len() == 2)
+                .and_then(|digits| u8::from_str_radix(digits, 16).ok())
+                .ok_or_else(|| anyhow!("invalid hex bytes '{}'", hex))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diff_memory() {
+        let before = [0, 1, 2, 3, 4, 5];
+        let after = [0, 9, 9, 3, 4, 7];
+        let writes = diff_memory(&before, &after);
+        assert_eq!(writes.len(), 2);
+        assert_eq!(writes[0].offset, 1);
+        assert_eq!(decode_hex(&writes[0].bytes).unwrap(), vec![9, 9]);
+        assert_eq!(writes[1].offset, 5);
+        assert_eq!(decode_hex(&writes[1].bytes).unwrap(), vec![7]);
+    }
+}
@@ -0,0 +1,227 @@
+//! Source map v3 support for modules without DWARF
+//! See also https://sourcemaps.info/spec.html
+
+use crate::commands::sourcemap::{line_offsets, ColumnType, LineInfo, SourceMap};
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::path::Path;
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RawSourceMap {
+    version: u32,
+    sources: Vec<Option<String>>,
+    source_root: Option<String>,
+    mappings: String,
+}
+
+/// Returns the URL recorded in `sourceMappingURL` custom section
+pub fn source_mapping_url(module: &[u8]) -> Result<Option<String>> {
+    let parser = wasmparser::Parser::new(0);
+    for payload in parser.parse_all(module) {
+        match payload? {
+            wasmparser::Payload::CustomSection(section) if section.name() == "sourceMappingURL" => {
+                let mut reader = wasmparser::BinaryReader::new(section.data());
+                return Ok(Some(reader.read_string()?.to_string()));
+            }
+            _ => continue,
+        }
+    }
+    Ok(None)
+}
+
+pub(crate) fn code_section_offset(module: &[u8]) -> Result<usize> {
+    let parser = wasmparser::Parser::new(0);
+    for payload in parser.parse_all(module) {
+        if let wasmparser::Payload::CodeSectionStart { range, .. } = payload? {
+            return Ok(range.start);
+        }
+    }
+    Err(anyhow!("no code section"))
+}
+
+fn base64_value(byte: u8) -> Result<i64> {
+    let value = match byte {
+        b'A'..=b'Z' => byte - b'A',
+        b'a'..=b'z' => byte - b'a' + 26,
+        b'0'..=b'9' => byte - b'0' + 52,
+        b'+' => 62,
+        b'/' => 63,
+        _ => return Err(anyhow!("invalid base64 character '{}'", byte as char)),
+    };
+    Ok(value as i64)
+}
+
+/// Decodes a Base64 VLQ encoded segment into its fields
+fn decode_segment(segment: &str) -> Result<Vec<i64>> {
+    let mut fields = vec![];
+    let mut value = 0;
+    let mut shift = 0;
+    for byte in segment.bytes() {
+        let digit = base64_value(byte)?;
+        value += (digit & 0b11111) << shift;
+        if digit & 0b100000 != 0 {
+            shift += 5;
+            continue;
+        }
+        // The least significant bit is the sign
+        let magnitude = value >> 1;
+        let field = if value & 1 == 1 {
+            -magnitude
+        } else {
+            magnitude
+        };
+        fields.push(field);
+        value = 0;
+        shift = 0;
+    }
+    if shift != 0 {
+        return Err(anyhow!("unterminated VLQ segment '{}'", segment));
+    }
+    Ok(fields)
+}
+
+pub struct JsSourceMap {
+    /// Pairs of code section offset and source location, sorted by offset.
+    /// `None` means the range has no corresponding source.
+    address_sorted_rows: Vec<(usize, Option<LineInfo>)>,
+    directory_map: RefCell<HashMap<String, String>>,
+}
+
+impl JsSourceMap {
+    /// Parses a source map whose generated columns are offsets in the module binary
+    pub fn parse(json: &[u8], module: &[u8], base_dir: Option<&Path>) -> Result<Self> {
+        let raw: RawSourceMap = serde_json::from_slice(json)?;
+        if raw.version != 3 {
+            return Err(anyhow!("unsupported source map version {}", raw.version));
+        }
+        let code_offset = code_section_offset(module)?;
+        let sources = raw
+            .sources
+            .iter()
+            .map(|source| {
+                let source = source.clone().unwrap_or_default();
+                let mut path = match raw.source_root {
+                    Some(ref root) => Path::new(root).join(source),
+                    None => Path::new(&source).to_path_buf(),
+                };
+                if let Some(base_dir) = base_dir {
+                    if path.is_relative() {
+                        path = base_dir.join(path);
+                    }
+                }
+                path.to_string_lossy().to_string()
+            })
+            .collect::<Vec<_>>();
+
+        let mut rows = Vec::new();
+        let (mut source, mut line, mut column) = (0, 0, 0);
+        // Wasm source maps have only one generated line
+        for generated_line in raw.mappings.split(';') {
+            let mut generated_column = 0;
+            for segment in generated_line.split(',').filter(|s| !s.is_empty()) {
+                let fields = decode_segment(segment)?;
+                generated_column += fields[0];
+                let line_info = if fields.len() >= 4 {
+                    source += fields[1];
+                    line += fields[2];
+                    column += fields[3];
+                    let filepath = sources
+                        .get(source as usize)
+                        .ok_or_else(|| anyhow!("invalid source index {}", source))?;
+                    Some(LineInfo {
+                        filepath: filepath.clone(),
+                        line: Some(line as u64 + 1),
+                        column: ColumnType::Column(column as u64 + 1),
+                    })
+                } else {
+                    None
+                };
+                match (generated_column as usize).checked_sub(code_offset) {
+                    Some(offset) => rows.push((offset, line_info)),
+                    None => continue,
+                }
+            }
+        }
+        rows.sort_by_key(|row| row.0);
+        Ok(Self {
+            address_sorted_rows: rows,
+            directory_map: RefCell::new(HashMap::new()),
+        })
+    }
+}
+
+impl JsSourceMap {
+    fn map_directory(&self, mut line_info: LineInfo) -> LineInfo {
+        for (from, to) in self.directory_map.borrow().iter() {
+            line_info.filepath = line_info.filepath.replace(from, to);
+        }
+        line_info
+    }
+}
+
+impl SourceMap for JsSourceMap {
+    fn set_directory_map(&self, from: String, to: String) {
+        self.directory_map.borrow_mut().insert(from, to);
+    }
+    fn find_line_info(&self, offset: usize) -> Option<LineInfo> {
+        let index = match self
+            .address_sorted_rows
+            .binary_search_by_key(&offset, |row| row.0)
+        {
+            Ok(i) => i,
+            Err(i) if i > 0 => i - 1,
+            Err(_) => return None,
+        };
+        let line_info = self.address_sorted_rows[index].1.clone()?;
+        Some(self.map_directory(line_info))
+    }
+    fn find_line_offsets(&self, filepath: &str, line: u64) -> Vec<usize> {
+        let rows = self.address_sorted_rows.iter().map(|(offset, line_info)| {
+            let line_info = line_info.clone().map(|info| self.map_directory(info));
+            (*offset, line_info)
+        });
+        line_offsets(rows, filepath, line)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_segment() {
+        assert_eq!(decode_segment("AAAA").unwrap(), vec![0, 0, 0, 0]);
+        assert_eq!(decode_segment("gGACD").unwrap(), vec![96, 0, 1, -1]);
+        assert_eq!(decode_segment("2HAClB").unwrap(), vec![123, 0, 1, -18]);
+        assert!(decode_segment("g").is_err());
+    }
+
+    #[test]
+    fn test_parse() {
+        // The code section starts at offset 10 of the module
+        let module = b"\0asm\x01\0\0\0\x0a\x01\x00";
+        let json = br#"{
+            "version": 3,
+            "sources": ["a.ts"],
+            "sourceRoot": "src",
+            "mappings": "KAAA,KACE,E"
+        }"#;
+        let sourcemap = JsSourceMap::parse(json, module, Some(Path::new("/maps"))).unwrap();
+        // The mapping before the code section is dropped
+        assert_eq!(sourcemap.address_sorted_rows.len(), 2);
+        let line_info = sourcemap.find_line_info(1).unwrap();
+        assert_eq!(line_info.filepath, "/maps/src/a.ts");
+        assert_eq!(line_info.line, Some(2));
+        assert_eq!(line_info.column, ColumnType::Column(3));
+        assert!(sourcemap.find_line_info(2).is_none());
+        assert_eq!(sourcemap.find_line_offsets("a.ts", 2), vec![0]);
+
+        let json = br#"{"version": 2, "sources": [], "mappings": ""}"#;
+        assert!(JsSourceMap::parse(json, module, None).is_err());
+        let json = br#"{"version": 3, "sources": ["a.ts"], "mappings": "UCAA"}"#;
+        assert!(JsSourceMap::parse(json, module, None).is_err());
+    }
+}
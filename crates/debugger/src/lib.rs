@@ -1,30 +1,193 @@
+mod cli;
 mod commands;
+mod component;
+mod coredump;
+mod coverage;
+mod dap;
 mod debugger;
 mod dwarf;
+mod gdb;
+mod host_calls;
+mod jsmap;
 mod process;
+mod profile;
+mod record;
+mod trace;
 
-use std::{cell::RefCell, rc::Rc};
+use std::{cell::RefCell, collections::HashMap, path::PathBuf, rc::Rc};
 
+pub use cli::{parse_env_var, parse_map_dirs};
 pub use commands::command::CommandContext;
 pub use commands::command::CommandResult;
-pub use commands::debugger::{Debugger, RunResult};
+pub use commands::debugger::{
+    Breakpoint, Debugger, OutputPrinter, RunResult, StepStyle, Watchpoint,
+};
+pub use commands::wasi::parse_fault;
+pub use coverage::{CoverageReport, FunctionCoverage};
+pub use dap::{serve_dap, serve_dap_stdio, serve_dap_tcp};
 pub use debugger::MainDebugger;
+pub use gdb::serve_gdb_remote;
+pub use host_calls::HostCallMode;
 pub use linefeed;
 pub use process::Interactive;
 pub use process::Process;
+pub use profile::{FunctionProfile, ProfileReport};
+pub use trace::TraceOptions;
+pub use wasminspect_wasi::{
+    DeterministicOptions, FaultOptions, ListenAddress, OutputTarget, VfsOptions, VfsSource,
+};
 
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, Context, Result};
 use commands::command;
 use log::warn;
 
+/// Debug info of a module, which the command context holds
+type DebugInfo = (
+    Box<dyn commands::sourcemap::SourceMap>,
+    Rc<dyn commands::subroutine::SubroutineMap>,
+);
+
+fn dwarf_debug_info(buffer: &[u8]) -> Result<DebugInfo> {
+    let debug_info = dwarf::transform_dwarf(buffer)?;
+    Ok((
+        Box::new(debug_info.sourcemap),
+        Rc::new(debug_info.subroutine),
+    ))
+}
+
+fn source_map_debug_info(module: &[u8], source_map: &std::path::Path) -> Result<DebugInfo> {
+    let json = std::fs::read(source_map)
+        .with_context(|| format!("failed to read source map {}", source_map.display()))?;
+    let sourcemap = jsmap::JsSourceMap::parse(&json, module, source_map.parent())?;
+    Ok((
+        Box::new(sourcemap),
+        Rc::new(commands::subroutine::EmptySubroutineMap::new()),
+    ))
+}
+
+fn set_debug_info(context: &mut commands::command::CommandContext, debug_info: DebugInfo) {
+    let (sourcemap, subroutine) = debug_info;
+    context.sourcemap = sourcemap;
+    context.subroutine = subroutine;
+}
+
 pub fn try_load_dwarf(
     buffer: &[u8],
     context: &mut commands::command::CommandContext,
 ) -> Result<()> {
-    use dwarf::transform_dwarf;
-    let debug_info = transform_dwarf(buffer)?;
-    context.sourcemap = Box::new(debug_info.sourcemap);
-    context.subroutine = Box::new(debug_info.subroutine);
+    set_debug_info(context, dwarf_debug_info(buffer)?);
+    Ok(())
+}
+
+/// Load debug info from a separate debug file built for the module
+pub fn try_load_separate_dwarf(
+    module: &[u8],
+    debug_file: &[u8],
+    context: &mut commands::command::CommandContext,
+) -> Result<()> {
+    dwarf::validate_debug_file(module, debug_file)?;
+    try_load_dwarf(debug_file, context)
+}
+
+/// Load source map v3 for the module which doesn't have DWARF
+pub fn try_load_source_map(
+    module: &[u8],
+    source_map: &std::path::Path,
+    context: &mut commands::command::CommandContext,
+) -> Result<()> {
+    set_debug_info(context, source_map_debug_info(module, source_map)?);
+    Ok(())
+}
+
+/// Reads the separate debug file, and checks that it's built for the module
+fn read_debug_file(module: &[u8], debug_file: &std::path::Path) -> Result<Vec<u8>> {
+    let bytes = std::fs::read(debug_file)
+        .with_context(|| format!("failed to read debug file {}", debug_file.display()))?;
+    dwarf::validate_debug_file(module, &bytes)?;
+    Ok(bytes)
+}
+
+/// Resolve a path recorded in the module relative to the module's directory
+fn resolve_module_relative_path(module_input: &ModuleInput, path: &str) -> PathBuf {
+    let path = PathBuf::from(path.trim_start_matches("file://"));
+    match module_input.dirname {
+        Some(ref dirname) if path.is_relative() => dirname.join(path),
+        _ => path,
+    }
+}
+
+/// Loads the debug info of `module` in the input. The debug file and the source map
+/// given by the input are used only for the main module.
+fn module_debug_info(
+    module_input: &ModuleInput,
+    module: &[u8],
+    is_main: bool,
+) -> Result<DebugInfo> {
+    if let Some(source_map) = module_input.source_map.as_ref().filter(|_| is_main) {
+        return source_map_debug_info(module, source_map);
+    }
+    let result = match module_input.debug_file {
+        Some(ref debug_file) if is_main => dwarf_debug_info(&read_debug_file(module, debug_file)?),
+        _ => match dwarf::external_debug_info(module)? {
+            // Fallback to the DWARF in the module if the recorded debug file is unavailable
+            Some(path) => {
+                let debug_file = resolve_module_relative_path(module_input, &path);
+                match read_debug_file(module, &debug_file) {
+                    Ok(bytes) => dwarf_debug_info(&bytes),
+                    Err(err) => {
+                        warn!("Failed to load external debug info: {}", err);
+                        dwarf_debug_info(module)
+                    }
+                }
+            }
+            None => dwarf_debug_info(module),
+        },
+    };
+    // Fallback to source map if the module doesn't have DWARF
+    if result.is_err() {
+        if let Some(url) = jsmap::source_mapping_url(module)? {
+            let source_map = resolve_module_relative_path(module_input, &url);
+            return source_map_debug_info(module, &source_map);
+        }
+    }
+    result
+}
+
+/// Loads the debug info of the input. The debug info of each core module of a component
+/// is loaded and looked up by the offsets of the modules in the component.
+fn load_debug_info(
+    module_input: &ModuleInput,
+    context: &mut commands::command::CommandContext,
+) -> Result<()> {
+    if !wasminspect_vm::is_component(&module_input.bytes) {
+        let debug_info = module_debug_info(module_input, &module_input.bytes, true)?;
+        set_debug_info(context, debug_info);
+        return Ok(());
+    }
+    let modules = wasminspect_vm::core_modules(&module_input.bytes)?;
+    let main_ordinal = debugger::main_core_module(&modules);
+    let mut sourcemaps = vec![];
+    let mut subroutines = vec![];
+    for (ordinal, (offset, module)) in modules.into_iter().enumerate() {
+        let (sourcemap, subroutine) =
+            match module_debug_info(module_input, module, Some(ordinal) == main_ordinal) {
+                Ok(debug_info) => debug_info,
+                Err(err) => {
+                    warn!(
+                        "Failed to load debug info of core module #{}: {}",
+                        ordinal, err
+                    );
+                    (
+                        Box::new(commands::sourcemap::EmptySourceMap::new()) as _,
+                        Rc::new(commands::subroutine::EmptySubroutineMap::new()) as _,
+                    )
+                }
+            };
+        sourcemaps.push((offset, sourcemap));
+        subroutines.push((offset, subroutine));
+    }
+    context.sourcemap = Box::new(component::ComponentSourceMap::new(sourcemaps));
+    context.subroutine = Rc::new(component::ComponentSubroutineMap::new(subroutines));
     Ok(())
 }
 
@@ -36,11 +199,25 @@ impl commands::debugger::OutputPrinter for ConsolePrinter {
     fn eprintln(&self, output: &str) {
         eprintln!("{}", output);
     }
+    fn confirm(&self, prompt: &str) -> bool {
+        use std::io::Write;
+        print!("{} [Y/n] ", prompt);
+        std::io::stdout().flush().unwrap();
+        let mut input = String::new();
+        std::io::stdin().read_line(&mut input).unwrap();
+        input == "Y\n" || input == "y\n"
+    }
 }
 
 pub struct ModuleInput {
     pub bytes: Vec<u8>,
     pub basename: String,
+    /// The directory containing the module, used to resolve `external_debug_info`
+    pub dirname: Option<PathBuf>,
+    /// The separate debug file which has debug info of the module
+    pub debug_file: Option<PathBuf>,
+    /// The source map v3 file used instead of DWARF
+    pub source_map: Option<PathBuf>,
 }
 
 pub fn start_debugger(
@@ -54,13 +231,13 @@ pub fn start_debugger(
     let mut debugger = debugger::MainDebugger::new(preopen_dirs, envs)?;
     let mut context = commands::command::CommandContext {
         sourcemap: Box::new(commands::sourcemap::EmptySourceMap::new()),
-        subroutine: Box::new(commands::subroutine::EmptySubroutineMap::new()),
+        subroutine: Rc::new(commands::subroutine::EmptySubroutineMap::new()),
         printer: Box::new(ConsolePrinter {}),
     };
 
     if let Some(ref module_input) = module_input {
         debugger.load_main_module(&module_input.bytes, module_input.basename.clone())?;
-        match try_load_dwarf(&module_input.bytes, &mut context) {
+        match load_debug_info(module_input, &mut context) {
             Ok(_) => (),
             Err(err) => {
                 warn!("Failed to load dwarf info: {}", err);
@@ -75,6 +252,7 @@ pub fn start_debugger(
             Box::new(commands::memory::MemoryCommand::new()),
             Box::new(commands::stack::StackCommand::new()),
             Box::new(commands::breakpoint::BreakpointCommand::new()),
+            Box::new(commands::watchpoint::WatchpointCommand::new()),
             Box::new(commands::disassemble::DisassembleCommand::new()),
             Box::new(commands::expression::ExpressionCommand::new()),
             Box::new(commands::global::GlobalCommand::new()),
@@ -82,6 +260,9 @@ pub fn start_debugger(
             Box::new(commands::frame::FrameCommand::new()),
             Box::new(commands::settings::SettingsCommand::new()),
             Box::new(commands::process::ProcessCommand::new()),
+            Box::new(commands::profile::ProfileCommand::new()),
+            Box::new(commands::target::TargetCommand::new()),
+            Box::new(commands::wasi::WasiCommand::new()),
         ],
         vec![
             Box::new(commands::run::RunCommand::new()),
@@ -91,13 +272,114 @@ pub fn start_debugger(
     Ok((process, context))
 }
 
+/// Run the entry function of the module with WASI and collect the line coverage.
+/// The coverage is reported even if the process traps or is interrupted.
+pub fn collect_coverage(
+    module_input: ModuleInput,
+    preopen_dirs: Vec<(String, String)>,
+    envs: Vec<(String, String)>,
+    wasi_args: &[String],
+) -> Result<CoverageReport> {
+    let (mut process, context) = start_debugger(Some(module_input), preopen_dirs, envs)?;
+    let debugger = &mut process.debugger;
+    debugger.enable_coverage();
+    debugger.instantiate(HashMap::new(), Some(wasi_args))?;
+    run_to_end(debugger);
+    debugger.coverage_report(context.sourcemap.as_ref())
+}
+
+/// Run the entry function of the module with WASI and count the executed instructions.
+/// The profile is reported even if the process traps or is interrupted.
+pub fn profile_module(
+    module_input: ModuleInput,
+    preopen_dirs: Vec<(String, String)>,
+    envs: Vec<(String, String)>,
+    wasi_args: &[String],
+) -> Result<ProfileReport> {
+    let (mut process, context) = start_debugger(Some(module_input), preopen_dirs, envs)?;
+    let debugger = &mut process.debugger;
+    debugger.start_profiling();
+    debugger.instantiate(HashMap::new(), Some(wasi_args))?;
+    run_to_end(debugger);
+    debugger.profile(context.sourcemap.as_ref())
+}
+
+fn run_to_end(debugger: &mut debugger::MainDebugger) {
+    match debugger.run(None, vec![]) {
+        Ok(RunResult::Finish(_)) | Ok(RunResult::Exit(_)) => {}
+        Ok(RunResult::Breakpoint) | Ok(RunResult::Exiting(_)) => warn!("Process is interrupted"),
+        Err(err) => warn!("Process stopped with error: {}", err),
+    }
+}
+
+/// Options of the debugger session given from the command line
+#[derive(Default)]
+pub struct SessionOptions {
+    /// Write a coredump to the file when the process traps
+    pub coredump_on_trap: Option<PathBuf>,
+    /// Inspect the coredump of the main module instead of launching the process
+    pub core: Option<PathBuf>,
+    /// Resume the process from the snapshot of the main module
+    pub restore: Option<PathBuf>,
+    /// Record or replay the calls of host functions
+    pub host_calls: Option<HostCallMode>,
+    /// Log calls and returns of functions from the start
+    pub trace: Option<TraceOptions>,
+    /// Show the names and types of traced parameters from DWARF
+    pub trace_dwarf: bool,
+    /// Log every WASI call with the decoded arguments and errno
+    pub trace_wasi: bool,
+    /// Make clocks, randomness and polling of WASI deterministic
+    pub wasi_deterministic: Option<DeterministicOptions>,
+    /// Preopen in-memory directories instead of host directories
+    pub wasi_vfs: Option<VfsOptions>,
+    /// Listeners given to the guest as socket file descriptors
+    pub wasi_listen: Vec<ListenAddress>,
+    /// Errors injected into WASI calls
+    pub wasi_faults: Vec<FaultOptions>,
+}
+
 pub fn run_loop(
     module_input: Option<ModuleInput>,
     init_source: Option<String>,
     preopen_dirs: Vec<(String, String)>,
     envs: Vec<(String, String)>,
+    session: SessionOptions,
 ) -> Result<()> {
-    let (mut process, context) = start_debugger(module_input, preopen_dirs, envs)?;
+    let (mut process, mut context) = start_debugger(module_input, preopen_dirs, envs)?;
+    process.debugger.set_coredump_path(session.coredump_on_trap);
+    let mut opts = process.debugger.get_opts();
+    opts.host_calls = session.host_calls;
+    opts.trace_wasi = session.trace_wasi;
+    opts.wasi_deterministic = session.wasi_deterministic;
+    opts.wasi_vfs = session.wasi_vfs;
+    opts.wasi_listen = session.wasi_listen;
+    opts.wasi_faults = session.wasi_faults;
+    process.debugger.set_opts(opts);
+    if let Some(trace) = session.trace {
+        let subroutine = if session.trace_dwarf {
+            Some(context.subroutine.clone())
+        } else {
+            None
+        };
+        process.debugger.start_trace(trace, subroutine)?;
+    }
+    if let Some(core) = session.core {
+        let bytes = std::fs::read(&core)
+            .with_context(|| format!("failed to read coredump {}", core.display()))?;
+        let executable_name = process.debugger.load_coredump(&bytes)?;
+        println!(
+            "Core file '{}' of '{}' is loaded. The process can't be resumed.",
+            core.display(),
+            executable_name
+        );
+    }
+    if let Some(snapshot) = session.restore {
+        let bytes = std::fs::read(&snapshot)
+            .with_context(|| format!("failed to read snapshot {}", snapshot.display()))?;
+        process.debugger.restore_snapshot(&bytes)?;
+        println!("Process is restored from '{}'", snapshot.display());
+    }
 
     {
         let is_default = init_source.is_none();
@@ -117,11 +399,13 @@ pub fn run_loop(
             }
         };
         for line in lines {
-            process.dispatch_command(&line, &context)?;
+            process.dispatch_command(&line, &mut context)?;
         }
     }
     let mut interactive = Interactive::new_with_loading_history()?;
     let process = Rc::new(RefCell::new(process));
-    while let CommandResult::ProcessFinish(_) = interactive.run_loop(&context, process.clone())? {}
+    while let CommandResult::ProcessFinish(_) =
+        interactive.run_loop(&mut context, process.clone())?
+    {}
     Ok(())
 }
@@ -32,10 +32,21 @@ impl<D: Debugger> Process<D> {
         })
     }
 
+    /// Expands the alias at the head of the line, if any
+    pub fn expand_alias(&self, line: &str) -> Result<String> {
+        match self.aliases.get(extract_command_name(line)) {
+            Some(alias) => {
+                let args = shell_words::split(line)?;
+                alias.run(args.iter().map(AsRef::as_ref).collect())
+            }
+            None => Ok(line.to_string()),
+        }
+    }
+
     pub fn dispatch_command(
         &mut self,
         line: &str,
-        context: &command::CommandContext,
+        context: &mut command::CommandContext,
     ) -> Result<Option<CommandResult>> {
         let cmd_name = extract_command_name(line);
         let args = shell_words::split(line)?;
@@ -45,7 +56,7 @@ impl<D: Debugger> Process<D> {
             match cmd.run(&mut self.debugger, context, args) {
                 Ok(result) => Ok(result),
                 Err(err) => {
-                    eprintln!("{}", err);
+                    context.printer.eprintln(&format!("{}", err));
                     Ok(None)
                 }
             }
@@ -53,15 +64,17 @@ impl<D: Debugger> Process<D> {
             let line = alias.run(args)?;
             self.dispatch_command(&line, context)
         } else if cmd_name == "help" {
-            println!("Available commands:");
+            context.printer.println("Available commands:");
             for command in self.commands.values() {
-                println!("  {} -- {}", command.name(), command.description());
+                let output = format!("  {} -- {}", command.name(), command.description());
+                context.printer.println(&output);
             }
             Ok(None)
         } else if cfg!(feature = "remote-api") && cmd_name == "start-server" {
             Ok(Some(CommandResult::Exit))
         } else {
-            eprintln!("'{}' is not a valid command.", cmd_name);
+            let output = format!("'{}' is not a valid command.", cmd_name);
+            context.printer.eprintln(&output);
             Ok(None)
         }
     }
@@ -103,7 +116,7 @@ impl Interactive {
     }
     pub fn run_step<D: Debugger>(
         &mut self,
-        context: &command::CommandContext,
+        context: &mut command::CommandContext,
         process: Rc<RefCell<Process<D>>>,
         last_line: &mut Option<String>,
         timeout: Option<Duration>,
@@ -127,7 +140,7 @@ impl Interactive {
 
     pub fn run_loop<D: Debugger>(
         &mut self,
-        context: &command::CommandContext,
+        context: &mut command::CommandContext,
         process: Rc<RefCell<Process<D>>>,
     ) -> Result<CommandResult> {
         let mut last_line: Option<String> = None;
@@ -0,0 +1,209 @@
+//! Instruction counting profiler.
+//!
+//! Each executed instruction is attributed to the call stack at the time, so the counts
+//! are exact and deterministic unlike sampling profilers. Call stacks are interned and
+//! only taken again when the depth of the stack or the current function changes.
+
+use crate::commands::sourcemap::SourceMap;
+use crate::commands::symbol::format_symbol;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::{self, Write};
+use wasminspect_vm::{ExecutableFuncAddr, Executor, Store};
+
+pub struct Profiler {
+    /// Call stacks from the outermost frame
+    stacks: Vec<Vec<ExecutableFuncAddr>>,
+    stack_ids: HashMap<Vec<ExecutableFuncAddr>, usize>,
+    /// Self instruction counts by stack id
+    counts: Vec<u64>,
+    /// Execution counts by code offset of the instruction
+    inst_counts: HashMap<usize, u64>,
+    current: Option<(usize, usize)>,
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        Self {
+            stacks: vec![],
+            stack_ids: HashMap::new(),
+            counts: vec![],
+            inst_counts: HashMap::new(),
+            current: None,
+        }
+    }
+
+    /// Counts the instruction to be executed next
+    pub fn step(&mut self, executor: &Executor, store: &Store) {
+        let depth = executor.stack.frame_depth();
+        let stack_id = match self.current {
+            Some((current_depth, stack_id))
+                if current_depth == depth
+                    && self.stacks[stack_id].last() == Some(&executor.pc.exec_addr()) =>
+            {
+                stack_id
+            }
+            _ => {
+                let stack = executor
+                    .stack
+                    .peek_frames()
+                    .iter()
+                    .map(|frame| frame.exec_addr)
+                    .collect::<Vec<_>>();
+                let stack_id = self.intern(stack);
+                self.current = Some((depth, stack_id));
+                stack_id
+            }
+        };
+        self.counts[stack_id] += 1;
+
+        let inst = store
+            .func_global(executor.pc.exec_addr())
+            .defined()
+            .and_then(|func| func.instructions().get(executor.pc.inst_index().0 as usize));
+        if let Some(inst) = inst {
+            *self.inst_counts.entry(inst.offset).or_insert(0) += 1;
+        }
+    }
+
+    fn intern(&mut self, stack: Vec<ExecutableFuncAddr>) -> usize {
+        if let Some(id) = self.stack_ids.get(&stack) {
+            return *id;
+        }
+        let id = self.stacks.len();
+        self.stacks.push(stack.clone());
+        self.stack_ids.insert(stack, id);
+        self.counts.push(0);
+        id
+    }
+
+    pub fn report(&self, store: &Store, sourcemap: &dyn SourceMap) -> ProfileReport {
+        let name = |addr: &ExecutableFuncAddr| store.func_global(*addr).name().clone();
+        let mut stacks = vec![];
+        let mut functions = HashMap::<String, FunctionProfile>::new();
+        for (stack, count) in self.stacks.iter().zip(self.counts.iter()) {
+            if *count == 0 {
+                continue;
+            }
+            let names = stack.iter().map(name).collect::<Vec<_>>();
+            // Count recursive calls only once for the inclusive cost
+            let mut seen = HashSet::new();
+            for name in &names {
+                if seen.insert(name) {
+                    let function = functions.entry(name.clone()).or_default();
+                    function.name = name.clone();
+                    function.total += count;
+                }
+            }
+            if let Some(name) = names.last() {
+                functions.get_mut(name).unwrap().self_count += count;
+            }
+            stacks.push((names, *count));
+        }
+        stacks.sort();
+        let mut functions = functions.into_values().collect::<Vec<_>>();
+        functions.sort_by(|a, b| b.self_count.cmp(&a.self_count).then(a.name.cmp(&b.name)));
+
+        let mut lines = BTreeMap::<(String, u64), u64>::new();
+        for (offset, count) in &self.inst_counts {
+            let info = match sourcemap.find_line_info(*offset) {
+                Some(info) => info,
+                None => continue,
+            };
+            if let Some(line) = info.line.filter(|line| *line > 0) {
+                *lines.entry((info.filepath, line)).or_insert(0) += count;
+            }
+        }
+        let mut lines = lines.into_iter().collect::<Vec<_>>();
+        lines.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+
+        ProfileReport {
+            total: self.counts.iter().sum(),
+            functions,
+            stacks,
+            lines,
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct FunctionProfile {
+    pub name: String,
+    /// Instructions executed in the function itself
+    pub self_count: u64,
+    /// Instructions executed in the function and its callees
+    pub total: u64,
+}
+
+pub struct ProfileReport {
+    /// Total number of executed instructions
+    pub total: u64,
+    /// Functions sorted by self cost
+    pub functions: Vec<FunctionProfile>,
+    /// Self costs of call stacks from the outermost function
+    pub stacks: Vec<(Vec<String>, u64)>,
+    /// Source lines sorted by the number of executed instructions
+    pub lines: Vec<((String, u64), u64)>,
+}
+
+impl ProfileReport {
+    fn percent(&self, count: u64) -> f64 {
+        if self.total == 0 {
+            0.0
+        } else {
+            count as f64 * 100.0 / self.total as f64
+        }
+    }
+
+    /// Writes folded stacks which flamegraph tools take
+    pub fn write_folded<W: Write>(&self, output: &mut W, demangle: bool) -> io::Result<()> {
+        for (stack, count) in &self.stacks {
+            let stack = stack
+                .iter()
+                .map(|name| format_symbol(name, demangle))
+                .collect::<Vec<_>>();
+            writeln!(output, "{} {}", stack.join(";"), count)?;
+        }
+        Ok(())
+    }
+
+    /// Writes the top functions sorted by self cost
+    pub fn write_top<W: Write>(
+        &self,
+        output: &mut W,
+        top: usize,
+        demangle: bool,
+    ) -> io::Result<()> {
+        writeln!(
+            output,
+            "{:>12} {:>7} {:>12} {:>7}  Function",
+            "Self", "%", "Total", "%"
+        )?;
+        for function in self.functions.iter().take(top) {
+            writeln!(
+                output,
+                "{:>12} {:>6.2}% {:>12} {:>6.2}%  {}",
+                function.self_count,
+                self.percent(function.self_count),
+                function.total,
+                self.percent(function.total),
+                format_symbol(&function.name, demangle)
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Writes the source lines which execute the most instructions
+    pub fn write_lines<W: Write>(&self, output: &mut W, top: usize) -> io::Result<()> {
+        for ((filepath, line), count) in self.lines.iter().take(top) {
+            writeln!(
+                output,
+                "{:>12} {:>6.2}%  {}:{}",
+                count,
+                self.percent(*count),
+                filepath,
+                line
+            )?;
+        }
+        Ok(())
+    }
+}
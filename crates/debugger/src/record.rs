@@ -0,0 +1,407 @@
+//! Execution history to rewind the process for reverse execution.
+//!
+//! Each recorded step keeps the executor state before the instruction and the writes
+//! made by the instruction. The whole store state is saved at periodic checkpoints, and
+//! rewinding restores the nearest checkpoint and replays the logged writes up to the
+//! target step. Host functions may write anywhere, so a checkpoint is also taken after
+//! every call which doesn't enter a defined function. Checkpoints cover all the module
+//! instances given to the history, and writes are applied to the module executing the step.
+
+use std::collections::VecDeque;
+use std::rc::Rc;
+use wasminspect_vm::{
+    Executor, GlobalAddr, InstructionKind, MemoryAddr, ModuleIndex, RefVal, Store, TableAddr,
+    WasmValue,
+};
+
+/// Default memory limit of the recorded history in bytes
+pub const DEFAULT_MAX_MEMORY: usize = 256 * 1024 * 1024;
+
+/// Maximum number of steps between checkpoints to bound the cost of rewinding
+const CHECKPOINT_INTERVAL: usize = 10_000;
+
+/// Memory snapshots are split into chunks to share unchanged ones between checkpoints
+const CHUNK_SIZE: usize = 0x1000;
+
+struct MemorySnapshot {
+    chunks: Vec<Rc<[u8]>>,
+}
+
+struct Checkpoint {
+    memories: Vec<MemorySnapshot>,
+    globals: Vec<WasmValue>,
+    tables: Vec<Vec<RefVal>>,
+}
+
+enum Write {
+    Memory { addr: usize, bytes: Vec<u8> },
+    MemorySize { pages: usize },
+    Global { index: usize, value: WasmValue },
+    Table { index: usize, elements: Vec<RefVal> },
+}
+
+struct Step {
+    /// The executor state before the step
+    executor: Executor,
+    /// The store state of each module before the step
+    checkpoint: Option<Vec<(ModuleIndex, Checkpoint)>>,
+    /// Writes to the store made by the step, in order
+    writes: Vec<Write>,
+    /// Estimated bytes used by this record
+    size: usize,
+}
+
+pub struct History {
+    steps: VecDeque<Step>,
+    max_memory: usize,
+    used_memory: usize,
+    steps_since_checkpoint: usize,
+    needs_checkpoint: bool,
+    /// The kind of the instruction being recorded and the frame depth before it
+    current: Option<(InstructionKind, usize)>,
+}
+
+fn frame_depth(executor: &Executor) -> usize {
+    executor.stack.peek_frames().len()
+}
+
+fn executor_size(executor: &Executor) -> usize {
+    let frames = executor.stack.peek_frames();
+    let locals: usize = frames.iter().map(|frame| frame.locals.len()).sum();
+    let values = executor.stack.peek_values().len();
+    std::mem::size_of::<Executor>() + (locals + values) * std::mem::size_of::<WasmValue>()
+}
+
+impl History {
+    pub fn new(max_memory: usize) -> Self {
+        Self {
+            steps: VecDeque::new(),
+            max_memory,
+            used_memory: 0,
+            steps_since_checkpoint: 0,
+            needs_checkpoint: true,
+            current: None,
+        }
+    }
+
+    pub fn set_max_memory(&mut self, max_memory: usize) {
+        self.max_memory = max_memory;
+        self.shrink_to_limit();
+    }
+
+    /// Returns the number of recorded steps. The current state is the one after the last step.
+    pub fn len(&self) -> usize {
+        self.steps.len()
+    }
+
+    /// Returns the executor state before the step
+    pub fn executor(&self, index: usize) -> &Executor {
+        &self.steps[index].executor
+    }
+
+    /// Returns true if the step wrote any byte in the memory range
+    pub fn writes_memory(&self, index: usize, addr: usize, size: usize) -> bool {
+        self.steps[index].writes.iter().any(|write| match write {
+            Write::Memory { addr: start, bytes } => {
+                *start < addr + size && addr < *start + bytes.len()
+            }
+            _ => false,
+        })
+    }
+
+    /// Records the state before executing the next instruction
+    pub fn begin_step(&mut self, executor: &Executor, store: &Store, modules: &[ModuleIndex]) {
+        let mut size = executor_size(executor);
+        let checkpoint = if self.needs_checkpoint
+            || self.steps.is_empty()
+            || self.steps_since_checkpoint >= CHECKPOINT_INTERVAL
+        {
+            let checkpoint = modules
+                .iter()
+                .enumerate()
+                .map(|(position, module_index)| {
+                    let (checkpoint, checkpoint_size) =
+                        self.checkpoint(store, *module_index, position);
+                    size += checkpoint_size;
+                    (*module_index, checkpoint)
+                })
+                .collect();
+            self.needs_checkpoint = false;
+            self.steps_since_checkpoint = 0;
+            Some(checkpoint)
+        } else {
+            None
+        };
+        self.steps_since_checkpoint += 1;
+        self.current = store
+            .func_global(executor.pc.exec_addr())
+            .defined()
+            .and_then(|func| func.instructions().get(executor.pc.inst_index().0 as usize))
+            .map(|inst| (inst.kind.clone(), frame_depth(executor)));
+        self.used_memory += size;
+        self.steps.push_back(Step {
+            executor: executor.clone(),
+            checkpoint,
+            writes: vec![],
+            size,
+        });
+    }
+
+    /// Logs bytes written to the memory by the current step
+    pub fn log_store(&mut self, addr: usize, bytes: &[u8]) {
+        self.push_write(Write::Memory {
+            addr,
+            bytes: bytes.to_vec(),
+        });
+    }
+
+    /// Logs writes to globals and tables made by the current step
+    pub fn end_step(&mut self, executor: &Executor, store: &Store) {
+        let (kind, depth) = match self.current.take() {
+            Some(current) => current,
+            None => return,
+        };
+        let module_index = match self.steps.back() {
+            Some(step) => step.executor.pc.module_index(),
+            None => return,
+        };
+        match kind {
+            InstructionKind::GlobalSet { global_index } => {
+                let index = global_index as usize;
+                let global = store.global(GlobalAddr::new_unsafe(module_index, index));
+                let value = global.borrow().value();
+                self.push_write(Write::Global { index, value });
+            }
+            InstructionKind::TableSet { table }
+            | InstructionKind::TableGrow { table }
+            | InstructionKind::TableFill { table }
+            | InstructionKind::TableInit { table, .. }
+            | InstructionKind::TableCopy {
+                dst_table: table, ..
+            } => {
+                let index = table as usize;
+                let elements = table_elements(store, TableAddr::new_unsafe(module_index, index));
+                self.push_write(Write::Table { index, elements });
+            }
+            InstructionKind::MemoryGrow { .. } => {
+                let memory = store.memory(MemoryAddr::new_unsafe(module_index, 0));
+                let pages = memory.borrow().page_count();
+                self.push_write(Write::MemorySize { pages });
+            }
+            InstructionKind::Call { .. } | InstructionKind::CallIndirect { .. }
+                if frame_depth(executor) <= depth =>
+            {
+                self.needs_checkpoint = true;
+            }
+            _ => (),
+        }
+        self.shrink_to_limit();
+    }
+
+    /// Restores the store to the state before the step, and returns the executor at that time.
+    /// Steps after it are discarded.
+    pub fn rewind(&mut self, index: usize, store: &Store) -> Executor {
+        let base = (0..=index)
+            .rev()
+            .find(|index| self.steps[*index].checkpoint.is_some())
+            .expect("the first step always has a checkpoint");
+        if let Some(checkpoint) = &self.steps[base].checkpoint {
+            for (module_index, checkpoint) in checkpoint.iter() {
+                restore_checkpoint(checkpoint, store, *module_index);
+            }
+        }
+        for step in self.steps.range(base..index) {
+            let module_index = step.executor.pc.module_index();
+            for write in step.writes.iter() {
+                apply_write(write, store, module_index);
+            }
+        }
+        let executor = self.steps[index].executor.clone();
+        // The restored state can't be derived from the remaining steps if it was a checkpoint
+        self.needs_checkpoint = self.steps[index].checkpoint.is_some();
+        for step in self.steps.drain(index..) {
+            self.used_memory -= step.size;
+        }
+        self.steps_since_checkpoint = index - base;
+        self.current = None;
+        executor
+    }
+
+    fn push_write(&mut self, write: Write) {
+        if let Some(step) = self.steps.back_mut() {
+            let size = match &write {
+                Write::Memory { bytes, .. } => bytes.len(),
+                Write::Table { elements, .. } => elements.len() * std::mem::size_of::<RefVal>(),
+                _ => 0,
+            } + std::mem::size_of::<Write>();
+            step.size += size;
+            step.writes.push(write);
+            self.used_memory += size;
+        }
+    }
+
+    /// Takes a snapshot of the module sharing unchanged memory chunks with the last checkpoint,
+    /// in which the module is at `position`. Returns the snapshot and the bytes newly allocated for it.
+    fn checkpoint(
+        &self,
+        store: &Store,
+        module_index: ModuleIndex,
+        position: usize,
+    ) -> (Checkpoint, usize) {
+        let last = self
+            .steps
+            .iter()
+            .rev()
+            .find_map(|step| step.checkpoint.as_ref())
+            .and_then(|checkpoint| checkpoint.get(position))
+            .map(|(_, checkpoint)| checkpoint);
+        let mut size = 0;
+        let memories = (0..store.memory_count(module_index))
+            .map(|index| {
+                let memory = store.memory(MemoryAddr::new_unsafe(module_index, index));
+                let memory = memory.borrow();
+                let last_chunks = last.and_then(|last| last.memories.get(index));
+                let chunks = memory
+                    .raw_data()
+                    .chunks(CHUNK_SIZE)
+                    .enumerate()
+                    .map(|(chunk_index, bytes)| {
+                        let last_chunk = last_chunks.and_then(|last| last.chunks.get(chunk_index));
+                        match last_chunk {
+                            Some(chunk) if chunk.as_ref() == bytes => chunk.clone(),
+                            _ => {
+                                size += bytes.len();
+                                Rc::from(bytes)
+                            }
+                        }
+                    })
+                    .collect();
+                MemorySnapshot { chunks }
+            })
+            .collect();
+        let globals = (0..store.global_count(module_index))
+            .map(|index| {
+                store
+                    .global(GlobalAddr::new_unsafe(module_index, index))
+                    .borrow()
+                    .value()
+            })
+            .collect::<Vec<_>>();
+        let tables = (0..store.table_count(module_index))
+            .map(|index| table_elements(store, TableAddr::new_unsafe(module_index, index)))
+            .collect::<Vec<_>>();
+        size += globals.len() * std::mem::size_of::<WasmValue>();
+        size += tables
+            .iter()
+            .map(|table| table.len() * std::mem::size_of::<RefVal>())
+            .sum::<usize>();
+        let checkpoint = Checkpoint {
+            memories,
+            globals,
+            tables,
+        };
+        (checkpoint, size)
+    }
+
+    /// Drops the oldest steps until the history fits in the memory limit.
+    /// Steps are dropped by the interval between checkpoints to keep the first step restorable.
+    fn shrink_to_limit(&mut self) {
+        while self.used_memory > self.max_memory {
+            let next_checkpoint = self
+                .steps
+                .iter()
+                .skip(1)
+                .position(|step| step.checkpoint.is_some())
+                .map(|index| index + 1);
+            let next_checkpoint = match next_checkpoint {
+                Some(index) => index,
+                None => {
+                    // Start a new interval to drop the current one later
+                    self.needs_checkpoint = self.steps.len() > 1;
+                    return;
+                }
+            };
+            let dropped = self.steps.drain(..next_checkpoint).collect::<Vec<_>>();
+            for step in dropped.iter() {
+                self.used_memory -= step.size;
+            }
+            // Chunks shared with the dropped checkpoint are now owned by the next one
+            let first = self.steps.front_mut().unwrap();
+            if let (Some(dropped), Some(checkpoint)) = (&dropped[0].checkpoint, &first.checkpoint) {
+                let shared = shared_bytes(dropped, checkpoint);
+                first.size += shared;
+                self.used_memory += shared;
+            }
+        }
+    }
+}
+
+fn shared_bytes(old: &[(ModuleIndex, Checkpoint)], new: &[(ModuleIndex, Checkpoint)]) -> usize {
+    old.iter()
+        .zip(new.iter())
+        .flat_map(|((_, old), (_, new))| old.memories.iter().zip(new.memories.iter()))
+        .map(|(old, new)| {
+            old.chunks
+                .iter()
+                .zip(new.chunks.iter())
+                .filter(|(old, new)| Rc::ptr_eq(old, new))
+                .map(|(chunk, _)| chunk.len())
+                .sum::<usize>()
+        })
+        .sum()
+}
+
+fn table_elements(store: &Store, addr: TableAddr) -> Vec<RefVal> {
+    let table = store.table(addr);
+    let table = table.borrow();
+    (0..table.buffer_len())
+        .filter_map(|index| table.get_at(index).ok())
+        .collect()
+}
+
+fn restore_checkpoint(checkpoint: &Checkpoint, store: &Store, module_index: ModuleIndex) {
+    for (index, snapshot) in checkpoint.memories.iter().enumerate() {
+        let memory = store.memory(MemoryAddr::new_unsafe(module_index, index));
+        let mut memory = memory.borrow_mut();
+        let len: usize = snapshot.chunks.iter().map(|chunk| chunk.len()).sum();
+        memory.set_page_count(len / wasminspect_vm::WASM_PAGE_SIZE);
+        let data = memory.raw_data_mut();
+        for (chunk_index, chunk) in snapshot.chunks.iter().enumerate() {
+            let offset = chunk_index * CHUNK_SIZE;
+            data[offset..offset + chunk.len()].copy_from_slice(chunk);
+        }
+    }
+    for (index, value) in checkpoint.globals.iter().enumerate() {
+        let global = store.global(GlobalAddr::new_unsafe(module_index, index));
+        let mut global = global.borrow_mut();
+        if global.is_mutable() {
+            global.set_value(*value);
+        }
+    }
+    for (index, elements) in checkpoint.tables.iter().enumerate() {
+        let table = store.table(TableAddr::new_unsafe(module_index, index));
+        table.borrow_mut().restore(elements.clone());
+    }
+}
+
+fn apply_write(write: &Write, store: &Store, module_index: ModuleIndex) {
+    match write {
+        Write::Memory { addr, bytes } => {
+            let memory = store.memory(MemoryAddr::new_unsafe(module_index, 0));
+            let mut memory = memory.borrow_mut();
+            memory.raw_data_mut()[*addr..*addr + bytes.len()].copy_from_slice(bytes);
+        }
+        Write::MemorySize { pages } => {
+            let memory = store.memory(MemoryAddr::new_unsafe(module_index, 0));
+            memory.borrow_mut().set_page_count(*pages);
+        }
+        Write::Global { index, value } => {
+            let global = store.global(GlobalAddr::new_unsafe(module_index, *index));
+            global.borrow_mut().set_value(*value);
+        }
+        Write::Table { index, elements } => {
+            let table = store.table(TableAddr::new_unsafe(module_index, *index));
+            table.borrow_mut().restore(elements.clone());
+        }
+    }
+}
@@ -0,0 +1,178 @@
+//! Function call tracing, which logs calls and returns of defined functions
+//! without stopping the process.
+
+use crate::commands::subroutine::SubroutineMap;
+use crate::commands::symbol::format_symbol;
+use anyhow::{Context, Result};
+use regex::Regex;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+use std::rc::Rc;
+use wasminspect_vm::{Executor, NumVal, RefVal, Store, WasmValue};
+use wasmparser::ValType;
+
+#[derive(Default)]
+pub struct TraceOptions {
+    /// Only calls of functions whose name matches are logged
+    pub filter: Option<Regex>,
+    /// Write the log to the file instead of stdout
+    pub output: Option<PathBuf>,
+}
+
+pub struct Tracer {
+    filter: Option<Regex>,
+    output: Box<dyn Write>,
+    subroutine: Option<Rc<dyn SubroutineMap>>,
+    demangle: bool,
+    /// Depth and whether it's logged for each active call since tracing started
+    calls: Vec<(usize, bool)>,
+}
+
+impl Tracer {
+    /// `subroutine` is used to show the names and types of parameters from DWARF
+    pub fn new(
+        options: TraceOptions,
+        subroutine: Option<Rc<dyn SubroutineMap>>,
+        demangle: bool,
+    ) -> Result<Self> {
+        let output: Box<dyn Write> = match options.output {
+            Some(path) => {
+                let file = File::create(&path)
+                    .with_context(|| format!("failed to create {}", path.display()))?;
+                Box::new(BufWriter::new(file))
+            }
+            None => Box::new(std::io::stdout()),
+        };
+        Ok(Self {
+            filter: options.filter,
+            output,
+            subroutine,
+            demangle,
+            calls: vec![],
+        })
+    }
+
+    /// Logs the call just entered. The executor has the frame of the callee.
+    pub fn call(&mut self, name: &str, executor: &Executor, store: &Store) {
+        let depth = executor.stack.frame_depth();
+        // Forget calls left by traps
+        while matches!(self.calls.last(), Some((d, _)) if *d >= depth) {
+            self.calls.pop();
+        }
+        let is_logged = self
+            .filter
+            .as_ref()
+            .map(|filter| filter.is_match(name))
+            .unwrap_or(true);
+        self.calls.push((depth, is_logged));
+        if !is_logged {
+            return;
+        }
+        let func = store.func_global(executor.pc.exec_addr());
+        let params = func.ty().params();
+        let args = match executor.stack.current_frame() {
+            Ok(frame) => &frame.locals[..params.len().min(frame.locals.len())],
+            Err(_) => &[],
+        };
+        let dwarf_params = match (&self.subroutine, func.defined()) {
+            (Some(subroutine), Some(func)) => func
+                .instructions()
+                .first()
+                .and_then(|inst| subroutine.variable_name_list(inst.offset, 0).ok())
+                .map(|vars| {
+                    vars.into_iter()
+                        .filter(|var| var.is_parameter)
+                        .collect::<Vec<_>>()
+                })
+                .filter(|vars| vars.len() == args.len()),
+            _ => None,
+        };
+        let args = match dwarf_params {
+            Some(vars) => vars
+                .iter()
+                .zip(args)
+                .map(|(var, arg)| {
+                    format!("{}: {} = {}", var.name, var.type_name, format_value(arg))
+                })
+                .collect::<Vec<_>>(),
+            None => params
+                .iter()
+                .zip(args)
+                .map(|(ty, arg)| format!("{} {}", type_name(ty), format_value(arg)))
+                .collect::<Vec<_>>(),
+        };
+        let line = format!(
+            "{}-> {}({})",
+            indent(depth),
+            format_symbol(name, self.demangle),
+            args.join(", ")
+        );
+        self.write(&line);
+    }
+
+    /// Logs the return from the function. The executor has returned to the caller.
+    pub fn ret(&mut self, name: &str, results: &[WasmValue], executor: &Executor) {
+        let depth = executor.stack.frame_depth() + 1;
+        let is_logged = match self.calls.last() {
+            Some((d, is_logged)) if *d == depth => *is_logged,
+            // The call was made before tracing started
+            _ => return,
+        };
+        self.calls.pop();
+        if !is_logged {
+            return;
+        }
+        let name = format_symbol(name, self.demangle);
+        let line = if results.is_empty() {
+            format!("{}<- {}", indent(depth), name)
+        } else {
+            let results = results
+                .iter()
+                .map(|value| format!("{} {}", type_name(&value.value_type()), format_value(value)))
+                .collect::<Vec<_>>();
+            format!("{}<- {} = {}", indent(depth), name, results.join(", "))
+        };
+        self.write(&line);
+    }
+
+    fn write(&mut self, line: &str) {
+        if let Err(err) = writeln!(self.output, "{}", line) {
+            log::warn!("Failed to write trace: {}", err);
+        }
+    }
+}
+
+impl Drop for Tracer {
+    fn drop(&mut self) {
+        let _ = self.output.flush();
+    }
+}
+
+fn indent(depth: usize) -> String {
+    "  ".repeat(depth.saturating_sub(1))
+}
+
+fn type_name(ty: &ValType) -> &'static str {
+    match ty {
+        ValType::I32 => "i32",
+        ValType::I64 => "i64",
+        ValType::F32 => "f32",
+        ValType::F64 => "f64",
+        ValType::V128 => "v128",
+        ValType::FuncRef => "funcref",
+        ValType::ExternRef => "externref",
+    }
+}
+
+fn format_value(value: &WasmValue) -> String {
+    match value {
+        WasmValue::Num(NumVal::I32(v)) => v.to_string(),
+        WasmValue::Num(NumVal::I64(v)) => v.to_string(),
+        WasmValue::Num(NumVal::F32(v)) => v.to_float().to_string(),
+        WasmValue::Num(NumVal::F64(v)) => v.to_float().to_string(),
+        WasmValue::Ref(RefVal::NullRef(_)) => "null".to_string(),
+        WasmValue::Ref(RefVal::FuncRef(addr)) => format!("{:?}", addr),
+        WasmValue::Ref(RefVal::ExternRef(v)) => v.to_string(),
+    }
+}
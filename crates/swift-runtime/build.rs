@@ -3,6 +3,10 @@ use std::env;
 static SWIFT_RUNTIME_LIB_DIR: &str = "SWIFT_RUNTIME_LIB_DIR";
 
 fn main() {
+    // The pure Rust demangler is used unless the runtime library is requested
+    if env::var_os("CARGO_FEATURE_NATIVE").is_none() {
+        return;
+    }
     let runtime_lib_dir = match env::var(SWIFT_RUNTIME_LIB_DIR) {
         Ok(val) => val,
         Err(_) => {
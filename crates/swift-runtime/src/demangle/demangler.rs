@@ -0,0 +1,1111 @@
+//! Parser of Swift 5 mangled names into node trees.
+//! The structure follows swift/lib/Demangling/Demangler.cpp
+
+use super::node::*;
+
+const MAX_NUM_WORDS: usize = 26;
+const MAX_REPEAT_COUNT: u64 = 2048;
+
+pub struct Demangler<'a> {
+    text: &'a [u8],
+    pos: usize,
+    node_stack: Vec<Node>,
+    substitutions: Vec<Node>,
+    words: Vec<String>,
+}
+
+fn is_word_start(c: u8) -> bool {
+    !c.is_ascii_digit() && c != b'_' && c != 0
+}
+
+fn is_word_end(c: u8, prev: u8) -> bool {
+    c == b'_' || c == 0 || (!prev.is_ascii_uppercase() && c.is_ascii_uppercase())
+}
+
+pub fn generic_parameter_name(depth: u64, mut index: u64) -> String {
+    let mut name = String::new();
+    loop {
+        name.push((b'A' + (index % 26) as u8) as char);
+        index /= 26;
+        if index == 0 {
+            break;
+        }
+    }
+    if depth != 0 {
+        name.push_str(&depth.to_string());
+    }
+    name
+}
+
+fn swift_type(kind: Kind, name: &str) -> Node {
+    Node::with_children(
+        kind,
+        vec![
+            Node::with_text(Kind::Module, "Swift"),
+            Node::with_text(Kind::Identifier, name),
+        ],
+    )
+    .into_type()
+}
+
+fn standard_substitution(c: u8) -> Option<Node> {
+    let (kind, name) = match c {
+        b'A' => (Kind::Structure, "AutoreleasingUnsafeMutablePointer"),
+        b'a' => (Kind::Structure, "Array"),
+        b'b' => (Kind::Structure, "Bool"),
+        b'D' => (Kind::Structure, "Dictionary"),
+        b'd' => (Kind::Structure, "Double"),
+        b'f' => (Kind::Structure, "Float"),
+        b'h' => (Kind::Structure, "Set"),
+        b'I' => (Kind::Structure, "DefaultIndices"),
+        b'i' => (Kind::Structure, "Int"),
+        b'J' => (Kind::Structure, "Character"),
+        b'N' => (Kind::Structure, "ClosedRange"),
+        b'n' => (Kind::Structure, "Range"),
+        b'O' => (Kind::Structure, "ObjectIdentifier"),
+        b'P' => (Kind::Structure, "UnsafePointer"),
+        b'p' => (Kind::Structure, "UnsafeMutablePointer"),
+        b'R' => (Kind::Structure, "UnsafeBufferPointer"),
+        b'r' => (Kind::Structure, "UnsafeMutableBufferPointer"),
+        b'S' => (Kind::Structure, "String"),
+        b's' => (Kind::Structure, "Substring"),
+        b'u' => (Kind::Structure, "UInt"),
+        b'V' => (Kind::Structure, "UnsafeRawPointer"),
+        b'v' => (Kind::Structure, "UnsafeMutableRawPointer"),
+        b'W' => (Kind::Structure, "UnsafeRawBufferPointer"),
+        b'w' => (Kind::Structure, "UnsafeMutableRawBufferPointer"),
+        b'q' => (Kind::Enum, "Optional"),
+        b'B' => (Kind::Protocol, "BinaryFloatingPoint"),
+        b'E' => (Kind::Protocol, "Encodable"),
+        b'e' => (Kind::Protocol, "Decodable"),
+        b'F' => (Kind::Protocol, "FloatingPoint"),
+        b'G' => (Kind::Protocol, "RandomNumberGenerator"),
+        b'H' => (Kind::Protocol, "Hashable"),
+        b'j' => (Kind::Protocol, "Numeric"),
+        b'K' => (Kind::Protocol, "BidirectionalCollection"),
+        b'k' => (Kind::Protocol, "RandomAccessCollection"),
+        b'L' => (Kind::Protocol, "Comparable"),
+        b'l' => (Kind::Protocol, "Collection"),
+        b'M' => (Kind::Protocol, "MutableCollection"),
+        b'm' => (Kind::Protocol, "RangeReplaceableCollection"),
+        b'Q' => (Kind::Protocol, "Equatable"),
+        b'T' => (Kind::Protocol, "Sequence"),
+        b't' => (Kind::Protocol, "IteratorProtocol"),
+        b'U' => (Kind::Protocol, "UnsignedInteger"),
+        b'X' => (Kind::Protocol, "RangeExpression"),
+        b'x' => (Kind::Protocol, "Strideable"),
+        b'Y' => (Kind::Protocol, "RawRepresentable"),
+        b'y' => (Kind::Protocol, "StringProtocol"),
+        b'Z' => (Kind::Protocol, "SignedInteger"),
+        b'z' => (Kind::Protocol, "BinaryInteger"),
+        _ => return None,
+    };
+    Some(swift_type(kind, name))
+}
+
+impl<'a> Demangler<'a> {
+    pub fn new(text: &'a str) -> Self {
+        Self {
+            text: text.as_bytes(),
+            pos: 0,
+            node_stack: vec![],
+            substitutions: vec![],
+            words: vec![],
+        }
+    }
+
+    /// Parses the whole symbol and returns `Global` node
+    pub fn demangle_symbol(mut self) -> Option<Node> {
+        let prefix_len = ["_$s", "$s", "_$S", "$S"]
+            .iter()
+            .find(|prefix| self.text.starts_with(prefix.as_bytes()))?
+            .len();
+        self.pos = prefix_len;
+
+        while self.pos < self.text.len() {
+            let node = self.demangle_operator()?;
+            self.node_stack.push(node);
+        }
+        if self.node_stack.is_empty() {
+            return None;
+        }
+
+        let mut attrs = vec![];
+        while let Some(attr) = self.pop_node_if(is_function_attr) {
+            attrs.push(attr);
+        }
+        let mut children = vec![];
+        for node in self.node_stack.drain(..) {
+            match node.kind {
+                Kind::Type => children.extend(node.children),
+                _ => children.push(node),
+            }
+        }
+        // Partial apply forwarders take the following nodes as their children
+        for mut attr in attrs.into_iter().rev() {
+            if attr.kind == Kind::PartialApplyForwarder {
+                attr.children.append(&mut children);
+            }
+            children.insert(0, attr);
+        }
+        Some(Node::with_children(Kind::Global, children))
+    }
+
+    fn peek(&self) -> u8 {
+        self.text.get(self.pos).copied().unwrap_or(0)
+    }
+
+    fn next(&mut self) -> u8 {
+        let c = self.peek();
+        if self.pos < self.text.len() {
+            self.pos += 1;
+        }
+        c
+    }
+
+    fn next_if(&mut self, c: u8) -> bool {
+        if self.pos < self.text.len() && self.peek() == c {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn push_back(&mut self) {
+        self.pos -= 1;
+    }
+
+    fn pop_node_if(&mut self, pred: impl Fn(Kind) -> bool) -> Option<Node> {
+        match self.node_stack.last() {
+            Some(node) if pred(node.kind) => self.node_stack.pop(),
+            _ => None,
+        }
+    }
+
+    fn pop_node_of(&mut self, kind: Kind) -> Option<Node> {
+        self.pop_node_if(|k| k == kind)
+    }
+
+    fn pop_type_and_get_child(&mut self) -> Option<Node> {
+        let ty = self.pop_node_of(Kind::Type)?;
+        ty.children.into_iter().next()
+    }
+
+    fn pop_type_and_get_any_generic(&mut self) -> Option<Node> {
+        let child = self.pop_type_and_get_child()?;
+        if is_any_generic(child.kind) {
+            Some(child)
+        } else {
+            None
+        }
+    }
+
+    fn pop_module(&mut self) -> Option<Node> {
+        if let Some(mut ident) = self.pop_node_of(Kind::Identifier) {
+            ident.kind = Kind::Module;
+            return Some(ident);
+        }
+        self.pop_node_of(Kind::Module)
+    }
+
+    fn pop_context(&mut self) -> Option<Node> {
+        if let Some(module) = self.pop_module() {
+            return Some(module);
+        }
+        if let Some(ty) = self.pop_node_of(Kind::Type) {
+            if ty.children.len() != 1 {
+                return None;
+            }
+            let child = ty.children.into_iter().next()?;
+            return if is_context(child.kind) {
+                Some(child)
+            } else {
+                None
+            };
+        }
+        self.pop_node_if(is_context)
+    }
+
+    fn demangle_natural(&mut self) -> Option<u64> {
+        if !self.peek().is_ascii_digit() {
+            return None;
+        }
+        let mut num: u64 = 0;
+        while self.peek().is_ascii_digit() {
+            let digit = (self.next() - b'0') as u64;
+            num = num.checked_mul(10)?.checked_add(digit)?;
+        }
+        Some(num)
+    }
+
+    fn demangle_index(&mut self) -> Option<u64> {
+        if self.next_if(b'_') {
+            return Some(0);
+        }
+        let num = self.demangle_natural()?;
+        if self.next_if(b'_') {
+            Some(num + 1)
+        } else {
+            None
+        }
+    }
+
+    fn demangle_operator(&mut self) -> Option<Node> {
+        let c = self.next();
+        match c {
+            b'A' => self.demangle_multi_substitutions(),
+            b'B' => self.demangle_builtin_type(),
+            b'C' => self.demangle_any_generic_type(Kind::Class),
+            b'D' => {
+                let ty = self.pop_node_of(Kind::Type)?;
+                Some(Node::with_child(Kind::DynamicSelf, ty).into_type())
+            }
+            b'E' => self.demangle_extension_context(),
+            b'F' => self.demangle_plain_function(),
+            b'G' => self.demangle_bound_generic_type(),
+            b'K' => Some(Node::new(Kind::ThrowsAnnotation)),
+            b'L' => self.demangle_local_identifier(),
+            b'M' => self.demangle_metatype(),
+            b'N' => {
+                let ty = self.pop_node_of(Kind::Type)?;
+                Some(Node::with_child(Kind::TypeMetadata, ty))
+            }
+            b'O' => self.demangle_any_generic_type(Kind::Enum),
+            b'P' => self.demangle_any_generic_type(Kind::Protocol),
+            b'Q' => self.demangle_archetype(),
+            b'R' => self.demangle_generic_requirement(),
+            b'S' => self.demangle_standard_substitution(),
+            b'T' => self.demangle_thunk_or_specialization(),
+            b'V' => self.demangle_any_generic_type(Kind::Structure),
+            b'W' => self.demangle_witness(),
+            b'X' => self.demangle_special_type(),
+            b'Y' => self.demangle_typed_annotation(),
+            b'Z' => {
+                let entity = self.pop_node_if(is_entity)?;
+                Some(Node::with_child(Kind::Static, entity))
+            }
+            b'a' => self.demangle_any_generic_type(Kind::TypeAlias),
+            b'c' => self.pop_function_type(Kind::FunctionType),
+            b'd' => Some(Node::new(Kind::VariadicMarker)),
+            b'f' => self.demangle_function_entity(),
+            b'h' => self.demangle_type_modifier(Kind::Shared),
+            b'i' => self.demangle_subscript(),
+            b'l' => self.demangle_generic_signature(false),
+            b'm' => {
+                let ty = self.pop_node_of(Kind::Type)?;
+                Some(Node::with_child(Kind::Metatype, ty).into_type())
+            }
+            b'n' => self.demangle_type_modifier(Kind::Owned),
+            b'o' => self.demangle_operator_identifier(),
+            b'p' => self.demangle_protocol_list(),
+            b'q' => Some(self.demangle_generic_param_index()?.into_type()),
+            b'r' => self.demangle_generic_signature(true),
+            b's' => Some(Node::with_text(Kind::Module, "Swift")),
+            b't' => self.pop_tuple(),
+            b'u' => {
+                let signature = self.pop_node_of(Kind::DependentGenericSignature)?;
+                let ty = self.pop_node_of(Kind::Type)?;
+                Some(
+                    Node::with_children(Kind::DependentGenericType, vec![signature, ty])
+                        .into_type(),
+                )
+            }
+            b'v' => {
+                let variable = self.demangle_entity(Kind::Variable)?;
+                self.demangle_accessor(variable)
+            }
+            b'x' => Some(dependent_generic_param_type(0, 0).into_type()),
+            b'y' => Some(Node::new(Kind::EmptyList)),
+            b'z' => self.demangle_type_modifier(Kind::InOut),
+            b'_' => Some(Node::new(Kind::FirstElementMarker)),
+            b'.' => {
+                self.push_back();
+                let suffix = String::from_utf8_lossy(&self.text[self.pos..]).to_string();
+                self.pos = self.text.len();
+                Some(Node::with_text(Kind::Suffix, suffix))
+            }
+            0 => None,
+            _ => {
+                self.push_back();
+                self.demangle_identifier()
+            }
+        }
+    }
+
+    fn demangle_identifier(&mut self) -> Option<Node> {
+        let c = self.peek();
+        if !c.is_ascii_digit() {
+            return None;
+        }
+        let mut has_word_substs = false;
+        if c == b'0' {
+            self.next();
+            // Punycode encoded identifiers are not supported
+            if self.peek() == b'0' {
+                return None;
+            }
+            has_word_substs = true;
+        }
+        let mut identifier = String::new();
+        loop {
+            while has_word_substs && self.peek().is_ascii_alphabetic() {
+                let c = self.next();
+                let word_index = if c.is_ascii_lowercase() {
+                    (c - b'a') as usize
+                } else {
+                    has_word_substs = false;
+                    (c - b'A') as usize
+                };
+                identifier.push_str(self.words.get(word_index)?);
+            }
+            if self.next_if(b'0') {
+                break;
+            }
+            let num_chars = self.demangle_natural()? as usize;
+            if num_chars == 0 || self.pos + num_chars > self.text.len() {
+                return None;
+            }
+            let slice = &self.text[self.pos..self.pos + num_chars];
+            identifier.push_str(std::str::from_utf8(slice).ok()?);
+
+            let mut word_start = None;
+            for idx in 0..=slice.len() {
+                let c = slice.get(idx).copied().unwrap_or(0);
+                if let Some(start) = word_start {
+                    if is_word_end(c, slice[idx - 1]) {
+                        if idx - start >= 2 && self.words.len() < MAX_NUM_WORDS {
+                            let word = String::from_utf8_lossy(&slice[start..idx]).to_string();
+                            self.words.push(word);
+                        }
+                        word_start = None;
+                    }
+                }
+                if word_start.is_none() && is_word_start(c) {
+                    word_start = Some(idx);
+                }
+            }
+            self.pos += num_chars;
+            if !has_word_substs {
+                break;
+            }
+        }
+        if identifier.is_empty() {
+            return None;
+        }
+        let ident = Node::with_text(Kind::Identifier, identifier);
+        self.substitutions.push(ident.clone());
+        Some(ident)
+    }
+
+    fn demangle_multi_substitutions(&mut self) -> Option<Node> {
+        let mut repeat_count: Option<u64> = None;
+        loop {
+            let c = self.next();
+            if c.is_ascii_lowercase() {
+                let node = self.push_multi_substitutions(repeat_count, (c - b'a') as usize)?;
+                self.node_stack.push(node);
+                repeat_count = None;
+                continue;
+            }
+            if c.is_ascii_uppercase() {
+                return self.push_multi_substitutions(repeat_count, (c - b'A') as usize);
+            }
+            if c == b'_' {
+                let index = repeat_count? as usize + 27;
+                return self.substitutions.get(index).cloned();
+            }
+            if c == 0 {
+                return None;
+            }
+            self.push_back();
+            repeat_count = Some(self.demangle_natural()?);
+        }
+    }
+
+    fn push_multi_substitutions(
+        &mut self,
+        repeat_count: Option<u64>,
+        index: usize,
+    ) -> Option<Node> {
+        let node = self.substitutions.get(index)?.clone();
+        let repeat_count = repeat_count.unwrap_or(1);
+        if repeat_count > MAX_REPEAT_COUNT {
+            return None;
+        }
+        for _ in 1..repeat_count {
+            self.node_stack.push(node.clone());
+        }
+        Some(node)
+    }
+
+    fn demangle_standard_substitution(&mut self) -> Option<Node> {
+        match self.next() {
+            b'o' => Some(Node::with_text(Kind::Module, "__C_Synthesized")),
+            b'C' => Some(Node::with_text(Kind::Module, "__C")),
+            b'g' => {
+                let ty = self.pop_node_of(Kind::Type)?;
+                let optional = Node::with_children(
+                    Kind::BoundGenericEnum,
+                    vec![
+                        swift_type(Kind::Enum, "Optional"),
+                        Node::with_child(Kind::TypeList, ty),
+                    ],
+                )
+                .into_type();
+                self.substitutions.push(optional.clone());
+                Some(optional)
+            }
+            _ => {
+                self.push_back();
+                let repeat_count = self.demangle_natural().unwrap_or(0);
+                if repeat_count > MAX_REPEAT_COUNT {
+                    return None;
+                }
+                // Second level substitutions for concurrency types are not supported
+                if self.peek() == b'c' {
+                    return None;
+                }
+                let node = standard_substitution(self.next())?;
+                for _ in 1..repeat_count {
+                    self.node_stack.push(node.clone());
+                }
+                Some(node)
+            }
+        }
+    }
+
+    fn demangle_builtin_type(&mut self) -> Option<Node> {
+        let name = match self.next() {
+            b'b' => "Builtin.BridgeObject".to_string(),
+            b'B' => "Builtin.UnsafeValueBuffer".to_string(),
+            b'f' => {
+                let size = self.demangle_index()?.checked_sub(1)?;
+                format!("Builtin.FPIEEE{}", size)
+            }
+            b'i' => {
+                let size = self.demangle_index()?.checked_sub(1)?;
+                format!("Builtin.Int{}", size)
+            }
+            b'I' => "Builtin.IntLiteral".to_string(),
+            b'o' => "Builtin.NativeObject".to_string(),
+            b'O' => "Builtin.UnknownObject".to_string(),
+            b'p' => "Builtin.RawPointer".to_string(),
+            b'w' => "Builtin.Word".to_string(),
+            _ => return None,
+        };
+        let ty = Node::with_text(Kind::BuiltinTypeName, name).into_type();
+        self.substitutions.push(ty.clone());
+        Some(ty)
+    }
+
+    fn demangle_any_generic_type(&mut self, kind: Kind) -> Option<Node> {
+        let name = self.pop_node_if(is_decl_name)?;
+        let context = self.pop_context()?;
+        let ty = Node::with_children(kind, vec![context, name]).into_type();
+        self.substitutions.push(ty.clone());
+        Some(ty)
+    }
+
+    fn demangle_extension_context(&mut self) -> Option<Node> {
+        let signature = self.pop_node_of(Kind::DependentGenericSignature);
+        let module = self.pop_module()?;
+        let ty = self.pop_type_and_get_any_generic()?;
+        let mut extension = Node::with_children(Kind::Extension, vec![module, ty]);
+        if let Some(signature) = signature {
+            extension.children.push(signature);
+        }
+        Some(extension)
+    }
+
+    fn demangle_plain_function(&mut self) -> Option<Node> {
+        let signature = self.pop_node_of(Kind::DependentGenericSignature);
+        let mut ty = self.pop_function_type(Kind::FunctionType)?;
+        let labels = self.pop_function_param_labels(&ty)?;
+        if let Some(signature) = signature {
+            ty = Node::with_children(Kind::DependentGenericType, vec![signature, ty]).into_type();
+        }
+        let name = self.pop_node_if(is_decl_name)?;
+        let context = self.pop_context()?;
+        let mut children = vec![context, name];
+        children.extend(labels);
+        children.push(ty);
+        Some(Node::with_children(Kind::Function, children))
+    }
+
+    fn pop_function_type(&mut self, kind: Kind) -> Option<Node> {
+        let mut func = Node::new(kind);
+        let annotations = [
+            Kind::ThrowsAnnotation,
+            Kind::ConcurrentFunctionType,
+            Kind::AsyncAnnotation,
+        ];
+        for annotation in annotations.iter() {
+            if let Some(node) = self.pop_node_of(*annotation) {
+                func.children.push(node);
+            }
+        }
+        func.children
+            .push(self.pop_function_params(Kind::ArgumentTuple)?);
+        func.children
+            .push(self.pop_function_params(Kind::ReturnType)?);
+        Some(func.into_type())
+    }
+
+    fn pop_function_params(&mut self, kind: Kind) -> Option<Node> {
+        let params = if self.pop_node_of(Kind::EmptyList).is_some() {
+            Node::new(Kind::Tuple).into_type()
+        } else {
+            self.pop_node_of(Kind::Type)?
+        };
+        Some(Node::with_child(kind, params))
+    }
+
+    /// Returns `Some(None)` if the function doesn't have any label
+    fn pop_function_param_labels(&mut self, ty: &Node) -> Option<Option<Node>> {
+        if self.pop_node_of(Kind::EmptyList).is_some() {
+            return Some(Some(Node::new(Kind::LabelList)));
+        }
+        if ty.kind != Kind::Type {
+            return Some(None);
+        }
+        let mut func = ty.child(0)?;
+        if func.kind == Kind::DependentGenericType {
+            func = func.child(1)?.child(0)?;
+        }
+        if func.kind != Kind::FunctionType && func.kind != Kind::NoEscapeFunctionType {
+            return Some(None);
+        }
+        let params = func
+            .child_of_kind(Kind::ArgumentTuple)?
+            .child(0)?
+            .child(0)?;
+        let num_params = match params.kind {
+            Kind::Tuple => params.children.len(),
+            _ => 1,
+        };
+        if num_params == 0 {
+            return Some(None);
+        }
+        let mut labels = vec![];
+        for _ in 0..num_params {
+            let label =
+                self.pop_node_if(|k| k == Kind::Identifier || k == Kind::FirstElementMarker)?;
+            labels.push(label);
+        }
+        labels.reverse();
+        Some(Some(Node::with_children(Kind::LabelList, labels)))
+    }
+
+    fn pop_tuple(&mut self) -> Option<Node> {
+        let mut tuple = Node::new(Kind::Tuple);
+        if self.pop_node_of(Kind::EmptyList).is_none() {
+            loop {
+                let is_first = self.pop_node_of(Kind::FirstElementMarker).is_some();
+                let mut element = Node::new(Kind::TupleElement);
+                let variadic = self.pop_node_of(Kind::VariadicMarker);
+                if let Some(ident) = self.pop_node_of(Kind::Identifier) {
+                    element
+                        .children
+                        .push(Node::with_text(Kind::TupleElementName, ident.text));
+                }
+                element.children.push(self.pop_node_of(Kind::Type)?);
+                element.children.extend(variadic);
+                tuple.children.push(element);
+                if is_first {
+                    break;
+                }
+            }
+            tuple.children.reverse();
+        }
+        Some(tuple.into_type())
+    }
+
+    fn pop_type_list(&mut self) -> Option<Node> {
+        let mut list = Node::new(Kind::TypeList);
+        if self.pop_node_of(Kind::EmptyList).is_none() {
+            loop {
+                let is_first = self.pop_node_of(Kind::FirstElementMarker).is_some();
+                list.children.push(self.pop_node_of(Kind::Type)?);
+                if is_first {
+                    break;
+                }
+            }
+            list.children.reverse();
+        }
+        Some(list)
+    }
+
+    fn pop_protocol(&mut self) -> Option<Node> {
+        if let Some(ty) = self.pop_node_of(Kind::Type) {
+            return match ty.child(0) {
+                Some(child) if child.kind == Kind::Protocol => Some(ty),
+                _ => None,
+            };
+        }
+        let name = self.pop_node_if(is_decl_name)?;
+        let context = self.pop_context()?;
+        Some(Node::with_children(Kind::Protocol, vec![context, name]).into_type())
+    }
+
+    fn demangle_protocol_list(&mut self) -> Option<Node> {
+        let mut list = Node::new(Kind::TypeList);
+        if self.pop_node_of(Kind::EmptyList).is_none() {
+            loop {
+                let is_first = self.pop_node_of(Kind::FirstElementMarker).is_some();
+                list.children.push(self.pop_protocol()?);
+                if is_first {
+                    break;
+                }
+            }
+            list.children.reverse();
+        }
+        Some(Node::with_child(Kind::ProtocolList, list).into_type())
+    }
+
+    fn demangle_bound_generic_type(&mut self) -> Option<Node> {
+        let mut type_lists = vec![];
+        loop {
+            let mut list = Node::new(Kind::TypeList);
+            while let Some(ty) = self.pop_node_of(Kind::Type) {
+                list.children.push(ty);
+            }
+            list.children.reverse();
+            type_lists.push(list);
+            if self.pop_node_of(Kind::EmptyList).is_some() {
+                break;
+            }
+            self.pop_node_of(Kind::FirstElementMarker)?;
+        }
+        let nominal = self.pop_type_and_get_any_generic()?;
+        let bound = demangle_bound_generic_args(nominal, &type_lists, 0)?;
+        let ty = bound.into_type();
+        self.substitutions.push(ty.clone());
+        Some(ty)
+    }
+
+    fn demangle_function_entity(&mut self) -> Option<Node> {
+        enum Args {
+            None,
+            TypeAndMaybePrivateName,
+            TypeAndIndex,
+            Index,
+        }
+        let (args, kind) = match self.next() {
+            b'D' => (Args::None, Kind::Deallocator),
+            b'd' => (Args::None, Kind::Destructor),
+            b'i' => (Args::None, Kind::Initializer),
+            b'C' => (Args::TypeAndMaybePrivateName, Kind::Allocator),
+            b'c' => (Args::TypeAndMaybePrivateName, Kind::Constructor),
+            b'U' => (Args::TypeAndIndex, Kind::ExplicitClosure),
+            b'u' => (Args::TypeAndIndex, Kind::ImplicitClosure),
+            b'A' => (Args::Index, Kind::DefaultArgumentInitializer),
+            _ => return None,
+        };
+        let mut children = vec![];
+        match args {
+            Args::None => {
+                children.push(self.pop_context()?);
+            }
+            Args::TypeAndMaybePrivateName => {
+                let private_name = self.pop_node_of(Kind::PrivateDeclName);
+                let ty = self.pop_node_of(Kind::Type)?;
+                let labels = self.pop_function_param_labels(&ty)?;
+                children.push(self.pop_context()?);
+                children.extend(labels);
+                children.push(ty);
+                children.extend(private_name);
+            }
+            Args::TypeAndIndex => {
+                let index = Node::with_index(Kind::Number, self.demangle_index()?);
+                let ty = self.pop_node_of(Kind::Type)?;
+                children.push(self.pop_context()?);
+                children.push(index);
+                children.push(ty);
+            }
+            Args::Index => {
+                let index = Node::with_index(Kind::Number, self.demangle_index()?);
+                children.push(self.pop_context()?);
+                children.push(index);
+            }
+        }
+        Some(Node::with_children(kind, children))
+    }
+
+    fn demangle_entity(&mut self, kind: Kind) -> Option<Node> {
+        let ty = self.pop_node_of(Kind::Type)?;
+        let labels = self.pop_function_param_labels(&ty)?;
+        let name = self.pop_node_if(is_decl_name)?;
+        let context = self.pop_context()?;
+        let mut children = vec![context, name];
+        children.extend(labels);
+        children.push(ty);
+        Some(Node::with_children(kind, children))
+    }
+
+    fn demangle_subscript(&mut self) -> Option<Node> {
+        let private_name = self.pop_node_of(Kind::PrivateDeclName);
+        let ty = self.pop_node_of(Kind::Type)?;
+        let labels = self.pop_function_param_labels(&ty)?;
+        let context = self.pop_context()?;
+        let mut children = vec![context];
+        children.extend(labels);
+        children.push(ty);
+        children.extend(private_name);
+        self.demangle_accessor(Node::with_children(Kind::Subscript, children))
+    }
+
+    fn demangle_accessor(&mut self, child: Node) -> Option<Node> {
+        let kind = match self.next() {
+            b's' => Kind::Setter,
+            b'g' => Kind::Getter,
+            b'G' => Kind::GlobalGetter,
+            b'w' => Kind::WillSet,
+            b'W' => Kind::DidSet,
+            b'r' => Kind::ReadAccessor,
+            b'M' => Kind::ModifyAccessor,
+            b'a' => match self.next() {
+                b'o' => Kind::NativeOwningMutableAddressor,
+                b'u' => Kind::UnsafeMutableAddressor,
+                _ => return None,
+            },
+            // Pseudo-accessor referring to the variable/subscript itself
+            b'p' => return Some(child),
+            _ => return None,
+        };
+        Some(Node::with_child(kind, child))
+    }
+
+    fn demangle_local_identifier(&mut self) -> Option<Node> {
+        if self.next_if(b'L') {
+            let discriminator = self.pop_node_of(Kind::Identifier)?;
+            let name = self.pop_node_if(is_decl_name)?;
+            return Some(Node::with_children(
+                Kind::PrivateDeclName,
+                vec![discriminator, name],
+            ));
+        }
+        let discriminator = Node::with_index(Kind::Number, self.demangle_index()?);
+        let name = self.pop_node_if(is_decl_name)?;
+        Some(Node::with_children(
+            Kind::LocalDeclName,
+            vec![discriminator, name],
+        ))
+    }
+
+    fn demangle_operator_identifier(&mut self) -> Option<Node> {
+        const OP_CHAR_TABLE: &[u8] = b"& @/= >    <*!|+?%-~   ^ .";
+        let ident = self.pop_node_of(Kind::Identifier)?;
+        let mut op = String::new();
+        for c in ident.text.chars() {
+            if !c.is_ascii() {
+                // Pass through Unicode characters
+                op.push(c);
+                continue;
+            }
+            if !c.is_ascii_lowercase() {
+                return None;
+            }
+            match OP_CHAR_TABLE[(c as u8 - b'a') as usize] {
+                b' ' => return None,
+                o => op.push(o as char),
+            }
+        }
+        let kind = match self.next() {
+            b'i' => Kind::InfixOperator,
+            b'p' => Kind::PrefixOperator,
+            b'P' => Kind::PostfixOperator,
+            _ => return None,
+        };
+        Some(Node::with_text(kind, op))
+    }
+
+    fn demangle_generic_param_index(&mut self) -> Option<Node> {
+        if self.next_if(b'd') {
+            let depth = self.demangle_index()? + 1;
+            let index = self.demangle_index()?;
+            return Some(dependent_generic_param_type(depth, index));
+        }
+        if self.next_if(b'z') {
+            return Some(dependent_generic_param_type(0, 0));
+        }
+        Some(dependent_generic_param_type(0, self.demangle_index()? + 1))
+    }
+
+    fn demangle_generic_signature(&mut self, has_param_counts: bool) -> Option<Node> {
+        let mut counts = vec![];
+        if has_param_counts {
+            while !self.next_if(b'l') {
+                let count = if self.next_if(b'z') {
+                    0
+                } else {
+                    self.demangle_index()? + 1
+                };
+                counts.push(Node::with_index(Kind::DependentGenericParamCount, count));
+            }
+        } else {
+            counts.push(Node::with_index(Kind::DependentGenericParamCount, 1));
+        }
+        let mut requirements = vec![];
+        while let Some(requirement) = self.pop_node_if(is_requirement) {
+            requirements.push(requirement);
+        }
+        requirements.reverse();
+        counts.extend(requirements);
+        Some(Node::with_children(Kind::DependentGenericSignature, counts))
+    }
+
+    fn demangle_generic_requirement(&mut self) -> Option<Node> {
+        enum TypeKind {
+            Generic,
+            Assoc,
+            Substitution,
+        }
+        enum ConstraintKind {
+            Protocol,
+            BaseClass,
+            SameType,
+        }
+        let (constraint_kind, type_kind) = match self.next() {
+            b'c' => (ConstraintKind::BaseClass, TypeKind::Assoc),
+            b'b' => (ConstraintKind::BaseClass, TypeKind::Generic),
+            b'B' => (ConstraintKind::BaseClass, TypeKind::Substitution),
+            b't' => (ConstraintKind::SameType, TypeKind::Assoc),
+            b's' => (ConstraintKind::SameType, TypeKind::Generic),
+            b'S' => (ConstraintKind::SameType, TypeKind::Substitution),
+            b'p' => (ConstraintKind::Protocol, TypeKind::Assoc),
+            b'Q' => (ConstraintKind::Protocol, TypeKind::Substitution),
+            _ => {
+                self.push_back();
+                (ConstraintKind::Protocol, TypeKind::Generic)
+            }
+        };
+        let constrained = match type_kind {
+            TypeKind::Generic => self.demangle_generic_param_index()?.into_type(),
+            TypeKind::Assoc => {
+                let param = self.demangle_generic_param_index()?;
+                let ty = self.demangle_associated_type_simple(Some(param))?;
+                self.substitutions.push(ty.clone());
+                ty
+            }
+            TypeKind::Substitution => self.pop_node_of(Kind::Type)?,
+        };
+        match constraint_kind {
+            ConstraintKind::Protocol => Some(Node::with_children(
+                Kind::DependentGenericConformanceRequirement,
+                vec![constrained, self.pop_protocol()?],
+            )),
+            ConstraintKind::BaseClass => Some(Node::with_children(
+                Kind::DependentGenericConformanceRequirement,
+                vec![constrained, self.pop_node_of(Kind::Type)?],
+            )),
+            ConstraintKind::SameType => Some(Node::with_children(
+                Kind::DependentGenericSameTypeRequirement,
+                vec![constrained, self.pop_node_of(Kind::Type)?],
+            )),
+        }
+    }
+
+    fn demangle_archetype(&mut self) -> Option<Node> {
+        let param = match self.next() {
+            b'a' => {
+                let ident = self.pop_node_of(Kind::Identifier)?;
+                let base = self.pop_type_and_get_child()?;
+                let ty =
+                    Node::with_children(Kind::AssociatedTypeRef, vec![base, ident]).into_type();
+                self.substitutions.push(ty.clone());
+                return Some(ty);
+            }
+            b'y' => self.demangle_generic_param_index()?,
+            b'z' => dependent_generic_param_type(0, 0),
+            _ => return None,
+        };
+        let ty = self.demangle_associated_type_simple(Some(param))?;
+        self.substitutions.push(ty.clone());
+        Some(ty)
+    }
+
+    fn demangle_associated_type_simple(&mut self, param: Option<Node>) -> Option<Node> {
+        let name = self.pop_assoc_type_name()?;
+        let base = match param {
+            Some(param) => param.into_type(),
+            None => self.pop_node_of(Kind::Type)?,
+        };
+        Some(Node::with_children(Kind::DependentMemberType, vec![base, name]).into_type())
+    }
+
+    fn pop_assoc_type_name(&mut self) -> Option<Node> {
+        let protocol = match self.pop_node_of(Kind::Type) {
+            Some(ty) if ty.child(0)?.kind == Kind::Protocol => Some(ty),
+            Some(_) => return None,
+            None => None,
+        };
+        let mut name = self.pop_node_of(Kind::Identifier)?;
+        name.kind = Kind::DependentAssociatedTypeRef;
+        name.children.extend(protocol);
+        Some(name)
+    }
+
+    fn demangle_type_modifier(&mut self, kind: Kind) -> Option<Node> {
+        let ty = self.pop_type_and_get_child()?;
+        Some(Node::with_child(kind, ty).into_type())
+    }
+
+    fn demangle_typed_annotation(&mut self) -> Option<Node> {
+        match self.next() {
+            b'a' => Some(Node::new(Kind::AsyncAnnotation)),
+            b'b' => Some(Node::new(Kind::ConcurrentFunctionType)),
+            _ => None,
+        }
+    }
+
+    fn demangle_special_type(&mut self) -> Option<Node> {
+        match self.next() {
+            b'E' => self.pop_function_type(Kind::NoEscapeFunctionType),
+            b'A' => self.pop_function_type(Kind::EscapingAutoClosureType),
+            b'f' => self.pop_function_type(Kind::ThinFunctionType),
+            b'K' => self.pop_function_type(Kind::AutoClosureType),
+            b'B' => self.pop_function_type(Kind::ObjCBlock),
+            b'C' => self.pop_function_type(Kind::CFunctionPointer),
+            _ => None,
+        }
+    }
+
+    fn demangle_metatype(&mut self) -> Option<Node> {
+        let kind = match self.next() {
+            b'a' => Kind::TypeMetadataAccessFunction,
+            b'f' => Kind::FullTypeMetadata,
+            b'n' => Kind::NominalTypeDescriptor,
+            b'm' => Kind::Metaclass,
+            b'L' => Kind::TypeMetadataLazyCache,
+            b'r' => Kind::TypeMetadataCompletionFunction,
+            b'I' => Kind::TypeMetadataInstantiationCache,
+            b'P' => Kind::GenericTypeMetadataPattern,
+            b'p' => {
+                let protocol = self.pop_protocol()?;
+                return Some(Node::with_child(Kind::ProtocolDescriptor, protocol));
+            }
+            _ => return None,
+        };
+        let ty = self.pop_node_of(Kind::Type)?;
+        Some(Node::with_child(kind, ty))
+    }
+
+    fn demangle_witness(&mut self) -> Option<Node> {
+        match self.next() {
+            b'V' => {
+                let ty = self.pop_node_of(Kind::Type)?;
+                Some(Node::with_child(Kind::ValueWitnessTable, ty))
+            }
+            b'P' => {
+                let conformance = self.pop_protocol_conformance()?;
+                Some(Node::with_child(Kind::ProtocolWitnessTable, conformance))
+            }
+            _ => None,
+        }
+    }
+
+    fn pop_protocol_conformance(&mut self) -> Option<Node> {
+        let signature = self.pop_node_of(Kind::DependentGenericSignature);
+        let module = self.pop_module()?;
+        let protocol = self.pop_protocol()?;
+        let mut ty = self.pop_node_of(Kind::Type)?;
+        if let Some(signature) = signature {
+            ty = Node::with_children(Kind::DependentGenericType, vec![signature, ty]).into_type();
+        }
+        Some(Node::with_children(
+            Kind::ProtocolConformance,
+            vec![ty, protocol, module],
+        ))
+    }
+
+    fn demangle_thunk_or_specialization(&mut self) -> Option<Node> {
+        match self.next() {
+            b'W' => {
+                let entity = self.pop_node_if(is_entity)?;
+                let conformance = self.pop_protocol_conformance()?;
+                Some(Node::with_children(
+                    Kind::ProtocolWitness,
+                    vec![conformance, entity],
+                ))
+            }
+            b'm' => Some(Node::new(Kind::MergedFunction)),
+            b'A' => Some(Node::new(Kind::PartialApplyForwarder)),
+            b'j' => {
+                let entity = self.pop_node_if(is_entity)?;
+                Some(Node::with_child(Kind::DispatchThunk, entity))
+            }
+            b'q' => {
+                let entity = self.pop_node_if(is_entity)?;
+                Some(Node::with_child(Kind::MethodDescriptor, entity))
+            }
+            b'g' => {
+                let mut spec = Node::new(Kind::GenericSpecialization);
+                if self.next_if(b'q') {
+                    spec.children.push(Node::new(Kind::IsSerialized));
+                }
+                let pass_id = self.next();
+                if !pass_id.is_ascii_digit() {
+                    return None;
+                }
+                spec.children.push(Node::with_index(
+                    Kind::SpecializationPassID,
+                    (pass_id - b'0') as u64,
+                ));
+                let types = self.pop_type_list()?;
+                for ty in types.children {
+                    spec.children
+                        .push(Node::with_child(Kind::GenericSpecializationParam, ty));
+                }
+                Some(spec)
+            }
+            _ => None,
+        }
+    }
+}
+
+fn dependent_generic_param_type(depth: u64, index: u64) -> Node {
+    let mut param = Node::with_text(
+        Kind::DependentGenericParamType,
+        generic_parameter_name(depth, index),
+    );
+    param.children = vec![
+        Node::with_index(Kind::Number, depth),
+        Node::with_index(Kind::Number, index),
+    ];
+    param
+}
+
+/// Applies generic arguments to the nominal type and its parent contexts.
+/// `type_lists` are ordered from the innermost type.
+fn demangle_bound_generic_args(nominal: Node, type_lists: &[Node], index: usize) -> Option<Node> {
+    let args = type_lists.get(index)?.clone();
+    let mut nominal = nominal;
+    let next_index = index + 1;
+    if next_index < type_lists.len() {
+        let context = nominal.children.first()?.clone();
+        let bound_parent = if context.kind == Kind::Extension {
+            let mut children = vec![
+                context.child(0)?.clone(),
+                demangle_bound_generic_args(context.child(1)?.clone(), type_lists, next_index)?,
+            ];
+            children.extend(context.child(2).cloned());
+            Node::with_children(Kind::Extension, children)
+        } else {
+            demangle_bound_generic_args(context, type_lists, next_index)?
+        };
+        nominal.children[0] = bound_parent;
+    }
+    if args.children.is_empty() {
+        return Some(nominal);
+    }
+    let kind = match nominal.kind {
+        Kind::Class => Kind::BoundGenericClass,
+        Kind::Protocol => Kind::BoundGenericProtocol,
+        Kind::Structure => Kind::BoundGenericStructure,
+        Kind::Enum => Kind::BoundGenericEnum,
+        Kind::TypeAlias => Kind::BoundGenericTypeAlias,
+        _ => return None,
+    };
+    Some(Node::with_children(kind, vec![nominal.into_type(), args]))
+}
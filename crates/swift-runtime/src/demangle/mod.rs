@@ -0,0 +1,162 @@
+//! Pure Rust implementation of Swift demangler used when the Swift runtime
+//! library is not available. It covers the common subset of the mangling
+//! grammar documented in swift/docs/ABI/Mangling.rst
+
+mod demangler;
+mod node;
+mod printer;
+
+/// Demangles a Swift 5 symbol in the same format as `swift_demangle`.
+/// Returns `None` if the symbol is malformed or uses unsupported manglings.
+pub fn demangle(symbol: &str) -> Option<String> {
+    let node = demangler::Demangler::new(symbol).demangle_symbol()?;
+    printer::print(&node)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::demangle;
+
+    #[test]
+    fn test_swiftwasm_symbols() {
+        let corpus = [
+            ("$sSi", "Swift.Int"),
+            ("$s4main3fooyyF", "main.foo() -> ()"),
+            ("$s4main3fooyySiF", "main.foo(Swift.Int) -> ()"),
+            ("$s4main3foo1xySi_tF", "main.foo(x: Swift.Int) -> ()"),
+            (
+                "$s4main3add1a1bS2i_SitF",
+                "main.add(a: Swift.Int, b: Swift.Int) -> Swift.Int",
+            ),
+            (
+                "$ss5print_9separator10terminatoryypd_S2StF",
+                "Swift.print(_: Any..., separator: Swift.String, terminator: Swift.String) -> ()",
+            ),
+            ("$sSS5countSivg", "Swift.String.count.getter : Swift.Int"),
+            (
+                "$sSi1poiyS2i_SitFZ",
+                "static Swift.Int.+ infix(Swift.Int, Swift.Int) -> Swift.Int",
+            ),
+            ("$sSa6appendyyxnF", "Swift.Array.append(__owned A) -> ()"),
+            (
+                "$sSayxSicig",
+                "Swift.Array.subscript.getter : (Swift.Int) -> A",
+            ),
+            ("$s4main3fooyyKF", "main.foo() throws -> ()"),
+            ("$s4main3fooyyYaKF", "main.foo() async throws -> ()"),
+            (
+                "$s4main1xSivau",
+                "main.x.unsafeMutableAddressor : Swift.Int",
+            ),
+            // nominal types
+            ("$s4main1SVN", "type metadata for main.S"),
+            ("$s4main1SVMn", "nominal type descriptor for main.S"),
+            ("$s4main1CCMa", "type metadata accessor for main.C"),
+            ("$s4main1EOMf", "full type metadata for main.E"),
+            ("$s4main1PMp", "protocol descriptor for main.P"),
+            (
+                "$s4main5OuterV5InnerVN",
+                "type metadata for main.Outer.Inner",
+            ),
+            ("$s4main1CCACycfC", "main.C.__allocating_init() -> main.C"),
+            (
+                "$s4main1SV1xACSi_tcfC",
+                "main.S.init(x: Swift.Int) -> main.S",
+            ),
+            ("$s4main1CCfD", "main.C.__deallocating_deinit"),
+            ("$s4main1CCfd", "main.C.deinit"),
+            ("$s4main1SV5valueSivs", "main.S.value.setter : Swift.Int"),
+            // generics
+            ("$sSaySiGN", "type metadata for Swift.Array<Swift.Int>"),
+            (
+                "$sSDySSSiGN",
+                "type metadata for Swift.Dictionary<Swift.String, Swift.Int>",
+            ),
+            ("$sSSSgN", "type metadata for Swift.Optional<Swift.String>"),
+            ("$s4main8identityyxxlF", "main.identity<A>(A) -> A"),
+            (
+                "$s4main5equalySbx_xtSQRzlF",
+                "main.equal<A where A: Swift.Equatable>(A, A) -> Swift.Bool",
+            ),
+            (
+                "$s4main8identityyxxlFSi_Tg5",
+                "generic specialization <Swift.Int> of main.identity<A>(A) -> A",
+            ),
+            // extensions
+            (
+                "$sSi4mainE6squareSiyF",
+                "(extension in main):Swift.Int.square() -> Swift.Int",
+            ),
+            // closures
+            (
+                "$s4main3fooyyFyyXEfU_",
+                "closure #1 () -> () in main.foo() -> ()",
+            ),
+            (
+                "$s4main3fooyyFS2icfU0_",
+                "closure #2 (Swift.Int) -> Swift.Int in main.foo() -> ()",
+            ),
+            (
+                "$s4main3fooyyFyycfU_TA",
+                "partial apply forwarder for closure #1 () -> () in main.foo() -> ()",
+            ),
+            // private declarations
+            (
+                "$s4main3foo33_0123456789ABCDEF0123456789ABCDEFLLyyF",
+                "main.(foo in _0123456789ABCDEF0123456789ABCDEF)() -> ()",
+            ),
+            // word substitutions
+            (
+                "$s4main8MyStructV0C4TypeVN",
+                "type metadata for main.MyStruct.StructType",
+            ),
+            ("$s4main3fooyyFTm", "merged main.foo() -> ()"),
+            // standard library symbols linked into SwiftWasm binaries
+            ("$sSiN", "type metadata for Swift.Int"),
+            ("$sSSN", "type metadata for Swift.String"),
+            ("$sSa12_endMutationyyF", "Swift.Array._endMutation() -> ()"),
+            (
+                "$sSi22_builtinIntegerLiteralSiBI_tcfC",
+                "Swift.Int.init(_builtinIntegerLiteral: Builtin.IntLiteral) -> Swift.Int",
+            ),
+            (
+                "$sSS21_builtinStringLiteral17utf8CodeUnitCount7isASCIISSBp_BwBi1_tcfC",
+                "Swift.String.init(_builtinStringLiteral: Builtin.RawPointer, utf8CodeUnitCount: Builtin.Word, isASCII: Builtin.Int1) -> Swift.String",
+            ),
+            (
+                "$ss27_allocateUninitializedArrayySayxG_BptBwlF",
+                "Swift._allocateUninitializedArray<A>(Builtin.Word) -> (Swift.Array<A>, Builtin.RawPointer)",
+            ),
+            (
+                "$ss26DefaultStringInterpolationV15literalCapacity18interpolationCountABSi_SitcfC",
+                "Swift.DefaultStringInterpolation.init(literalCapacity: Swift.Int, interpolationCount: Swift.Int) -> Swift.DefaultStringInterpolation",
+            ),
+            (
+                "$ss26DefaultStringInterpolationV13appendLiteralyySSF",
+                "Swift.DefaultStringInterpolation.appendLiteral(Swift.String) -> ()",
+            ),
+            (
+                "$ss26DefaultStringInterpolationV06appendC0yyxlF",
+                "Swift.DefaultStringInterpolation.appendInterpolation<A>(A) -> ()",
+            ),
+            (
+                "$sSS19stringInterpolationSSs013DefaultStringB0V_tcfC",
+                "Swift.String.init(stringInterpolation: Swift.DefaultStringInterpolation) -> Swift.String",
+            ),
+            (
+                "$ss17_assertionFailure__4file4line5flagss5NeverOs12StaticStringV_A2HSus6UInt32VtF",
+                "Swift._assertionFailure(_: Swift.StaticString, _: Swift.StaticString, file: Swift.StaticString, line: Swift.UInt, flags: Swift.UInt32) -> Swift.Never",
+            ),
+        ];
+        for (mangled, expected) in corpus.iter() {
+            assert_eq!(demangle(mangled).as_deref(), Some(*expected), "{}", mangled);
+        }
+    }
+
+    #[test]
+    fn test_malformed_symbols() {
+        for mangled in ["$s", "$s4mai", "_ZN4main3fooE", "$s4main3fooyyFXX"].iter() {
+            assert_eq!(demangle(mangled), None, "{}", mangled);
+        }
+    }
+}
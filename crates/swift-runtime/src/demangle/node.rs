@@ -0,0 +1,233 @@
+/// Subset of node kinds defined in swift/Demangling/DemangleNodes.def
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Kind {
+    Allocator,
+    ArgumentTuple,
+    AssociatedTypeRef,
+    AsyncAnnotation,
+    AutoClosureType,
+    BoundGenericClass,
+    BoundGenericEnum,
+    BoundGenericProtocol,
+    BoundGenericStructure,
+    BoundGenericTypeAlias,
+    BuiltinTypeName,
+    CFunctionPointer,
+    Class,
+    ConcurrentFunctionType,
+    Constructor,
+    Deallocator,
+    DefaultArgumentInitializer,
+    DependentAssociatedTypeRef,
+    DependentGenericConformanceRequirement,
+    DependentGenericParamCount,
+    DependentGenericParamType,
+    DependentGenericSameTypeRequirement,
+    DependentGenericSignature,
+    DependentGenericType,
+    DependentMemberType,
+    Destructor,
+    DidSet,
+    DispatchThunk,
+    DynamicSelf,
+    EmptyList,
+    Enum,
+    EscapingAutoClosureType,
+    ExplicitClosure,
+    Extension,
+    FirstElementMarker,
+    FullTypeMetadata,
+    Function,
+    FunctionType,
+    GenericSpecialization,
+    GenericSpecializationParam,
+    GenericTypeMetadataPattern,
+    Getter,
+    Global,
+    GlobalGetter,
+    Identifier,
+    ImplicitClosure,
+    InOut,
+    InfixOperator,
+    Initializer,
+    IsSerialized,
+    LabelList,
+    LocalDeclName,
+    MergedFunction,
+    Metaclass,
+    Metatype,
+    MethodDescriptor,
+    ModifyAccessor,
+    Module,
+    NativeOwningMutableAddressor,
+    NoEscapeFunctionType,
+    NominalTypeDescriptor,
+    Number,
+    ObjCBlock,
+    Owned,
+    PartialApplyForwarder,
+    PostfixOperator,
+    PrefixOperator,
+    PrivateDeclName,
+    Protocol,
+    ProtocolConformance,
+    ProtocolDescriptor,
+    ProtocolList,
+    ProtocolWitness,
+    ProtocolWitnessTable,
+    ReadAccessor,
+    ReturnType,
+    Setter,
+    Shared,
+    SpecializationPassID,
+    Static,
+    Structure,
+    Subscript,
+    Suffix,
+    ThinFunctionType,
+    ThrowsAnnotation,
+    Tuple,
+    TupleElement,
+    TupleElementName,
+    Type,
+    TypeAlias,
+    TypeList,
+    TypeMetadata,
+    TypeMetadataAccessFunction,
+    TypeMetadataCompletionFunction,
+    TypeMetadataInstantiationCache,
+    TypeMetadataLazyCache,
+    UnsafeMutableAddressor,
+    VariadicMarker,
+    ValueWitnessTable,
+    Variable,
+    WillSet,
+}
+
+#[derive(Clone, Debug)]
+pub struct Node {
+    pub kind: Kind,
+    pub text: String,
+    pub index: u64,
+    pub children: Vec<Node>,
+}
+
+impl Node {
+    pub fn new(kind: Kind) -> Self {
+        Self {
+            kind,
+            text: String::new(),
+            index: 0,
+            children: vec![],
+        }
+    }
+
+    pub fn with_text(kind: Kind, text: impl Into<String>) -> Self {
+        Self {
+            text: text.into(),
+            ..Self::new(kind)
+        }
+    }
+
+    pub fn with_index(kind: Kind, index: u64) -> Self {
+        Self {
+            index,
+            ..Self::new(kind)
+        }
+    }
+
+    pub fn with_children(kind: Kind, children: Vec<Node>) -> Self {
+        Self {
+            children,
+            ..Self::new(kind)
+        }
+    }
+
+    pub fn with_child(kind: Kind, child: Node) -> Self {
+        Self::with_children(kind, vec![child])
+    }
+
+    /// Wraps the node with `Type` node
+    pub fn into_type(self) -> Self {
+        Self::with_child(Kind::Type, self)
+    }
+
+    pub fn child(&self, index: usize) -> Option<&Node> {
+        self.children.get(index)
+    }
+
+    pub fn child_of_kind(&self, kind: Kind) -> Option<&Node> {
+        self.children.iter().find(|child| child.kind == kind)
+    }
+}
+
+pub fn is_context(kind: Kind) -> bool {
+    matches!(
+        kind,
+        Kind::Allocator
+            | Kind::Class
+            | Kind::Constructor
+            | Kind::Deallocator
+            | Kind::DefaultArgumentInitializer
+            | Kind::Destructor
+            | Kind::DidSet
+            | Kind::Enum
+            | Kind::ExplicitClosure
+            | Kind::Extension
+            | Kind::Function
+            | Kind::Getter
+            | Kind::GlobalGetter
+            | Kind::ImplicitClosure
+            | Kind::Initializer
+            | Kind::ModifyAccessor
+            | Kind::Module
+            | Kind::NativeOwningMutableAddressor
+            | Kind::Protocol
+            | Kind::ReadAccessor
+            | Kind::Setter
+            | Kind::Static
+            | Kind::Structure
+            | Kind::Subscript
+            | Kind::TypeAlias
+            | Kind::UnsafeMutableAddressor
+            | Kind::Variable
+            | Kind::WillSet
+    )
+}
+
+pub fn is_entity(kind: Kind) -> bool {
+    kind == Kind::Type || is_context(kind)
+}
+
+pub fn is_decl_name(kind: Kind) -> bool {
+    matches!(
+        kind,
+        Kind::Identifier
+            | Kind::LocalDeclName
+            | Kind::PrivateDeclName
+            | Kind::PrefixOperator
+            | Kind::PostfixOperator
+            | Kind::InfixOperator
+    )
+}
+
+pub fn is_any_generic(kind: Kind) -> bool {
+    matches!(
+        kind,
+        Kind::Structure | Kind::Class | Kind::Enum | Kind::Protocol | Kind::TypeAlias
+    )
+}
+
+pub fn is_requirement(kind: Kind) -> bool {
+    matches!(
+        kind,
+        Kind::DependentGenericConformanceRequirement | Kind::DependentGenericSameTypeRequirement
+    )
+}
+
+pub fn is_function_attr(kind: Kind) -> bool {
+    matches!(
+        kind,
+        Kind::GenericSpecialization | Kind::MergedFunction | Kind::PartialApplyForwarder
+    )
+}
@@ -0,0 +1,686 @@
+//! Printer of demangled node trees.
+//! The output follows swift/lib/Demangling/NodePrinter.cpp with the default options
+
+use super::demangler::generic_parameter_name;
+use super::node::*;
+
+struct Malformed;
+
+/// The remaining context which couldn't be printed in prefix form
+type PrintResult<'n> = Result<Option<&'n Node>, Malformed>;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum TypePrinting {
+    NoType,
+    WithColon,
+    FunctionStyle,
+}
+
+fn child(node: &Node, index: usize) -> Result<&Node, Malformed> {
+    node.child(index).ok_or(Malformed)
+}
+
+fn need_space_before_type(ty: &Node) -> bool {
+    match ty.kind {
+        Kind::Type => ty.child(0).map(need_space_before_type).unwrap_or(true),
+        Kind::FunctionType | Kind::NoEscapeFunctionType | Kind::DependentGenericType => false,
+        _ => true,
+    }
+}
+
+pub fn print(node: &Node) -> Option<String> {
+    let mut printer = Printer { out: String::new() };
+    printer.print(node, false).ok()?;
+    Some(printer.out)
+}
+
+struct Printer {
+    out: String,
+}
+
+impl Printer {
+    fn print_children(&mut self, node: &Node, separator: &str) -> Result<(), Malformed> {
+        for (i, child) in node.children.iter().enumerate() {
+            if i != 0 {
+                self.out.push_str(separator);
+            }
+            self.print(child, false)?;
+        }
+        Ok(())
+    }
+
+    fn print<'n>(&mut self, node: &'n Node, as_prefix_context: bool) -> PrintResult<'n> {
+        use TypePrinting::*;
+        match node.kind {
+            Kind::Global => self.print_children(node, "")?,
+            Kind::Suffix => {
+                self.out
+                    .push_str(&format!(" with unmangled suffix {:?}", node.text));
+            }
+            Kind::Type => {
+                self.print(child(node, 0)?, false)?;
+            }
+            Kind::Module | Kind::Identifier | Kind::BuiltinTypeName => {
+                self.out.push_str(&node.text);
+            }
+            Kind::DependentGenericParamType => self.out.push_str(&node.text),
+            Kind::Structure | Kind::Class | Kind::Enum | Kind::Protocol | Kind::TypeAlias => {
+                return self.print_entity(node, as_prefix_context, NoType, true, "", None, None);
+            }
+            Kind::Function => {
+                return self.print_entity(
+                    node,
+                    as_prefix_context,
+                    FunctionStyle,
+                    true,
+                    "",
+                    None,
+                    None,
+                );
+            }
+            Kind::Variable => {
+                return self.print_entity(node, as_prefix_context, WithColon, true, "", None, None);
+            }
+            Kind::Subscript => {
+                return self.print_entity(
+                    node,
+                    as_prefix_context,
+                    WithColon,
+                    false,
+                    "",
+                    None,
+                    Some("subscript"),
+                );
+            }
+            Kind::Allocator => {
+                let name = if child(node, 0)?.kind == Kind::Class {
+                    "__allocating_init"
+                } else {
+                    "init"
+                };
+                return self.print_entity(
+                    node,
+                    as_prefix_context,
+                    FunctionStyle,
+                    false,
+                    name,
+                    None,
+                    None,
+                );
+            }
+            Kind::Constructor => {
+                return self.print_entity(
+                    node,
+                    as_prefix_context,
+                    FunctionStyle,
+                    false,
+                    "init",
+                    None,
+                    None,
+                );
+            }
+            Kind::Destructor => {
+                return self.print_entity(
+                    node,
+                    as_prefix_context,
+                    NoType,
+                    false,
+                    "deinit",
+                    None,
+                    None,
+                );
+            }
+            Kind::Deallocator => {
+                let name = if child(node, 0)?.kind == Kind::Class {
+                    "__deallocating_deinit"
+                } else {
+                    "deallocator"
+                };
+                return self.print_entity(node, as_prefix_context, NoType, false, name, None, None);
+            }
+            Kind::Initializer => {
+                return self.print_entity(
+                    node,
+                    as_prefix_context,
+                    NoType,
+                    false,
+                    "variable initialization expression",
+                    None,
+                    None,
+                );
+            }
+            Kind::DefaultArgumentInitializer => {
+                let index = child(node, 1)?.index;
+                return self.print_entity(
+                    node,
+                    as_prefix_context,
+                    NoType,
+                    false,
+                    "default argument ",
+                    Some(index),
+                    None,
+                );
+            }
+            Kind::ExplicitClosure | Kind::ImplicitClosure => {
+                let name = if node.kind == Kind::ExplicitClosure {
+                    "closure #"
+                } else {
+                    "implicit closure #"
+                };
+                let index = child(node, 1)?.index + 1;
+                return self.print_entity(
+                    node,
+                    as_prefix_context,
+                    FunctionStyle,
+                    false,
+                    name,
+                    Some(index),
+                    None,
+                );
+            }
+            Kind::Getter | Kind::GlobalGetter => {
+                return self.print_abstract_storage(child(node, 0)?, as_prefix_context, "getter");
+            }
+            Kind::Setter => {
+                return self.print_abstract_storage(child(node, 0)?, as_prefix_context, "setter");
+            }
+            Kind::WillSet => {
+                return self.print_abstract_storage(child(node, 0)?, as_prefix_context, "willset");
+            }
+            Kind::DidSet => {
+                return self.print_abstract_storage(child(node, 0)?, as_prefix_context, "didset");
+            }
+            Kind::ReadAccessor => {
+                return self.print_abstract_storage(child(node, 0)?, as_prefix_context, "read");
+            }
+            Kind::ModifyAccessor => {
+                return self.print_abstract_storage(child(node, 0)?, as_prefix_context, "modify");
+            }
+            Kind::UnsafeMutableAddressor => {
+                return self.print_abstract_storage(
+                    child(node, 0)?,
+                    as_prefix_context,
+                    "unsafeMutableAddressor",
+                );
+            }
+            Kind::NativeOwningMutableAddressor => {
+                return self.print_abstract_storage(
+                    child(node, 0)?,
+                    as_prefix_context,
+                    "nativeOwningMutableAddressor",
+                );
+            }
+            Kind::Static => {
+                self.out.push_str("static ");
+                self.print(child(node, 0)?, false)?;
+            }
+            Kind::Extension => {
+                self.out.push_str("(extension in ");
+                self.print(child(node, 0)?, true)?;
+                self.out.push_str("):");
+                self.print(child(node, 1)?, false)?;
+                if let Some(signature) = node.child(2) {
+                    self.print(signature, false)?;
+                }
+            }
+            Kind::LocalDeclName => {
+                self.print(child(node, 1)?, false)?;
+                self.out
+                    .push_str(&format!(" #{}", child(node, 0)?.index + 1));
+            }
+            Kind::PrivateDeclName => {
+                self.out.push('(');
+                self.print(child(node, 1)?, false)?;
+                self.out.push_str(&format!(" in {})", child(node, 0)?.text));
+            }
+            Kind::InfixOperator => self.out.push_str(&format!("{} infix", node.text)),
+            Kind::PrefixOperator => self.out.push_str(&format!("{} prefix", node.text)),
+            Kind::PostfixOperator => self.out.push_str(&format!("{} postfix", node.text)),
+            Kind::FunctionType | Kind::NoEscapeFunctionType => {
+                self.print_function_type(None, node)?;
+            }
+            Kind::AutoClosureType => {
+                self.out.push_str("@autoclosure ");
+                self.print_function_type(None, node)?;
+            }
+            Kind::EscapingAutoClosureType => {
+                self.out.push_str("@escaping @autoclosure ");
+                self.print_function_type(None, node)?;
+            }
+            Kind::ThinFunctionType => {
+                self.out.push_str("@convention(thin) ");
+                self.print_function_type(None, node)?;
+            }
+            Kind::CFunctionPointer => {
+                self.out.push_str("@convention(c) ");
+                self.print_function_type(None, node)?;
+            }
+            Kind::ObjCBlock => {
+                self.out.push_str("@convention(block) ");
+                self.print_function_type(None, node)?;
+            }
+            Kind::ArgumentTuple => self.print_function_parameters(None, node)?,
+            Kind::ReturnType => {
+                self.out.push_str(" -> ");
+                self.print_children(node, "")?;
+            }
+            Kind::Tuple => {
+                self.out.push('(');
+                self.print_children(node, ", ")?;
+                self.out.push(')');
+            }
+            Kind::TupleElement => {
+                if let Some(label) = node.child_of_kind(Kind::TupleElementName) {
+                    self.out.push_str(&format!("{}: ", label.text));
+                }
+                self.print(node.child_of_kind(Kind::Type).ok_or(Malformed)?, false)?;
+                if node.child_of_kind(Kind::VariadicMarker).is_some() {
+                    self.out.push_str("...");
+                }
+            }
+            Kind::BoundGenericClass
+            | Kind::BoundGenericEnum
+            | Kind::BoundGenericProtocol
+            | Kind::BoundGenericStructure
+            | Kind::BoundGenericTypeAlias => {
+                self.print(child(node, 0)?, false)?;
+                self.out.push('<');
+                self.print_children(child(node, 1)?, ", ")?;
+                self.out.push('>');
+            }
+            Kind::TypeList => self.print_children(node, "")?,
+            Kind::DependentGenericSignature => self.print_generic_signature(node)?,
+            Kind::DependentGenericConformanceRequirement => {
+                self.print(child(node, 0)?, false)?;
+                self.out.push_str(": ");
+                self.print(child(node, 1)?, false)?;
+            }
+            Kind::DependentGenericSameTypeRequirement => {
+                self.print(child(node, 0)?, false)?;
+                self.out.push_str(" == ");
+                self.print(child(node, 1)?, false)?;
+            }
+            Kind::DependentGenericType => {
+                let ty = child(node, 1)?;
+                self.print(child(node, 0)?, false)?;
+                if need_space_before_type(ty) {
+                    self.out.push(' ');
+                }
+                self.print(ty, false)?;
+            }
+            Kind::DependentMemberType => {
+                self.print(child(node, 0)?, false)?;
+                self.out.push('.');
+                self.print(child(node, 1)?, false)?;
+            }
+            Kind::DependentAssociatedTypeRef => {
+                if let Some(protocol) = node.child(0) {
+                    self.print(protocol, false)?;
+                    self.out.push('.');
+                }
+                self.out.push_str(&node.text);
+            }
+            Kind::AssociatedTypeRef => {
+                self.print(child(node, 0)?, false)?;
+                self.out.push_str(&format!(".{}", child(node, 1)?.text));
+            }
+            Kind::ProtocolList => {
+                let protocols = child(node, 0)?;
+                if protocols.children.is_empty() {
+                    self.out.push_str("Any");
+                } else {
+                    self.print_children(protocols, " & ")?;
+                }
+            }
+            Kind::InOut => {
+                self.out.push_str("inout ");
+                self.print(child(node, 0)?, false)?;
+            }
+            Kind::Owned => {
+                self.out.push_str("__owned ");
+                self.print(child(node, 0)?, false)?;
+            }
+            Kind::Shared => {
+                self.out.push_str("__shared ");
+                self.print(child(node, 0)?, false)?;
+            }
+            Kind::Metatype => {
+                self.print(child(node, 0)?, false)?;
+                self.out.push_str(".Type");
+            }
+            Kind::DynamicSelf => self.out.push_str("Self"),
+            Kind::TypeMetadata => self.print_with_prefix("type metadata for ", node)?,
+            Kind::TypeMetadataAccessFunction => {
+                self.print_with_prefix("type metadata accessor for ", node)?
+            }
+            Kind::FullTypeMetadata => self.print_with_prefix("full type metadata for ", node)?,
+            Kind::NominalTypeDescriptor => {
+                self.print_with_prefix("nominal type descriptor for ", node)?
+            }
+            Kind::Metaclass => self.print_with_prefix("metaclass for ", node)?,
+            Kind::TypeMetadataLazyCache => {
+                self.print_with_prefix("lazy cache variable for type metadata for ", node)?
+            }
+            Kind::TypeMetadataCompletionFunction => {
+                self.print_with_prefix("type metadata completion function for ", node)?
+            }
+            Kind::TypeMetadataInstantiationCache => {
+                self.print_with_prefix("type metadata instantiation cache for ", node)?
+            }
+            Kind::GenericTypeMetadataPattern => {
+                self.print_with_prefix("generic type metadata pattern for ", node)?
+            }
+            Kind::ProtocolDescriptor => self.print_with_prefix("protocol descriptor for ", node)?,
+            Kind::ValueWitnessTable => self.print_with_prefix("value witness table for ", node)?,
+            Kind::ProtocolWitnessTable => {
+                self.print_with_prefix("protocol witness table for ", node)?
+            }
+            Kind::DispatchThunk => self.print_with_prefix("dispatch thunk of ", node)?,
+            Kind::MethodDescriptor => self.print_with_prefix("method descriptor for ", node)?,
+            Kind::ProtocolConformance => {
+                self.print(child(node, 0)?, false)?;
+                self.out.push_str(" : ");
+                self.print(child(node, 1)?, false)?;
+                self.out.push_str(" in ");
+                self.print(child(node, 2)?, false)?;
+            }
+            Kind::ProtocolWitness => {
+                self.out.push_str("protocol witness for ");
+                self.print(child(node, 1)?, false)?;
+                self.out.push_str(" in conformance ");
+                self.print(child(node, 0)?, false)?;
+            }
+            Kind::MergedFunction => self.out.push_str("merged "),
+            Kind::PartialApplyForwarder => {
+                self.out.push_str("partial apply forwarder");
+                if !node.children.is_empty() {
+                    self.out.push_str(" for ");
+                    self.print_children(node, "")?;
+                }
+            }
+            Kind::GenericSpecialization => {
+                self.out.push_str("generic specialization <");
+                let mut separator = "";
+                for child in node.children.iter() {
+                    match child.kind {
+                        Kind::SpecializationPassID => continue,
+                        Kind::IsSerialized => {}
+                        _ if child.children.is_empty() => continue,
+                        _ => {}
+                    }
+                    self.out.push_str(separator);
+                    separator = ", ";
+                    self.print(child, false)?;
+                }
+                self.out.push_str("> of ");
+            }
+            Kind::GenericSpecializationParam => {
+                self.print(child(node, 0)?, false)?;
+                for (i, requirement) in node.children.iter().enumerate().skip(1) {
+                    self.out.push_str(if i == 1 { " with " } else { " and " });
+                    self.print(requirement, false)?;
+                }
+            }
+            Kind::IsSerialized => self.out.push_str("serialized"),
+            Kind::LabelList => {}
+            _ => return Err(Malformed),
+        }
+        Ok(None)
+    }
+
+    fn print_with_prefix(&mut self, prefix: &str, node: &Node) -> Result<(), Malformed> {
+        self.out.push_str(prefix);
+        self.print(child(node, 0)?, false)?;
+        Ok(())
+    }
+
+    fn print_abstract_storage<'n>(
+        &mut self,
+        node: &'n Node,
+        as_prefix_context: bool,
+        extra_name: &str,
+    ) -> PrintResult<'n> {
+        match node.kind {
+            Kind::Variable => self.print_entity(
+                node,
+                as_prefix_context,
+                TypePrinting::WithColon,
+                true,
+                extra_name,
+                None,
+                None,
+            ),
+            Kind::Subscript => self.print_entity(
+                node,
+                as_prefix_context,
+                TypePrinting::WithColon,
+                false,
+                extra_name,
+                None,
+                Some("subscript"),
+            ),
+            _ => Err(Malformed),
+        }
+    }
+
+    /// Prints the entity either in prefix form "<context>.<name>" or in suffix
+    /// form "<name> in <context>"
+    #[allow(clippy::too_many_arguments)]
+    fn print_entity<'n>(
+        &mut self,
+        entity: &'n Node,
+        as_prefix_context: bool,
+        mut type_printing: TypePrinting,
+        has_name: bool,
+        extra_name: &str,
+        extra_index: Option<u64>,
+        overwrite_name: Option<&str>,
+    ) -> PrintResult<'n> {
+        let mut extra_name = extra_name.to_string();
+        let mut extra_index = extra_index;
+        let mut multi_word_name = extra_name.contains(' ');
+        // A local name (e.g. MyStruct #1) does not look good if its context is
+        // printed in prefix form
+        if has_name && child(entity, 1)?.kind == Kind::LocalDeclName {
+            multi_word_name = true;
+        }
+        if as_prefix_context && (type_printing != TypePrinting::NoType || multi_word_name) {
+            // If the context has a type to be printed, we can't use the prefix form
+            return Ok(Some(entity));
+        }
+
+        let context = child(entity, 0)?;
+        let postfix_context = if multi_word_name {
+            // If the name contains some spaces we don't print the context now but
+            // later in suffix form
+            Some(context)
+        } else {
+            let current = self.out.len();
+            let postfix_context = self.print(context, true)?;
+            // Was the context printed as prefix?
+            if self.out.len() != current {
+                self.out.push('.');
+            }
+            postfix_context
+        };
+
+        if has_name || overwrite_name.is_some() {
+            if !extra_name.is_empty() && multi_word_name {
+                self.out.push_str(&extra_name);
+                if let Some(index) = extra_index {
+                    self.out.push_str(&index.to_string());
+                }
+                self.out.push_str(" of ");
+                extra_name.clear();
+                extra_index = None;
+            }
+            let current = self.out.len();
+            match overwrite_name {
+                Some(name) => self.out.push_str(name),
+                None => {
+                    let name = child(entity, 1)?;
+                    self.print(name, false)?;
+                }
+            }
+            if self.out.len() != current && !extra_name.is_empty() {
+                self.out.push('.');
+            }
+        }
+        if !extra_name.is_empty() {
+            self.out.push_str(&extra_name);
+            if let Some(index) = extra_index {
+                self.out.push_str(&index.to_string());
+            }
+        }
+        if type_printing != TypePrinting::NoType {
+            let ty = child(entity.child_of_kind(Kind::Type).ok_or(Malformed)?, 0)?;
+            if type_printing == TypePrinting::FunctionStyle {
+                let mut t = ty;
+                while t.kind == Kind::DependentGenericType {
+                    t = child(child(t, 1)?, 0)?;
+                }
+                if !matches!(
+                    t.kind,
+                    Kind::FunctionType
+                        | Kind::NoEscapeFunctionType
+                        | Kind::ThinFunctionType
+                        | Kind::CFunctionPointer
+                ) {
+                    type_printing = TypePrinting::WithColon;
+                }
+            }
+            if type_printing == TypePrinting::WithColon {
+                self.out.push_str(" : ");
+            } else if multi_word_name || need_space_before_type(ty) {
+                self.out.push(' ');
+            }
+            self.print_entity_type(entity, ty)?;
+        }
+        if !as_prefix_context {
+            if let Some(context) = postfix_context {
+                // Print any left over context which couldn't be printed in prefix form
+                match entity.kind {
+                    Kind::DefaultArgumentInitializer | Kind::Initializer => {
+                        self.out.push_str(" of ")
+                    }
+                    _ => self.out.push_str(" in "),
+                }
+                self.print(context, false)?;
+                return Ok(None);
+            }
+        }
+        Ok(postfix_context)
+    }
+
+    fn print_entity_type(&mut self, entity: &Node, ty: &Node) -> Result<(), Malformed> {
+        match entity.child_of_kind(Kind::LabelList) {
+            Some(labels) => {
+                let mut t = ty;
+                while t.kind == Kind::DependentGenericType {
+                    self.print(child(t, 0)?, false)?;
+                    t = child(child(t, 1)?, 0)?;
+                }
+                self.print_function_type(Some(labels), t)
+            }
+            None => self.print(ty, false).map(|_| ()),
+        }
+    }
+
+    fn print_function_type(&mut self, labels: Option<&Node>, node: &Node) -> Result<(), Malformed> {
+        let mut is_throws = false;
+        let mut is_async = false;
+        let mut start = 0;
+        while let Some(annotation) = node.child(start) {
+            match annotation.kind {
+                Kind::ThrowsAnnotation => is_throws = true,
+                Kind::AsyncAnnotation => is_async = true,
+                Kind::ConcurrentFunctionType => self.out.push_str("@Sendable "),
+                _ => break,
+            }
+            start += 1;
+        }
+        self.print_function_parameters(labels, child(node, start)?)?;
+        if is_async {
+            self.out.push_str(" async");
+        }
+        if is_throws {
+            self.out.push_str(" throws");
+        }
+        self.print(child(node, start + 1)?, false)?;
+        Ok(())
+    }
+
+    fn print_function_parameters(
+        &mut self,
+        labels: Option<&Node>,
+        parameter_type: &Node,
+    ) -> Result<(), Malformed> {
+        if parameter_type.kind != Kind::ArgumentTuple {
+            return Err(Malformed);
+        }
+        let parameters = child(child(parameter_type, 0)?, 0)?;
+        if parameters.kind != Kind::Tuple {
+            // Only a single not-named parameter
+            self.out.push('(');
+            self.print(parameters, false)?;
+            self.out.push(')');
+            return Ok(());
+        }
+        let labels = labels.filter(|labels| !labels.children.is_empty());
+        self.out.push('(');
+        for (i, param) in parameters.children.iter().enumerate() {
+            if i != 0 {
+                self.out.push_str(", ");
+            }
+            if let Some(labels) = labels {
+                let label = child(labels, i)?;
+                match label.kind {
+                    Kind::Identifier => self.out.push_str(&label.text),
+                    _ => self.out.push('_'),
+                }
+                self.out.push_str(": ");
+            }
+            self.print(param, false)?;
+        }
+        self.out.push(')');
+        Ok(())
+    }
+
+    fn print_generic_signature(&mut self, node: &Node) -> Result<(), Malformed> {
+        self.out.push('<');
+        let counts = node
+            .children
+            .iter()
+            .take_while(|child| child.kind == Kind::DependentGenericParamCount)
+            .collect::<Vec<_>>();
+        for (depth, count) in counts.iter().enumerate() {
+            if depth != 0 {
+                self.out.push_str("><");
+            }
+            for index in 0..count.index {
+                if index != 0 {
+                    self.out.push_str(", ");
+                }
+                // Limit the number of printed generic parameters for malformed symbols
+                if index >= 128 {
+                    self.out.push_str("...");
+                    break;
+                }
+                self.out
+                    .push_str(&generic_parameter_name(depth as u64, index));
+            }
+        }
+        let requirements = &node.children[counts.len()..];
+        if !requirements.is_empty() {
+            self.out.push_str(" where ");
+            for (i, requirement) in requirements.iter().enumerate() {
+                if i != 0 {
+                    self.out.push_str(", ");
+                }
+                self.print(requirement, false)?;
+            }
+        }
+        self.out.push('>');
+        Ok(())
+    }
+}
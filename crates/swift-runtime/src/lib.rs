@@ -1,5 +1,9 @@
+mod demangle;
+
+#[cfg(feature = "native")]
 use std::ffi;
 
+#[cfg(feature = "native")]
 extern "C" {
     fn swift_demangle(
         mangledName: *const u8,
@@ -14,6 +18,7 @@ extern "C" {
 pub enum DemangleError {
     Utf8Error(std::str::Utf8Error),
     Null,
+    Malformed,
 }
 
 impl std::error::Error for DemangleError {}
@@ -25,11 +30,25 @@ impl std::fmt::Display for DemangleError {
                 write!(f, "Error while interpolating C string: {:?}", err)
             }
             DemangleError::Null => write!(f, "swift_demangle returns null"),
+            DemangleError::Malformed => write!(f, "malformed or unsupported mangled name"),
+        }
+    }
+}
+
+/// Demangles a Swift symbol using the Swift runtime library if it's linked,
+/// or the pure Rust demangler otherwise.
+pub fn demangle(symbol: &str) -> Result<String, DemangleError> {
+    #[cfg(feature = "native")]
+    {
+        if let Ok(demangled) = native_demangle(symbol) {
+            return Ok(demangled.to_string());
         }
     }
+    demangle::demangle(symbol).ok_or(DemangleError::Malformed)
 }
 
-pub fn demangle(symbol: &str) -> Result<&str, DemangleError> {
+#[cfg(feature = "native")]
+fn native_demangle(symbol: &str) -> Result<&str, DemangleError> {
     unsafe {
         let demangled = swift_demangle(
             symbol.as_ptr(),
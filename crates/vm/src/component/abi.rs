@@ -0,0 +1,841 @@
+//! The canonical ABI lowering host functions into core functions.
+//!
+//! Core values are handled as raw bits zero-extended to `u64`, so that the payloads of
+//! variants can be read from and written to the joined flat types without conversion.
+
+use super::types::{ComponentValue, FuncType, ResourceDtor, ResourceType, ValueType};
+use crate::address::{ExecutableFuncAddr, ResolvedMemoryAddr};
+use crate::config::Config;
+use crate::executor::Trap;
+use crate::func::FunctionInstance;
+use crate::host::HostContext;
+use crate::store::Store;
+use crate::value::{NumVal, Value};
+use std::cell::RefCell;
+
+const MAX_FLAT_PARAMS: usize = 16;
+const MAX_FLAT_RESULTS: usize = 1;
+
+pub(crate) fn trap(message: String) -> Trap {
+    Trap::HostFunctionError(message.into())
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum CoreType {
+    I32,
+    I64,
+    F32,
+    F64,
+}
+
+impl CoreType {
+    fn join(self, other: CoreType) -> CoreType {
+        match (self, other) {
+            (a, b) if a == b => a,
+            (CoreType::I32, CoreType::F32) | (CoreType::F32, CoreType::I32) => CoreType::I32,
+            _ => CoreType::I64,
+        }
+    }
+
+    fn value(self, bits: u64) -> Value {
+        match self {
+            CoreType::I32 => Value::I32(bits as u32 as i32),
+            CoreType::I64 => Value::I64(bits as i64),
+            CoreType::F32 => Value::F32(bits as u32),
+            CoreType::F64 => Value::F64(bits),
+        }
+    }
+
+    fn val_type(self) -> wasmparser::ValType {
+        match self {
+            CoreType::I32 => wasmparser::ValType::I32,
+            CoreType::I64 => wasmparser::ValType::I64,
+            CoreType::F32 => wasmparser::ValType::F32,
+            CoreType::F64 => wasmparser::ValType::F64,
+        }
+    }
+}
+
+fn bits(value: &Value) -> u64 {
+    match value {
+        Value::Num(NumVal::I32(v)) => *v as u32 as u64,
+        Value::Num(NumVal::I64(v)) => *v as u64,
+        Value::Num(NumVal::F32(v)) => v.to_bits() as u64,
+        Value::Num(NumVal::F64(v)) => v.to_bits(),
+        Value::Ref(_) => 0,
+    }
+}
+
+fn align_to(offset: u32, align: u32) -> u32 {
+    (offset + align - 1) / align * align
+}
+
+fn discriminant_size(cases: usize) -> u32 {
+    if cases <= 1 << 8 {
+        1
+    } else if cases <= 1 << 16 {
+        2
+    } else {
+        4
+    }
+}
+
+fn flags_size(count: usize) -> u32 {
+    match count {
+        0 => 0,
+        1..=8 => 1,
+        9..=16 => 2,
+        _ => 4 * ((count as u32 + 31) / 32),
+    }
+}
+
+/// Returns the payload types of the cases if the type is a variant in the ABI
+fn cases(ty: &ValueType) -> Option<Vec<Option<&ValueType>>> {
+    Some(match ty {
+        ValueType::Variant(cases) => cases.iter().map(|case| case.as_ref()).collect(),
+        ValueType::Enum(count) => vec![None; *count],
+        ValueType::Option(ty) => vec![None, Some(ty)],
+        ValueType::Result(ok, err) => vec![ok.as_deref(), err.as_deref()],
+        _ => return None,
+    })
+}
+
+fn alignment(ty: &ValueType) -> u32 {
+    use ValueType::*;
+    match ty {
+        Bool | S8 | U8 => 1,
+        S16 | U16 => 2,
+        S32 | U32 | F32 | Char | Own(_) | Borrow(_) | String | List(_) => 4,
+        S64 | U64 | F64 => 8,
+        Flags(count) => flags_size(*count).clamp(1, 4),
+        Record(fields) | Tuple(fields) => fields.iter().map(alignment).max().unwrap_or(1),
+        _ => {
+            let cases = cases(ty).unwrap();
+            cases
+                .iter()
+                .flatten()
+                .map(|ty| alignment(ty))
+                .fold(discriminant_size(cases.len()), u32::max)
+        }
+    }
+}
+
+fn payload_offset(cases: &[Option<&ValueType>]) -> u32 {
+    let max_align = cases.iter().flatten().map(|ty| alignment(ty)).max();
+    align_to(discriminant_size(cases.len()), max_align.unwrap_or(1))
+}
+
+fn size(ty: &ValueType) -> u32 {
+    use ValueType::*;
+    match ty {
+        Bool | S8 | U8 => 1,
+        S16 | U16 => 2,
+        S32 | U32 | F32 | Char | Own(_) | Borrow(_) => 4,
+        S64 | U64 | F64 | String | List(_) => 8,
+        Flags(count) => flags_size(*count),
+        Record(fields) | Tuple(fields) => {
+            let end = fields
+                .iter()
+                .fold(0, |offset, ty| align_to(offset, alignment(ty)) + size(ty));
+            align_to(end, alignment(ty))
+        }
+        _ => {
+            let cases = cases(ty).unwrap();
+            let max_size = cases.iter().flatten().map(|ty| size(ty)).max();
+            align_to(
+                payload_offset(&cases) + max_size.unwrap_or(0),
+                alignment(ty),
+            )
+        }
+    }
+}
+
+fn flatten(ty: &ValueType, out: &mut Vec<CoreType>) {
+    use ValueType::*;
+    match ty {
+        Bool | S8 | U8 | S16 | U16 | S32 | U32 | Char | Own(_) | Borrow(_) | Enum(_) => {
+            out.push(CoreType::I32)
+        }
+        S64 | U64 => out.push(CoreType::I64),
+        F32 => out.push(CoreType::F32),
+        F64 => out.push(CoreType::F64),
+        String | List(_) => out.extend([CoreType::I32, CoreType::I32]),
+        Flags(count) => out.extend(vec![CoreType::I32; (*count + 31) / 32]),
+        Record(fields) | Tuple(fields) => fields.iter().for_each(|ty| flatten(ty, out)),
+        _ => {
+            out.push(CoreType::I32);
+            let mut joined: Vec<CoreType> = Vec::new();
+            for ty in cases(ty).unwrap().into_iter().flatten() {
+                let mut case = Vec::new();
+                flatten(ty, &mut case);
+                for (index, ty) in case.into_iter().enumerate() {
+                    match joined.get_mut(index) {
+                        Some(joined) => *joined = joined.join(ty),
+                        None => joined.push(ty),
+                    }
+                }
+            }
+            out.extend(joined);
+        }
+    }
+}
+
+fn flatten_all(types: &[ValueType]) -> Vec<CoreType> {
+    let mut out = Vec::new();
+    types.iter().for_each(|ty| flatten(ty, &mut out));
+    out
+}
+
+fn flat_len(ty: &ValueType) -> usize {
+    let mut out = Vec::new();
+    flatten(ty, &mut out);
+    out.len()
+}
+
+/// Returns the type of the core function lowered from the function
+pub(crate) fn lowered_func_type(ty: &FuncType) -> wasmparser::FuncType {
+    let mut params = flatten_all(&ty.params);
+    if params.len() > MAX_FLAT_PARAMS {
+        params = vec![CoreType::I32];
+    }
+    let mut results = flatten_all(&ty.results);
+    if results.len() > MAX_FLAT_RESULTS {
+        params.push(CoreType::I32);
+        results = vec![];
+    }
+    wasmparser::FuncType::new(
+        params.into_iter().map(CoreType::val_type),
+        results.into_iter().map(CoreType::val_type),
+    )
+}
+
+struct Handle {
+    resource: ResourceType,
+    rep: u32,
+    own: bool,
+}
+
+/// Handles of resources given to the component
+#[derive(Default)]
+pub(crate) struct HandleTable {
+    /// The index 0 is reserved to be never a valid handle
+    entries: Vec<Option<Handle>>,
+    free: Vec<u32>,
+}
+
+impl HandleTable {
+    pub(crate) fn insert(&mut self, resource: &ResourceType, rep: u32, own: bool) -> u32 {
+        if self.entries.is_empty() {
+            self.entries.push(None);
+        }
+        let handle = Some(Handle {
+            resource: resource.clone(),
+            rep,
+            own,
+        });
+        match self.free.pop() {
+            Some(index) => {
+                self.entries[index as usize] = handle;
+                index
+            }
+            None => {
+                self.entries.push(handle);
+                self.entries.len() as u32 - 1
+            }
+        }
+    }
+
+    fn get(&self, index: u32, resource: &ResourceType) -> Result<&Handle, Trap> {
+        match self.entries.get(index as usize) {
+            Some(Some(handle)) if handle.resource == *resource => Ok(handle),
+            _ => Err(trap(format!(
+                "invalid handle {} of {}",
+                index,
+                resource.name()
+            ))),
+        }
+    }
+
+    pub(crate) fn rep(&self, index: u32, resource: &ResourceType) -> Result<u32, Trap> {
+        self.get(index, resource).map(|handle| handle.rep)
+    }
+
+    /// Removes the handle, and returns the representation if the handle is owned
+    pub(crate) fn remove(
+        &mut self,
+        index: u32,
+        resource: &ResourceType,
+    ) -> Result<Option<u32>, Trap> {
+        let handle = self.get(index, resource)?;
+        let rep = if handle.own { Some(handle.rep) } else { None };
+        self.entries[index as usize] = None;
+        self.free.push(index);
+        Ok(rep)
+    }
+}
+
+/// Calls the core function without the interceptor, used for `realloc` and destructors
+/// called in the middle of a host function
+pub(crate) fn call_core(
+    store: &Store,
+    config: &Config,
+    func: ExecutableFuncAddr,
+    args: Vec<Value>,
+) -> Result<Vec<Value>, Trap> {
+    match store.func_global(func) {
+        FunctionInstance::Defined(defined) => {
+            crate::execute_defined_func(defined, func, args, store, config).map_err(|err| match err
+            {
+                crate::WasmError::ExecutionError(trap) => trap,
+                err => trap(err.to_string()),
+            })
+        }
+        FunctionInstance::Native(host) => {
+            let mut results = Vec::new();
+            let mut ctx = HostContext { mem: &mut [] };
+            host.code()
+                .call_with_context(&args, &mut results, &mut ctx, store)?;
+            Ok(results)
+        }
+    }
+}
+
+/// Runs the destructor of the resource dropped by the component
+pub(crate) fn drop_resource(
+    store: &Store,
+    config: &Config,
+    resource: &ResourceType,
+    rep: u32,
+) -> Result<(), Trap> {
+    match &resource.0.dtor {
+        ResourceDtor::None => Ok(()),
+        ResourceDtor::Host(dtor) => {
+            dtor(rep);
+            Ok(())
+        }
+        ResourceDtor::Core(func) => {
+            call_core(store, config, *func, vec![Value::I32(rep as i32)]).map(|_| ())
+        }
+    }
+}
+
+/// The canonical options and the state used while lowering a call
+pub(crate) struct LowerContext<'a> {
+    pub(crate) store: &'a Store,
+    pub(crate) config: &'a Config,
+    pub(crate) memory: Option<ResolvedMemoryAddr>,
+    pub(crate) realloc: Option<ExecutableFuncAddr>,
+    pub(crate) handles: &'a RefCell<HandleTable>,
+}
+
+impl LowerContext<'_> {
+    fn memory(&self) -> Result<ResolvedMemoryAddr, Trap> {
+        self.memory
+            .ok_or_else(|| trap("canonical option 'memory' is required".to_string()))
+    }
+
+    fn read(&self, ptr: u32, len: u32) -> Result<Vec<u8>, Trap> {
+        let memory = self.store.resolved_memory(self.memory()?);
+        let memory = memory.borrow();
+        memory
+            .raw_data()
+            .get(ptr as usize..ptr as usize + len as usize)
+            .map(|bytes| bytes.to_vec())
+            .ok_or_else(|| trap(format!("out of bounds memory access at {:#x}", ptr)))
+    }
+
+    fn write(&self, ptr: u32, bytes: &[u8]) -> Result<(), Trap> {
+        let memory = self.store.resolved_memory(self.memory()?);
+        let mut memory = memory.borrow_mut();
+        Ok(memory.store(ptr as usize, bytes)?)
+    }
+
+    fn read_uint(&self, ptr: u32, size: u32) -> Result<u64, Trap> {
+        let mut bytes = [0; 8];
+        bytes[..size as usize].copy_from_slice(&self.read(ptr, size)?);
+        Ok(u64::from_le_bytes(bytes))
+    }
+
+    fn write_uint(&self, ptr: u32, size: u32, value: u64) -> Result<(), Trap> {
+        self.write(ptr, &value.to_le_bytes()[..size as usize])
+    }
+
+    fn realloc(&self, align: u32, size: u32) -> Result<u32, Trap> {
+        let realloc = self
+            .realloc
+            .ok_or_else(|| trap("canonical option 'realloc' is required".to_string()))?;
+        let args = [0, 0, align, size].map(|v| Value::I32(v as i32)).to_vec();
+        let results = call_core(self.store, self.config, realloc, args)?;
+        let ptr = match results.first().and_then(|v| v.as_i32()) {
+            Some(ptr) => ptr as u32,
+            None => return Err(trap("realloc returned an invalid value".to_string())),
+        };
+        if ptr % align != 0 {
+            return Err(trap(format!(
+                "realloc returned an unaligned pointer {:#x}",
+                ptr
+            )));
+        }
+        Ok(ptr)
+    }
+
+    fn load_string(&self, ptr: u32, len: u32) -> Result<String, Trap> {
+        String::from_utf8(self.read(ptr, len)?)
+            .map_err(|_| trap(format!("invalid UTF-8 string at {:#x}", ptr)))
+    }
+
+    fn load_list(&self, ptr: u32, len: u32, ty: &ValueType) -> Result<ComponentValue, Trap> {
+        let elem_size = size(ty);
+        let values = (0..len)
+            .map(|index| self.load(ptr + index * elem_size, ty))
+            .collect::<std::result::Result<_, _>>()?;
+        Ok(ComponentValue::List(values))
+    }
+
+    fn lift_own(&self, handle: u32, resource: &ResourceType) -> Result<ComponentValue, Trap> {
+        match self.handles.borrow_mut().remove(handle, resource)? {
+            Some(rep) => Ok(ComponentValue::Own(rep)),
+            None => Err(trap(format!("handle {} is not owned", handle))),
+        }
+    }
+
+    fn lift_borrow(&self, handle: u32, resource: &ResourceType) -> Result<ComponentValue, Trap> {
+        let rep = self.handles.borrow().rep(handle, resource)?;
+        Ok(ComponentValue::Borrow(rep))
+    }
+
+    fn lift_char(value: u64) -> Result<ComponentValue, Trap> {
+        char::from_u32(value as u32)
+            .map(ComponentValue::Char)
+            .ok_or_else(|| trap(format!("invalid char {:#x}", value)))
+    }
+
+    fn lift_case(
+        case: u32,
+        cases: &[Option<&ValueType>],
+        payload: Option<ComponentValue>,
+        ty: &ValueType,
+    ) -> Result<ComponentValue, Trap> {
+        if case as usize >= cases.len() {
+            return Err(trap(format!("invalid discriminant {}", case)));
+        }
+        let payload = payload.map(Box::new);
+        Ok(match ty {
+            ValueType::Variant(_) => ComponentValue::Variant(case, payload),
+            ValueType::Enum(_) => ComponentValue::Enum(case),
+            ValueType::Option(_) => ComponentValue::Option(payload.filter(|_| case == 1)),
+            _ if case == 0 => ComponentValue::Result(Ok(payload)),
+            _ => ComponentValue::Result(Err(payload)),
+        })
+    }
+
+    fn lift_flags(words: impl Iterator<Item = u64>, count: usize) -> ComponentValue {
+        let words = words.collect::<Vec<_>>();
+        ComponentValue::Flags(
+            (0..count)
+                .map(|index| words[index / 32] & (1 << (index % 32)) != 0)
+                .collect(),
+        )
+    }
+
+    /// Reads the value of the type from the memory
+    fn load(&self, ptr: u32, ty: &ValueType) -> Result<ComponentValue, Trap> {
+        use ValueType::*;
+        if ptr % alignment(ty) != 0 {
+            return Err(trap(format!("unaligned pointer {:#x}", ptr)));
+        }
+        Ok(match ty {
+            Bool => ComponentValue::Bool(self.read_uint(ptr, 1)? != 0),
+            S8 => ComponentValue::S8(self.read_uint(ptr, 1)? as i8),
+            U8 => ComponentValue::U8(self.read_uint(ptr, 1)? as u8),
+            S16 => ComponentValue::S16(self.read_uint(ptr, 2)? as i16),
+            U16 => ComponentValue::U16(self.read_uint(ptr, 2)? as u16),
+            S32 => ComponentValue::S32(self.read_uint(ptr, 4)? as i32),
+            U32 => ComponentValue::U32(self.read_uint(ptr, 4)? as u32),
+            S64 => ComponentValue::S64(self.read_uint(ptr, 8)? as i64),
+            U64 => ComponentValue::U64(self.read_uint(ptr, 8)?),
+            F32 => ComponentValue::F32(f32::from_bits(self.read_uint(ptr, 4)? as u32)),
+            F64 => ComponentValue::F64(f64::from_bits(self.read_uint(ptr, 8)?)),
+            Char => Self::lift_char(self.read_uint(ptr, 4)?)?,
+            String => {
+                let data = self.read_uint(ptr, 4)? as u32;
+                let len = self.read_uint(ptr + 4, 4)? as u32;
+                ComponentValue::String(self.load_string(data, len)?)
+            }
+            List(elem) => {
+                let data = self.read_uint(ptr, 4)? as u32;
+                let len = self.read_uint(ptr + 4, 4)? as u32;
+                self.load_list(data, len, elem)?
+            }
+            Record(fields) | Tuple(fields) => {
+                let mut offset = 0;
+                let mut values = Vec::new();
+                for field in fields {
+                    offset = align_to(offset, alignment(field));
+                    values.push(self.load(ptr + offset, field)?);
+                    offset += size(field);
+                }
+                match ty {
+                    Record(_) => ComponentValue::Record(values),
+                    _ => ComponentValue::Tuple(values),
+                }
+            }
+            Flags(count) => {
+                let size = flags_size(*count);
+                let words = (0..(size + 3) / 4)
+                    .map(|index| self.read_uint(ptr + index * 4, size.min(4)))
+                    .collect::<std::result::Result<Vec<_>, _>>()?;
+                Self::lift_flags(words.into_iter(), *count)
+            }
+            Own(resource) => self.lift_own(self.read_uint(ptr, 4)? as u32, resource)?,
+            Borrow(resource) => self.lift_borrow(self.read_uint(ptr, 4)? as u32, resource)?,
+            _ => {
+                let cases = cases(ty).unwrap();
+                let case = self.read_uint(ptr, discriminant_size(cases.len()))? as u32;
+                let payload = match cases.get(case as usize) {
+                    Some(Some(payload)) => Some(self.load(ptr + payload_offset(&cases), payload)?),
+                    _ => None,
+                };
+                Self::lift_case(case, &cases, payload, ty)?
+            }
+        })
+    }
+
+    /// Reads the value of the type from the flat core values
+    fn lift_flat(
+        &self,
+        values: &mut impl Iterator<Item = u64>,
+        ty: &ValueType,
+    ) -> Result<ComponentValue, Trap> {
+        use ValueType::*;
+        let mut next = || {
+            values
+                .next()
+                .ok_or_else(|| trap("too few arguments".to_string()))
+        };
+        Ok(match ty {
+            Bool => ComponentValue::Bool(next()? as u32 != 0),
+            S8 => ComponentValue::S8(next()? as i8),
+            U8 => ComponentValue::U8(next()? as u8),
+            S16 => ComponentValue::S16(next()? as i16),
+            U16 => ComponentValue::U16(next()? as u16),
+            S32 => ComponentValue::S32(next()? as i32),
+            U32 => ComponentValue::U32(next()? as u32),
+            S64 => ComponentValue::S64(next()? as i64),
+            U64 => ComponentValue::U64(next()?),
+            F32 => ComponentValue::F32(f32::from_bits(next()? as u32)),
+            F64 => ComponentValue::F64(f64::from_bits(next()?)),
+            Char => Self::lift_char(next()?)?,
+            String => {
+                let (data, len) = (next()? as u32, next()? as u32);
+                ComponentValue::String(self.load_string(data, len)?)
+            }
+            List(elem) => {
+                let (data, len) = (next()? as u32, next()? as u32);
+                self.load_list(data, len, elem)?
+            }
+            Record(fields) | Tuple(fields) => {
+                let values = fields
+                    .iter()
+                    .map(|field| self.lift_flat(values, field))
+                    .collect::<std::result::Result<_, _>>()?;
+                match ty {
+                    Record(_) => ComponentValue::Record(values),
+                    _ => ComponentValue::Tuple(values),
+                }
+            }
+            Flags(count) => {
+                let words = (0..(*count + 31) / 32)
+                    .map(|_| next())
+                    .collect::<std::result::Result<Vec<_>, _>>()?;
+                Self::lift_flags(words.into_iter(), *count)
+            }
+            Own(resource) => self.lift_own(next()? as u32, resource)?,
+            Borrow(resource) => self.lift_borrow(next()? as u32, resource)?,
+            _ => {
+                let cases = cases(ty).unwrap();
+                let case = next()? as u32;
+                let (payload, consumed) = match cases.get(case as usize) {
+                    Some(Some(payload)) => {
+                        (Some(self.lift_flat(values, payload)?), flat_len(payload))
+                    }
+                    _ => (None, 0),
+                };
+                // Skip the rest of the joined payload
+                for _ in consumed..flat_len(ty) - 1 {
+                    values.next();
+                }
+                Self::lift_case(case, &cases, payload, ty)?
+            }
+        })
+    }
+
+    fn mismatch(value: &ComponentValue, ty: &ValueType) -> Trap {
+        trap(format!("host returned {:?} for the type {:?}", value, ty))
+    }
+
+    /// Returns the case index and the payload of the value of a variant type
+    fn case_of<'v>(
+        value: &'v ComponentValue,
+        ty: &ValueType,
+    ) -> Result<(u32, Option<&'v ComponentValue>), Trap> {
+        let case = match (value, ty) {
+            (ComponentValue::Variant(case, payload), ValueType::Variant(_)) => {
+                (*case, payload.as_deref())
+            }
+            (ComponentValue::Enum(case), ValueType::Enum(_)) => (*case, None),
+            (ComponentValue::Option(None), ValueType::Option(_)) => (0, None),
+            (ComponentValue::Option(Some(payload)), ValueType::Option(_)) => (1, Some(&**payload)),
+            (ComponentValue::Result(Ok(payload)), ValueType::Result(..)) => (0, payload.as_deref()),
+            (ComponentValue::Result(Err(payload)), ValueType::Result(..)) => {
+                (1, payload.as_deref())
+            }
+            _ => return Err(Self::mismatch(value, ty)),
+        };
+        let cases = cases(ty).unwrap();
+        match (cases.get(case.0 as usize), case.1) {
+            (Some(Some(_)), Some(_)) | (Some(None), None) => Ok(case),
+            _ => Err(Self::mismatch(value, ty)),
+        }
+    }
+
+    fn flags_words(flags: &[bool], count: usize) -> Vec<u64> {
+        let mut words = vec![0; (count + 31) / 32];
+        for (index, _) in flags.iter().enumerate().filter(|(_, set)| **set) {
+            words[index / 32] |= 1 << (index % 32);
+        }
+        words
+    }
+
+    fn store_string(&self, value: &str) -> Result<(u32, u32), Trap> {
+        let ptr = self.realloc(1, value.len() as u32)?;
+        self.write(ptr, value.as_bytes())?;
+        Ok((ptr, value.len() as u32))
+    }
+
+    fn store_list(&self, values: &[ComponentValue], ty: &ValueType) -> Result<(u32, u32), Trap> {
+        let elem_size = size(ty);
+        let ptr = self.realloc(alignment(ty), elem_size * values.len() as u32)?;
+        for (index, value) in values.iter().enumerate() {
+            self.store(value, ty, ptr + index as u32 * elem_size)?;
+        }
+        Ok((ptr, values.len() as u32))
+    }
+
+    fn lower_resource(&self, rep: u32, resource: &ResourceType, own: bool) -> u64 {
+        self.handles.borrow_mut().insert(resource, rep, own) as u64
+    }
+
+    /// Writes the value of the type to the memory
+    fn store(&self, value: &ComponentValue, ty: &ValueType, ptr: u32) -> Result<(), Trap> {
+        use ComponentValue as V;
+        use ValueType as T;
+        match (value, ty) {
+            (V::Bool(v), T::Bool) => self.write_uint(ptr, 1, *v as u64),
+            (V::S8(v), T::S8) => self.write_uint(ptr, 1, *v as u8 as u64),
+            (V::U8(v), T::U8) => self.write_uint(ptr, 1, *v as u64),
+            (V::S16(v), T::S16) => self.write_uint(ptr, 2, *v as u16 as u64),
+            (V::U16(v), T::U16) => self.write_uint(ptr, 2, *v as u64),
+            (V::S32(v), T::S32) => self.write_uint(ptr, 4, *v as u32 as u64),
+            (V::U32(v), T::U32) => self.write_uint(ptr, 4, *v as u64),
+            (V::S64(v), T::S64) => self.write_uint(ptr, 8, *v as u64),
+            (V::U64(v), T::U64) => self.write_uint(ptr, 8, *v),
+            (V::F32(v), T::F32) => self.write_uint(ptr, 4, v.to_bits() as u64),
+            (V::F64(v), T::F64) => self.write_uint(ptr, 8, v.to_bits()),
+            (V::Char(v), T::Char) => self.write_uint(ptr, 4, *v as u64),
+            (V::String(v), T::String) => {
+                let (data, len) = self.store_string(v)?;
+                self.write_uint(ptr, 4, data as u64)?;
+                self.write_uint(ptr + 4, 4, len as u64)
+            }
+            (V::List(values), T::List(elem)) => {
+                let (data, len) = self.store_list(values, elem)?;
+                self.write_uint(ptr, 4, data as u64)?;
+                self.write_uint(ptr + 4, 4, len as u64)
+            }
+            (V::Record(values), T::Record(fields)) | (V::Tuple(values), T::Tuple(fields))
+                if values.len() == fields.len() =>
+            {
+                let mut offset = 0;
+                for (value, field) in values.iter().zip(fields) {
+                    offset = align_to(offset, alignment(field));
+                    self.store(value, field, ptr + offset)?;
+                    offset += size(field);
+                }
+                Ok(())
+            }
+            (V::Flags(flags), T::Flags(count)) if flags.len() == *count => {
+                let size = flags_size(*count);
+                for (index, word) in Self::flags_words(flags, *count).into_iter().enumerate() {
+                    self.write_uint(ptr + index as u32 * 4, size.min(4), word)?;
+                }
+                Ok(())
+            }
+            (V::Own(rep), T::Own(resource)) => {
+                let handle = self.lower_resource(*rep, resource, true);
+                self.write_uint(ptr, 4, handle)
+            }
+            (V::Borrow(rep), T::Borrow(resource)) => {
+                let handle = self.lower_resource(*rep, resource, false);
+                self.write_uint(ptr, 4, handle)
+            }
+            _ if cases(ty).is_some() => {
+                let (case, payload) = Self::case_of(value, ty)?;
+                let cases = cases(ty).unwrap();
+                self.write_uint(ptr, discriminant_size(cases.len()), case as u64)?;
+                match (payload, cases[case as usize]) {
+                    (Some(payload), Some(payload_ty)) => {
+                        self.store(payload, payload_ty, ptr + payload_offset(&cases))
+                    }
+                    _ => Ok(()),
+                }
+            }
+            _ => Err(Self::mismatch(value, ty)),
+        }
+    }
+
+    /// Converts the value of the type to flat core values
+    fn lower_flat(
+        &self,
+        value: &ComponentValue,
+        ty: &ValueType,
+        out: &mut Vec<u64>,
+    ) -> Result<(), Trap> {
+        use ComponentValue as V;
+        use ValueType as T;
+        match (value, ty) {
+            (V::Bool(v), T::Bool) => out.push(*v as u64),
+            (V::S8(v), T::S8) => out.push(*v as i32 as u32 as u64),
+            (V::U8(v), T::U8) => out.push(*v as u64),
+            (V::S16(v), T::S16) => out.push(*v as i32 as u32 as u64),
+            (V::U16(v), T::U16) => out.push(*v as u64),
+            (V::S32(v), T::S32) => out.push(*v as u32 as u64),
+            (V::U32(v), T::U32) => out.push(*v as u64),
+            (V::S64(v), T::S64) => out.push(*v as u64),
+            (V::U64(v), T::U64) => out.push(*v),
+            (V::F32(v), T::F32) => out.push(v.to_bits() as u64),
+            (V::F64(v), T::F64) => out.push(v.to_bits()),
+            (V::Char(v), T::Char) => out.push(*v as u64),
+            (V::String(v), T::String) => {
+                let (data, len) = self.store_string(v)?;
+                out.extend([data as u64, len as u64]);
+            }
+            (V::List(values), T::List(elem)) => {
+                let (data, len) = self.store_list(values, elem)?;
+                out.extend([data as u64, len as u64]);
+            }
+            (V::Record(values), T::Record(fields)) | (V::Tuple(values), T::Tuple(fields))
+                if values.len() == fields.len() =>
+            {
+                for (value, field) in values.iter().zip(fields) {
+                    self.lower_flat(value, field, out)?;
+                }
+            }
+            (V::Flags(flags), T::Flags(count)) if flags.len() == *count => {
+                out.extend(Self::flags_words(flags, *count));
+            }
+            (V::Own(rep), T::Own(resource)) => out.push(self.lower_resource(*rep, resource, true)),
+            (V::Borrow(rep), T::Borrow(resource)) => {
+                out.push(self.lower_resource(*rep, resource, false))
+            }
+            _ if cases(ty).is_some() => {
+                let (case, payload) = Self::case_of(value, ty)?;
+                let start = out.len();
+                out.push(case as u64);
+                if let (Some(payload), Some(payload_ty)) =
+                    (payload, cases(ty).unwrap()[case as usize])
+                {
+                    self.lower_flat(payload, payload_ty, out)?;
+                }
+                out.resize(start + flat_len(ty), 0);
+            }
+            _ => return Err(Self::mismatch(value, ty)),
+        }
+        Ok(())
+    }
+
+    /// Calls the host function with the arguments lifted from the core arguments, and
+    /// returns the core results lowered from the results of the host function
+    pub(crate) fn call_host(
+        &self,
+        ty: &FuncType,
+        args: &[Value],
+        func: &dyn Fn(Vec<ComponentValue>) -> Result<Vec<ComponentValue>, Trap>,
+    ) -> Result<Vec<Value>, Trap> {
+        let args = args.iter().map(bits).collect::<Vec<_>>();
+        let flat_params = flatten_all(&ty.params);
+        let params = if flat_params.len() > MAX_FLAT_PARAMS {
+            let tuple = ValueType::Tuple(ty.params.clone());
+            match self.load(args[0] as u32, &tuple)? {
+                ComponentValue::Tuple(values) => values,
+                _ => unreachable!(),
+            }
+        } else {
+            let mut values = args.iter().copied();
+            ty.params
+                .iter()
+                .map(|param| self.lift_flat(&mut values, param))
+                .collect::<std::result::Result<_, _>>()?
+        };
+        let results = func(params)?;
+        if results.len() != ty.results.len() {
+            return Err(trap(format!(
+                "host returned {} results but {} are expected",
+                results.len(),
+                ty.results.len()
+            )));
+        }
+        let flat_results = flatten_all(&ty.results);
+        if flat_results.len() > MAX_FLAT_RESULTS {
+            let retptr = *args.last().unwrap() as u32;
+            let tuple = ValueType::Tuple(ty.results.clone());
+            self.store(&ComponentValue::Tuple(results), &tuple, retptr)?;
+            return Ok(vec![]);
+        }
+        let mut out = Vec::new();
+        for (value, ty) in results.iter().zip(&ty.results) {
+            self.lower_flat(value, ty, &mut out)?;
+        }
+        Ok(out
+            .into_iter()
+            .zip(flat_results)
+            .map(|(bits, ty)| ty.value(bits))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_layout_and_flattening() {
+        let result = ValueType::Result(
+            Some(Box::new(ValueType::List(Box::new(ValueType::U8)))),
+            Some(Box::new(ValueType::Variant(vec![
+                Some(ValueType::U64),
+                None,
+            ]))),
+        );
+        assert_eq!(size(&result), 24);
+        assert_eq!(alignment(&result), 8);
+        assert_eq!(
+            flatten_all(&[result]),
+            vec![CoreType::I32, CoreType::I32, CoreType::I64]
+        );
+
+        let option = ValueType::Option(Box::new(ValueType::F32));
+        assert_eq!((size(&option), alignment(&option)), (8, 4));
+        let mut flat = Vec::new();
+        flatten(&option, &mut flat);
+        assert_eq!(flat, vec![CoreType::I32, CoreType::F32]);
+
+        let ty = FuncType {
+            params: vec![ValueType::String],
+            results: vec![ValueType::Tuple(vec![ValueType::U32, ValueType::U32])],
+        };
+        let lowered = lowered_func_type(&ty);
+        assert_eq!(lowered.params().len(), 3);
+        assert!(lowered.results().is_empty());
+    }
+}
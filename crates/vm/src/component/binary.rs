@@ -0,0 +1,765 @@
+//! Decoder of the component binary format.
+//!
+//! Core modules embedded in the component are not decoded here but kept as byte ranges,
+//! so that they can be loaded by `Store::load_module` as they are.
+
+use anyhow::{anyhow, Result};
+use std::ops::Range;
+use std::rc::Rc;
+
+const MAGIC: &[u8] = b"\0asm";
+const COMPONENT_VERSION: u16 = 0x0d;
+const COMPONENT_LAYER: u16 = 1;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum CoreSort {
+    Func,
+    Table,
+    Memory,
+    Global,
+    Tag,
+    Type,
+    Module,
+    Instance,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Sort {
+    Core(CoreSort),
+    Func,
+    Value,
+    Type,
+    Component,
+    Instance,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct SortIndex {
+    pub(crate) sort: Sort,
+    pub(crate) index: u32,
+}
+
+#[derive(Debug)]
+pub(crate) enum CoreInstance {
+    Instantiate {
+        module: u32,
+        args: Vec<(String, u32)>,
+    },
+    FromExports(Vec<(String, CoreSort, u32)>),
+}
+
+#[derive(Debug)]
+pub(crate) enum Instance {
+    Instantiate {
+        component: u32,
+        args: Vec<(String, SortIndex)>,
+    },
+    FromExports(Vec<(String, SortIndex)>),
+}
+
+#[derive(Clone, Debug)]
+pub(crate) enum Alias {
+    InstanceExport {
+        sort: Sort,
+        instance: u32,
+        name: String,
+    },
+    CoreInstanceExport {
+        sort: CoreSort,
+        instance: u32,
+        name: String,
+    },
+    Outer {
+        sort: Sort,
+        count: u32,
+        index: u32,
+    },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum PrimitiveType {
+    Bool,
+    S8,
+    U8,
+    S16,
+    U16,
+    S32,
+    U32,
+    S64,
+    U64,
+    F32,
+    F64,
+    Char,
+    String,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum ValType {
+    Primitive(PrimitiveType),
+    Type(u32),
+}
+
+#[derive(Debug)]
+pub(crate) enum DefinedType {
+    Primitive(PrimitiveType),
+    Record(Vec<(String, ValType)>),
+    Variant(Vec<(String, Option<ValType>)>),
+    List(ValType),
+    Tuple(Vec<ValType>),
+    Flags(Vec<String>),
+    Enum(Vec<String>),
+    Option(ValType),
+    Result(Option<ValType>, Option<ValType>),
+    Own(u32),
+    Borrow(u32),
+}
+
+#[derive(Debug)]
+pub(crate) struct FuncType {
+    pub(crate) params: Vec<(String, ValType)>,
+    pub(crate) results: Vec<ValType>,
+}
+
+#[derive(Debug)]
+pub(crate) enum TypeDecl {
+    Defined(DefinedType),
+    Func(FuncType),
+    /// Component types are only used to import components, which is not supported
+    Component,
+    Instance(Rc<Vec<InstanceDecl>>),
+    Resource {
+        dtor: Option<u32>,
+    },
+}
+
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum TypeBound {
+    Eq(u32),
+    SubResource,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum ExternDesc {
+    Module,
+    Func(u32),
+    Value,
+    Type(TypeBound),
+    Component,
+    Instance(u32),
+}
+
+#[derive(Debug)]
+pub(crate) enum InstanceDecl {
+    CoreType,
+    Type(Rc<TypeDecl>),
+    Alias(Alias),
+    Export { name: String, desc: ExternDesc },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum StringEncoding {
+    Utf8,
+    Utf16,
+    Latin1Utf16,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct CanonOptions {
+    pub(crate) string_encoding: StringEncoding,
+    pub(crate) memory: Option<u32>,
+    pub(crate) realloc: Option<u32>,
+    pub(crate) post_return: Option<u32>,
+}
+
+#[derive(Debug)]
+pub(crate) enum Canon {
+    Lift { core_func: u32 },
+    Lower { func: u32, options: CanonOptions },
+    ResourceNew(u32),
+    ResourceDrop(u32),
+    ResourceRep(u32),
+}
+
+/// A definition in the component, in the order of the binary, which matters because
+/// each definition appends an item to the index space of its sort
+#[derive(Debug)]
+pub(crate) enum Definition {
+    /// The ordinal of the core module in the whole binary
+    CoreModule(usize),
+    CoreInstance(CoreInstance),
+    CoreType,
+    Component(Rc<Component>),
+    Instance(Instance),
+    Alias(Alias),
+    Type(Rc<TypeDecl>),
+    Canon(Canon),
+    Import {
+        name: String,
+        desc: ExternDesc,
+    },
+    Export {
+        name: String,
+        item: SortIndex,
+    },
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct Component {
+    pub(crate) definitions: Vec<Definition>,
+}
+
+pub(crate) struct ComponentBinary {
+    pub(crate) root: Rc<Component>,
+    /// Byte ranges of the core modules in the order of appearance, nested components
+    /// included
+    pub(crate) modules: Vec<Range<usize>>,
+}
+
+/// Returns true if the bytes start with the header of a component rather than a core module
+pub fn is_component(bytes: &[u8]) -> bool {
+    bytes.len() >= 8 && &bytes[0..4] == MAGIC && bytes[6..8] == COMPONENT_LAYER.to_le_bytes()
+}
+
+/// Returns the core modules embedded in the component with their offsets in it, nested
+/// components included
+pub fn core_modules(bytes: &[u8]) -> Result<Vec<(usize, &[u8])>> {
+    let binary = parse(bytes)?;
+    Ok(binary
+        .modules
+        .into_iter()
+        .map(|range| (range.start, &bytes[range]))
+        .collect())
+}
+
+pub(crate) fn parse(bytes: &[u8]) -> Result<ComponentBinary> {
+    let mut modules = Vec::new();
+    let mut reader = Reader {
+        bytes,
+        pos: 0,
+        end: bytes.len(),
+    };
+    let root = reader.component(&mut modules)?;
+    Ok(ComponentBinary {
+        root: Rc::new(root),
+        modules,
+    })
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+    end: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn error<T>(&self, message: &str) -> Result<T> {
+        Err(anyhow!("{} (at offset {:#x})", message, self.pos))
+    }
+
+    fn eof(&self) -> bool {
+        self.pos >= self.end
+    }
+
+    fn peek(&self) -> Result<u8> {
+        if self.eof() {
+            return self.error("unexpected end of component");
+        }
+        Ok(self.bytes[self.pos])
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        let byte = self.peek()?;
+        self.pos += 1;
+        Ok(byte)
+    }
+
+    fn leb(&mut self, max_bits: u32) -> Result<u64> {
+        let mut result = 0u64;
+        let mut shift = 0;
+        loop {
+            let byte = self.u8()?;
+            if shift >= max_bits {
+                return self.error("integer is too large");
+            }
+            result |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(result);
+            }
+            shift += 7;
+        }
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        Ok(self.leb(32)? as u32)
+    }
+
+    fn take(&mut self, len: usize) -> Result<Range<usize>> {
+        if self.end - self.pos < len {
+            return self.error("unexpected end of component");
+        }
+        let range = self.pos..self.pos + len;
+        self.pos += len;
+        Ok(range)
+    }
+
+    fn string(&mut self) -> Result<String> {
+        let len = self.u32()? as usize;
+        let range = self.take(len)?;
+        match std::str::from_utf8(&self.bytes[range]) {
+            Ok(s) => Ok(s.to_string()),
+            Err(_) => self.error("malformed UTF-8 name"),
+        }
+    }
+
+    /// Reads an import or export name, which has a prefix telling if a version suffix follows
+    fn extern_name(&mut self) -> Result<String> {
+        match self.u8()? {
+            0x00 => self.string(),
+            0x01 => {
+                let name = self.string()?;
+                self.string()?;
+                Ok(name)
+            }
+            _ => self.error("malformed extern name"),
+        }
+    }
+
+    fn vec<T>(&mut self, mut f: impl FnMut(&mut Self) -> Result<T>) -> Result<Vec<T>> {
+        let count = self.u32()?;
+        (0..count).map(|_| f(self)).collect()
+    }
+
+    fn optional<T>(&mut self, f: impl FnOnce(&mut Self) -> Result<T>) -> Result<Option<T>> {
+        match self.u8()? {
+            0x00 => Ok(None),
+            0x01 => Ok(Some(f(self)?)),
+            _ => self.error("malformed optional"),
+        }
+    }
+
+    fn component(&mut self, modules: &mut Vec<Range<usize>>) -> Result<Component> {
+        let header = self.take(8)?;
+        if !is_component(&self.bytes[header.clone()]) {
+            return self.error("not a component binary");
+        }
+        let version =
+            u16::from_le_bytes([self.bytes[header.start + 4], self.bytes[header.start + 5]]);
+        if version != COMPONENT_VERSION {
+            return self.error(&format!("unsupported component version {:#x}", version));
+        }
+        let mut component = Component::default();
+        let definitions = &mut component.definitions;
+        while !self.eof() {
+            let id = self.u8()?;
+            let size = self.u32()? as usize;
+            let section = self.take(size)?;
+            let mut reader = Reader {
+                bytes: self.bytes,
+                pos: section.start,
+                end: section.end,
+            };
+            match id {
+                0 => {}
+                1 => {
+                    definitions.push(Definition::CoreModule(modules.len()));
+                    modules.push(section);
+                    continue;
+                }
+                2 => definitions.extend(reader.vec(Self::core_instance)?),
+                3 => {
+                    definitions.extend(reader.vec(|r| r.core_type().map(|_| Definition::CoreType))?)
+                }
+                4 => {
+                    let nested = reader.component(modules)?;
+                    definitions.push(Definition::Component(Rc::new(nested)));
+                    continue;
+                }
+                5 => definitions.extend(reader.vec(Self::instance)?),
+                6 => definitions.extend(reader.vec(|r| r.alias().map(Definition::Alias))?),
+                7 => definitions.extend(reader.vec(|r| r.type_decl().map(Definition::Type))?),
+                8 => definitions.extend(reader.vec(|r| r.canon().map(Definition::Canon))?),
+                9 => return self.error("start functions of components are not supported"),
+                10 => definitions.extend(reader.vec(|r| {
+                    let name = r.extern_name()?;
+                    let desc = r.extern_desc()?;
+                    Ok(Definition::Import { name, desc })
+                })?),
+                11 => definitions.extend(reader.vec(|r| {
+                    let name = r.extern_name()?;
+                    let item = r.sort_index()?;
+                    r.optional(Self::extern_desc)?;
+                    Ok(Definition::Export { name, item })
+                })?),
+                12 => return self.error("value definitions are not supported"),
+                _ => return self.error(&format!("unknown section id {}", id)),
+            }
+            if id != 0 && !reader.eof() {
+                return reader.error("section size mismatch");
+            }
+        }
+        Ok(component)
+    }
+
+    fn core_sort(&mut self) -> Result<CoreSort> {
+        Ok(match self.u8()? {
+            0x00 => CoreSort::Func,
+            0x01 => CoreSort::Table,
+            0x02 => CoreSort::Memory,
+            0x03 => CoreSort::Global,
+            0x04 => CoreSort::Tag,
+            0x10 => CoreSort::Type,
+            0x11 => CoreSort::Module,
+            0x12 => CoreSort::Instance,
+            _ => return self.error("malformed core sort"),
+        })
+    }
+
+    fn sort(&mut self) -> Result<Sort> {
+        Ok(match self.u8()? {
+            0x00 => Sort::Core(self.core_sort()?),
+            0x01 => Sort::Func,
+            0x02 => Sort::Value,
+            0x03 => Sort::Type,
+            0x04 => Sort::Component,
+            0x05 => Sort::Instance,
+            _ => return self.error("malformed sort"),
+        })
+    }
+
+    fn sort_index(&mut self) -> Result<SortIndex> {
+        let sort = self.sort()?;
+        let index = self.u32()?;
+        Ok(SortIndex { sort, index })
+    }
+
+    fn core_instance(&mut self) -> Result<Definition> {
+        let instance = match self.u8()? {
+            0x00 => {
+                let module = self.u32()?;
+                let args = self.vec(|r| {
+                    let name = r.string()?;
+                    if r.u8()? != 0x12 {
+                        return r.error("instantiation argument must be a core instance");
+                    }
+                    Ok((name, r.u32()?))
+                })?;
+                CoreInstance::Instantiate { module, args }
+            }
+            0x01 => CoreInstance::FromExports(self.vec(|r| {
+                let name = r.string()?;
+                let sort = r.core_sort()?;
+                Ok((name, sort, r.u32()?))
+            })?),
+            _ => return self.error("malformed core instance"),
+        };
+        Ok(Definition::CoreInstance(instance))
+    }
+
+    fn instance(&mut self) -> Result<Definition> {
+        let instance = match self.u8()? {
+            0x00 => {
+                let component = self.u32()?;
+                let args = self.vec(|r| Ok((r.string()?, r.sort_index()?)))?;
+                Instance::Instantiate { component, args }
+            }
+            0x01 => Instance::FromExports(self.vec(|r| Ok((r.extern_name()?, r.sort_index()?)))?),
+            _ => return self.error("malformed instance"),
+        };
+        Ok(Definition::Instance(instance))
+    }
+
+    fn alias(&mut self) -> Result<Alias> {
+        let sort = self.sort()?;
+        Ok(match self.u8()? {
+            0x00 => Alias::InstanceExport {
+                sort,
+                instance: self.u32()?,
+                name: self.string()?,
+            },
+            0x01 => match sort {
+                Sort::Core(sort) => Alias::CoreInstanceExport {
+                    sort,
+                    instance: self.u32()?,
+                    name: self.string()?,
+                },
+                _ => return self.error("core export alias must have a core sort"),
+            },
+            0x02 => Alias::Outer {
+                sort,
+                count: self.u32()?,
+                index: self.u32()?,
+            },
+            _ => return self.error("malformed alias"),
+        })
+    }
+
+    /// Skips a core value type
+    fn core_valtype(&mut self) -> Result<()> {
+        match self.u8()? {
+            0x7f | 0x7e | 0x7d | 0x7c | 0x7b | 0x70 | 0x6f => Ok(()),
+            // (ref null? heaptype)
+            0x63 | 0x64 => self.leb(33).map(|_| ()),
+            _ => self.error("malformed core value type"),
+        }
+    }
+
+    fn limits(&mut self) -> Result<()> {
+        let flags = self.u8()?;
+        self.leb(64)?;
+        if flags & 0x01 != 0 {
+            self.leb(64)?;
+        }
+        Ok(())
+    }
+
+    fn core_import_desc(&mut self) -> Result<()> {
+        match self.u8()? {
+            0x00 => self.u32().map(|_| ()),
+            0x01 => {
+                self.core_valtype()?;
+                self.limits()
+            }
+            0x02 => self.limits(),
+            0x03 => {
+                self.core_valtype()?;
+                self.u8().map(|_| ())
+            }
+            0x04 => {
+                self.u8()?;
+                self.u32().map(|_| ())
+            }
+            _ => self.error("malformed core import description"),
+        }
+    }
+
+    /// Skips a core type, which is only used to type-check instantiation
+    fn core_type(&mut self) -> Result<()> {
+        let mut kind = self.u8()?;
+        if kind == 0x00 {
+            kind = self.u8()?;
+        }
+        match kind {
+            0x60 => {
+                self.vec(Self::core_valtype)?;
+                self.vec(Self::core_valtype)?;
+            }
+            0x50 => {
+                self.vec(|r| match r.u8()? {
+                    0x00 => {
+                        r.string()?;
+                        r.string()?;
+                        r.core_import_desc()
+                    }
+                    0x01 => r.core_type(),
+                    0x02 => {
+                        r.core_sort()?;
+                        r.u8()?;
+                        r.u32()?;
+                        r.u32().map(|_| ())
+                    }
+                    0x03 => {
+                        r.string()?;
+                        r.core_import_desc()
+                    }
+                    _ => r.error("malformed core module declaration"),
+                })?;
+            }
+            _ => return self.error("unsupported core type"),
+        }
+        Ok(())
+    }
+
+    fn primitive(byte: u8) -> Option<PrimitiveType> {
+        Some(match byte {
+            0x7f => PrimitiveType::Bool,
+            0x7e => PrimitiveType::S8,
+            0x7d => PrimitiveType::U8,
+            0x7c => PrimitiveType::S16,
+            0x7b => PrimitiveType::U16,
+            0x7a => PrimitiveType::S32,
+            0x79 => PrimitiveType::U32,
+            0x78 => PrimitiveType::S64,
+            0x77 => PrimitiveType::U64,
+            0x76 => PrimitiveType::F32,
+            0x75 => PrimitiveType::F64,
+            0x74 => PrimitiveType::Char,
+            0x73 => PrimitiveType::String,
+            _ => return None,
+        })
+    }
+
+    fn valtype(&mut self) -> Result<ValType> {
+        match Self::primitive(self.peek()?) {
+            Some(primitive) => {
+                self.pos += 1;
+                Ok(ValType::Primitive(primitive))
+            }
+            None => Ok(ValType::Type(self.u32()?)),
+        }
+    }
+
+    fn defined_type(&mut self, kind: u8) -> Result<DefinedType> {
+        if let Some(primitive) = Self::primitive(kind) {
+            return Ok(DefinedType::Primitive(primitive));
+        }
+        Ok(match kind {
+            0x72 => DefinedType::Record(self.vec(|r| Ok((r.string()?, r.valtype()?)))?),
+            0x71 => DefinedType::Variant(self.vec(|r| {
+                let name = r.string()?;
+                let ty = r.optional(Self::valtype)?;
+                if r.u8()? != 0x00 {
+                    return r.error("variant case refinements are not supported");
+                }
+                Ok((name, ty))
+            })?),
+            0x70 => DefinedType::List(self.valtype()?),
+            0x6f => DefinedType::Tuple(self.vec(Self::valtype)?),
+            0x6e => DefinedType::Flags(self.vec(Self::string)?),
+            0x6d => DefinedType::Enum(self.vec(Self::string)?),
+            0x6b => DefinedType::Option(self.valtype()?),
+            0x6a => {
+                let ok = self.optional(Self::valtype)?;
+                let err = self.optional(Self::valtype)?;
+                DefinedType::Result(ok, err)
+            }
+            0x69 => DefinedType::Own(self.u32()?),
+            0x68 => DefinedType::Borrow(self.u32()?),
+            _ => return self.error(&format!("unsupported type {:#x}", kind)),
+        })
+    }
+
+    fn type_decl(&mut self) -> Result<Rc<TypeDecl>> {
+        let decl = match self.u8()? {
+            0x40 => {
+                let params = self.vec(|r| Ok((r.string()?, r.valtype()?)))?;
+                let results = match self.u8()? {
+                    0x00 => vec![self.valtype()?],
+                    0x01 => self.vec(|r| {
+                        r.string()?;
+                        r.valtype()
+                    })?,
+                    _ => return self.error("malformed function results"),
+                };
+                TypeDecl::Func(FuncType { params, results })
+            }
+            0x41 => {
+                self.vec(|r| match r.peek()? {
+                    0x03 => {
+                        r.pos += 1;
+                        r.extern_name()?;
+                        r.extern_desc().map(|_| ())
+                    }
+                    _ => r.instance_decl().map(|_| ()),
+                })?;
+                TypeDecl::Component
+            }
+            0x42 => TypeDecl::Instance(Rc::new(self.vec(Self::instance_decl)?)),
+            0x3f => {
+                if self.u8()? != 0x7f {
+                    return self.error("resource representation must be i32");
+                }
+                TypeDecl::Resource {
+                    dtor: self.optional(Self::u32)?,
+                }
+            }
+            kind => TypeDecl::Defined(self.defined_type(kind)?),
+        };
+        Ok(Rc::new(decl))
+    }
+
+    fn instance_decl(&mut self) -> Result<InstanceDecl> {
+        Ok(match self.u8()? {
+            0x00 => {
+                self.core_type()?;
+                InstanceDecl::CoreType
+            }
+            0x01 => InstanceDecl::Type(self.type_decl()?),
+            0x02 => InstanceDecl::Alias(self.alias()?),
+            0x04 => InstanceDecl::Export {
+                name: self.extern_name()?,
+                desc: self.extern_desc()?,
+            },
+            _ => return self.error("malformed instance type declaration"),
+        })
+    }
+
+    fn extern_desc(&mut self) -> Result<ExternDesc> {
+        Ok(match self.u8()? {
+            0x00 => {
+                if self.u8()? != 0x11 {
+                    return self.error("malformed core module type");
+                }
+                self.u32()?;
+                ExternDesc::Module
+            }
+            0x01 => ExternDesc::Func(self.u32()?),
+            0x02 => {
+                match self.u8()? {
+                    0x00 => self.u32().map(|_| ())?,
+                    0x01 => self.valtype().map(|_| ())?,
+                    _ => return self.error("malformed value bound"),
+                }
+                ExternDesc::Value
+            }
+            0x03 => ExternDesc::Type(match self.u8()? {
+                0x00 => TypeBound::Eq(self.u32()?),
+                0x01 => TypeBound::SubResource,
+                _ => return self.error("malformed type bound"),
+            }),
+            0x04 => {
+                self.u32()?;
+                ExternDesc::Component
+            }
+            0x05 => ExternDesc::Instance(self.u32()?),
+            _ => return self.error("malformed extern description"),
+        })
+    }
+
+    fn canon_options(&mut self) -> Result<CanonOptions> {
+        let mut options = CanonOptions {
+            string_encoding: StringEncoding::Utf8,
+            memory: None,
+            realloc: None,
+            post_return: None,
+        };
+        for _ in 0..self.u32()? {
+            match self.u8()? {
+                0x00 => options.string_encoding = StringEncoding::Utf8,
+                0x01 => options.string_encoding = StringEncoding::Utf16,
+                0x02 => options.string_encoding = StringEncoding::Latin1Utf16,
+                0x03 => options.memory = Some(self.u32()?),
+                0x04 => options.realloc = Some(self.u32()?),
+                0x05 => options.post_return = Some(self.u32()?),
+                _ => return self.error("unsupported canonical option"),
+            }
+        }
+        Ok(options)
+    }
+
+    fn canon(&mut self) -> Result<Canon> {
+        Ok(match self.u8()? {
+            0x00 => {
+                if self.u8()? != 0x00 {
+                    return self.error("malformed canon lift");
+                }
+                let core_func = self.u32()?;
+                // Lifted functions are called by the debugger as core functions
+                self.canon_options()?;
+                self.u32()?;
+                Canon::Lift { core_func }
+            }
+            0x01 => {
+                if self.u8()? != 0x00 {
+                    return self.error("malformed canon lower");
+                }
+                let func = self.u32()?;
+                let options = self.canon_options()?;
+                Canon::Lower { func, options }
+            }
+            0x02 => Canon::ResourceNew(self.u32()?),
+            0x03 => Canon::ResourceDrop(self.u32()?),
+            0x04 => Canon::ResourceRep(self.u32()?),
+            kind => return self.error(&format!("unsupported canonical function {:#x}", kind)),
+        })
+    }
+}
@@ -0,0 +1,844 @@
+//! Instantiation of components.
+//!
+//! Core modules are loaded into the store as usual, and the index spaces of the
+//! component are evaluated in the order of the definitions. Imported instances are
+//! implemented by `ComponentHost`, and their functions are lowered into host functions
+//! of the store.
+
+use super::abi::{self, HandleTable, LowerContext};
+use super::binary::{
+    self, Alias, Canon, Component, CoreInstance, CoreSort, DefinedType, Definition, ExternDesc,
+    Instance, InstanceDecl, PrimitiveType, Sort, SortIndex, StringEncoding, TypeBound, TypeDecl,
+    ValType,
+};
+use super::types::{ComponentValue, FuncType, ResourceDtor, ResourceType, ValueType};
+use crate::address::*;
+use crate::config::Config;
+use crate::executor::Trap;
+use crate::host::HostFuncBody;
+use crate::module::{HostExport, ModuleIndex};
+use crate::store::Store;
+use crate::value::Value;
+use anyhow::{anyhow, Result};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+pub type ComponentHostFunc = Rc<dyn Fn(Vec<ComponentValue>) -> Result<Vec<ComponentValue>, Trap>>;
+
+#[derive(Clone)]
+pub enum ComponentHostItem {
+    Func(ComponentHostFunc),
+    /// A resource type with the destructor called with the representation
+    Resource(Rc<dyn Fn(u32)>),
+}
+
+/// Host implementation of the instances imported by components, keyed by the
+/// interface names like `wasi:cli/stdout@0.2.0`
+#[derive(Default, Clone)]
+pub struct ComponentHost {
+    instances: HashMap<String, HashMap<String, ComponentHostItem>>,
+}
+
+/// Compares the names ignoring the version, which is only checked if both have one
+fn name_matches(name: &str, other: &str) -> bool {
+    let (name, version) = name.split_once('@').unwrap_or((name, ""));
+    let (other, other_version) = other.split_once('@').unwrap_or((other, ""));
+    name == other && (version.is_empty() || other_version.is_empty() || version == other_version)
+}
+
+impl ComponentHost {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn func<F>(&mut self, instance: &str, name: &str, func: F)
+    where
+        F: Fn(Vec<ComponentValue>) -> Result<Vec<ComponentValue>, Trap> + 'static,
+    {
+        self.instances
+            .entry(instance.to_string())
+            .or_default()
+            .insert(name.to_string(), ComponentHostItem::Func(Rc::new(func)));
+    }
+
+    pub fn resource<F>(&mut self, instance: &str, name: &str, dtor: F)
+    where
+        F: Fn(u32) + 'static,
+    {
+        self.instances
+            .entry(instance.to_string())
+            .or_default()
+            .insert(name.to_string(), ComponentHostItem::Resource(Rc::new(dtor)));
+    }
+
+    fn instance(&self, name: &str) -> Option<&HashMap<String, ComponentHostItem>> {
+        self.instances.get(name).or_else(|| {
+            self.instances
+                .iter()
+                .find(|(key, _)| name_matches(key, name))
+                .map(|(_, items)| items)
+        })
+    }
+}
+
+#[derive(Clone, Copy)]
+struct CoreFunc {
+    exec: ExecutableFuncAddr,
+    /// The address in the module defining the function, if it's not a host function
+    addr: Option<FuncAddr>,
+}
+
+enum Func {
+    Host {
+        instance: String,
+        name: String,
+        ty: Rc<FuncType>,
+        func: Option<ComponentHostFunc>,
+    },
+    Lifted {
+        core: CoreFunc,
+    },
+}
+
+struct InstanceTypeDef {
+    decls: Rc<Vec<InstanceDecl>>,
+    scope: Rc<Scope>,
+}
+
+#[derive(Clone)]
+enum TypeDef {
+    Value(ValueType),
+    Func(Rc<FuncType>),
+    Resource(ResourceType),
+    Instance(Rc<InstanceTypeDef>),
+    Component,
+}
+
+#[derive(Clone)]
+enum Item {
+    Func(Rc<Func>),
+    Instance(Rc<InstanceValue>),
+    Type(TypeDef),
+    Component(Rc<ComponentClosure>),
+    /// The ordinal of the core module
+    Module(usize),
+}
+
+#[derive(Default)]
+struct InstanceValue {
+    items: HashMap<String, Item>,
+}
+
+/// Index spaces of an enclosing component visible to `alias outer`
+struct Scope {
+    types: Vec<TypeDef>,
+    modules: Vec<usize>,
+    components: Vec<Rc<ComponentClosure>>,
+    parent: Option<Rc<Scope>>,
+}
+
+impl Scope {
+    fn outer(self: &Rc<Self>, count: u32) -> Result<&Rc<Self>> {
+        let mut scope = self;
+        for _ in 1..count {
+            scope = scope
+                .parent
+                .as_ref()
+                .ok_or_else(|| anyhow!("outer alias count {} is out of bounds", count))?;
+        }
+        Ok(scope)
+    }
+}
+
+struct ComponentClosure {
+    component: Rc<Component>,
+    scope: Rc<Scope>,
+}
+
+#[derive(Default)]
+struct Spaces {
+    core_modules: Vec<usize>,
+    core_instances: Vec<ModuleIndex>,
+    core_funcs: Vec<CoreFunc>,
+    core_tables: Vec<ResolvedTableAddr>,
+    core_memories: Vec<ResolvedMemoryAddr>,
+    core_globals: Vec<ResolvedGlobalAddr>,
+    types: Vec<TypeDef>,
+    funcs: Vec<Rc<Func>>,
+    instances: Vec<Rc<InstanceValue>>,
+    components: Vec<Rc<ComponentClosure>>,
+}
+
+fn get<T: Clone>(items: &[T], index: u32, kind: &str) -> Result<T> {
+    items
+        .get(index as usize)
+        .cloned()
+        .ok_or_else(|| anyhow!("{} index {} is out of bounds", kind, index))
+}
+
+impl Spaces {
+    fn scope(&self, parent: &Option<Rc<Scope>>) -> Rc<Scope> {
+        Rc::new(Scope {
+            types: self.types.clone(),
+            modules: self.core_modules.clone(),
+            components: self.components.clone(),
+            parent: parent.clone(),
+        })
+    }
+
+    fn item(&self, index: SortIndex) -> Result<Item> {
+        let SortIndex { sort, index } = index;
+        Ok(match sort {
+            Sort::Func => Item::Func(get(&self.funcs, index, "function")?),
+            Sort::Instance => Item::Instance(get(&self.instances, index, "instance")?),
+            Sort::Type => Item::Type(get(&self.types, index, "type")?),
+            Sort::Component => Item::Component(get(&self.components, index, "component")?),
+            Sort::Core(CoreSort::Module) => Item::Module(get(&self.core_modules, index, "module")?),
+            _ => return Err(anyhow!("{:?} can't be exported from components", sort)),
+        })
+    }
+
+    fn push(&mut self, item: Item) {
+        match item {
+            Item::Func(func) => self.funcs.push(func),
+            Item::Instance(instance) => self.instances.push(instance),
+            Item::Type(ty) => self.types.push(ty),
+            Item::Component(component) => self.components.push(component),
+            Item::Module(module) => self.core_modules.push(module),
+        }
+    }
+
+    fn func_type(&self, index: u32) -> Result<Rc<FuncType>> {
+        match get(&self.types, index, "type")? {
+            TypeDef::Func(ty) => Ok(ty),
+            _ => Err(anyhow!("type {} is not a function type", index)),
+        }
+    }
+
+    fn resource_type(&self, index: u32) -> Result<ResourceType> {
+        match get(&self.types, index, "type")? {
+            TypeDef::Resource(ty) => Ok(ty),
+            _ => Err(anyhow!("type {} is not a resource type", index)),
+        }
+    }
+}
+
+fn sort_of(item: &Item) -> Sort {
+    match item {
+        Item::Func(_) => Sort::Func,
+        Item::Instance(_) => Sort::Instance,
+        Item::Type(_) => Sort::Type,
+        Item::Component(_) => Sort::Component,
+        Item::Module(_) => Sort::Core(CoreSort::Module),
+    }
+}
+
+fn primitive_type(ty: PrimitiveType) -> ValueType {
+    match ty {
+        PrimitiveType::Bool => ValueType::Bool,
+        PrimitiveType::S8 => ValueType::S8,
+        PrimitiveType::U8 => ValueType::U8,
+        PrimitiveType::S16 => ValueType::S16,
+        PrimitiveType::U16 => ValueType::U16,
+        PrimitiveType::S32 => ValueType::S32,
+        PrimitiveType::U32 => ValueType::U32,
+        PrimitiveType::S64 => ValueType::S64,
+        PrimitiveType::U64 => ValueType::U64,
+        PrimitiveType::F32 => ValueType::F32,
+        PrimitiveType::F64 => ValueType::F64,
+        PrimitiveType::Char => ValueType::Char,
+        PrimitiveType::String => ValueType::String,
+    }
+}
+
+fn value_type(ty: ValType, types: &[TypeDef]) -> Result<ValueType> {
+    match ty {
+        ValType::Primitive(ty) => Ok(primitive_type(ty)),
+        ValType::Type(index) => match get(types, index, "type")? {
+            TypeDef::Value(ty) => Ok(ty),
+            _ => Err(anyhow!("type {} is not a value type", index)),
+        },
+    }
+}
+
+fn resource(index: u32, types: &[TypeDef]) -> Result<ResourceType> {
+    match get(types, index, "type")? {
+        TypeDef::Resource(ty) => Ok(ty),
+        _ => Err(anyhow!("type {} is not a resource type", index)),
+    }
+}
+
+fn defined_type(ty: &DefinedType, types: &[TypeDef]) -> Result<ValueType> {
+    let boxed = |ty: ValType| value_type(ty, types).map(Box::new);
+    let optional = |ty: Option<ValType>| ty.map(boxed).transpose();
+    Ok(match ty {
+        DefinedType::Primitive(ty) => primitive_type(*ty),
+        DefinedType::Record(fields) => ValueType::Record(
+            fields
+                .iter()
+                .map(|(_, ty)| value_type(*ty, types))
+                .collect::<Result<_>>()?,
+        ),
+        DefinedType::Variant(cases) => ValueType::Variant(
+            cases
+                .iter()
+                .map(|(_, ty)| ty.map(|ty| value_type(ty, types)).transpose())
+                .collect::<Result<_>>()?,
+        ),
+        DefinedType::List(ty) => ValueType::List(boxed(*ty)?),
+        DefinedType::Tuple(fields) => ValueType::Tuple(
+            fields
+                .iter()
+                .map(|ty| value_type(*ty, types))
+                .collect::<Result<_>>()?,
+        ),
+        DefinedType::Flags(names) => ValueType::Flags(names.len()),
+        DefinedType::Enum(names) => ValueType::Enum(names.len()),
+        DefinedType::Option(ty) => ValueType::Option(boxed(*ty)?),
+        DefinedType::Result(ok, err) => ValueType::Result(optional(*ok)?, optional(*err)?),
+        DefinedType::Own(index) => ValueType::Own(resource(*index, types)?),
+        DefinedType::Borrow(index) => ValueType::Borrow(resource(*index, types)?),
+    })
+}
+
+/// Resolves the type declaration except resource types, which are only defined by
+/// components
+fn type_def(
+    decl: &TypeDecl,
+    types: &[TypeDef],
+    scope: impl FnOnce() -> Rc<Scope>,
+) -> Result<TypeDef> {
+    Ok(match decl {
+        TypeDecl::Defined(ty) => TypeDef::Value(defined_type(ty, types)?),
+        TypeDecl::Func(ty) => TypeDef::Func(Rc::new(FuncType {
+            params: ty
+                .params
+                .iter()
+                .map(|(_, ty)| value_type(*ty, types))
+                .collect::<Result<_>>()?,
+            results: ty
+                .results
+                .iter()
+                .map(|ty| value_type(*ty, types))
+                .collect::<Result<_>>()?,
+        })),
+        TypeDecl::Component => TypeDef::Component,
+        TypeDecl::Instance(decls) => TypeDef::Instance(Rc::new(InstanceTypeDef {
+            decls: decls.clone(),
+            scope: scope(),
+        })),
+        TypeDecl::Resource { .. } => {
+            return Err(anyhow!("resource types can only be defined by components"))
+        }
+    })
+}
+
+/// A component instantiated in the store
+pub struct ComponentInstance {
+    /// Instances of the core modules in the order of instantiation, with the ordinals
+    /// of the modules in `core_modules`
+    pub modules: Vec<(ModuleIndex, usize)>,
+    exports: HashMap<String, Item>,
+}
+
+impl ComponentInstance {
+    /// Returns the core function lifted as the exported function. The function is looked up
+    /// in the exported instance if `instance` is given.
+    pub fn lifted_func(&self, instance: Option<&str>, name: &str) -> Option<FuncAddr> {
+        fn find<'a>(items: &'a HashMap<String, Item>, name: &str) -> Option<&'a Item> {
+            items.get(name).or_else(|| {
+                items
+                    .iter()
+                    .find(|(key, _)| name_matches(key, name))
+                    .map(|(_, item)| item)
+            })
+        }
+        let item = match instance {
+            Some(instance) => match find(&self.exports, instance)? {
+                Item::Instance(instance) => find(&instance.items, name)?,
+                _ => return None,
+            },
+            None => find(&self.exports, name)?,
+        };
+        match item {
+            Item::Func(func) => match **func {
+                Func::Lifted { core } => core.addr,
+                Func::Host { .. } => None,
+            },
+            _ => None,
+        }
+    }
+}
+
+struct Instantiator<'a> {
+    store: &'a mut Store,
+    host: &'a ComponentHost,
+    bytes: &'a [u8],
+    binary: &'a binary::ComponentBinary,
+    config: Rc<Config>,
+    handles: Rc<RefCell<HandleTable>>,
+    modules: Vec<(ModuleIndex, usize)>,
+}
+
+impl Instantiator<'_> {
+    /// Evaluates the definitions of the component. `args` is `None` for the root
+    /// component, whose imports are implemented by the host.
+    fn instantiate(
+        &mut self,
+        component: &Component,
+        scope: Option<Rc<Scope>>,
+        args: Option<HashMap<String, Item>>,
+    ) -> Result<HashMap<String, Item>> {
+        let mut s = Spaces::default();
+        let mut exports = HashMap::new();
+        for definition in &component.definitions {
+            match definition {
+                Definition::CoreModule(ordinal) => s.core_modules.push(*ordinal),
+                Definition::CoreInstance(instance) => {
+                    let module_index = self.core_instance(&s, instance)?;
+                    s.core_instances.push(module_index);
+                }
+                Definition::CoreType => {}
+                Definition::Component(component) => {
+                    let closure = ComponentClosure {
+                        component: component.clone(),
+                        scope: s.scope(&scope),
+                    };
+                    s.components.push(Rc::new(closure));
+                }
+                Definition::Instance(Instance::Instantiate { component, args }) => {
+                    let closure = get(&s.components, *component, "component")?;
+                    let args = args
+                        .iter()
+                        .map(|(name, index)| Ok((name.clone(), s.item(*index)?)))
+                        .collect::<Result<_>>()?;
+                    let items = self.instantiate(
+                        &closure.component,
+                        Some(closure.scope.clone()),
+                        Some(args),
+                    )?;
+                    s.instances.push(Rc::new(InstanceValue { items }));
+                }
+                Definition::Instance(Instance::FromExports(items)) => {
+                    let items = items
+                        .iter()
+                        .map(|(name, index)| Ok((name.clone(), s.item(*index)?)))
+                        .collect::<Result<_>>()?;
+                    s.instances.push(Rc::new(InstanceValue { items }));
+                }
+                Definition::Alias(alias) => self.alias(&mut s, &scope, alias)?,
+                Definition::Type(decl) => {
+                    let ty = match &**decl {
+                        TypeDecl::Resource { dtor } => {
+                            let dtor = match dtor {
+                                Some(index) => ResourceDtor::Core(
+                                    get(&s.core_funcs, *index, "core function")?.exec,
+                                ),
+                                None => ResourceDtor::None,
+                            };
+                            TypeDef::Resource(ResourceType::new(
+                                format!("resource {}", s.types.len()),
+                                dtor,
+                            ))
+                        }
+                        decl => type_def(decl, &s.types, || s.scope(&scope))?,
+                    };
+                    s.types.push(ty);
+                }
+                Definition::Canon(canon) => self.canon(&mut s, canon)?,
+                Definition::Import { name, desc } => {
+                    let item = match &args {
+                        Some(args) => match args.get(name) {
+                            Some(item) => item.clone(),
+                            None => match desc {
+                                ExternDesc::Type(TypeBound::Eq(index)) => {
+                                    Item::Type(get(&s.types, *index, "type")?)
+                                }
+                                _ => {
+                                    return Err(anyhow!(
+                                        "missing instantiation argument '{}'",
+                                        name
+                                    ))
+                                }
+                            },
+                        },
+                        None => self.import(&s, name, desc)?,
+                    };
+                    s.push(item);
+                }
+                Definition::Export { name, item } => {
+                    let item = s.item(*item)?;
+                    s.push(item.clone());
+                    exports.insert(name.clone(), item);
+                }
+            }
+        }
+        Ok(exports)
+    }
+
+    fn core_instance(&mut self, s: &Spaces, instance: &CoreInstance) -> Result<ModuleIndex> {
+        match instance {
+            CoreInstance::Instantiate { module, args } => {
+                for (name, instance) in args {
+                    let module_index = get(&s.core_instances, *instance, "core instance")?;
+                    self.store.register_name(name.clone(), module_index);
+                }
+                let ordinal = get(&s.core_modules, *module, "module")?;
+                let range = self.binary.modules[ordinal].clone();
+                let offset = range.start;
+                let module_index = self
+                    .store
+                    .load_embedded_module(&self.bytes[range], offset)?;
+                self.modules.push((module_index, ordinal));
+                let start_func = self
+                    .store
+                    .module(module_index)
+                    .defined()
+                    .and_then(|module| *module.start_func_addr());
+                if let Some(start_func) = start_func {
+                    crate::invoke_func_ignoring_break(start_func, vec![], self.store, &self.config)
+                        .map_err(|err| anyhow!("failed to run the start function: {}", err))?;
+                }
+                Ok(module_index)
+            }
+            CoreInstance::FromExports(items) => {
+                let mut values = HashMap::new();
+                for (name, sort, index) in items {
+                    let value = match sort {
+                        CoreSort::Func => {
+                            HostExport::Func(get(&s.core_funcs, *index, "core function")?.exec)
+                        }
+                        CoreSort::Table => HostExport::Table(get(&s.core_tables, *index, "table")?),
+                        CoreSort::Memory => {
+                            HostExport::Mem(get(&s.core_memories, *index, "memory")?)
+                        }
+                        CoreSort::Global => {
+                            HostExport::Global(get(&s.core_globals, *index, "global")?)
+                        }
+                        _ => return Err(anyhow!("core {:?} can't be exported", sort)),
+                    };
+                    values.insert(name.clone(), value);
+                }
+                Ok(self.store.load_host_exports(values))
+            }
+        }
+    }
+
+    fn alias(&mut self, s: &mut Spaces, scope: &Option<Rc<Scope>>, alias: &Alias) -> Result<()> {
+        match alias {
+            Alias::InstanceExport {
+                sort,
+                instance,
+                name,
+            } => {
+                let instance = get(&s.instances, *instance, "instance")?;
+                let item = instance
+                    .items
+                    .get(name)
+                    .ok_or_else(|| anyhow!("instance has no export '{}'", name))?;
+                if sort_of(item) != *sort {
+                    return Err(anyhow!("export '{}' is not {:?}", name, sort));
+                }
+                s.push(item.clone());
+            }
+            Alias::CoreInstanceExport {
+                sort,
+                instance,
+                name,
+            } => {
+                let module_index = get(&s.core_instances, *instance, "core instance")?;
+                let export = self
+                    .store
+                    .resolve_export(module_index, name)
+                    .ok_or_else(|| anyhow!("core instance has no export '{}'", name))?;
+                match (sort, export) {
+                    (CoreSort::Func, HostExport::Func(exec)) => {
+                        let addr = self
+                            .store
+                            .module(module_index)
+                            .defined()
+                            .and_then(|module| module.exported_func(name).ok().flatten());
+                        s.core_funcs.push(CoreFunc { exec, addr });
+                    }
+                    (CoreSort::Table, HostExport::Table(addr)) => s.core_tables.push(addr),
+                    (CoreSort::Memory, HostExport::Mem(addr)) => s.core_memories.push(addr),
+                    (CoreSort::Global, HostExport::Global(addr)) => s.core_globals.push(addr),
+                    _ => return Err(anyhow!("core export '{}' is not {:?}", name, sort)),
+                }
+            }
+            Alias::Outer { sort, count, index } => {
+                let outer = match scope {
+                    Some(scope) if *count > 0 => scope.outer(*count)?,
+                    _ => return Err(anyhow!("outer alias count {} is not supported", count)),
+                };
+                let item = match sort {
+                    Sort::Type => Item::Type(get(&outer.types, *index, "type")?),
+                    Sort::Component => {
+                        Item::Component(get(&outer.components, *index, "component")?)
+                    }
+                    Sort::Core(CoreSort::Module) => {
+                        Item::Module(get(&outer.modules, *index, "module")?)
+                    }
+                    _ => return Err(anyhow!("outer alias of {:?} is not supported", sort)),
+                };
+                s.push(item);
+            }
+        }
+        Ok(())
+    }
+
+    fn import(&mut self, s: &Spaces, name: &str, desc: &ExternDesc) -> Result<Item> {
+        Ok(match desc {
+            ExternDesc::Instance(index) => match get(&s.types, *index, "type")? {
+                TypeDef::Instance(ty) => Item::Instance(Rc::new(self.host_instance(&ty, name)?)),
+                _ => return Err(anyhow!("type {} is not an instance type", index)),
+            },
+            ExternDesc::Func(index) => Item::Func(Rc::new(Func::Host {
+                instance: String::new(),
+                name: name.to_string(),
+                ty: s.func_type(*index)?,
+                func: self.host_item("", name).and_then(host_func),
+            })),
+            ExternDesc::Type(TypeBound::SubResource) => {
+                Item::Type(TypeDef::Resource(self.host_resource("", name)))
+            }
+            ExternDesc::Type(TypeBound::Eq(index)) => Item::Type(get(&s.types, *index, "type")?),
+            _ => {
+                return Err(anyhow!(
+                    "importing '{}' of {:?} is not supported",
+                    name,
+                    desc
+                ))
+            }
+        })
+    }
+
+    fn host_item(&self, instance: &str, name: &str) -> Option<&ComponentHostItem> {
+        self.host.instance(instance)?.get(name)
+    }
+
+    fn host_resource(&self, instance: &str, name: &str) -> ResourceType {
+        let dtor = match self.host_item(instance, name) {
+            Some(ComponentHostItem::Resource(dtor)) => ResourceDtor::Host(dtor.clone()),
+            _ => ResourceDtor::None,
+        };
+        ResourceType::new(format!("{}#{}", instance, name), dtor)
+    }
+
+    /// Creates the instance imported by the component from the instance type. Functions
+    /// missing in the host trap when they are called.
+    fn host_instance(&mut self, ty: &InstanceTypeDef, instance: &str) -> Result<InstanceValue> {
+        let mut types = Vec::new();
+        let mut items = HashMap::new();
+        for decl in ty.decls.iter() {
+            match decl {
+                InstanceDecl::CoreType => {}
+                InstanceDecl::Type(decl) => {
+                    let def = type_def(decl, &types, || {
+                        Rc::new(Scope {
+                            types: types.clone(),
+                            modules: vec![],
+                            components: vec![],
+                            parent: Some(ty.scope.clone()),
+                        })
+                    })?;
+                    types.push(def);
+                }
+                InstanceDecl::Alias(Alias::Outer {
+                    sort: Sort::Type,
+                    count,
+                    index,
+                }) => types.push(get(&ty.scope.outer(*count)?.types, *index, "type")?),
+                InstanceDecl::Alias(alias) => {
+                    return Err(anyhow!("{:?} in instance types is not supported", alias))
+                }
+                InstanceDecl::Export { name, desc } => {
+                    let item = match desc {
+                        ExternDesc::Type(TypeBound::SubResource) => {
+                            Item::Type(TypeDef::Resource(self.host_resource(instance, name)))
+                        }
+                        ExternDesc::Type(TypeBound::Eq(index)) => {
+                            Item::Type(get(&types, *index, "type")?)
+                        }
+                        ExternDesc::Func(index) => {
+                            let ty = match get(&types, *index, "type")? {
+                                TypeDef::Func(ty) => ty,
+                                _ => return Err(anyhow!("type {} is not a function type", index)),
+                            };
+                            Item::Func(Rc::new(Func::Host {
+                                instance: instance.to_string(),
+                                name: name.clone(),
+                                ty,
+                                func: self.host_item(instance, name).and_then(host_func),
+                            }))
+                        }
+                        ExternDesc::Instance(index) => match get(&types, *index, "type")? {
+                            TypeDef::Instance(ty) => {
+                                Item::Instance(Rc::new(self.host_instance(&ty, name)?))
+                            }
+                            _ => return Err(anyhow!("type {} is not an instance type", index)),
+                        },
+                        _ => {
+                            return Err(anyhow!(
+                                "exporting '{}' of {:?} is not supported",
+                                name,
+                                desc
+                            ))
+                        }
+                    };
+                    if let Item::Type(ty) = &item {
+                        types.push(ty.clone());
+                    }
+                    items.insert(name.clone(), item);
+                }
+            }
+        }
+        Ok(InstanceValue { items })
+    }
+
+    fn push_core_func(&mut self, s: &mut Spaces, instance: &str, name: &str, body: HostFuncBody) {
+        let exec = self
+            .store
+            .push_host_func(instance.to_string(), name.to_string(), body);
+        s.core_funcs.push(CoreFunc { exec, addr: None });
+    }
+
+    fn canon(&mut self, s: &mut Spaces, canon: &Canon) -> Result<()> {
+        let config = self.config.clone();
+        let handles = self.handles.clone();
+        match canon {
+            Canon::Lift { core_func, .. } => {
+                let core = get(&s.core_funcs, *core_func, "core function")?;
+                s.funcs.push(Rc::new(Func::Lifted { core }));
+            }
+            Canon::Lower { func, options } => {
+                let func = get(&s.funcs, *func, "function")?;
+                let (instance, name, ty, func) = match &*func {
+                    Func::Host {
+                        instance,
+                        name,
+                        ty,
+                        func,
+                    } => (instance.clone(), name.clone(), ty.clone(), func.clone()),
+                    Func::Lifted { .. } => {
+                        return Err(anyhow!(
+                            "lowering functions lifted by components is not supported"
+                        ))
+                    }
+                };
+                if options.string_encoding != StringEncoding::Utf8 {
+                    return Err(anyhow!(
+                        "{:?} string encoding is not supported",
+                        options.string_encoding
+                    ));
+                }
+                let memory = options
+                    .memory
+                    .map(|index| get(&s.core_memories, index, "memory"))
+                    .transpose()?;
+                let realloc = options
+                    .realloc
+                    .map(|index| get(&s.core_funcs, index, "core function").map(|func| func.exec))
+                    .transpose()?;
+                let core_ty = abi::lowered_func_type(&ty);
+                let missing = format!("{}#{} is not implemented by the host", instance, name);
+                let body =
+                    HostFuncBody::new_without_memory(core_ty, move |args, results, _, store| {
+                        let func = func.as_ref().ok_or_else(|| abi::trap(missing.clone()))?;
+                        let cx = LowerContext {
+                            store,
+                            config: &config,
+                            memory,
+                            realloc,
+                            handles: &handles,
+                        };
+                        results.extend(cx.call_host(&ty, args, func.as_ref())?);
+                        Ok(())
+                    });
+                self.push_core_func(s, &instance, &name, body);
+            }
+            Canon::ResourceNew(index) => {
+                let resource = s.resource_type(*index)?;
+                let ty = wasmparser::FuncType::new(
+                    [wasmparser::ValType::I32],
+                    [wasmparser::ValType::I32],
+                );
+                let name = format!("[resource-new]{}", resource.name());
+                let body = HostFuncBody::new_without_memory(ty, move |args, results, _, _| {
+                    let rep = args[0].as_i32().unwrap() as u32;
+                    let handle = handles.borrow_mut().insert(&resource, rep, true);
+                    results.push(Value::I32(handle as i32));
+                    Ok(())
+                });
+                self.push_core_func(s, "", &name, body);
+            }
+            Canon::ResourceDrop(index) => {
+                let resource = s.resource_type(*index)?;
+                let ty = wasmparser::FuncType::new([wasmparser::ValType::I32], []);
+                let name = format!("[resource-drop]{}", resource.name());
+                let body = HostFuncBody::new_without_memory(ty, move |args, _, _, store| {
+                    let handle = args[0].as_i32().unwrap() as u32;
+                    let rep = handles.borrow_mut().remove(handle, &resource)?;
+                    match rep {
+                        Some(rep) => abi::drop_resource(store, &config, &resource, rep),
+                        None => Ok(()),
+                    }
+                });
+                self.push_core_func(s, "", &name, body);
+            }
+            Canon::ResourceRep(index) => {
+                let resource = s.resource_type(*index)?;
+                let ty = wasmparser::FuncType::new(
+                    [wasmparser::ValType::I32],
+                    [wasmparser::ValType::I32],
+                );
+                let name = format!("[resource-rep]{}", resource.name());
+                let body = HostFuncBody::new_without_memory(ty, move |args, results, _, _| {
+                    let handle = args[0].as_i32().unwrap() as u32;
+                    let rep = handles.borrow().rep(handle, &resource)?;
+                    results.push(Value::I32(rep as i32));
+                    Ok(())
+                });
+                self.push_core_func(s, "", &name, body);
+            }
+        }
+        Ok(())
+    }
+}
+
+fn host_func(item: &ComponentHostItem) -> Option<ComponentHostFunc> {
+    match item {
+        ComponentHostItem::Func(func) => Some(func.clone()),
+        ComponentHostItem::Resource(_) => None,
+    }
+}
+
+impl Store {
+    /// Instantiates the component with the imports implemented by the host. The core
+    /// modules are loaded into the store and their start functions are run.
+    pub fn load_component(
+        &mut self,
+        bytes: &[u8],
+        host: &ComponentHost,
+        config: &Config,
+    ) -> Result<ComponentInstance> {
+        let binary = binary::parse(bytes)?;
+        let mut instantiator = Instantiator {
+            store: self,
+            host,
+            bytes,
+            binary: &binary,
+            config: Rc::new(Config {
+                features: config.features,
+            }),
+            handles: Rc::new(RefCell::new(HandleTable::default())),
+            modules: Vec::new(),
+        };
+        let exports = instantiator.instantiate(&binary.root, None, None)?;
+        Ok(ComponentInstance {
+            modules: instantiator.modules,
+            exports,
+        })
+    }
+}
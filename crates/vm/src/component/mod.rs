@@ -0,0 +1,175 @@
+//! Support of components of the component model.
+//!
+//! A component is instantiated by loading its core modules into the store and linking
+//! them through the canonical ABI, so the debugger executes core functions as usual.
+
+mod abi;
+mod binary;
+mod instance;
+mod types;
+
+pub use binary::{core_modules, is_component};
+pub use instance::{ComponentHost, ComponentHostFunc, ComponentHostItem, ComponentInstance};
+pub use types::ComponentValue;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{invoke_func_ignoring_break, Config, Store};
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    fn vec(items: &[Vec<u8>]) -> Vec<u8> {
+        let mut bytes = vec![items.len() as u8];
+        items.iter().for_each(|item| bytes.extend(item));
+        bytes
+    }
+
+    fn name(name: &str) -> Vec<u8> {
+        let mut bytes = vec![name.len() as u8];
+        bytes.extend(name.as_bytes());
+        bytes
+    }
+
+    fn section(id: u8, contents: Vec<u8>) -> Vec<u8> {
+        let mut bytes = vec![id, contents.len() as u8];
+        bytes.extend(contents);
+        bytes
+    }
+
+    fn concat(parts: &[Vec<u8>]) -> Vec<u8> {
+        parts.concat()
+    }
+
+    const CORE_HEADER: &[u8] = b"\0asm\x01\0\0\0";
+
+    /// A component running `(func (export "run") (result u32))` of the core module,
+    /// which calls the imported `log: func(msg: string)` with "hi" in the memory
+    fn component() -> Vec<u8> {
+        let memory_module = concat(&[
+            CORE_HEADER.to_vec(),
+            section(5, vec(&[vec![0x00, 0x01]])),
+            section(7, vec(&[concat(&[name("memory"), vec![0x02, 0x00]])])),
+            section(
+                11,
+                vec(&[concat(&[vec![0x00, 0x41, 0x00, 0x0b], name("hi")])]),
+            ),
+        ]);
+        let main_module = concat(&[
+            CORE_HEADER.to_vec(),
+            section(
+                1,
+                vec(&[
+                    vec![0x60, 0x02, 0x7f, 0x7f, 0x00],
+                    vec![0x60, 0x00, 0x01, 0x7f],
+                ]),
+            ),
+            section(
+                2,
+                vec(&[
+                    concat(&[name("env"), name("memory"), vec![0x02, 0x00, 0x01]]),
+                    concat(&[name("host"), name("log"), vec![0x00, 0x00]]),
+                ]),
+            ),
+            section(3, vec(&[vec![0x01]])),
+            section(7, vec(&[concat(&[name("run"), vec![0x00, 0x01]])])),
+            section(
+                10,
+                vec(&[section_body(&[
+                    0x00, 0x41, 0x00, 0x41, 0x02, 0x10, 0x00, 0x41, 0x07, 0x0b,
+                ])]),
+            ),
+        ]);
+        concat(&[
+            b"\0asm\x0d\0\x01\0".to_vec(),
+            section(
+                7,
+                vec(&[concat(&[
+                    vec![0x42, 0x02, 0x01, 0x40],
+                    vec(&[concat(&[name("msg"), vec![0x73]])]),
+                    vec![0x01, 0x00, 0x04, 0x00],
+                    name("log"),
+                    vec![0x01, 0x00],
+                ])]),
+            ),
+            section(
+                10,
+                vec(&[concat(&[
+                    vec![0x00],
+                    name("test:demo/host@1.0.0"),
+                    vec![0x05, 0x00],
+                ])]),
+            ),
+            section(6, vec(&[concat(&[vec![0x01, 0x00, 0x00], name("log")])])),
+            section(1, memory_module),
+            section(1, main_module),
+            section(2, vec(&[vec![0x00, 0x00, 0x00]])),
+            section(
+                6,
+                vec(&[concat(&[vec![0x00, 0x02, 0x01, 0x00], name("memory")])]),
+            ),
+            section(8, vec(&[vec![0x01, 0x00, 0x00, 0x01, 0x03, 0x00]])),
+            section(
+                2,
+                vec(&[
+                    concat(&[vec![0x01, 0x01], name("log"), vec![0x00, 0x00]]),
+                    concat(&[
+                        vec![0x00, 0x01, 0x02],
+                        name("env"),
+                        vec![0x12, 0x00],
+                        name("host"),
+                        vec![0x12, 0x01],
+                    ]),
+                ]),
+            ),
+            section(
+                6,
+                vec(&[concat(&[vec![0x00, 0x00, 0x01, 0x02], name("run")])]),
+            ),
+            section(7, vec(&[vec![0x40, 0x00, 0x00, 0x79]])),
+            section(8, vec(&[vec![0x00, 0x00, 0x01, 0x00, 0x01]])),
+            section(
+                11,
+                vec(&[concat(&[vec![0x00], name("run"), vec![0x01, 0x01, 0x00]])]),
+            ),
+        ])
+    }
+
+    fn section_body(code: &[u8]) -> Vec<u8> {
+        let mut bytes = vec![code.len() as u8];
+        bytes.extend(code);
+        bytes
+    }
+
+    #[test]
+    fn test_lower_host_function() {
+        let bytes = component();
+        assert!(is_component(&bytes));
+        assert_eq!(core_modules(&bytes).unwrap().len(), 2);
+
+        let logs = Rc::new(RefCell::new(Vec::new()));
+        let mut host = ComponentHost::new();
+        let host_logs = logs.clone();
+        host.func("test:demo/host", "log", move |args| {
+            host_logs.borrow_mut().extend(args);
+            Ok(vec![])
+        });
+        let config = Config::default();
+        let mut store = Store::new();
+        let instance = store.load_component(&bytes, &host, &config).unwrap();
+        assert_eq!(instance.modules.len(), 2);
+
+        let run = instance.lifted_func(None, "run").unwrap();
+        // Instruction offsets are shifted by the offset of the module in the component
+        let (offset, main_module) = core_modules(&bytes).unwrap()[1];
+        let (func, _) = store.func(run).unwrap();
+        let inst_offset = func.defined().unwrap().instructions()[0].offset;
+        assert!((offset..offset + main_module.len()).contains(&inst_offset));
+        let results = invoke_func_ignoring_break(run, vec![], &store, &config).unwrap();
+        assert_eq!(results[0].as_i32(), Some(7));
+        assert_eq!(
+            *logs.borrow(),
+            vec![ComponentValue::String("hi".to_string())]
+        );
+    }
+}
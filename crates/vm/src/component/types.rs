@@ -0,0 +1,104 @@
+use crate::address::ExecutableFuncAddr;
+use std::fmt;
+use std::rc::Rc;
+
+/// A value of the component model passed to and from host functions
+#[derive(Clone, Debug, PartialEq)]
+pub enum ComponentValue {
+    Bool(bool),
+    S8(i8),
+    U8(u8),
+    S16(i16),
+    U16(u16),
+    S32(i32),
+    U32(u32),
+    S64(i64),
+    U64(u64),
+    F32(f32),
+    F64(f64),
+    Char(char),
+    String(String),
+    List(Vec<ComponentValue>),
+    Record(Vec<ComponentValue>),
+    Tuple(Vec<ComponentValue>),
+    /// The case index and its payload
+    Variant(u32, Option<Box<ComponentValue>>),
+    Enum(u32),
+    Option(Option<Box<ComponentValue>>),
+    Result(Result<Option<Box<ComponentValue>>, Option<Box<ComponentValue>>>),
+    Flags(Vec<bool>),
+    /// The representation of an owned resource
+    Own(u32),
+    /// The representation of a borrowed resource
+    Borrow(u32),
+}
+
+pub(crate) enum ResourceDtor {
+    None,
+    Host(Rc<dyn Fn(u32)>),
+    Core(ExecutableFuncAddr),
+}
+
+pub(crate) struct ResourceInfo {
+    pub(crate) name: String,
+    pub(crate) dtor: ResourceDtor,
+}
+
+/// A resource type, which is distinguished by its identity rather than its name
+#[derive(Clone)]
+pub(crate) struct ResourceType(pub(crate) Rc<ResourceInfo>);
+
+impl ResourceType {
+    pub(crate) fn new(name: String, dtor: ResourceDtor) -> Self {
+        Self(Rc::new(ResourceInfo { name, dtor }))
+    }
+
+    pub(crate) fn name(&self) -> &str {
+        &self.0.name
+    }
+}
+
+impl PartialEq for ResourceType {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl fmt::Debug for ResourceType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "resource {}", self.0.name)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum ValueType {
+    Bool,
+    S8,
+    U8,
+    S16,
+    U16,
+    S32,
+    U32,
+    S64,
+    U64,
+    F32,
+    F64,
+    Char,
+    String,
+    List(Box<ValueType>),
+    Record(Vec<ValueType>),
+    Tuple(Vec<ValueType>),
+    Variant(Vec<Option<ValueType>>),
+    Enum(usize),
+    Option(Box<ValueType>),
+    Result(Option<Box<ValueType>>, Option<Box<ValueType>>),
+    Flags(usize),
+    Own(ResourceType),
+    Borrow(ResourceType),
+}
+
+#[derive(Debug)]
+pub(crate) struct FuncType {
+    pub(crate) params: Vec<ValueType>,
+    pub(crate) results: Vec<ValueType>,
+}
@@ -65,6 +65,10 @@ impl ElementInstance {
             .map(|addr| *addr)
     }
 
+    pub fn is_dropped(&self) -> bool {
+        self.elem.is_empty()
+    }
+
     pub fn drop_elem(&mut self) {
         self.elem = vec![];
     }
@@ -55,6 +55,8 @@ pub enum Trap {
         base: u32,
         offset: u64,
     },
+    /// The process requested to exit with the status
+    Exit(i32),
 }
 
 impl std::error::Error for Trap {}
@@ -81,6 +83,7 @@ impl std::fmt::Display for Trap {
             ),
             Self::UndefinedFunc(addr) => write!(f, "uninitialized element {:?}", addr),
             Self::Unreachable => write!(f, "unreachable"),
+            Self::Exit(status) => write!(f, "Process exited with status {}", status),
             Self::MemoryAddrOverflow { base, offset } => write!(
                 f,
                 "out of bounds memory access: memory address overflow (base: {}, offset: {})",
@@ -140,6 +143,7 @@ impl std::fmt::Display for ReturnValError {
     }
 }
 
+#[derive(Clone)]
 pub struct Executor {
     pub pc: ProgramCounter,
     pub stack: Stack,
@@ -155,6 +159,20 @@ impl Executor {
         Self { pc, stack }
     }
 
+    /// Pushes a frame called from the current pc and moves the pc into it.
+    /// This is used to restore a call stack without executing instructions.
+    pub fn push_frame(
+        &mut self,
+        frame: CallFrame,
+        arity: usize,
+        pc: ProgramCounter,
+    ) -> ExecResult<()> {
+        self.stack.set_frame(frame).map_err(Trap::Stack)?;
+        self.stack.push_label(Label::Return { arity });
+        self.pc = pc;
+        Ok(())
+    }
+
     pub fn pop_result(&mut self, return_ty: Vec<ValType>) -> ReturnValResult {
         let mut results = vec![];
         for ty in return_ty.into_iter().rev() {
@@ -257,23 +275,25 @@ impl Executor {
                 }
                 Signal::Next
             }
-            InstructionKind::Else => self.branch(0, store)?,
+            InstructionKind::Else => self.branch(0, store, interceptor)?,
             InstructionKind::End => {
                 if self.stack.is_func_top_level().map_err(Trap::Stack)? {
                     // When the end of a function is reached without a jump
                     let ret_pc = self.stack.current_frame().map_err(Trap::Stack)?.ret_pc;
                     let func = store.func_global(self.pc.exec_addr());
-                    let results = self
+                    let mut results = self
                         .stack
                         .pop_values(func.ty().results().len())
                         .map_err(Trap::Stack)?;
+                    results.reverse();
                     self.stack.pop_label().map_err(Trap::Stack)?;
                     self.stack.pop_frame().map_err(Trap::Stack)?;
-                    self.stack.push_values(results.into_iter().rev());
+                    self.stack.push_values(results.iter().copied());
                     if let Some(ret_pc) = ret_pc {
                         self.pc = ret_pc;
-                        Signal::Next
+                        interceptor.return_func(func.name(), &results, self, store)?
                     } else {
+                        interceptor.return_func(func.name(), &results, self, store)?;
                         Signal::End
                     }
                 } else {
@@ -289,11 +309,13 @@ impl Executor {
                     Signal::Next
                 }
             }
-            InstructionKind::Br { relative_depth } => self.branch(*relative_depth, store)?,
+            InstructionKind::Br { relative_depth } => {
+                self.branch(*relative_depth, store, interceptor)?
+            }
             InstructionKind::BrIf { relative_depth } => {
                 let val = self.stack.pop_value().map_err(Trap::Stack)?;
                 if val != Value::I32(0) {
-                    self.branch(*relative_depth, store)?
+                    self.branch(*relative_depth, store, interceptor)?
                 } else {
                     Signal::Next
                 }
@@ -306,9 +328,9 @@ impl Executor {
                 } else {
                     targets.default
                 };
-                self.branch(depth, store)?
+                self.branch(depth, store, interceptor)?
             }
-            InstructionKind::Return => self.do_return(store)?,
+            InstructionKind::Return => self.do_return(store, interceptor)?,
             InstructionKind::Call { function_index } => {
                 let frame = self.stack.current_frame().map_err(Trap::Stack)?;
                 let addr = FuncAddr::new_unsafe(frame.module_index(), *function_index as usize);
@@ -604,7 +626,7 @@ impl Executor {
                 dst_mem.borrow().validate_region(dst_base, n)?;
                 dst_mem.borrow_mut().store(dst_base, &values)?;
 
-                Signal::Next
+                interceptor.after_store(dst_base, &values)?
             }
             InstructionKind::MemoryFill { mem } => {
                 let addr = MemoryAddr::new_unsafe(module_index, *mem as usize);
@@ -616,10 +638,10 @@ impl Executor {
 
                 mem.borrow().validate_region(offset, n)?;
 
-                mem.borrow_mut()
-                    .store(offset, &std::iter::repeat(val).take(n).collect::<Vec<_>>())?;
+                let values = std::iter::repeat(val).take(n).collect::<Vec<_>>();
+                mem.borrow_mut().store(offset, &values)?;
 
-                Signal::Next
+                interceptor.after_store(offset, &values)?
             }
             InstructionKind::MemoryInit { data_index, mem } => {
                 let mem_addr = MemoryAddr::new_unsafe(module_index, *mem as usize);
@@ -633,9 +655,10 @@ impl Executor {
                 mem.borrow().validate_region(dst_base, n)?;
                 data.borrow().validate_region(src_base, n)?;
 
-                mem.borrow_mut()
-                    .store(dst_base, &data.borrow().raw()[src_base..(src_base + n)])?;
-                Signal::Next
+                let data = data.borrow();
+                let values = &data.raw()[src_base..(src_base + n)];
+                mem.borrow_mut().store(dst_base, values)?;
+                interceptor.after_store(dst_base, values)?
             }
             InstructionKind::DataDrop { data_index } => {
                 let data_addr = DataAddr::new_unsafe(module_index, *data_index as usize);
@@ -865,7 +888,12 @@ impl Executor {
         Ok(ref_val)
     }
 
-    fn branch(&mut self, depth: u32, store: &Store) -> ExecResult<Signal> {
+    fn branch<I: Interceptor>(
+        &mut self,
+        depth: u32,
+        store: &Store,
+        interceptor: &I,
+    ) -> ExecResult<Signal> {
         let depth = depth as usize;
         let label = *self.stack.frame_label(depth).map_err(Trap::Stack)?;
 
@@ -889,7 +917,7 @@ impl Executor {
         match label {
             Label::Loop { label, .. } => self.pc.loop_jump(&label),
             Label::Return { .. } => {
-                return self.do_return(store);
+                return self.do_return(store, interceptor);
             }
             Label::If { .. } | Label::Block { .. } => {
                 let mut depth = depth + 1;
@@ -1008,20 +1036,21 @@ impl Executor {
             }
         }
     }
-    fn do_return(&mut self, store: &Store) -> ExecResult<Signal> {
+    fn do_return<I: Interceptor>(&mut self, store: &Store, interceptor: &I) -> ExecResult<Signal> {
         let ret_pc = self.stack.current_frame().map_err(Trap::Stack)?.ret_pc;
         let func = store.func_global(self.pc.exec_addr());
         let arity = func.ty().results().len();
-        let results = self.stack.pop_values(arity).map_err(Trap::Stack)?;
+        let mut results = self.stack.pop_values(arity).map_err(Trap::Stack)?;
+        results.reverse();
         self.stack
             .pop_while(|v| !matches!(v, StackValue::Activation(_)));
         self.stack.pop_frame().map_err(Trap::Stack)?;
-        self.stack.push_values(results.into_iter().rev());
+        self.stack.push_values(results.iter().copied());
 
         if let Some(ret_pc) = ret_pc {
             self.pc = ret_pc;
         }
-        Ok(Signal::Next)
+        interceptor.return_func(func.name(), &results, self, store)
     }
 
     /// Returns a pair of arities for parameter and result
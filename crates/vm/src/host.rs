@@ -28,6 +28,8 @@ type HostCode = dyn Fn(&[Value], &mut Vec<Value>, &mut HostContext, &Store) -> R
 pub struct HostFuncBody {
     ty: FuncType,
     code: Box<HostCode>,
+    /// Borrow the memory of the caller module while calling the body
+    borrows_memory: bool,
 }
 
 impl HostFuncBody {
@@ -39,6 +41,21 @@ impl HostFuncBody {
         Self {
             ty,
             code: Box::new(code),
+            borrows_memory: true,
+        }
+    }
+
+    /// Creates the body called with an empty memory, which accesses memories through the
+    /// store by itself, e.g. to call `realloc` of the guest in the middle of the call
+    pub fn new_without_memory<F>(ty: FuncType, code: F) -> Self
+    where
+        F: Fn(&[Value], &mut Vec<Value>, &mut HostContext, &Store) -> Result<(), Trap>,
+        F: 'static,
+    {
+        Self {
+            ty,
+            code: Box::new(code),
+            borrows_memory: false,
         }
     }
 
@@ -49,19 +66,30 @@ impl HostFuncBody {
         store: &Store,
         module_index: ModuleIndex,
     ) -> Result<(), Trap> {
-        if store.memory_count(module_index) > 0 {
+        if self.borrows_memory && store.memory_count(module_index) > 0 {
             let mem_addr = MemoryAddr::new_unsafe(module_index, 0);
             let mem = store.memory(mem_addr);
             let mem = &mut mem.borrow_mut();
             let raw_mem = mem.raw_data_mut();
             let mut ctx = HostContext { mem: raw_mem };
-            (self.code)(param, results, &mut ctx, store)
+            self.call_with_context(param, results, &mut ctx, store)
         } else {
             let mut ctx = HostContext { mem: &mut [] };
-            (self.code)(param, results, &mut ctx, store)
+            self.call_with_context(param, results, &mut ctx, store)
         }
     }
 
+    /// Calls the body with the memory already borrowed by the caller, e.g. by another host function wrapping this one
+    pub fn call_with_context(
+        &self,
+        param: &[Value],
+        results: &mut Vec<Value>,
+        ctx: &mut HostContext,
+        store: &Store,
+    ) -> Result<(), Trap> {
+        (self.code)(param, results, ctx, store)
+    }
+
     pub fn ty(&self) -> &FuncType {
         &self.ty
     }
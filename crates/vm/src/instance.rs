@@ -80,6 +80,6 @@ impl WasmInstance {
         } else {
             return Err(WasmError::EntryFunctionNotFound("_start".to_string()));
         };
-        invoke_func_ignoring_break(func_addr, arguments, &mut self.store, config)
+        invoke_func_ignoring_break(func_addr, arguments, &self.store, config)
     }
 }
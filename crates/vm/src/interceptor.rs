@@ -1,9 +1,18 @@
 use crate::executor::{ExecResult, Signal};
 use crate::inst::Instruction;
+use crate::value::Value;
 use crate::{Executor, Store};
 
 pub trait Interceptor {
     fn invoke_func(&self, name: &str, executor: &Executor, store: &Store) -> ExecResult<Signal>;
+    /// Called after returning from a defined function to the caller
+    fn return_func(
+        &self,
+        name: &str,
+        results: &[Value],
+        executor: &Executor,
+        store: &Store,
+    ) -> ExecResult<Signal>;
     fn execute_inst(&self, inst: &Instruction) -> ExecResult<Signal>;
     fn after_store(&self, addr: usize, bytes: &[u8]) -> ExecResult<Signal>;
 }
@@ -19,6 +28,15 @@ impl Interceptor for NopInterceptor {
     fn invoke_func(&self, _name: &str, _executor: &Executor, _store: &Store) -> ExecResult<Signal> {
         Ok(Signal::Next)
     }
+    fn return_func(
+        &self,
+        _name: &str,
+        _results: &[Value],
+        _executor: &Executor,
+        _store: &Store,
+    ) -> ExecResult<Signal> {
+        Ok(Signal::Next)
+    }
     fn execute_inst(&self, _inst: &Instruction) -> ExecResult<Signal> {
         Ok(Signal::Next)
     }
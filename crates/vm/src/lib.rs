@@ -1,4 +1,5 @@
 mod address;
+mod component;
 mod config;
 mod data;
 mod elem;
@@ -13,12 +14,17 @@ mod interceptor;
 mod linker;
 mod memory;
 mod module;
+mod snapshot;
 mod stack;
 mod store;
 mod table;
 mod value;
 
 pub use self::address::*;
+pub use self::component::{
+    core_modules, is_component, ComponentHost, ComponentHostFunc, ComponentHostItem,
+    ComponentInstance, ComponentValue,
+};
 pub use self::config::Config;
 pub use self::executor::{Executor, Signal, Trap, WasmError};
 pub use self::func::{FunctionInstance, InstIndex};
@@ -29,18 +35,21 @@ pub use self::instance::WasmInstance;
 pub use self::interceptor::{Interceptor, NopInterceptor};
 pub use self::memory::MemoryInstance as HostMemory;
 pub use self::module::{DefinedModuleInstance, ModuleIndex};
+pub use self::snapshot::{module_hash, restore_snapshot, save_snapshot};
 pub use self::stack::{CallFrame, ProgramCounter};
 pub use self::store::Store;
 pub use self::table::TableInstance as HostTable;
 pub use self::value::Value as WasmValue;
 pub use self::value::*;
 
+use self::func::DefinedFunctionInstance;
+
 pub const WASM_PAGE_SIZE: usize = 0x10000;
 
 pub fn invoke_func_ignoring_break(
     func_addr: FuncAddr,
     arguments: Vec<WasmValue>,
-    store: &mut Store,
+    store: &Store,
     config: &Config,
 ) -> Result<Vec<WasmValue>, WasmError> {
     match store
@@ -58,26 +67,37 @@ pub fn invoke_func_ignoring_break(
             }
         }
         (FunctionInstance::Defined(func), exec_addr) => {
-            let (frame, ret_types) = {
-                let ret_types = func.ty().results();
-                let frame = CallFrame::new_from_func(exec_addr, func, arguments, None);
-                (frame, ret_types)
-            };
-            let pc = ProgramCounter::new(func.module_index(), exec_addr, InstIndex::zero());
-            let interceptor = NopInterceptor::new();
-            let mut executor = Executor::new(frame, ret_types.len(), pc);
-            loop {
-                let result = executor.execute_step(store, &interceptor, config);
-                match result {
-                    Ok(Signal::Next) => continue,
-                    Ok(Signal::Breakpoint) => continue,
-                    Ok(Signal::End) => match executor.pop_result(ret_types.to_vec()) {
-                        Ok(values) => return Ok(values),
-                        Err(err) => return Err(WasmError::ReturnValueError(err)),
-                    },
-                    Err(err) => return Err(WasmError::ExecutionError(err)),
-                }
-            }
+            execute_defined_func(func, exec_addr, arguments, store, config)
+        }
+    }
+}
+
+/// Executes the function to the end on a new executor without the interceptor
+pub(crate) fn execute_defined_func(
+    func: &DefinedFunctionInstance,
+    exec_addr: ExecutableFuncAddr,
+    arguments: Vec<WasmValue>,
+    store: &Store,
+    config: &Config,
+) -> Result<Vec<WasmValue>, WasmError> {
+    let (frame, ret_types) = {
+        let ret_types = func.ty().results();
+        let frame = CallFrame::new_from_func(exec_addr, func, arguments, None);
+        (frame, ret_types)
+    };
+    let pc = ProgramCounter::new(func.module_index(), exec_addr, InstIndex::zero());
+    let interceptor = NopInterceptor::new();
+    let mut executor = Executor::new(frame, ret_types.len(), pc);
+    loop {
+        let result = executor.execute_step(store, &interceptor, config);
+        match result {
+            Ok(Signal::Next) => continue,
+            Ok(Signal::Breakpoint) => continue,
+            Ok(Signal::End) => match executor.pop_result(ret_types.to_vec()) {
+                Ok(values) => return Ok(values),
+                Err(err) => return Err(WasmError::ReturnValueError(err)),
+            },
+            Err(err) => return Err(WasmError::ExecutionError(err)),
         }
     }
 }
@@ -5,7 +5,6 @@ use std::hash::Hash;
 
 /// An address value which points an `Item` in `LinkableCollection`
 /// The pointee item must be exists in the collection.
-#[derive(PartialEq, Eq, Hash)]
 pub struct GlobalAddress<Item>(usize, std::marker::PhantomData<Item>);
 
 impl<Item> Clone for GlobalAddress<Item> {
@@ -16,6 +15,19 @@ impl<Item> Clone for GlobalAddress<Item> {
 
 impl<Item> Copy for GlobalAddress<Item> {}
 
+impl<Item> PartialEq for GlobalAddress<Item> {
+    fn eq(&self, other: &Self) -> bool {
+        self.0 == other.0
+    }
+}
+
+impl<Item> Eq for GlobalAddress<Item> {}
+impl<Item> Hash for GlobalAddress<Item> {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        state.write_usize(self.0);
+    }
+}
+
 impl<Item> fmt::Debug for GlobalAddress<Item> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "GlobalAddress({})", self.0)
@@ -186,6 +198,17 @@ impl<Item> LinkableCollection<Item> {
         )
     }
 
+    pub(crate) fn index_of(
+        &self,
+        module_index: ModuleIndex,
+        address: GlobalAddress<Item>,
+    ) -> Option<usize> {
+        self.item_addrs_by_module
+            .get(&module_index)?
+            .iter()
+            .position(|index| *index == address.0)
+    }
+
     pub(crate) fn is_empty(&self, module_index: ModuleIndex) -> bool {
         self.item_addrs_by_module
             .get(&module_index)
@@ -102,6 +102,13 @@ impl MemoryInstance {
         self.initial = len;
         Ok(())
     }
+    /// Shrinks or grows the memory to the given number of pages without limit checks.
+    /// This is used to rewind the memory to a recorded state.
+    pub fn set_page_count(&mut self, pages: usize) {
+        self.data.resize(pages * WASM_PAGE_SIZE, 0);
+        self.initial = pages;
+    }
+
     pub fn raw_data_mut(&mut self) -> &mut [u8] {
         &mut self.data
     }
@@ -157,6 +157,7 @@ impl std::fmt::Display for HostModuleError {
 
 type HostModuleResult<T> = std::result::Result<T, HostModuleError>;
 
+#[derive(Clone, Copy)]
 pub enum HostExport {
     Func(ExecutableFuncAddr),
     Global(ResolvedGlobalAddr),
@@ -181,6 +182,10 @@ impl HostModuleInstance {
 }
 
 impl HostModuleInstance {
+    pub(crate) fn get(&self, name: &str) -> Option<&HostExport> {
+        self.values.get(name)
+    }
+
     pub(crate) fn global_by_name(
         &self,
         name: String,
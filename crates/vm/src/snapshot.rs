@@ -0,0 +1,453 @@
+//! Serialization of the execution state of a module instance.
+//!
+//! A snapshot holds the hash of the module bytes, memories, globals, tables, dropped
+//! segments, and the call frames, labels, operand stack and PC of an executor running
+//! in the module. Host modules are not serialized. Instantiate the same module with
+//! host modules in a fresh store, then restore the snapshot into it.
+
+use crate::address::*;
+use crate::executor::Executor;
+use crate::func::InstIndex;
+use crate::module::ModuleIndex;
+use crate::stack::{CallFrame, Label, ProgramCounter, Stack, StackValue};
+use crate::store::Store;
+use crate::value::{NumVal, RefType, RefVal, Value};
+use anyhow::{anyhow, Result};
+
+const MAGIC: &[u8; 8] = b"\0wasmsnp";
+const VERSION: u32 = 1;
+
+/// Returns the 64-bit FNV-1a hash of the module bytes recorded in snapshots
+pub fn module_hash(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// Serializes the state of the module instance and the executor running in it
+pub fn save_snapshot(
+    store: &Store,
+    module_index: ModuleIndex,
+    module_bytes: &[u8],
+    executor: &Executor,
+) -> Result<Vec<u8>> {
+    let mut writer = Writer::default();
+    writer.bytes.extend_from_slice(MAGIC);
+    writer.u32(VERSION);
+    writer.u64(module_hash(module_bytes));
+
+    let memory_count = store.memory_count(module_index);
+    writer.u32(memory_count as u32);
+    for index in 0..memory_count {
+        let memory = store.memory(MemoryAddr::new_unsafe(module_index, index));
+        let memory = memory.borrow();
+        writer.u64(memory.raw_data().len() as u64);
+        writer.bytes.extend_from_slice(memory.raw_data());
+    }
+
+    let global_count = store.global_count(module_index);
+    writer.u32(global_count as u32);
+    for index in 0..global_count {
+        let global = store.global(GlobalAddr::new_unsafe(module_index, index));
+        writer.value(global.borrow().value());
+    }
+
+    let table_count = store.table_count(module_index);
+    writer.u32(table_count as u32);
+    for index in 0..table_count {
+        let table = store.table(TableAddr::new_unsafe(module_index, index));
+        let table = table.borrow();
+        writer.u32(table.buffer_len() as u32);
+        for index in 0..table.buffer_len() {
+            let element = table
+                .get_at(index)
+                .map_err(|err| anyhow!("Failed to read table: {:?}", err))?;
+            writer.ref_val(element);
+        }
+    }
+
+    let data_count = store.data_count(module_index);
+    writer.u32(data_count as u32);
+    for index in 0..data_count {
+        let data = store.data(DataAddr::new_unsafe(module_index, index));
+        let is_dropped = data.borrow().raw().is_empty();
+        writer.u8(is_dropped as u8);
+    }
+    let elem_count = store.elem_count(module_index);
+    writer.u32(elem_count as u32);
+    for index in 0..elem_count {
+        let elem = store.elem(ElemAddr::new_unsafe(module_index, index));
+        let is_dropped = elem.borrow().is_dropped();
+        writer.u8(is_dropped as u8);
+    }
+
+    writer.pc(store, module_index, executor.pc)?;
+    let entries = executor.stack.entries();
+    writer.u32(entries.len() as u32);
+    for entry in entries {
+        match entry {
+            StackValue::Value(value) => {
+                writer.u8(0);
+                writer.value(*value);
+            }
+            StackValue::Label(label) => {
+                writer.u8(1);
+                writer.label(*label);
+            }
+            StackValue::Activation(frame) => {
+                writer.u8(2);
+                writer.func(store, module_index, frame.exec_addr)?;
+                match frame.ret_pc {
+                    Some(pc) => {
+                        writer.u8(1);
+                        writer.pc(store, module_index, pc)?;
+                    }
+                    None => writer.u8(0),
+                }
+                writer.u32(frame.locals.len() as u32);
+                for local in frame.locals.iter() {
+                    writer.value(*local);
+                }
+            }
+        }
+    }
+    Ok(writer.bytes)
+}
+
+/// Restores the state of the module instance from a snapshot of the same module,
+/// and returns the executor to resume
+pub fn restore_snapshot(
+    store: &Store,
+    module_index: ModuleIndex,
+    module_bytes: &[u8],
+    snapshot: &[u8],
+) -> Result<Executor> {
+    let mut reader = Reader {
+        bytes: snapshot,
+        offset: 0,
+    };
+    if reader.take(MAGIC.len())? != MAGIC {
+        return Err(anyhow!("Not a snapshot file"));
+    }
+    let version = reader.u32()?;
+    if version != VERSION {
+        return Err(anyhow!("Unsupported snapshot version {}", version));
+    }
+    if reader.u64()? != module_hash(module_bytes) {
+        return Err(anyhow!("The snapshot was taken from a different module"));
+    }
+
+    let memory_count = reader.count(store.memory_count(module_index), "memories")?;
+    for index in 0..memory_count {
+        let len = reader.u64()? as usize;
+        let data = reader.take(len)?;
+        let memory = store.memory(MemoryAddr::new_unsafe(module_index, index));
+        let mut memory = memory.borrow_mut();
+        memory.set_page_count(len / crate::WASM_PAGE_SIZE);
+        memory.raw_data_mut().copy_from_slice(data);
+    }
+
+    let global_count = reader.count(store.global_count(module_index), "globals")?;
+    for index in 0..global_count {
+        let value = reader.value()?;
+        let global = store.global(GlobalAddr::new_unsafe(module_index, index));
+        let mut global = global.borrow_mut();
+        if global.is_mutable() {
+            global.set_value(value);
+        }
+    }
+
+    let table_count = reader.count(store.table_count(module_index), "tables")?;
+    for index in 0..table_count {
+        let len = reader.u32()? as usize;
+        let elements = (0..len)
+            .map(|_| reader.ref_val())
+            .collect::<Result<Vec<_>>>()?;
+        let table = store.table(TableAddr::new_unsafe(module_index, index));
+        table.borrow_mut().restore(elements);
+    }
+
+    let data_count = reader.count(store.data_count(module_index), "data segments")?;
+    for index in 0..data_count {
+        if reader.u8()? != 0 {
+            let data = store.data(DataAddr::new_unsafe(module_index, index));
+            data.borrow_mut().drop_bytes();
+        }
+    }
+    let elem_count = reader.count(store.elem_count(module_index), "element segments")?;
+    for index in 0..elem_count {
+        if reader.u8()? != 0 {
+            let elem = store.elem(ElemAddr::new_unsafe(module_index, index));
+            elem.borrow_mut().drop_elem();
+        }
+    }
+
+    let pc = reader.pc(store, module_index)?;
+    let len = reader.u32()?;
+    let mut entries = vec![];
+    for _ in 0..len {
+        let entry = match reader.u8()? {
+            0 => StackValue::Value(reader.value()?),
+            1 => StackValue::Label(reader.label()?),
+            2 => {
+                let exec_addr = reader.func(store, module_index)?;
+                let ret_pc = match reader.u8()? {
+                    0 => None,
+                    _ => Some(reader.pc(store, module_index)?),
+                };
+                let func = store
+                    .func_global(exec_addr)
+                    .defined()
+                    .ok_or_else(|| anyhow!("Host function can't have a frame"))?;
+                let mut frame = CallFrame::new_from_func(exec_addr, func, vec![], ret_pc);
+                let local_count = reader.count(frame.locals.len(), "locals")?;
+                for index in 0..local_count {
+                    frame.set_local(index, reader.value()?);
+                }
+                StackValue::Activation(frame)
+            }
+            tag => return Err(anyhow!("Invalid stack entry {}", tag)),
+        };
+        entries.push(entry);
+    }
+    Ok(Executor {
+        pc,
+        stack: Stack::from_entries(entries),
+    })
+}
+
+#[derive(Default)]
+struct Writer {
+    bytes: Vec<u8>,
+}
+
+impl Writer {
+    fn u8(&mut self, value: u8) {
+        self.bytes.push(value);
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn u64(&mut self, value: u64) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn value(&mut self, value: Value) {
+        match value {
+            Value::Num(NumVal::I32(v)) => {
+                self.u8(0);
+                self.u32(v as u32);
+            }
+            Value::Num(NumVal::I64(v)) => {
+                self.u8(1);
+                self.u64(v as u64);
+            }
+            Value::Num(NumVal::F32(v)) => {
+                self.u8(2);
+                self.u32(v.to_bits());
+            }
+            Value::Num(NumVal::F64(v)) => {
+                self.u8(3);
+                self.u64(v.to_bits());
+            }
+            Value::Ref(v) => {
+                self.u8(4);
+                self.ref_val(v);
+            }
+        }
+    }
+
+    fn ref_val(&mut self, value: RefVal) {
+        match value {
+            RefVal::NullRef(RefType::FuncRef) => self.u8(0),
+            RefVal::NullRef(RefType::ExternRef) => self.u8(1),
+            RefVal::FuncRef(addr) => {
+                self.u8(2);
+                self.u32(addr.module_index().0);
+                self.u32(addr.1 as u32);
+            }
+            RefVal::ExternRef(v) => {
+                self.u8(3);
+                self.u32(v);
+            }
+        }
+    }
+
+    fn label(&mut self, label: Label) {
+        match label {
+            Label::If { arity } => {
+                self.u8(0);
+                self.u32(arity as u32);
+            }
+            Label::Block { arity } => {
+                self.u8(1);
+                self.u32(arity as u32);
+            }
+            Label::Loop { arity, label } => {
+                self.u8(2);
+                self.u32(arity as u32);
+                self.u32(label.inst_index().0);
+            }
+            Label::Return { arity } => {
+                self.u8(3);
+                self.u32(arity as u32);
+            }
+        }
+    }
+
+    /// Functions are identified by the index in the module to be stable across stores
+    fn func(
+        &mut self,
+        store: &Store,
+        module_index: ModuleIndex,
+        addr: ExecutableFuncAddr,
+    ) -> Result<()> {
+        let index = store
+            .func_index(module_index, addr)
+            .ok_or_else(|| anyhow!("Function {:?} is not in the module", addr))?;
+        self.u32(index as u32);
+        Ok(())
+    }
+
+    fn pc(&mut self, store: &Store, module_index: ModuleIndex, pc: ProgramCounter) -> Result<()> {
+        self.func(store, module_index, pc.exec_addr())?;
+        self.u32(pc.inst_index().0);
+        Ok(())
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        let end = self
+            .offset
+            .checked_add(len)
+            .filter(|end| *end <= self.bytes.len())
+            .ok_or_else(|| anyhow!("Unexpected end of snapshot"))?;
+        let bytes = &self.bytes[self.offset..end];
+        self.offset = end;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        let mut bytes = [0; 4];
+        bytes.copy_from_slice(self.take(4)?);
+        Ok(u32::from_le_bytes(bytes))
+    }
+
+    fn u64(&mut self) -> Result<u64> {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(self.take(8)?);
+        Ok(u64::from_le_bytes(bytes))
+    }
+
+    /// Reads the number of items, which must match with the instantiated module
+    fn count(&mut self, expected: usize, name: &str) -> Result<usize> {
+        let count = self.u32()? as usize;
+        if count != expected {
+            return Err(anyhow!(
+                "The snapshot has {} {}, but the module has {}",
+                count,
+                name,
+                expected
+            ));
+        }
+        Ok(count)
+    }
+
+    fn value(&mut self) -> Result<Value> {
+        Ok(match self.u8()? {
+            0 => Value::I32(self.u32()? as i32),
+            1 => Value::I64(self.u64()? as i64),
+            2 => Value::F32(self.u32()?),
+            3 => Value::F64(self.u64()?),
+            4 => Value::Ref(self.ref_val()?),
+            tag => return Err(anyhow!("Invalid value type {}", tag)),
+        })
+    }
+
+    fn ref_val(&mut self) -> Result<RefVal> {
+        Ok(match self.u8()? {
+            0 => RefVal::NullRef(RefType::FuncRef),
+            1 => RefVal::NullRef(RefType::ExternRef),
+            2 => {
+                let module_index = ModuleIndex(self.u32()?);
+                let index = self.u32()? as usize;
+                RefVal::FuncRef(FuncAddr::new_unsafe(module_index, index))
+            }
+            3 => RefVal::ExternRef(self.u32()?),
+            tag => return Err(anyhow!("Invalid reference type {}", tag)),
+        })
+    }
+
+    fn label(&mut self) -> Result<Label> {
+        let tag = self.u8()?;
+        let arity = self.u32()? as usize;
+        Ok(match tag {
+            0 => Label::If { arity },
+            1 => Label::Block { arity },
+            2 => Label::new_loop(InstIndex(self.u32()?), arity),
+            3 => Label::Return { arity },
+            tag => return Err(anyhow!("Invalid label {}", tag)),
+        })
+    }
+
+    fn func(&mut self, store: &Store, module_index: ModuleIndex) -> Result<ExecutableFuncAddr> {
+        let index = self.u32()? as usize;
+        let (_, exec_addr) = store
+            .func(FuncAddr::new_unsafe(module_index, index))
+            .ok_or_else(|| anyhow!("Function {} not found", index))?;
+        Ok(exec_addr)
+    }
+
+    fn pc(&mut self, store: &Store, module_index: ModuleIndex) -> Result<ProgramCounter> {
+        let exec_addr = self.func(store, module_index)?;
+        let inst_index = InstIndex(self.u32()?);
+        Ok(ProgramCounter::new(module_index, exec_addr, inst_index))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_module_hash() {
+        assert_eq!(module_hash(b""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(module_hash(b"a"), 0xaf63_dc4c_8601_ec8c);
+    }
+
+    #[test]
+    fn test_value_roundtrip() {
+        let values = vec![
+            Value::I32(-1),
+            Value::I64(i64::MIN),
+            Value::F32(0x7fc0_0001),
+            Value::F64(0x7ff8_0000_0000_0001),
+            Value::Ref(RefVal::NullRef(RefType::ExternRef)),
+            Value::Ref(RefVal::ExternRef(42)),
+        ];
+        let mut writer = Writer::default();
+        for value in values.iter() {
+            writer.value(*value);
+        }
+        let mut reader = Reader {
+            bytes: &writer.bytes,
+            offset: 0,
+        };
+        for value in values {
+            assert_eq!(reader.value().unwrap(), value);
+        }
+        assert!(reader.u8().is_err());
+    }
+}
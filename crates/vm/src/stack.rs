@@ -48,6 +48,12 @@ pub struct LoopLabel {
     inst_index: InstIndex,
 }
 
+impl LoopLabel {
+    pub(crate) fn inst_index(&self) -> InstIndex {
+        self.inst_index
+    }
+}
+
 impl Label {
     pub fn new_loop(inst_index: InstIndex, arity: usize) -> Self {
         Self::Loop {
@@ -239,7 +245,7 @@ impl std::fmt::Display for StackValue {
     }
 }
 
-#[derive(Default)]
+#[derive(Clone, Default)]
 pub struct Stack {
     stack: Vec<StackValue>,
     frame_index: Vec<usize>,
@@ -279,6 +285,25 @@ impl Stack {
     }
 }
 
+// Snapshot
+impl Stack {
+    pub(crate) fn entries(&self) -> &[StackValue] {
+        &self.stack
+    }
+
+    pub(crate) fn from_entries(stack: Vec<StackValue>) -> Self {
+        let frame_index = stack
+            .iter()
+            .enumerate()
+            .filter_map(|(index, value)| match value {
+                StackValue::Activation(_) => Some(index),
+                _ => None,
+            })
+            .collect();
+        Self { stack, frame_index }
+    }
+}
+
 impl Stack {
     pub fn pop_while<F: Fn(&StackValue) -> bool>(&mut self, f: F) -> Vec<StackValue> {
         let mut result = vec![];
@@ -288,6 +313,11 @@ impl Stack {
         result
     }
 
+    /// Returns the number of call frames, which is cheaper than `peek_frames`
+    pub fn frame_depth(&self) -> usize {
+        self.frame_index.len()
+    }
+
     pub fn current_frame_index(&self) -> Result<usize> {
         self.frame_index.last().cloned().ok_or(Error::NoCallFrame)
     }
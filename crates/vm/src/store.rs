@@ -2,9 +2,10 @@ use crate::address::*;
 use crate::data::DataInstance;
 use crate::elem::ElementInstance;
 use crate::executor::eval_const_expr;
+use crate::export::ExternalValue;
 use crate::func::{DefinedFunctionInstance, FunctionInstance, NativeFunctionInstance};
 use crate::global::GlobalInstance;
-use crate::host::HostValue;
+use crate::host::{HostFuncBody, HostValue};
 use crate::linker::LinkableCollection;
 use crate::memory::{self, MemoryInstance};
 use crate::module::{
@@ -48,10 +49,19 @@ impl Store {
         self.funcs.get(addr)
     }
 
+    /// Returns the index of the function in the function index space of the module
+    pub fn func_index(&self, module_index: ModuleIndex, addr: ExecutableFuncAddr) -> Option<usize> {
+        self.funcs.index_of(module_index, addr)
+    }
+
     pub fn global(&self, addr: GlobalAddr) -> Rc<RefCell<GlobalInstance>> {
         self.globals.get(addr).unwrap().0.clone()
     }
 
+    pub fn global_count(&self, addr: ModuleIndex) -> usize {
+        self.globals.items(addr).map(|c| c.len()).unwrap_or(0)
+    }
+
     pub fn scan_global_by_name(
         &self,
         module_index: ModuleIndex,
@@ -66,6 +76,10 @@ impl Store {
         self.tables.get(addr).unwrap().0.clone()
     }
 
+    pub fn table_count(&self, addr: ModuleIndex) -> usize {
+        self.tables.items(addr).map(|c| c.len()).unwrap_or(0)
+    }
+
     pub fn memory(&self, addr: MemoryAddr) -> Rc<RefCell<MemoryInstance>> {
         self.mems.get(addr).unwrap().0.clone()
     }
@@ -74,6 +88,14 @@ impl Store {
         self.mems.items(addr).map(|c| c.len()).unwrap_or(0)
     }
 
+    pub(crate) fn elem_count(&self, addr: ModuleIndex) -> usize {
+        self.elems.items(addr).map(|c| c.len()).unwrap_or(0)
+    }
+
+    pub(crate) fn data_count(&self, addr: ModuleIndex) -> usize {
+        self.data.items(addr).map(|c| c.len()).unwrap_or(0)
+    }
+
     pub fn elem(&self, addr: ElemAddr) -> Rc<RefCell<ElementInstance>> {
         self.elems.get(addr).unwrap().0.clone()
     }
@@ -130,6 +152,48 @@ impl Store {
         self.module_index_by_name.insert(name, module_index);
     }
 
+    /// Loads the exports bundled by a component as a host module without name
+    pub(crate) fn load_host_exports(&mut self, values: HashMap<String, HostExport>) -> ModuleIndex {
+        let module_index = ModuleIndex(self.modules.len() as u32);
+        let instance = HostModuleInstance::new(values);
+        self.modules.push(ModuleInstance::Host(instance));
+        module_index
+    }
+
+    pub(crate) fn push_host_func(
+        &mut self,
+        module_name: String,
+        field_name: String,
+        body: HostFuncBody,
+    ) -> ExecutableFuncAddr {
+        let instance =
+            NativeFunctionInstance::new(body.ty().clone(), module_name, field_name, body);
+        self.funcs.push_global(FunctionInstance::Native(instance))
+    }
+
+    /// Resolves the export of the module to the address shared between modules
+    pub(crate) fn resolve_export(
+        &self,
+        module_index: ModuleIndex,
+        name: &str,
+    ) -> Option<HostExport> {
+        match self.module(module_index) {
+            ModuleInstance::Defined(defined) => {
+                Some(match defined.exported_by_name(name)?.value() {
+                    ExternalValue::Func(addr) => HostExport::Func(self.funcs.resolve(*addr)?),
+                    ExternalValue::Global(addr) => HostExport::Global(self.globals.resolve(*addr)?),
+                    ExternalValue::Memory(addr) => HostExport::Mem(self.mems.resolve(*addr)?),
+                    ExternalValue::Table(addr) => HostExport::Table(self.tables.resolve(*addr)?),
+                })
+            }
+            ModuleInstance::Host(host) => host.get(name).copied(),
+        }
+    }
+
+    pub(crate) fn resolved_memory(&self, addr: ResolvedMemoryAddr) -> Rc<RefCell<MemoryInstance>> {
+        self.mems.get_global(addr).clone()
+    }
+
     pub fn add_embed_context<T: std::any::Any>(&mut self, ctx: Box<T>) {
         let type_id = std::any::TypeId::of::<T>();
         self.embedded_contexts.insert(type_id, ctx);
@@ -254,6 +318,7 @@ impl Store {
         name: Option<String>,
         reader: &[u8],
         module_index: ModuleIndex,
+        offset: usize,
     ) -> Result<ModuleIndex> {
         let mut types = Vec::new();
         let mut elem_segs = Vec::new();
@@ -271,7 +336,7 @@ impl Store {
 
         let mut code_section_base_offset = None;
 
-        let parser = wasmparser::Parser::new(0);
+        let parser = wasmparser::Parser::new(offset as u64);
 
         for payload in parser.parse_all(reader) {
             use wasmparser::Payload;
@@ -316,7 +381,8 @@ impl Store {
                     }
                 }
                 Payload::CodeSectionStart { count, range, .. } => {
-                    code_section_base_offset = Some(range.start);
+                    // Instruction offsets are relative to the code section plus `offset`
+                    code_section_base_offset = Some(range.start - offset);
                     bodies.reserve_exact(count as usize);
                 }
                 Payload::CodeSectionEntry(entry) => {
@@ -393,13 +459,24 @@ impl Store {
     pub fn load_module(&mut self, name: Option<String>, reader: &[u8]) -> Result<ModuleIndex> {
         let module_index = ModuleIndex(self.modules.len() as u32);
 
-        let result: Result<ModuleIndex> = self.load_module_internal(name, reader, module_index);
+        let result: Result<ModuleIndex> = self.load_module_internal(name, reader, module_index, 0);
         match result {
             Ok(ok) => Ok(ok),
             Err(err) => Err(err),
         }
     }
 
+    /// Loads the module embedded at `offset` of a larger binary like a component. The
+    /// instruction offsets are shifted by `offset` to be distinct among the modules.
+    pub(crate) fn load_embedded_module(
+        &mut self,
+        reader: &[u8],
+        offset: usize,
+    ) -> Result<ModuleIndex> {
+        let module_index = ModuleIndex(self.modules.len() as u32);
+        self.load_module_internal(None, reader, module_index, offset)
+    }
+
     fn load_imports(
         &mut self,
         imports: Vec<Import>,
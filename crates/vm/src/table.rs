@@ -86,6 +86,12 @@ impl TableInstance {
         self.buffer.len()
     }
 
+    /// Replaces all elements including the size of the table.
+    /// This is used to rewind the table to a recorded state.
+    pub fn restore(&mut self, buffer: Vec<RefVal>) {
+        self.buffer = buffer;
+    }
+
     pub fn get_at(&self, index: usize) -> Result<RefVal> {
         self.buffer
             .get(index)
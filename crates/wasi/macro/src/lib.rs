@@ -8,3 +8,13 @@ use proc_macro::TokenStream;
 pub fn define_wasi_fn_for_wasminspect(args: TokenStream) -> TokenStream {
     wasi::define_wasi_fn_for_wasminspect(args.into()).into()
 }
+
+#[proc_macro]
+pub fn wasi_errno_names(args: TokenStream) -> TokenStream {
+    wasi::wasi_errno_names(args.into()).into()
+}
+
+#[proc_macro]
+pub fn wasi_signatures(args: TokenStream) -> TokenStream {
+    wasi::wasi_signatures(args.into()).into()
+}
@@ -1,15 +1,131 @@
 use crate::utils;
-use proc_macro2::{Ident, Literal, Span, TokenStream};
+use proc_macro2::{Ident, Literal, Span, TokenStream, TokenTree};
 use quote::quote;
 use utils::witx_target_module_map_ident;
-use witx::WasmType;
+use witx::{BuiltinType, InterfaceFunc, Type, TypeRef, WasmType};
+
+fn type_name(tref: &TypeRef) -> String {
+    match tref {
+        TypeRef::Name(named) => named.name.as_str().to_string(),
+        TypeRef::Value(ty) => match &**ty {
+            Type::List(elem) if matches!(&**elem.type_(), Type::Builtin(BuiltinType::Char)) => {
+                "string".to_string()
+            }
+            Type::List(elem) => format!("list<{}>", type_name(elem)),
+            Type::Pointer(elem) => format!("pointer<{}>", type_name(elem)),
+            Type::ConstPointer(elem) => format!("const_pointer<{}>", type_name(elem)),
+            Type::Builtin(builtin) => match builtin {
+                BuiltinType::Char => "char",
+                BuiltinType::U8 { .. } => "u8",
+                BuiltinType::U16 => "u16",
+                BuiltinType::U32 { .. } => "u32",
+                BuiltinType::U64 => "u64",
+                BuiltinType::S8 => "s8",
+                BuiltinType::S16 => "s16",
+                BuiltinType::S32 => "s32",
+                BuiltinType::S64 => "s64",
+                BuiltinType::F32 => "f32",
+                BuiltinType::F64 => "f64",
+            }
+            .to_string(),
+            ty => ty.kind().to_string(),
+        },
+    }
+}
+
+fn case_names<'a>(names: impl Iterator<Item = &'a witx::Id>) -> TokenStream {
+    let names = names.map(|name| name.as_str());
+    quote! { &[#(#names),*] }
+}
+
+/// How the tracer decodes the lowered values of the parameter
+fn emit_trace_param(tref: &TypeRef) -> TokenStream {
+    match &**tref.type_() {
+        Type::Record(record) if record.bitflags_repr().is_some() => {
+            let names = case_names(record.members.iter().map(|member| &member.name));
+            quote! { trace::Param::Flags(#names) }
+        }
+        Type::Variant(variant) if variant.is_enum() => {
+            let names = case_names(variant.cases.iter().map(|case| &case.name));
+            quote! { trace::Param::Enum(#names) }
+        }
+        Type::List(elem) => match &**elem.type_() {
+            Type::Builtin(BuiltinType::Char) => quote! { trace::Param::Str },
+            // iovec and ciovec
+            Type::Record(record)
+                if record.members.len() == 2
+                    && record.members[0].name.as_str() == "buf"
+                    && record.members[1].name.as_str() == "buf_len" =>
+            {
+                let is_const = matches!(&**record.members[0].tref.type_(), Type::ConstPointer(_));
+                quote! { trace::Param::Iovecs { is_const: #is_const } }
+            }
+            _ => quote! { trace::Param::List },
+        },
+        Type::Pointer(_) | Type::ConstPointer(_) | Type::Record(_) => {
+            quote! { trace::Param::Pointer }
+        }
+        Type::Builtin(_) | Type::Handle(_) | Type::Variant(_) => quote! { trace::Param::Int },
+    }
+}
+
+/// How the tracer reads the value written to the out pointer
+fn emit_trace_out(tref: &TypeRef) -> TokenStream {
+    match &**tref.type_() {
+        Type::Builtin(BuiltinType::U64) | Type::Builtin(BuiltinType::S64) => {
+            quote! { trace::Out::U64 }
+        }
+        Type::Builtin(BuiltinType::F32) | Type::Builtin(BuiltinType::F64) => {
+            quote! { trace::Out::Pointer }
+        }
+        Type::Builtin(_) | Type::Handle(_) => quote! { trace::Out::U32 },
+        _ => quote! { trace::Out::Pointer },
+    }
+}
+
+fn emit_trace_signature(func: &InterfaceFunc) -> TokenStream {
+    let name = func.name.as_str();
+    let params = func.params.iter().map(|param| {
+        let param_name = param.name.as_str();
+        let ty_name = type_name(&param.tref);
+        let kind = emit_trace_param(&param.tref);
+        quote! { (#param_name, #ty_name, #kind) }
+    });
+    let mut outs = Vec::new();
+    for result in func.results.iter() {
+        let ok = match &**result.tref.type_() {
+            Type::Variant(variant) => variant.as_expected().and_then(|(ok, _)| ok),
+            _ => None,
+        };
+        match ok.map(|ok| &**ok.type_()) {
+            Some(Type::Record(record)) if record.is_tuple() => outs.extend(
+                record
+                    .members
+                    .iter()
+                    .map(|member| emit_trace_out(&member.tref)),
+            ),
+            Some(_) => outs.push(emit_trace_out(ok.unwrap())),
+            None => {}
+        }
+    }
+    quote! {
+        trace::Signature {
+            name: #name,
+            params: &[#(#params),*],
+            outs: &[#(#outs),*],
+            errno_names: WASI_ERRNO_NAMES,
+        }
+    }
+}
 
 fn emit_func_extern(
     name: &str,
     params: &[WasmType],
     returns: &[WasmType],
     module_map_id: &Ident,
+    snapshot_id: &Ident,
     module_id: &Ident,
+    signature: TokenStream,
 ) -> TokenStream {
     let to_wasmparser_ty = |abi_ty: &WasmType| match abi_ty {
         WasmType::I32 => quote! { ::wasmparser::ValType::I32 },
@@ -36,79 +152,91 @@ fn emit_func_extern(
         arg_values.push(quote! { args[#idx_lit].#cast_fn().unwrap() });
     }
 
-    let mut return_types = Vec::new();
-    for ret in returns {
-        let ret = to_wasmparser_ty(ret);
-        return_types.push(quote! { #ret });
-    }
-
-    let mut ret_value = quote! {};
-    if let Some(ret_ty) = returns.first() {
-        assert!(returns.len() == 1);
-        let (primitive_ty, ty_case) = match ret_ty {
-            WasmType::I32 => (quote! { i32 }, quote! { WasmValue::I32 }),
-            WasmType::I64 => (quote! { i64 }, quote! { WasmValue::I64 }),
-            WasmType::F32 => (quote! { f32 }, quote! { WasmValue::F32 }),
-            WasmType::F64 => (quote! { f64 }, quote! { WasmValue::F64 }),
-        };
-        ret_value = quote! { ret.push(#ty_case(result as #primitive_ty)); };
-    }
-
     let name_id = Ident::new(name, Span::call_site());
     let name_str = name;
-    let call_expr = if name == "proc_exit" {
-        quote! {
-            let result = crate::wasi_proc_exit(
-                #(#arg_values),*
-            );
-        }
-    } else {
-        quote! {
-            let result = match wasi_common::snapshots::preview_1::#module_id::#name_id(
-                &*wasi_ctx,
-                &mem,
-                #(#arg_values),*
-            ) {
-                Ok(result) => result,
-                Err(e) => return Err(Trap::HostFunctionError(Box::new(WasiError(format!("{:?}", e))))),
-            };
-            #ret_value
-        }
-    };
-    quote! {
-        let ty = ::wasmparser::FuncType::new(
-            vec![#(#param_types),*],
-            vec![#(#return_types),*],
-        );
-        let func = HostValue::Func(HostFuncBody::new(ty, move |args, ret, ctx, store| {
-            log::debug!("{}({:?})", #name, args);
-            let wasi_ctx = store.get_embed_context::<WasiContext>().unwrap();
-            let mut wasi_ctx = wasi_ctx.ctx.borrow_mut();
+    if name == "proc_exit" {
+        // proc_exit has no errno to return or inject
+        return quote! {{
+            static SIGNATURE: trace::Signature = #signature;
+            let ty = ::wasmparser::FuncType::new(vec![#(#param_types),*], vec![]);
+            let func = HostValue::Func(HostFuncBody::new(ty, move |args, _ret, ctx, store| {
+                log::debug!("{}({:?})", #name, args);
+                let wasi_ctx = store.get_embed_context::<WasiContext>().unwrap();
+                if wasi_ctx.trace.get() {
+                    SIGNATURE.log_call(&SIGNATURE.format_call(args, ctx.mem));
+                }
+                crate::wasi_proc_exit(
+                    #(#arg_values),*
+                )
+            }));
+            #module_map_id.insert(#name_str.to_string(), func);
+        }};
+    }
+    // The other functions return errno
+    assert!(matches!(returns, [WasmType::I32]));
+    quote! {{
+        static SIGNATURE: trace::Signature = #signature;
+        let func = crate::wasi_func(&SIGNATURE, vec![#(#param_types),*], move |args, ctx, wasi_ctx| {
+            let wasi_ctx = wasi_ctx.ctx.borrow_mut();
             let bc = unsafe { borrow::BorrowChecker::new() };
             let mem = WasiMemory {
                 mem: ctx.mem.as_mut_ptr(),
                 mem_size: ctx.mem.len() as u32,
                 bc,
             };
-            #call_expr
-            Ok(())
-        }));
+            wasi_common::snapshots::#snapshot_id::#module_id::#name_id(
+                &*wasi_ctx,
+                &mem,
+                #(#arg_values),*
+            ).map(|result| result as i32)
+        });
         #module_map_id.insert(#name_str.to_string(), func);
+    }}
+}
+
+fn load_witx(arg: TokenTree) -> witx::Document {
+    let path = utils::witx_path_from_arg(arg);
+    match witx::load(&[&path]) {
+        Ok(doc) => doc,
+        Err(e) => {
+            panic!("error opening file {}: {}", path.display(), e);
+        }
     }
 }
 
+/// Expands to the names of the errno values of the witx document
+pub fn wasi_errno_names(args: TokenStream) -> TokenStream {
+    let doc = load_witx(args.into_iter().next().expect("witx path"));
+    let errno = doc.error_types().next();
+    errno
+        .and_then(|errno| match &**errno.type_() {
+            Type::Variant(variant) => Some(case_names(variant.cases.iter().map(|case| &case.name))),
+            _ => None,
+        })
+        .unwrap_or_else(|| quote! { &[] })
+}
+
+/// Expands to the signatures of all functions of the witx document
+pub fn wasi_signatures(args: TokenStream) -> TokenStream {
+    let doc = load_witx(args.into_iter().next().expect("witx path"));
+    let signatures = doc
+        .modules()
+        .flat_map(|module| module.funcs().collect::<Vec<_>>())
+        .map(|func| emit_trace_signature(&func));
+    quote! { &[#(#signatures),*] }
+}
+
 pub fn define_wasi_fn_for_wasminspect(args: TokenStream) -> TokenStream {
     let mut args = args.into_iter();
     let module_map_id = witx_target_module_map_ident(args.next().expect("module map id"));
     args.next(); // consume ","
     let module_map_id = Ident::new(&module_map_id, Span::call_site());
-    let path = utils::witx_path_from_arg(args.next().expect("witx path"));
-    let doc = match witx::load(&[&path]) {
-        Ok(doc) => doc,
-        Err(e) => {
-            panic!("error opening file {}: {}", path.display(), e);
-        }
-    };
+    let doc = load_witx(args.next().expect("witx path"));
+    args.next(); // consume ","
+
+    // The module of wasi-common which implements the snapshot
+    let snapshot_id = witx_target_module_map_ident(args.next().expect("snapshot module id"));
+    let snapshot_id = Ident::new(&snapshot_id, Span::call_site());
 
     let mut ctor_externs = Vec::new();
 
@@ -125,7 +253,9 @@ pub fn define_wasi_fn_for_wasminspect(args: TokenStream) -> TokenStream {
                 &params,
                 &returns,
                 &module_map_id,
+                &snapshot_id,
                 &module_id,
+                emit_trace_signature(&func),
             ));
         }
     }
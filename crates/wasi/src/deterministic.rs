@@ -0,0 +1,348 @@
+//! Deterministic replacements of the WASI functions which depend on the host
+//! environment.
+//!
+//! Clocks are replaced with a virtual clock which advances by a fixed tick on every
+//! read, `random_get` is fed by a seeded PRNG, and `sched_yield` and `poll_oneoff`
+//! never block, so two runs of the same module make the same observations.
+
+use crate::trace::Signature;
+use crate::wasi_func;
+use anyhow::anyhow;
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::time::Duration;
+use wasminspect_vm::*;
+use wasmparser::ValType;
+
+/// Nanoseconds the virtual clock advances on every read
+const CLOCK_TICK: u64 = 1_000;
+
+const ERRNO_SUCCESS: i32 = 0;
+const ERRNO_FAULT: i32 = 21;
+const ERRNO_INVAL: i32 = 28;
+
+pub(crate) const CLOCKID_REALTIME: i32 = 0;
+pub(crate) const CLOCKID_MONOTONIC: i32 = 1;
+const CLOCKID_THREAD_CPUTIME: i32 = 3;
+
+const EVENTTYPE_CLOCK: u8 = 0;
+const SUBSCRIPTION_CLOCK_ABSTIME: u16 = 1;
+const EVENT_SIZE: usize = 32;
+
+#[derive(Clone, Debug, Default)]
+pub struct DeterministicOptions {
+    /// The initial time of the realtime clock since the Unix epoch
+    pub clock_start: Duration,
+    /// The seed of the PRNG for `random_get`
+    pub random_seed: u64,
+}
+
+pub(crate) struct DeterministicEnv {
+    clock_start: u64,
+    /// Nanoseconds elapsed on the virtual clock
+    elapsed: Cell<u64>,
+    rng_state: Cell<u64>,
+}
+
+impl DeterministicEnv {
+    pub(crate) fn new(options: &DeterministicOptions) -> Self {
+        Self {
+            clock_start: options.clock_start.as_nanos() as u64,
+            elapsed: Cell::new(0),
+            rng_state: Cell::new(options.random_seed),
+        }
+    }
+
+    pub(crate) fn now(&self, clock_id: i32) -> u64 {
+        let elapsed = self.elapsed.get() + CLOCK_TICK;
+        self.elapsed.set(elapsed);
+        if clock_id == CLOCKID_REALTIME {
+            self.clock_start + elapsed
+        } else {
+            elapsed
+        }
+    }
+
+    /// SplitMix64
+    pub(crate) fn next_random(&self) -> u64 {
+        let state = self.rng_state.get().wrapping_add(0x9e37_79b9_7f4a_7c15);
+        self.rng_state.set(state);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+}
+
+/// Layout of the clock subscription, which differs between preview0 and preview1
+pub(crate) struct SubscriptionLayout {
+    size: usize,
+    clock_id: usize,
+    timeout: usize,
+    flags: usize,
+}
+
+pub(crate) const PREVIEW1_SUBSCRIPTION: SubscriptionLayout = SubscriptionLayout {
+    size: 48,
+    clock_id: 16,
+    timeout: 24,
+    flags: 40,
+};
+
+pub(crate) const PREVIEW0_SUBSCRIPTION: SubscriptionLayout = SubscriptionLayout {
+    size: 56,
+    clock_id: 24,
+    timeout: 32,
+    flags: 48,
+};
+
+fn is_valid_clock(clock_id: i32) -> bool {
+    (CLOCKID_REALTIME..=CLOCKID_THREAD_CPUTIME).contains(&clock_id)
+}
+
+fn errno(is_success: bool) -> i32 {
+    if is_success {
+        ERRNO_SUCCESS
+    } else {
+        ERRNO_FAULT
+    }
+}
+
+fn arg(args: &[WasmValue], index: usize) -> usize {
+    args[index].as_i32().unwrap() as u32 as usize
+}
+
+fn write_bytes(mem: &mut [u8], ptr: usize, bytes: &[u8]) -> bool {
+    match mem.get_mut(ptr..ptr + bytes.len()) {
+        Some(dst) => {
+            dst.copy_from_slice(bytes);
+            true
+        }
+        None => false,
+    }
+}
+
+fn read_bytes<const N: usize>(mem: &[u8], ptr: usize) -> Option<[u8; N]> {
+    let mut bytes = [0; N];
+    bytes.copy_from_slice(mem.get(ptr..ptr + N)?);
+    Some(bytes)
+}
+
+fn func<F>(signature: &'static Signature, params: Vec<ValType>, code: F) -> HostValue
+where
+    F: Fn(&[WasmValue], &mut HostContext, &DeterministicEnv) -> i32 + 'static,
+{
+    wasi_func(signature, params, move |args, ctx, wasi_ctx| {
+        let env = wasi_ctx
+            .deterministic
+            .as_ref()
+            .ok_or_else(|| anyhow!("deterministic WASI environment is not set up"))?;
+        Ok::<_, anyhow::Error>(code(args, ctx, env))
+    })
+}
+
+/// Replaces the functions of the WASI module with the deterministic ones
+pub(crate) fn override_functions(
+    module: &mut HashMap<String, HostValue>,
+    signatures: &'static [Signature],
+    subscription: &'static SubscriptionLayout,
+) {
+    use ValType::*;
+    let signature = |name| crate::signature(signatures, name);
+    module.insert(
+        "clock_res_get".to_string(),
+        func(
+            signature("clock_res_get"),
+            vec![I32, I32],
+            |args, ctx, _| {
+                if !is_valid_clock(args[0].as_i32().unwrap()) {
+                    return ERRNO_INVAL;
+                }
+                errno(write_bytes(
+                    ctx.mem,
+                    arg(args, 1),
+                    &CLOCK_TICK.to_le_bytes(),
+                ))
+            },
+        ),
+    );
+    module.insert(
+        "clock_time_get".to_string(),
+        func(
+            signature("clock_time_get"),
+            vec![I32, I64, I32],
+            |args, ctx, env| {
+                let clock_id = args[0].as_i32().unwrap();
+                if !is_valid_clock(clock_id) {
+                    return ERRNO_INVAL;
+                }
+                let now = env.now(clock_id);
+                errno(write_bytes(ctx.mem, arg(args, 2), &now.to_le_bytes()))
+            },
+        ),
+    );
+    module.insert(
+        "random_get".to_string(),
+        func(signature("random_get"), vec![I32, I32], |args, ctx, env| {
+            let (ptr, len) = (arg(args, 0), arg(args, 1));
+            let buf = match ctx.mem.get_mut(ptr..ptr + len) {
+                Some(buf) => buf,
+                None => return ERRNO_FAULT,
+            };
+            for chunk in buf.chunks_mut(8) {
+                let random = env.next_random().to_le_bytes();
+                chunk.copy_from_slice(&random[..chunk.len()]);
+            }
+            ERRNO_SUCCESS
+        }),
+    );
+    module.insert(
+        "sched_yield".to_string(),
+        func(signature("sched_yield"), vec![], |_, _, _| ERRNO_SUCCESS),
+    );
+    module.insert(
+        "poll_oneoff".to_string(),
+        func(
+            signature("poll_oneoff"),
+            vec![I32, I32, I32, I32],
+            move |args, ctx, env| {
+                poll_oneoff(ctx.mem, args, env, subscription).unwrap_or(ERRNO_FAULT)
+            },
+        ),
+    );
+}
+
+/// Reports fd subscriptions as ready without blocking. If there are only clock
+/// subscriptions, the virtual clock jumps to the earliest deadline instead of sleeping.
+fn poll_oneoff(
+    mem: &mut [u8],
+    args: &[WasmValue],
+    env: &DeterministicEnv,
+    layout: &SubscriptionLayout,
+) -> Option<i32> {
+    let (input, output, count, nevents_ptr) =
+        (arg(args, 0), arg(args, 1), arg(args, 2), arg(args, 3));
+    if count == 0 {
+        return Some(ERRNO_INVAL);
+    }
+    let mut clocks = vec![];
+    let mut fds = vec![];
+    for index in 0..count {
+        let sub = input + index * layout.size;
+        let userdata = read_bytes::<8>(mem, sub)?;
+        let tag = *mem.get(sub + 8)?;
+        if tag == EVENTTYPE_CLOCK {
+            let clock_id = i32::from_le_bytes(read_bytes(mem, sub + layout.clock_id)?);
+            let timeout = u64::from_le_bytes(read_bytes(mem, sub + layout.timeout)?);
+            let flags = u16::from_le_bytes(read_bytes(mem, sub + layout.flags)?);
+            let deadline = if flags & SUBSCRIPTION_CLOCK_ABSTIME != 0 {
+                let origin = if clock_id == CLOCKID_REALTIME {
+                    env.clock_start
+                } else {
+                    0
+                };
+                timeout.saturating_sub(origin)
+            } else {
+                env.elapsed.get().saturating_add(timeout)
+            };
+            clocks.push((userdata, deadline));
+        } else {
+            fds.push((userdata, tag));
+        }
+    }
+    let mut events = vec![];
+    if fds.is_empty() {
+        let deadline = clocks.iter().map(|(_, deadline)| *deadline).min()?;
+        if deadline > env.elapsed.get() {
+            env.elapsed.set(deadline);
+        }
+        for (userdata, deadline) in clocks {
+            if deadline <= env.elapsed.get() {
+                events.push((userdata, EVENTTYPE_CLOCK));
+            }
+        }
+    } else {
+        events = fds;
+    }
+    for (index, (userdata, tag)) in events.iter().enumerate() {
+        let mut event = [0; EVENT_SIZE];
+        event[0..8].copy_from_slice(userdata);
+        event[10] = *tag;
+        if !write_bytes(mem, output + index * EVENT_SIZE, &event) {
+            return Some(ERRNO_FAULT);
+        }
+    }
+    let nevents = (events.len() as u32).to_le_bytes();
+    Some(errno(write_bytes(mem, nevents_ptr, &nevents)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{instantiate_wasi, parse_errno, FaultOptions, StdioOptions};
+
+    #[test]
+    fn test_poll_oneoff_advances_clock() {
+        let env = DeterministicEnv::new(&DeterministicOptions::default());
+        let layout = &PREVIEW1_SUBSCRIPTION;
+        let mut mem = vec![0; 128];
+        // A relative clock subscription of 1ms on the monotonic clock
+        mem[0..8].copy_from_slice(&7u64.to_le_bytes());
+        mem[layout.clock_id..layout.clock_id + 4].copy_from_slice(&1i32.to_le_bytes());
+        mem[layout.timeout..layout.timeout + 8].copy_from_slice(&1_000_000u64.to_le_bytes());
+        let args = [0, 64, 1, 120].map(WasmValue::I32);
+        assert_eq!(
+            poll_oneoff(&mut mem, &args, &env, layout),
+            Some(ERRNO_SUCCESS)
+        );
+        assert_eq!(mem[120], 1);
+        assert_eq!(mem[64..72], 7u64.to_le_bytes());
+        assert_eq!(env.now(1), 1_000_000 + CLOCK_TICK);
+    }
+
+    #[test]
+    fn test_fault_on_deterministic_function() {
+        let deterministic = DeterministicOptions::default();
+        let (ctx, mut modules) = instantiate_wasi(
+            &[],
+            vec![],
+            &[],
+            &StdioOptions::default(),
+            Some(&deterministic),
+            None,
+            &[],
+        )
+        .unwrap();
+        let preview1 = modules.get_mut("wasi_snapshot_preview1").unwrap();
+        let func = match preview1.remove("clock_time_get") {
+            Some(HostValue::Func(func)) => func,
+            _ => panic!("clock_time_get is not a function"),
+        };
+        let errno = parse_errno("io").unwrap();
+        ctx.add_fault(FaultOptions {
+            function: "clock_time_get".to_string(),
+            errno,
+            skip: 0,
+            count: Some(1),
+            path_regex: None,
+        });
+        let mut store = Store::new();
+        store.add_embed_context(Box::new(ctx));
+        let args = [
+            WasmValue::I32(CLOCKID_MONOTONIC),
+            WasmValue::I64(0),
+            WasmValue::I32(8),
+        ];
+        let mut mem = vec![0; 16];
+        let mut call = || {
+            let mut ret = vec![];
+            let mut ctx = HostContext { mem: &mut mem };
+            func.call_with_context(&args, &mut ret, &mut ctx, &store)
+                .unwrap();
+            ret[0].as_i32().unwrap()
+        };
+        assert_eq!(call(), errno as i32);
+        assert_eq!(call(), ERRNO_SUCCESS);
+        assert_eq!(mem[8..16], CLOCK_TICK.to_le_bytes());
+    }
+}
@@ -0,0 +1,195 @@
+//! Injection of errors into WASI calls.
+//!
+//! The functions generated by `define_wasi_fn_for_wasminspect!` ask the injector before
+//! calling wasi-common, and return the errno of the first matching fault instead.
+
+use crate::trace::Signature;
+use crate::WASI_ERRNO_NAMES;
+use regex::Regex;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use wasminspect_vm::WasmValue;
+
+/// Parses the errno given by the WASI name like `noent`, the POSIX name like `ENOENT`
+/// or the number
+pub fn parse_errno(name: &str) -> anyhow::Result<u16> {
+    let lower = name.to_lowercase();
+    let position = |name: &str| WASI_ERRNO_NAMES.iter().position(|errno| *errno == name);
+    position(&lower)
+        .or_else(|| lower.strip_prefix('e').and_then(position))
+        .map(|errno| errno as u16)
+        .or_else(|| {
+            lower
+                .parse()
+                .ok()
+                .filter(|errno| (*errno as usize) < WASI_ERRNO_NAMES.len())
+        })
+        .ok_or_else(|| anyhow::anyhow!("unknown errno '{}'", name))
+}
+
+fn errno_name(errno: u16) -> &'static str {
+    WASI_ERRNO_NAMES.get(errno as usize).unwrap_or(&"?")
+}
+
+/// WASI calls to make fail
+#[derive(Clone, Debug)]
+pub struct FaultOptions {
+    pub function: String,
+    /// The errno returned instead of calling the function
+    pub errno: u16,
+    /// Number of matching calls to let through before failing
+    pub skip: u32,
+    /// Number of calls to fail, or all of them if `None`
+    pub count: Option<u32>,
+    /// Only fail the calls with a path matching the regex. Paths relative to a
+    /// preopened directory are joined to the directory name.
+    pub path_regex: Option<Regex>,
+}
+
+impl fmt::Display for FaultOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} --errno {}", self.function, errno_name(self.errno))?;
+        if self.skip != 0 {
+            write!(f, " --skip {}", self.skip)?;
+        }
+        if let Some(count) = self.count {
+            write!(f, " --count {}", count)?;
+        }
+        if let Some(regex) = &self.path_regex {
+            write!(f, " --path-regex {}", regex)?;
+        }
+        Ok(())
+    }
+}
+
+struct Fault {
+    options: FaultOptions,
+    /// Number of calls matched so far
+    matched: u32,
+    /// Number of calls failed so far
+    injected: u32,
+}
+
+pub(crate) struct FaultInjector {
+    faults: RefCell<Vec<Fault>>,
+    /// Guest paths of the preopened directories by file descriptor
+    preopens: HashMap<u32, String>,
+}
+
+impl FaultInjector {
+    pub(crate) fn new(preopens: HashMap<u32, String>) -> Self {
+        Self {
+            faults: RefCell::new(Vec::new()),
+            preopens,
+        }
+    }
+
+    pub(crate) fn add(&self, options: FaultOptions) {
+        self.faults.borrow_mut().push(Fault {
+            options,
+            matched: 0,
+            injected: 0,
+        });
+    }
+
+    pub(crate) fn clear(&self) {
+        self.faults.borrow_mut().clear();
+    }
+
+    pub(crate) fn list(&self) -> Vec<(FaultOptions, u32)> {
+        self.faults
+            .borrow()
+            .iter()
+            .map(|fault| (fault.options.clone(), fault.injected))
+            .collect()
+    }
+
+    fn paths(&self, signature: &Signature, args: &[WasmValue], mem: &[u8]) -> Vec<String> {
+        signature
+            .paths(args, mem)
+            .into_iter()
+            .map(
+                |(fd, path)| match fd.and_then(|fd| self.preopens.get(&fd)) {
+                    Some(dir) if !path.starts_with('/') => {
+                        format!("{}/{}", dir.trim_end_matches('/'), path)
+                    }
+                    _ => path,
+                },
+            )
+            .collect()
+    }
+
+    /// Returns the errno to fail the call with, if any fault matches it
+    pub(crate) fn check(
+        &self,
+        signature: &Signature,
+        args: &[WasmValue],
+        mem: &[u8],
+    ) -> Option<i32> {
+        let mut faults = self.faults.borrow_mut();
+        let mut paths = None;
+        for fault in faults.iter_mut() {
+            if fault.options.function != signature.name {
+                continue;
+            }
+            if let Some(regex) = &fault.options.path_regex {
+                let paths = paths.get_or_insert_with(|| self.paths(signature, args, mem));
+                if !paths.iter().any(|path| regex.is_match(path)) {
+                    continue;
+                }
+            }
+            fault.matched += 1;
+            if fault.matched <= fault.options.skip {
+                continue;
+            }
+            if matches!(fault.options.count, Some(count) if fault.injected >= count) {
+                continue;
+            }
+            fault.injected += 1;
+            log::warn!(
+                "injected {} into {} (call #{})",
+                errno_name(fault.options.errno),
+                signature.name,
+                fault.matched
+            );
+            return Some(fault.options.errno as i32);
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::trace::Param;
+
+    #[test]
+    fn test_skip_count_and_path_regex() {
+        const SIGNATURE: Signature = Signature {
+            name: "path_open",
+            params: &[("fd", "fd", Param::Int), ("path", "string", Param::Str)],
+            outs: &[],
+            errno_names: &[],
+        };
+        assert_eq!(parse_errno("ENOENT").unwrap(), 44);
+        assert_eq!(parse_errno("exist").unwrap(), 20);
+        assert!(parse_errno("enope").is_err());
+
+        let injector = FaultInjector::new(vec![(3, "/data".to_string())].into_iter().collect());
+        injector.add(FaultOptions {
+            function: "path_open".to_string(),
+            errno: parse_errno("noent").unwrap(),
+            skip: 1,
+            count: Some(1),
+            path_regex: Some(Regex::new("^/data/").unwrap()),
+        });
+        let mem = b"a.txt".to_vec();
+        let call = |fd| injector.check(&SIGNATURE, &[fd, 0, 5].map(WasmValue::I32), &mem);
+        assert_eq!(call(4), None);
+        assert_eq!(call(3), None);
+        assert_eq!(call(3), Some(44));
+        assert_eq!(call(3), None);
+        assert_eq!(injector.list()[0].1, 1);
+    }
+}
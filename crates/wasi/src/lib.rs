@@ -1,14 +1,86 @@
 use cap_std::fs::Dir;
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 use std::collections::HashMap;
+use std::fmt::Debug;
 use wasi_cap_std_sync::WasiCtxBuilder;
 use wasi_common::WasiCtx;
 use wasminspect_vm::*;
 use wasmparser::{FuncType, ValType};
 mod borrow;
+mod deterministic;
+mod fault;
+mod preview2;
+mod socket;
+mod stdio;
+mod trace;
+mod vfs;
+
+use deterministic::DeterministicEnv;
+pub use deterministic::DeterministicOptions;
+use fault::FaultInjector;
+pub use fault::{parse_errno, FaultOptions};
+pub use preview2::instantiate_preview2;
+pub use socket::ListenAddress;
+use socket::{Socket, SocketTable};
+pub use stdio::{OutputBuffer, OutputTarget, StdioOptions};
+use trace::Signature;
+pub use vfs::{VfsEntry, VfsOptions, VfsSource, VirtualFs};
+
+/// Names of the WASI errno values, which are the same in preview0 and preview1
+pub(crate) const WASI_ERRNO_NAMES: &[&str] =
+    wasminspect_wasi_macro::wasi_errno_names!("phases/snapshot/witx/wasi_snapshot_preview1.witx");
+
+const PREVIEW1_SIGNATURES: &[Signature] =
+    wasminspect_wasi_macro::wasi_signatures!("phases/snapshot/witx/wasi_snapshot_preview1.witx");
+
+const PREVIEW0_SIGNATURES: &[Signature] =
+    wasminspect_wasi_macro::wasi_signatures!("phases/old/snapshot_0/witx/wasi_unstable.witx");
+
+/// Returns whether the function is defined by `wasi_snapshot_preview1` or `wasi_unstable`
+pub fn is_wasi_function(name: &str) -> bool {
+    PREVIEW1_SIGNATURES
+        .iter()
+        .chain(PREVIEW0_SIGNATURES)
+        .chain(std::iter::once(&socket::SOCK_ACCEPT))
+        .any(|signature| signature.name == name)
+}
 
 pub struct WasiContext {
     ctx: RefCell<WasiCtx>,
+    /// Log every WASI call to stderr
+    trace: Cell<bool>,
+    /// The virtual clock and PRNG if the environment is deterministic
+    deterministic: Option<DeterministicEnv>,
+    /// The in-memory filesystem preopened to the guest
+    vfs: Option<VirtualFs>,
+    /// Sockets given to the guest, keyed by the file descriptors
+    sockets: SocketTable,
+    /// Errors injected into WASI calls
+    faults: FaultInjector,
+}
+
+impl WasiContext {
+    pub fn set_trace(&self, enabled: bool) {
+        self.trace.set(enabled);
+    }
+
+    pub fn vfs(&self) -> Option<&VirtualFs> {
+        self.vfs.as_ref()
+    }
+
+    /// Makes the matching WASI calls fail from now on
+    pub fn add_fault(&self, fault: FaultOptions) {
+        self.faults.add(fault);
+    }
+
+    pub fn clear_faults(&self) {
+        self.faults.clear();
+    }
+
+    /// Returns the faults with the number of calls failed by each
+    pub fn faults(&self) -> Vec<(FaultOptions, u32)> {
+        self.faults.list()
+    }
 }
 
 #[derive(Debug)]
@@ -20,45 +92,158 @@ impl std::fmt::Display for WasiError {
     }
 }
 
+/// Returns the signature of the function generated from the witx definitions
+pub(crate) fn signature(signatures: &'static [Signature], name: &str) -> &'static Signature {
+    signatures
+        .iter()
+        .find(|signature| signature.name == name)
+        .unwrap_or_else(|| panic!("no WASI function named {}", name))
+}
+
+/// Creates the WASI function which returns the errno computed by `body`. The calls are
+/// logged if tracing is enabled by `set_trace`, and fail without calling `body` if they
+/// match a fault added by `add_fault`.
+pub(crate) fn wasi_func<F, E>(
+    signature: &'static Signature,
+    params: Vec<ValType>,
+    body: F,
+) -> HostValue
+where
+    F: Fn(&[WasmValue], &mut HostContext, &WasiContext) -> Result<i32, E> + 'static,
+    E: Debug,
+{
+    let ty = FuncType::new(params, vec![ValType::I32]);
+    HostValue::Func(HostFuncBody::new(ty, move |args, ret, ctx, store| {
+        log::debug!("{}({:?})", signature.name, args);
+        let wasi_ctx = store.get_embed_context::<WasiContext>().ok_or_else(|| {
+            Trap::HostFunctionError(Box::new(WasiError(
+                "WASI context is not set up".to_string(),
+            )))
+        })?;
+        // Decode the arguments before the call changes the memory
+        let call = if wasi_ctx.trace.get() {
+            Some(signature.format_call(args, ctx.mem))
+        } else {
+            None
+        };
+        let errno = match wasi_ctx.faults.check(signature, args, ctx.mem) {
+            Some(errno) => errno,
+            None => match body(args, ctx, wasi_ctx) {
+                Ok(errno) => errno,
+                Err(e) => {
+                    if let Some(call) = &call {
+                        signature.log_trap(call, &e);
+                    }
+                    return Err(Trap::HostFunctionError(Box::new(WasiError(format!(
+                        "{:?}",
+                        e
+                    )))));
+                }
+            },
+        };
+        if let Some(call) = &call {
+            signature.log_return(call, errno, args, ctx.mem);
+        }
+        ret.push(WasmValue::I32(errno));
+        Ok(())
+    }))
+}
+
 fn wasi_proc_exit(status: i32) -> Result<(), Trap> {
-    std::process::exit(status);
+    Err(Trap::Exit(status))
 }
 
+/// Creates the WASI context and the host modules of `wasi_snapshot_preview1` and
+/// `wasi_unstable` keyed by the module names. Clocks, `random_get` and polling are
+/// replaced with deterministic ones if `deterministic` is given, and the mounts of
+/// `vfs` are preopened as in-memory directories. Listeners bound to `listen` are given
+/// as the file descriptors following the preopened directories, and the socket functions
+/// are replaced only if there are any.
 pub fn instantiate_wasi(
     args: &[String],
     preopen_dirs: Vec<(String, Dir)>,
     envs: &[(String, String)],
-) -> anyhow::Result<(WasiContext, HashMap<String, HostValue>)> {
-    let builder = WasiCtxBuilder::new();
-    let mut builder = builder.inherit_stdio().args(args)?.envs(envs)?;
+    stdio: &StdioOptions,
+    deterministic: Option<&DeterministicOptions>,
+    vfs: Option<&VfsOptions>,
+    listen: &[ListenAddress],
+) -> anyhow::Result<(WasiContext, HashMap<String, HashMap<String, HostValue>>)> {
+    let builder = stdio::set_stdio(WasiCtxBuilder::new(), stdio)?;
+    let mut builder = builder.args(args)?.envs(envs)?;
 
+    // Preopened directories are numbered from 3 following the standard streams
+    let mut preopens = Vec::new();
     for (name, dir) in preopen_dirs.into_iter() {
+        preopens.push(name.clone());
         builder = builder.preopened_dir(dir, name)?;
     }
 
-    let wasi_ctx = builder.build()?;
+    let mut wasi_ctx = builder.build()?;
 
+    let vfs = vfs.map(VirtualFs::new).transpose()?;
+    if let Some(vfs) = &vfs {
+        for (name, dir) in vfs.preopen_dirs() {
+            preopens.push(name.to_string());
+            wasi_ctx.push_preopened_dir(Box::new(dir), name)?;
+        }
+    }
+
+    let mut preview1 = wasi_snapshot_preview1();
+    let mut preview0 = wasi_unstable();
+    if deterministic.is_some() {
+        deterministic::override_functions(
+            &mut preview1,
+            PREVIEW1_SIGNATURES,
+            &deterministic::PREVIEW1_SUBSCRIPTION,
+        );
+        deterministic::override_functions(
+            &mut preview0,
+            PREVIEW0_SIGNATURES,
+            &deterministic::PREVIEW0_SUBSCRIPTION,
+        );
+    }
+    if !listen.is_empty() {
+        socket::override_functions(&mut preview1, PREVIEW1_SIGNATURES, true);
+        socket::override_functions(&mut preview0, PREVIEW0_SIGNATURES, false);
+    }
+    let mut modules = HashMap::new();
+    modules.insert("wasi_snapshot_preview1".to_string(), preview1);
+    modules.insert("wasi_unstable".to_string(), preview0);
+
+    let context = WasiContext {
+        ctx: RefCell::new(wasi_ctx),
+        trace: Cell::new(false),
+        deterministic: deterministic.map(DeterministicEnv::new),
+        vfs,
+        sockets: SocketTable::default(),
+        faults: FaultInjector::new((3..).zip(preopens).collect()),
+    };
+    for address in listen {
+        socket::insert_socket(&context, Socket::bind(address)?)?;
+    }
+    Ok((context, modules))
+}
+
+fn wasi_snapshot_preview1() -> HashMap<String, HostValue> {
     let mut module: HashMap<String, HostValue> = HashMap::new();
 
     wasminspect_wasi_macro::define_wasi_fn_for_wasminspect!(
         module,
-        "phases/snapshot/witx/wasi_snapshot_preview1.witx"
+        "phases/snapshot/witx/wasi_snapshot_preview1.witx",
+        preview_1
     );
+    module
+}
 
-    module.insert(
-        "sock_accept".to_string(),
-        HostValue::Func(HostFuncBody::new(
-            FuncType::new(vec![ValType::I32, ValType::I32, ValType::I32], vec![ValType::I32]),
-            move |_, _, _, _| {
-                Err(Trap::HostFunctionError(Box::new(WasiError(
-                    "sock_accept is not supported".to_string(),
-                ))))
-            }
-        )),
-    );
+/// Preview0 differs from preview1 in some signatures and struct layouts, so the
+/// functions go through the preview0 adapters of wasi-common
+fn wasi_unstable() -> HashMap<String, HostValue> {
+    let mut module: HashMap<String, HostValue> = HashMap::new();
 
-    let context = WasiContext {
-        ctx: RefCell::new(wasi_ctx),
-    };
-    Ok((context, module))
+    wasminspect_wasi_macro::define_wasi_fn_for_wasminspect!(
+        module,
+        "phases/old/snapshot_0/witx/wasi_unstable.witx",
+        preview_0
+    );
+    module
 }
@@ -0,0 +1,1038 @@
+//! Host of the WASI 0.2 interfaces imported by components.
+//!
+//! Resources given to the guest are kept in a table keyed by their representations,
+//! and removed by the destructors when the guest drops the last handle.
+
+use crate::deterministic::{DeterministicEnv, CLOCKID_MONOTONIC, CLOCKID_REALTIME};
+use crate::stdio::{self, Stream};
+use crate::{DeterministicOptions, StdioOptions, WasiError};
+use cap_std::fs::{Dir, File, FileType, Metadata, OpenOptions};
+use std::cell::{Cell, RefCell};
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::hash::{BuildHasher, Hasher};
+use std::io::{ErrorKind, Read, Seek, SeekFrom, Write};
+use std::rc::Rc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use wasminspect_vm::{ComponentHost, ComponentValue as Value, Trap};
+
+/// The number of bytes `check-write` allows at once
+const WRITE_BUDGET: u64 = 4096;
+/// The maximum number of bytes returned by a read
+const READ_LIMIT: u64 = 64 * 1024;
+
+// Cases of `stream-error`
+const STREAM_ERROR_LAST_OPERATION_FAILED: u32 = 0;
+const STREAM_ERROR_CLOSED: u32 = 1;
+
+// Cases of `descriptor-type`
+const DESCRIPTOR_TYPE_UNKNOWN: u32 = 0;
+const DESCRIPTOR_TYPE_DIRECTORY: u32 = 3;
+const DESCRIPTOR_TYPE_SYMBOLIC_LINK: u32 = 5;
+const DESCRIPTOR_TYPE_REGULAR_FILE: u32 = 6;
+
+// Cases of the filesystem `error-code`
+const ERROR_ACCESS: u32 = 0;
+const ERROR_EXIST: u32 = 7;
+const ERROR_INVALID: u32 = 12;
+const ERROR_IO: u32 = 13;
+const ERROR_IS_DIRECTORY: u32 = 14;
+const ERROR_NO_ENTRY: u32 = 20;
+const ERROR_NOT_DIRECTORY: u32 = 24;
+
+type FsResult<T> = std::result::Result<T, u32>;
+type HostFunc = fn(&Preview2, Vec<Value>) -> Result<Vec<Value>, Trap>;
+
+enum Input {
+    Reader(Rc<RefCell<Box<dyn Read>>>),
+    /// A file read from the offset
+    File(Rc<RefCell<File>>, u64),
+}
+
+enum Output {
+    Writer(Rc<RefCell<Box<dyn Write>>>),
+    /// A file written at the offset, or appended if it's `None`
+    File(Rc<RefCell<File>>, Option<u64>),
+}
+
+#[derive(Clone)]
+enum Descriptor {
+    Dir(Rc<Dir>),
+    File(Rc<RefCell<File>>),
+}
+
+impl Descriptor {
+    fn dir(&self) -> FsResult<&Dir> {
+        match self {
+            Descriptor::Dir(dir) => Ok(dir),
+            Descriptor::File(_) => Err(ERROR_NOT_DIRECTORY),
+        }
+    }
+
+    fn file(&self) -> FsResult<&Rc<RefCell<File>>> {
+        match self {
+            Descriptor::Dir(_) => Err(ERROR_IS_DIRECTORY),
+            Descriptor::File(file) => Ok(file),
+        }
+    }
+
+    fn metadata(&self) -> FsResult<Metadata> {
+        match self {
+            Descriptor::Dir(dir) => dir.dir_metadata(),
+            Descriptor::File(file) => file.borrow().metadata(),
+        }
+        .map_err(error_code)
+    }
+}
+
+enum Entry {
+    Input(Input),
+    Output(Output),
+    /// Ready once the monotonic clock reaches the deadline, or always if it's `None`
+    Pollable(Option<u64>),
+    Error(String),
+    Descriptor(Descriptor),
+    DirectoryEntries(std::vec::IntoIter<Value>),
+}
+
+struct Preview2 {
+    args: Vec<String>,
+    envs: Vec<(String, String)>,
+    preopens: Vec<(String, Rc<Dir>)>,
+    stdin: Rc<RefCell<Box<dyn Read>>>,
+    stdout: Rc<RefCell<Box<dyn Write>>>,
+    stderr: Rc<RefCell<Box<dyn Write>>>,
+    deterministic: Option<DeterministicEnv>,
+    started: Instant,
+    entries: RefCell<HashMap<u32, Entry>>,
+    next_rep: Cell<u32>,
+}
+
+impl Preview2 {
+    fn insert(&self, entry: Entry) -> Value {
+        let rep = self.next_rep.get();
+        self.next_rep.set(rep + 1);
+        self.entries.borrow_mut().insert(rep, entry);
+        Value::Own(rep)
+    }
+
+    /// Applies `f` to the resource, which traps if it's not of the expected kind
+    fn with<T>(&self, rep: u32, f: impl FnOnce(&mut Entry) -> Option<T>) -> Result<T, Trap> {
+        let result = self.entries.borrow_mut().get_mut(&rep).and_then(f);
+        result.ok_or_else(|| trap(format!("unexpected resource {}", rep)))
+    }
+
+    fn descriptor(&self, rep: u32) -> Result<Descriptor, Trap> {
+        self.with(rep, |entry| match entry {
+            Entry::Descriptor(descriptor) => Some(descriptor.clone()),
+            _ => None,
+        })
+    }
+
+    fn stream_error(&self, error: Option<std::io::Error>) -> Value {
+        let case = match error {
+            Some(error) => Value::Variant(
+                STREAM_ERROR_LAST_OPERATION_FAILED,
+                Some(Box::new(self.insert(Entry::Error(error.to_string())))),
+            ),
+            None => Value::Variant(STREAM_ERROR_CLOSED, None),
+        };
+        Value::Result(Err(Some(Box::new(case))))
+    }
+
+    fn monotonic_now(&self) -> u64 {
+        match &self.deterministic {
+            Some(env) => env.now(CLOCKID_MONOTONIC),
+            None => self.started.elapsed().as_nanos() as u64,
+        }
+    }
+
+    fn wall_clock_now(&self) -> Duration {
+        match &self.deterministic {
+            Some(env) => Duration::from_nanos(env.now(CLOCKID_REALTIME)),
+            None => SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Sleeps until the deadline, which returns immediately on the virtual clock
+    fn wait(&self, deadline: u64) {
+        if self.deterministic.is_none() {
+            let now = self.monotonic_now();
+            if deadline > now {
+                std::thread::sleep(Duration::from_nanos(deadline - now));
+            }
+        }
+    }
+
+    fn random_bytes(&self, len: u64) -> Vec<u8> {
+        let mut bytes = vec![0; len as usize];
+        match &self.deterministic {
+            Some(env) => {
+                for chunk in bytes.chunks_mut(8) {
+                    chunk.copy_from_slice(&env.next_random().to_le_bytes()[..chunk.len()]);
+                }
+            }
+            None => fill_random(&mut bytes),
+        }
+        bytes
+    }
+
+    fn random_u64(&self) -> u64 {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(&self.random_bytes(8));
+        u64::from_le_bytes(bytes)
+    }
+}
+
+/// Reads the bytes from the OS, falling back to the randomly seeded hasher of std
+fn fill_random(bytes: &mut [u8]) {
+    let urandom = std::fs::File::open("/dev/urandom").and_then(|mut file| file.read_exact(bytes));
+    if urandom.is_err() {
+        for chunk in bytes.chunks_mut(8) {
+            let value = RandomState::new().build_hasher().finish();
+            chunk.copy_from_slice(&value.to_le_bytes()[..chunk.len()]);
+        }
+    }
+}
+
+fn trap(message: String) -> Trap {
+    Trap::HostFunctionError(Box::new(WasiError(message)))
+}
+
+fn invalid_args(args: &[Value]) -> Trap {
+    trap(format!("unexpected arguments {:?}", args))
+}
+
+fn rep_arg(args: &[Value], index: usize) -> Result<u32, Trap> {
+    match args.get(index) {
+        Some(Value::Own(rep)) | Some(Value::Borrow(rep)) => Ok(*rep),
+        _ => Err(invalid_args(args)),
+    }
+}
+
+fn u64_arg(args: &[Value], index: usize) -> Result<u64, Trap> {
+    match args.get(index) {
+        Some(Value::U64(value)) => Ok(*value),
+        _ => Err(invalid_args(args)),
+    }
+}
+
+fn bytes_arg(args: &[Value], index: usize) -> Result<Vec<u8>, Trap> {
+    match args.get(index) {
+        Some(Value::List(items)) => items
+            .iter()
+            .map(|item| match item {
+                Value::U8(byte) => Ok(*byte),
+                _ => Err(invalid_args(args)),
+            })
+            .collect(),
+        _ => Err(invalid_args(args)),
+    }
+}
+
+fn string_arg(args: &[Value], index: usize) -> Result<&str, Trap> {
+    match args.get(index) {
+        Some(Value::String(value)) => Ok(value),
+        _ => Err(invalid_args(args)),
+    }
+}
+
+fn flags_arg(args: &[Value], index: usize) -> Result<&[bool], Trap> {
+    match args.get(index) {
+        Some(Value::Flags(flags)) => Ok(flags),
+        _ => Err(invalid_args(args)),
+    }
+}
+
+fn flag(flags: &[bool], index: usize) -> bool {
+    flags.get(index).copied().unwrap_or(false)
+}
+
+fn ok(value: Option<Value>) -> Value {
+    Value::Result(Ok(value.map(Box::new)))
+}
+
+fn bytes(bytes: Vec<u8>) -> Value {
+    Value::List(bytes.into_iter().map(Value::U8).collect())
+}
+
+fn datetime(time: Duration) -> Value {
+    Value::Record(vec![
+        Value::U64(time.as_secs()),
+        Value::U32(time.subsec_nanos()),
+    ])
+}
+
+fn fs_result(result: FsResult<Option<Value>>) -> Value {
+    Value::Result(
+        result
+            .map(|value| value.map(Box::new))
+            .map_err(|code| Some(Box::new(Value::Enum(code)))),
+    )
+}
+
+fn error_code(error: std::io::Error) -> u32 {
+    match error.kind() {
+        ErrorKind::NotFound => ERROR_NO_ENTRY,
+        ErrorKind::PermissionDenied => ERROR_ACCESS,
+        ErrorKind::AlreadyExists => ERROR_EXIST,
+        ErrorKind::InvalidInput => ERROR_INVALID,
+        _ => ERROR_IO,
+    }
+}
+
+fn descriptor_type(file_type: FileType) -> Value {
+    Value::Enum(if file_type.is_dir() {
+        DESCRIPTOR_TYPE_DIRECTORY
+    } else if file_type.is_file() {
+        DESCRIPTOR_TYPE_REGULAR_FILE
+    } else if file_type.is_symlink() {
+        DESCRIPTOR_TYPE_SYMBOLIC_LINK
+    } else {
+        DESCRIPTOR_TYPE_UNKNOWN
+    })
+}
+
+fn timestamp(time: std::io::Result<cap_std::time::SystemTime>) -> Value {
+    let time = time
+        .ok()
+        .and_then(|time| time.into_std().duration_since(UNIX_EPOCH).ok());
+    Value::Option(time.map(|time| Box::new(datetime(time))))
+}
+
+/// The `descriptor-stat` record. The link count isn't available through cap-std, so it's always 1.
+fn descriptor_stat(metadata: Metadata) -> Value {
+    Value::Record(vec![
+        descriptor_type(metadata.file_type()),
+        Value::U64(1),
+        Value::U64(metadata.len()),
+        timestamp(metadata.accessed()),
+        timestamp(metadata.modified()),
+        Value::Option(None),
+    ])
+}
+
+fn read_input(input: &mut Input, len: u64) -> std::io::Result<Vec<u8>> {
+    let mut buffer = vec![0; len.min(READ_LIMIT) as usize];
+    let size = match input {
+        Input::Reader(reader) => reader.borrow_mut().read(&mut buffer)?,
+        Input::File(file, offset) => {
+            let mut file = file.borrow_mut();
+            file.seek(SeekFrom::Start(*offset))?;
+            let size = file.read(&mut buffer)?;
+            *offset += size as u64;
+            size
+        }
+    };
+    buffer.truncate(size);
+    Ok(buffer)
+}
+
+fn write_output(output: &mut Output, contents: &[u8]) -> std::io::Result<()> {
+    match output {
+        Output::Writer(writer) => writer.borrow_mut().write_all(contents),
+        Output::File(file, offset) => {
+            let mut file = file.borrow_mut();
+            file.seek(offset.map_or(SeekFrom::End(0), SeekFrom::Start))?;
+            file.write_all(contents)?;
+            if let Some(offset) = offset {
+                *offset += contents.len() as u64;
+            }
+            Ok(())
+        }
+    }
+}
+
+fn flush_output(output: &mut Output) -> std::io::Result<()> {
+    match output {
+        Output::Writer(writer) => writer.borrow_mut().flush(),
+        Output::File(file, _) => file.borrow_mut().flush(),
+    }
+}
+
+/// Reads from the input stream, which is closed once a read returns nothing
+fn stream_read(state: &Preview2, args: Vec<Value>) -> Result<Vec<Value>, Trap> {
+    let len = u64_arg(&args, 1)?;
+    let result = state.with(rep_arg(&args, 0)?, |entry| match entry {
+        Entry::Input(input) => Some(read_input(input, len)),
+        _ => None,
+    })?;
+    Ok(vec![match result {
+        Ok(buffer) if buffer.is_empty() && len > 0 => state.stream_error(None),
+        Ok(buffer) => ok(Some(bytes(buffer))),
+        Err(error) => state.stream_error(Some(error)),
+    }])
+}
+
+fn stream_skip(state: &Preview2, args: Vec<Value>) -> Result<Vec<Value>, Trap> {
+    let result = stream_read(state, args)?.remove(0);
+    Ok(vec![match result {
+        Value::Result(Ok(Some(list))) => match *list {
+            Value::List(items) => ok(Some(Value::U64(items.len() as u64))),
+            _ => unreachable!(),
+        },
+        error => error,
+    }])
+}
+
+/// Writes the contents to the output stream, and flushes it if `flush` is set
+fn stream_write(state: &Preview2, args: Vec<Value>, flush: bool) -> Result<Vec<Value>, Trap> {
+    let contents = match args.get(1) {
+        Some(Value::U64(len)) => vec![0; *len as usize],
+        _ => bytes_arg(&args, 1)?,
+    };
+    let result = state.with(rep_arg(&args, 0)?, |entry| match entry {
+        Entry::Output(output) => Some(write_output(output, &contents).and_then(|_| {
+            if flush {
+                flush_output(output)
+            } else {
+                Ok(())
+            }
+        })),
+        _ => None,
+    })?;
+    Ok(vec![match result {
+        Ok(()) => ok(None),
+        Err(error) => state.stream_error(Some(error)),
+    }])
+}
+
+fn stream_flush(state: &Preview2, args: Vec<Value>) -> Result<Vec<Value>, Trap> {
+    let result = state.with(rep_arg(&args, 0)?, |entry| match entry {
+        Entry::Output(output) => Some(flush_output(output)),
+        _ => None,
+    })?;
+    Ok(vec![match result {
+        Ok(()) => ok(None),
+        Err(error) => state.stream_error(Some(error)),
+    }])
+}
+
+fn subscribe_ready(state: &Preview2, _: Vec<Value>) -> Result<Vec<Value>, Trap> {
+    Ok(vec![state.insert(Entry::Pollable(None))])
+}
+
+fn open_at(state: &Preview2, args: &[Value]) -> Result<FsResult<Descriptor>, Trap> {
+    let descriptor = state.descriptor(rep_arg(args, 0)?)?;
+    let path = string_arg(args, 2)?;
+    let open_flags = flags_arg(args, 3)?;
+    let flags = flags_arg(args, 4)?;
+    let (create, directory, exclusive, truncate) = (
+        flag(open_flags, 0),
+        flag(open_flags, 1),
+        flag(open_flags, 2),
+        flag(open_flags, 3),
+    );
+    let (read, write) = (flag(flags, 0), flag(flags, 1));
+    Ok(descriptor.dir().and_then(|dir| {
+        let is_dir = dir.metadata(path).map(|m| m.is_dir()).unwrap_or(false);
+        if directory || (is_dir && !create) {
+            return dir
+                .open_dir(path)
+                .map(|dir| Descriptor::Dir(Rc::new(dir)))
+                .map_err(error_code);
+        }
+        let mut options = OpenOptions::new();
+        options
+            .read(read || !write)
+            .write(write)
+            .create(create)
+            .create_new(exclusive)
+            .truncate(truncate);
+        dir.open_with(path, &options)
+            .map(|file| Descriptor::File(Rc::new(RefCell::new(file))))
+            .map_err(error_code)
+    }))
+}
+
+fn read_directory(descriptor: &Descriptor) -> FsResult<Vec<Value>> {
+    let mut entries = Vec::new();
+    for entry in descriptor.dir()?.entries().map_err(error_code)? {
+        let entry = entry.map_err(error_code)?;
+        let file_type = entry.file_type().map_err(error_code)?;
+        entries.push(Value::Record(vec![
+            descriptor_type(file_type),
+            Value::String(entry.file_name().to_string_lossy().into_owned()),
+        ]));
+    }
+    Ok(entries)
+}
+
+/// Applies `f` to the directory of the descriptor and the path given as the
+/// arguments, which is the last argument of the `*-at` functions
+fn at_path<T>(
+    state: &Preview2,
+    args: &[Value],
+    f: impl FnOnce(&Dir, &str) -> std::io::Result<T>,
+) -> Result<FsResult<T>, Trap> {
+    let descriptor = state.descriptor(rep_arg(args, 0)?)?;
+    let path = string_arg(args, args.len() - 1)?;
+    Ok(descriptor
+        .dir()
+        .and_then(|dir| f(dir, path).map_err(error_code)))
+}
+
+fn define(
+    host: &mut ComponentHost,
+    state: &Rc<Preview2>,
+    instance: &str,
+    name: &str,
+    func: HostFunc,
+) {
+    let state = state.clone();
+    host.func(instance, name, move |args| func(&state, args));
+}
+
+fn define_resource(host: &mut ComponentHost, state: &Rc<Preview2>, instance: &str, name: &str) {
+    let state = state.clone();
+    host.resource(instance, name, move |rep| {
+        state.entries.borrow_mut().remove(&rep);
+    });
+}
+
+fn define_cli(host: &mut ComponentHost, state: &Rc<Preview2>) {
+    define(
+        host,
+        state,
+        "wasi:cli/environment",
+        "get-environment",
+        |state, _| {
+            let envs = state.envs.iter().map(|(key, value)| {
+                Value::Tuple(vec![
+                    Value::String(key.clone()),
+                    Value::String(value.clone()),
+                ])
+            });
+            Ok(vec![Value::List(envs.collect())])
+        },
+    );
+    define(
+        host,
+        state,
+        "wasi:cli/environment",
+        "get-arguments",
+        |state, _| {
+            let args = state.args.iter().cloned().map(Value::String);
+            Ok(vec![Value::List(args.collect())])
+        },
+    );
+    define(
+        host,
+        state,
+        "wasi:cli/environment",
+        "initial-cwd",
+        |_, _| Ok(vec![Value::Option(None)]),
+    );
+    define(host, state, "wasi:cli/exit", "exit", |_, args| {
+        match args.first() {
+            Some(Value::Result(Ok(_))) => Err(Trap::Exit(0)),
+            Some(Value::Result(Err(_))) => Err(Trap::Exit(1)),
+            _ => Err(invalid_args(&args)),
+        }
+    });
+    define(
+        host,
+        state,
+        "wasi:cli/exit",
+        "exit-with-code",
+        |_, args| match args.first() {
+            Some(Value::U8(code)) => Err(Trap::Exit(*code as i32)),
+            _ => Err(invalid_args(&args)),
+        },
+    );
+    define(host, state, "wasi:cli/stdin", "get-stdin", |state, _| {
+        let input = Input::Reader(state.stdin.clone());
+        Ok(vec![state.insert(Entry::Input(input))])
+    });
+    define(host, state, "wasi:cli/stdout", "get-stdout", |state, _| {
+        let output = Output::Writer(state.stdout.clone());
+        Ok(vec![state.insert(Entry::Output(output))])
+    });
+    define(host, state, "wasi:cli/stderr", "get-stderr", |state, _| {
+        let output = Output::Writer(state.stderr.clone());
+        Ok(vec![state.insert(Entry::Output(output))])
+    });
+
+    // The standard streams are never terminals
+    define_resource(host, state, "wasi:cli/terminal-input", "terminal-input");
+    define_resource(host, state, "wasi:cli/terminal-output", "terminal-output");
+    for (instance, name) in &[
+        ("wasi:cli/terminal-stdin", "get-terminal-stdin"),
+        ("wasi:cli/terminal-stdout", "get-terminal-stdout"),
+        ("wasi:cli/terminal-stderr", "get-terminal-stderr"),
+    ] {
+        define(host, state, instance, name, |_, _| {
+            Ok(vec![Value::Option(None)])
+        });
+    }
+}
+
+fn define_io(host: &mut ComponentHost, state: &Rc<Preview2>) {
+    define_resource(host, state, "wasi:io/error", "error");
+    define(
+        host,
+        state,
+        "wasi:io/error",
+        "[method]error.to-debug-string",
+        |state, args| {
+            let message = state.with(rep_arg(&args, 0)?, |entry| match entry {
+                Entry::Error(message) => Some(message.clone()),
+                _ => None,
+            })?;
+            Ok(vec![Value::String(message)])
+        },
+    );
+
+    define_resource(host, state, "wasi:io/poll", "pollable");
+    fn deadline(state: &Preview2, rep: u32) -> Result<Option<u64>, Trap> {
+        state.with(rep, |entry| match entry {
+            Entry::Pollable(deadline) => Some(*deadline),
+            _ => None,
+        })
+    }
+    define(
+        host,
+        state,
+        "wasi:io/poll",
+        "[method]pollable.ready",
+        |state, args| {
+            let deadline = deadline(state, rep_arg(&args, 0)?)?;
+            let now = state.monotonic_now();
+            Ok(vec![Value::Bool(
+                deadline.filter(|deadline| *deadline > now).is_none(),
+            )])
+        },
+    );
+    define(
+        host,
+        state,
+        "wasi:io/poll",
+        "[method]pollable.block",
+        |state, args| {
+            if let Some(deadline) = deadline(state, rep_arg(&args, 0)?)? {
+                state.wait(deadline);
+            }
+            Ok(vec![])
+        },
+    );
+    define(host, state, "wasi:io/poll", "poll", |state, args| {
+        let deadlines = match args.first() {
+            Some(Value::List(items)) => items
+                .iter()
+                .enumerate()
+                .map(|(index, _)| deadline(state, rep_arg(items, index)?))
+                .collect::<Result<Vec<_>, _>>()?,
+            _ => return Err(invalid_args(&args)),
+        };
+        // Waits for the earliest deadline if none of them is ready
+        let now = state.monotonic_now();
+        let earliest = deadlines.iter().flatten().min().copied();
+        let until = match earliest {
+            Some(earliest) if deadlines.iter().all(Option::is_some) && earliest > now => {
+                state.wait(earliest);
+                earliest
+            }
+            _ => now,
+        };
+        let ready = deadlines
+            .iter()
+            .enumerate()
+            .filter(|(_, deadline)| deadline.filter(|deadline| *deadline > until).is_none())
+            .map(|(index, _)| Value::U32(index as u32));
+        Ok(vec![Value::List(ready.collect())])
+    });
+
+    define_resource(host, state, "wasi:io/streams", "input-stream");
+    define_resource(host, state, "wasi:io/streams", "output-stream");
+    for method in &["read", "blocking-read"] {
+        let name = format!("[method]input-stream.{}", method);
+        define(host, state, "wasi:io/streams", &name, stream_read);
+    }
+    for method in &["skip", "blocking-skip"] {
+        let name = format!("[method]input-stream.{}", method);
+        define(host, state, "wasi:io/streams", &name, stream_skip);
+    }
+    for resource in &["input-stream", "output-stream"] {
+        let name = format!("[method]{}.subscribe", resource);
+        define(host, state, "wasi:io/streams", &name, subscribe_ready);
+    }
+    define(
+        host,
+        state,
+        "wasi:io/streams",
+        "[method]output-stream.check-write",
+        |state, args| {
+            state.with(rep_arg(&args, 0)?, |entry| match entry {
+                Entry::Output(_) => Some(()),
+                _ => None,
+            })?;
+            Ok(vec![ok(Some(Value::U64(WRITE_BUDGET)))])
+        },
+    );
+    for method in &["write", "write-zeroes"] {
+        let name = format!("[method]output-stream.{}", method);
+        define(host, state, "wasi:io/streams", &name, |state, args| {
+            stream_write(state, args, false)
+        });
+    }
+    for method in &[
+        "blocking-write-and-flush",
+        "blocking-write-zeroes-and-flush",
+    ] {
+        let name = format!("[method]output-stream.{}", method);
+        define(host, state, "wasi:io/streams", &name, |state, args| {
+            stream_write(state, args, true)
+        });
+    }
+    for method in &["flush", "blocking-flush"] {
+        let name = format!("[method]output-stream.{}", method);
+        define(host, state, "wasi:io/streams", &name, stream_flush);
+    }
+}
+
+fn define_clocks(host: &mut ComponentHost, state: &Rc<Preview2>) {
+    define(host, state, "wasi:clocks/wall-clock", "now", |state, _| {
+        Ok(vec![datetime(state.wall_clock_now())])
+    });
+    define(
+        host,
+        state,
+        "wasi:clocks/wall-clock",
+        "resolution",
+        |_, _| Ok(vec![datetime(Duration::from_nanos(1))]),
+    );
+    define(
+        host,
+        state,
+        "wasi:clocks/monotonic-clock",
+        "now",
+        |state, _| Ok(vec![Value::U64(state.monotonic_now())]),
+    );
+    define(
+        host,
+        state,
+        "wasi:clocks/monotonic-clock",
+        "resolution",
+        |_, _| Ok(vec![Value::U64(1)]),
+    );
+    define(
+        host,
+        state,
+        "wasi:clocks/monotonic-clock",
+        "subscribe-instant",
+        |state, args| {
+            let deadline = u64_arg(&args, 0)?;
+            Ok(vec![state.insert(Entry::Pollable(Some(deadline)))])
+        },
+    );
+    define(
+        host,
+        state,
+        "wasi:clocks/monotonic-clock",
+        "subscribe-duration",
+        |state, args| {
+            let deadline = state.monotonic_now() + u64_arg(&args, 0)?;
+            Ok(vec![state.insert(Entry::Pollable(Some(deadline)))])
+        },
+    );
+}
+
+/// The insecure interfaces share the source with the secure one
+fn define_random(host: &mut ComponentHost, state: &Rc<Preview2>) {
+    for (instance, prefix) in &[
+        ("wasi:random/random", "get-random"),
+        ("wasi:random/insecure", "get-insecure-random"),
+    ] {
+        define(
+            host,
+            state,
+            instance,
+            &format!("{}-bytes", prefix),
+            |state, args| Ok(vec![bytes(state.random_bytes(u64_arg(&args, 0)?))]),
+        );
+        define(
+            host,
+            state,
+            instance,
+            &format!("{}-u64", prefix),
+            |state, _| Ok(vec![Value::U64(state.random_u64())]),
+        );
+    }
+    define(
+        host,
+        state,
+        "wasi:random/insecure-seed",
+        "insecure-seed",
+        |state, _| {
+            let seed = vec![
+                Value::U64(state.random_u64()),
+                Value::U64(state.random_u64()),
+            ];
+            Ok(vec![Value::Tuple(seed)])
+        },
+    );
+}
+
+fn define_filesystem(host: &mut ComponentHost, state: &Rc<Preview2>) {
+    const TYPES: &str = "wasi:filesystem/types";
+    define(
+        host,
+        state,
+        "wasi:filesystem/preopens",
+        "get-directories",
+        |state, _| {
+            let preopens = state.preopens.iter().map(|(name, dir)| {
+                let descriptor = state.insert(Entry::Descriptor(Descriptor::Dir(dir.clone())));
+                Value::Tuple(vec![descriptor, Value::String(name.clone())])
+            });
+            Ok(vec![Value::List(preopens.collect())])
+        },
+    );
+
+    define_resource(host, state, TYPES, "descriptor");
+    define_resource(host, state, TYPES, "directory-entry-stream");
+    define(
+        host,
+        state,
+        TYPES,
+        "[method]descriptor.read-via-stream",
+        |state, args| {
+            let descriptor = state.descriptor(rep_arg(&args, 0)?)?;
+            let offset = u64_arg(&args, 1)?;
+            let input = descriptor
+                .file()
+                .map(|file| Some(state.insert(Entry::Input(Input::File(file.clone(), offset)))));
+            Ok(vec![fs_result(input)])
+        },
+    );
+    define(
+        host,
+        state,
+        TYPES,
+        "[method]descriptor.write-via-stream",
+        |state, args| {
+            let descriptor = state.descriptor(rep_arg(&args, 0)?)?;
+            let offset = u64_arg(&args, 1)?;
+            let output = descriptor.file().map(|file| {
+                let output = Output::File(file.clone(), Some(offset));
+                Some(state.insert(Entry::Output(output)))
+            });
+            Ok(vec![fs_result(output)])
+        },
+    );
+    define(
+        host,
+        state,
+        TYPES,
+        "[method]descriptor.append-via-stream",
+        |state, args| {
+            let descriptor = state.descriptor(rep_arg(&args, 0)?)?;
+            let output = descriptor.file().map(|file| {
+                let output = Output::File(file.clone(), None);
+                Some(state.insert(Entry::Output(output)))
+            });
+            Ok(vec![fs_result(output)])
+        },
+    );
+    define(
+        host,
+        state,
+        TYPES,
+        "[method]descriptor.get-flags",
+        |state, args| {
+            let descriptor = state.descriptor(rep_arg(&args, 0)?)?;
+            // read, write and mutate-directory
+            let is_dir = matches!(descriptor, Descriptor::Dir(_));
+            let flags = vec![true, true, false, false, false, is_dir];
+            Ok(vec![fs_result(Ok(Some(Value::Flags(flags))))])
+        },
+    );
+    define(
+        host,
+        state,
+        TYPES,
+        "[method]descriptor.get-type",
+        |state, args| {
+            let metadata = state.descriptor(rep_arg(&args, 0)?)?.metadata();
+            let file_type = metadata.map(|metadata| Some(descriptor_type(metadata.file_type())));
+            Ok(vec![fs_result(file_type)])
+        },
+    );
+    define(
+        host,
+        state,
+        TYPES,
+        "[method]descriptor.stat",
+        |state, args| {
+            let metadata = state.descriptor(rep_arg(&args, 0)?)?.metadata();
+            Ok(vec![fs_result(
+                metadata.map(|metadata| Some(descriptor_stat(metadata))),
+            )])
+        },
+    );
+    define(
+        host,
+        state,
+        TYPES,
+        "[method]descriptor.stat-at",
+        |state, args| {
+            let metadata = at_path(state, &args, |dir, path| dir.metadata(path))?;
+            Ok(vec![fs_result(
+                metadata.map(|metadata| Some(descriptor_stat(metadata))),
+            )])
+        },
+    );
+    define(
+        host,
+        state,
+        TYPES,
+        "[method]descriptor.read",
+        |state, args| {
+            let descriptor = state.descriptor(rep_arg(&args, 0)?)?;
+            let (len, offset) = (u64_arg(&args, 1)?, u64_arg(&args, 2)?);
+            let result = descriptor.file().and_then(|file| {
+                let mut input = Input::File(file.clone(), offset);
+                let buffer = read_input(&mut input, len).map_err(error_code)?;
+                let end = (buffer.len() as u64) < len;
+                Ok(Some(Value::Tuple(vec![bytes(buffer), Value::Bool(end)])))
+            });
+            Ok(vec![fs_result(result)])
+        },
+    );
+    define(
+        host,
+        state,
+        TYPES,
+        "[method]descriptor.write",
+        |state, args| {
+            let descriptor = state.descriptor(rep_arg(&args, 0)?)?;
+            let (contents, offset) = (bytes_arg(&args, 1)?, u64_arg(&args, 2)?);
+            let result = descriptor.file().and_then(|file| {
+                let mut output = Output::File(file.clone(), Some(offset));
+                write_output(&mut output, &contents).map_err(error_code)?;
+                Ok(Some(Value::U64(contents.len() as u64)))
+            });
+            Ok(vec![fs_result(result)])
+        },
+    );
+    for method in &["sync", "sync-data"] {
+        let name = format!("[method]descriptor.{}", method);
+        define(host, state, TYPES, &name, |state, args| {
+            state.descriptor(rep_arg(&args, 0)?)?;
+            Ok(vec![ok(None)])
+        });
+    }
+    define(
+        host,
+        state,
+        TYPES,
+        "[method]descriptor.open-at",
+        |state, args| {
+            let descriptor = open_at(state, &args)?;
+            let descriptor =
+                descriptor.map(|descriptor| Some(state.insert(Entry::Descriptor(descriptor))));
+            Ok(vec![fs_result(descriptor)])
+        },
+    );
+    define(
+        host,
+        state,
+        TYPES,
+        "[method]descriptor.create-directory-at",
+        |state, args| {
+            let result = at_path(state, &args, |dir, path| dir.create_dir(path))?;
+            Ok(vec![fs_result(result.map(|_| None))])
+        },
+    );
+    define(
+        host,
+        state,
+        TYPES,
+        "[method]descriptor.remove-directory-at",
+        |state, args| {
+            let result = at_path(state, &args, |dir, path| dir.remove_dir(path))?;
+            Ok(vec![fs_result(result.map(|_| None))])
+        },
+    );
+    define(
+        host,
+        state,
+        TYPES,
+        "[method]descriptor.unlink-file-at",
+        |state, args| {
+            let result = at_path(state, &args, |dir, path| dir.remove_file(path))?;
+            Ok(vec![fs_result(result.map(|_| None))])
+        },
+    );
+    define(
+        host,
+        state,
+        TYPES,
+        "[method]descriptor.read-directory",
+        |state, args| {
+            let descriptor = state.descriptor(rep_arg(&args, 0)?)?;
+            let entries = read_directory(&descriptor)
+                .map(|entries| Some(state.insert(Entry::DirectoryEntries(entries.into_iter()))));
+            Ok(vec![fs_result(entries)])
+        },
+    );
+    define(
+        host,
+        state,
+        TYPES,
+        "[method]directory-entry-stream.read-directory-entry",
+        |state, args| {
+            let entry = state.with(rep_arg(&args, 0)?, |entry| match entry {
+                Entry::DirectoryEntries(entries) => Some(entries.next()),
+                _ => None,
+            })?;
+            Ok(vec![ok(Some(Value::Option(entry.map(Box::new))))])
+        },
+    );
+    // Errors of streams are never of the filesystem
+    define(host, state, TYPES, "filesystem-error-code", |_, _| {
+        Ok(vec![Value::Option(None)])
+    });
+}
+
+/// Creates the host of the WASI 0.2 interfaces imported by components. The interfaces
+/// are registered without versions so that any 0.2 release matches. Clocks and random
+/// numbers are deterministic if `deterministic` is given.
+pub fn instantiate_preview2(
+    args: &[String],
+    preopen_dirs: Vec<(String, Dir)>,
+    envs: &[(String, String)],
+    stdio: &StdioOptions,
+    deterministic: Option<&DeterministicOptions>,
+) -> anyhow::Result<ComponentHost> {
+    let state = Rc::new(Preview2 {
+        args: args.to_vec(),
+        envs: envs.to_vec(),
+        preopens: preopen_dirs
+            .into_iter()
+            .map(|(name, dir)| (name, Rc::new(dir)))
+            .collect(),
+        stdin: Rc::new(RefCell::new(stdio::input_reader(stdio)?)),
+        stdout: Rc::new(RefCell::new(stdio::output_writer(
+            Stream::Stdout,
+            &stdio.stdout,
+        )?)),
+        stderr: Rc::new(RefCell::new(stdio::output_writer(
+            Stream::Stderr,
+            &stdio.stderr,
+        )?)),
+        deterministic: deterministic.map(DeterministicEnv::new),
+        started: Instant::now(),
+        entries: RefCell::new(HashMap::new()),
+        next_rep: Cell::new(1),
+    });
+    let mut host = ComponentHost::new();
+    define_cli(&mut host, &state);
+    define_io(&mut host, &state);
+    define_clocks(&mut host, &state);
+    define_random(&mut host, &state);
+    define_filesystem(&mut host, &state);
+    Ok(host)
+}
@@ -0,0 +1,421 @@
+//! WASI sockets over listeners preopened by the host.
+//!
+//! Listeners are given to the guest as file descriptors after the preopened directories.
+//! wasi-common owns the descriptors, so `fd_read`, `fd_write` and `fd_close` work as for
+//! files, but `sock_accept`, `sock_recv`, `sock_send` and `sock_shutdown` are implemented
+//! here because wasi-common doesn't support them. They replace the functions of
+//! wasi-common only if any listener is given.
+
+mod file;
+
+use crate::trace::{Out, Param, Signature};
+use crate::{wasi_func, WasiContext, WASI_ERRNO_NAMES};
+use anyhow::Context;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::PathBuf;
+use std::sync::{Arc, Weak};
+use wasminspect_vm::*;
+use wasmparser::ValType;
+
+const ERRNO_SUCCESS: i32 = 0;
+const ERRNO_AGAIN: i32 = 6;
+const ERRNO_CONNRESET: i32 = 15;
+const ERRNO_FAULT: i32 = 21;
+const ERRNO_INVAL: i32 = 28;
+const ERRNO_IO: i32 = 29;
+const ERRNO_NOTCONN: i32 = 53;
+const ERRNO_NOTSOCK: i32 = 57;
+const ERRNO_NOTSUP: i32 = 58;
+const ERRNO_PIPE: i32 = 64;
+
+const FDFLAGS_NONBLOCK: i32 = 1 << 2;
+const RIFLAGS_RECV_PEEK: i32 = 1 << 0;
+const RIFLAGS_RECV_WAITALL: i32 = 1 << 1;
+const SDFLAGS_RD: i32 = 1 << 0;
+const SDFLAGS_WR: i32 = 1 << 1;
+
+/// `sock_accept` was added to preview1 after the witx of wasi-common
+pub(crate) static SOCK_ACCEPT: Signature = Signature {
+    name: "sock_accept",
+    params: &[
+        ("fd", "fd", Param::Int),
+        (
+            "flags",
+            "fdflags",
+            Param::Flags(&["append", "dsync", "nonblock", "rsync", "sync"]),
+        ),
+    ],
+    outs: &[Out::U32],
+    errno_names: WASI_ERRNO_NAMES,
+};
+
+/// Address of a listener preopened for the guest
+#[derive(Clone, Debug)]
+pub enum ListenAddress {
+    Tcp(String),
+    Unix(PathBuf),
+}
+
+pub(crate) enum Socket {
+    TcpListener(TcpListener),
+    TcpStream(TcpStream),
+    #[cfg(unix)]
+    UnixListener(UnixListener),
+    #[cfg(unix)]
+    UnixStream(UnixStream),
+}
+
+impl Socket {
+    pub(crate) fn bind(address: &ListenAddress) -> anyhow::Result<Self> {
+        match address {
+            ListenAddress::Tcp(address) => {
+                let listener = TcpListener::bind(address)
+                    .with_context(|| format!("failed to listen on {}", address))?;
+                Ok(Self::TcpListener(listener))
+            }
+            #[cfg(unix)]
+            ListenAddress::Unix(path) => {
+                // A socket file left by the previous launch prevents binding
+                if let Ok(meta) = std::fs::symlink_metadata(path) {
+                    use std::os::unix::fs::FileTypeExt;
+                    if meta.file_type().is_socket() {
+                        std::fs::remove_file(path)?;
+                    }
+                }
+                let listener = UnixListener::bind(path)
+                    .with_context(|| format!("failed to listen on {}", path.display()))?;
+                Ok(Self::UnixListener(listener))
+            }
+            #[cfg(not(unix))]
+            ListenAddress::Unix(path) => Err(anyhow::anyhow!(
+                "Unix sockets are not supported on this platform: {}",
+                path.display()
+            )),
+        }
+    }
+
+    fn is_listener(&self) -> bool {
+        match self {
+            Self::TcpListener(_) => true,
+            #[cfg(unix)]
+            Self::UnixListener(_) => true,
+            _ => false,
+        }
+    }
+
+    pub(crate) fn accept(&self) -> io::Result<Socket> {
+        match self {
+            Self::TcpListener(listener) => Ok(Self::TcpStream(listener.accept()?.0)),
+            #[cfg(unix)]
+            Self::UnixListener(listener) => Ok(Self::UnixStream(listener.accept()?.0)),
+            _ => Err(io::ErrorKind::InvalidInput.into()),
+        }
+    }
+
+    pub(crate) fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+        match self {
+            Self::TcpListener(listener) => listener.set_nonblocking(nonblocking),
+            Self::TcpStream(stream) => stream.set_nonblocking(nonblocking),
+            #[cfg(unix)]
+            Self::UnixListener(listener) => listener.set_nonblocking(nonblocking),
+            #[cfg(unix)]
+            Self::UnixStream(stream) => stream.set_nonblocking(nonblocking),
+        }
+    }
+
+    pub(crate) fn recv(&self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Self::TcpStream(stream) => (&mut &*stream).read(buf),
+            #[cfg(unix)]
+            Self::UnixStream(stream) => (&mut &*stream).read(buf),
+            _ => Err(io::ErrorKind::NotConnected.into()),
+        }
+    }
+
+    fn peek(&self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Self::TcpStream(stream) => stream.peek(buf),
+            _ => Err(io::ErrorKind::Other.into()),
+        }
+    }
+
+    pub(crate) fn send(&self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Self::TcpStream(stream) => (&mut &*stream).write(buf),
+            #[cfg(unix)]
+            Self::UnixStream(stream) => (&mut &*stream).write(buf),
+            _ => Err(io::ErrorKind::NotConnected.into()),
+        }
+    }
+
+    fn shutdown(&self, how: Shutdown) -> io::Result<()> {
+        match self {
+            Self::TcpStream(stream) => stream.shutdown(how),
+            #[cfg(unix)]
+            Self::UnixStream(stream) => stream.shutdown(how),
+            _ => Err(io::ErrorKind::NotConnected.into()),
+        }
+    }
+}
+
+fn errno(err: &io::Error) -> i32 {
+    match err.kind() {
+        io::ErrorKind::WouldBlock => ERRNO_AGAIN,
+        io::ErrorKind::ConnectionReset | io::ErrorKind::ConnectionAborted => ERRNO_CONNRESET,
+        io::ErrorKind::NotConnected => ERRNO_NOTCONN,
+        io::ErrorKind::BrokenPipe => ERRNO_PIPE,
+        io::ErrorKind::InvalidInput => ERRNO_INVAL,
+        io::ErrorKind::Other => ERRNO_NOTSUP,
+        _ => ERRNO_IO,
+    }
+}
+
+/// Sockets by file descriptor. They are owned by the wasi-common descriptor table, so
+/// a closed descriptor can't be upgraded.
+#[derive(Default)]
+pub(crate) struct SocketTable(RefCell<HashMap<u32, Weak<Socket>>>);
+
+impl SocketTable {
+    fn get(&self, fd: u32) -> Option<Arc<Socket>> {
+        self.0.borrow().get(&fd).and_then(Weak::upgrade)
+    }
+
+    fn insert(&self, fd: u32, socket: &Arc<Socket>) {
+        self.0.borrow_mut().insert(fd, Arc::downgrade(socket));
+    }
+}
+
+/// Gives the socket to the guest and returns its file descriptor
+pub(crate) fn insert_socket(ctx: &WasiContext, socket: Socket) -> anyhow::Result<u32> {
+    let socket = Arc::new(socket);
+    let fd = file::insert_file(&ctx.ctx.borrow(), socket.clone())?;
+    ctx.sockets.insert(fd, &socket);
+    Ok(fd)
+}
+
+fn arg(args: &[WasmValue], index: usize) -> i32 {
+    args[index].as_i32().unwrap()
+}
+
+fn write_u32(mem: &mut [u8], ptr: i32, value: u32) -> bool {
+    let ptr = ptr as u32 as usize;
+    match mem.get_mut(ptr..ptr + 4) {
+        Some(dst) => {
+            dst.copy_from_slice(&value.to_le_bytes());
+            true
+        }
+        None => false,
+    }
+}
+
+/// Returns the memory ranges of the iovec array
+fn iovecs(mem: &[u8], ptr: i32, len: i32) -> Option<Vec<std::ops::Range<usize>>> {
+    (0..len as u32 as usize)
+        .map(|index| {
+            let iovec = ptr as u32 as usize + index * 8;
+            let field = |offset: usize| -> Option<usize> {
+                let bytes = mem.get(iovec + offset..iovec + offset + 4)?;
+                Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize)
+            };
+            let (buf, buf_len) = (field(0)?, field(4)?);
+            if buf + buf_len > mem.len() {
+                return None;
+            }
+            Some(buf..buf + buf_len)
+        })
+        .collect()
+}
+
+fn func<F>(signature: &'static Signature, params: usize, code: F) -> HostValue
+where
+    F: Fn(&[WasmValue], &mut [u8], &WasiContext) -> i32 + 'static,
+{
+    wasi_func(
+        signature,
+        vec![ValType::I32; params],
+        move |args, ctx, wasi_ctx| Ok::<_, Infallible>(code(args, ctx.mem, wasi_ctx)),
+    )
+}
+
+fn with_socket(ctx: &WasiContext, fd: i32, f: impl FnOnce(&Socket) -> i32) -> i32 {
+    match ctx.sockets.get(fd as u32) {
+        Some(socket) => f(&socket),
+        None => ERRNO_NOTSOCK,
+    }
+}
+
+fn sock_recv(args: &[WasmValue], mem: &mut [u8], ctx: &WasiContext) -> i32 {
+    let (fd, ri_data, ri_data_len, ri_flags) =
+        (arg(args, 0), arg(args, 1), arg(args, 2), arg(args, 3));
+    let (ro_datalen, ro_flags) = (arg(args, 4), arg(args, 5));
+    let ranges = match iovecs(mem, ri_data, ri_data_len) {
+        Some(ranges) => ranges,
+        None => return ERRNO_FAULT,
+    };
+    let total = ranges.iter().map(|range| range.len()).sum::<usize>();
+    let mut buf = vec![0; total];
+    let result = with_socket(ctx, fd, |socket| {
+        let mut received = 0;
+        while received < total {
+            let result = if ri_flags & RIFLAGS_RECV_PEEK != 0 {
+                socket.peek(&mut buf[received..])
+            } else {
+                socket.recv(&mut buf[received..])
+            };
+            match result {
+                Ok(0) => break,
+                Ok(len) => received += len,
+                Err(err) if received == 0 => return errno(&err),
+                Err(_) => break,
+            }
+            if ri_flags & RIFLAGS_RECV_WAITALL == 0 || ri_flags & RIFLAGS_RECV_PEEK != 0 {
+                break;
+            }
+        }
+        buf.truncate(received);
+        ERRNO_SUCCESS
+    });
+    if result != ERRNO_SUCCESS {
+        return result;
+    }
+    let mut data = &buf[..];
+    for range in ranges {
+        let len = range.len().min(data.len());
+        mem[range.start..range.start + len].copy_from_slice(&data[..len]);
+        data = &data[len..];
+    }
+    if !write_u32(mem, ro_datalen, buf.len() as u32) || !write_u32(mem, ro_flags, 0) {
+        return ERRNO_FAULT;
+    }
+    ERRNO_SUCCESS
+}
+
+fn sock_send(args: &[WasmValue], mem: &mut [u8], ctx: &WasiContext) -> i32 {
+    let (fd, si_data, si_data_len, so_datalen) =
+        (arg(args, 0), arg(args, 1), arg(args, 2), arg(args, 4));
+    let ranges = match iovecs(mem, si_data, si_data_len) {
+        Some(ranges) => ranges,
+        None => return ERRNO_FAULT,
+    };
+    let data = ranges
+        .into_iter()
+        .flat_map(|range| mem[range].to_vec())
+        .collect::<Vec<_>>();
+    let mut sent = 0;
+    let result = with_socket(ctx, fd, |socket| match socket.send(&data) {
+        Ok(len) => {
+            sent = len;
+            ERRNO_SUCCESS
+        }
+        Err(err) => errno(&err),
+    });
+    if result != ERRNO_SUCCESS {
+        return result;
+    }
+    if !write_u32(mem, so_datalen, sent as u32) {
+        return ERRNO_FAULT;
+    }
+    ERRNO_SUCCESS
+}
+
+fn sock_shutdown(args: &[WasmValue], _mem: &mut [u8], ctx: &WasiContext) -> i32 {
+    let how = match arg(args, 1) {
+        SDFLAGS_RD => Shutdown::Read,
+        SDFLAGS_WR => Shutdown::Write,
+        flags if flags == SDFLAGS_RD | SDFLAGS_WR => Shutdown::Both,
+        _ => return ERRNO_INVAL,
+    };
+    with_socket(ctx, arg(args, 0), |socket| match socket.shutdown(how) {
+        Ok(()) => ERRNO_SUCCESS,
+        Err(err) => errno(&err),
+    })
+}
+
+fn sock_accept(args: &[WasmValue], mem: &mut [u8], ctx: &WasiContext) -> i32 {
+    let (fd, flags, ro_fd) = (arg(args, 0), arg(args, 1), arg(args, 2));
+    let accepted = match ctx.sockets.get(fd as u32) {
+        Some(socket) if socket.is_listener() => socket.accept(),
+        Some(_) => return ERRNO_INVAL,
+        None => return ERRNO_NOTSOCK,
+    };
+    let accepted = match accepted {
+        Ok(accepted) => accepted,
+        Err(err) => return errno(&err),
+    };
+    if flags & FDFLAGS_NONBLOCK != 0 {
+        if let Err(err) = accepted.set_nonblocking(true) {
+            return errno(&err);
+        }
+    }
+    let new_fd = match insert_socket(ctx, accepted) {
+        Ok(new_fd) => new_fd,
+        Err(_) => return ERRNO_IO,
+    };
+    if !write_u32(mem, ro_fd, new_fd) {
+        return ERRNO_FAULT;
+    }
+    ERRNO_SUCCESS
+}
+
+/// Replaces the socket functions of the WASI module. Preview0 has no `sock_accept`.
+pub(crate) fn override_functions(
+    module: &mut HashMap<String, HostValue>,
+    signatures: &'static [Signature],
+    with_accept: bool,
+) {
+    let signature = |name| crate::signature(signatures, name);
+    module.insert(
+        "sock_recv".to_string(),
+        func(signature("sock_recv"), 6, sock_recv),
+    );
+    module.insert(
+        "sock_send".to_string(),
+        func(signature("sock_send"), 5, sock_send),
+    );
+    module.insert(
+        "sock_shutdown".to_string(),
+        func(signature("sock_shutdown"), 2, sock_shutdown),
+    );
+    if with_accept {
+        module.insert(
+            "sock_accept".to_string(),
+            func(&SOCK_ACCEPT, 3, sock_accept),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_loopback() {
+        let listener = Socket::bind(&ListenAddress::Tcp("127.0.0.1:0".to_string())).unwrap();
+        let address = match &listener {
+            Socket::TcpListener(listener) => listener.local_addr().unwrap(),
+            _ => unreachable!(),
+        };
+        let client = std::thread::spawn(move || {
+            let mut stream = TcpStream::connect(address).unwrap();
+            stream.write_all(b"ping").unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+            response
+        });
+        let stream = listener.accept().unwrap();
+        let mut buf = [0; 4];
+        assert_eq!(stream.peek(&mut buf).unwrap(), 4);
+        assert_eq!(stream.recv(&mut buf).unwrap(), 4);
+        assert_eq!(&buf, b"ping");
+        assert_eq!(stream.send(b"pong").unwrap(), 4);
+        stream.shutdown(Shutdown::Write).unwrap();
+        assert_eq!(client.join().unwrap(), "pong");
+        assert_eq!(errno(&listener.recv(&mut buf).unwrap_err()), ERRNO_NOTCONN);
+    }
+}
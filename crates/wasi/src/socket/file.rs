@@ -0,0 +1,138 @@
+//! `WasiFile` for sockets, so that wasi-common can read, write and close them
+
+use super::Socket;
+use std::any::Any;
+use std::io::{self, IoSlice, IoSliceMut, Read, SeekFrom, Write};
+use std::sync::Arc;
+use wasi_common::file::{Advice, FdFlags, FileCaps, FileType, Filestat};
+use wasi_common::{Error, ErrorKind, SystemTimeSpec, WasiCtx, WasiFile};
+
+struct SocketFile {
+    socket: Arc<Socket>,
+    fdflags: FdFlags,
+}
+
+/// Inserts the socket at the lowest free file descriptor
+pub(super) fn insert_file(ctx: &WasiCtx, socket: Arc<Socket>) -> anyhow::Result<u32> {
+    let fd = {
+        let table = ctx.table();
+        (3..u32::MAX)
+            .find(|fd| !table.contains_key(*fd))
+            .ok_or_else(|| anyhow::anyhow!("no file descriptor is available"))?
+    };
+    let file = SocketFile {
+        socket,
+        fdflags: FdFlags::empty(),
+    };
+    ctx.insert_file(fd, Box::new(file), FileCaps::all());
+    Ok(fd)
+}
+
+impl Read for &SocketFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.socket.recv(buf)
+    }
+}
+
+impl Write for &SocketFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.socket.send(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl WasiFile for SocketFile {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn datasync(&self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn sync(&self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn get_filetype(&self) -> Result<FileType, Error> {
+        Ok(FileType::SocketStream)
+    }
+
+    fn get_fdflags(&self) -> Result<FdFlags, Error> {
+        Ok(self.fdflags)
+    }
+
+    fn set_fdflags(&mut self, flags: FdFlags) -> Result<(), Error> {
+        if flags.intersects(!FdFlags::NONBLOCK) {
+            return Err(ErrorKind::Inval.into());
+        }
+        self.socket
+            .set_nonblocking(flags.contains(FdFlags::NONBLOCK))?;
+        self.fdflags = flags;
+        Ok(())
+    }
+
+    fn get_filestat(&self) -> Result<Filestat, Error> {
+        Ok(Filestat {
+            device_id: 0,
+            inode: 0,
+            filetype: FileType::SocketStream,
+            nlink: 1,
+            size: 0,
+            atim: None,
+            mtim: None,
+            ctim: None,
+        })
+    }
+
+    fn set_filestat_size(&self, _size: u64) -> Result<(), Error> {
+        Err(ErrorKind::Badf.into())
+    }
+
+    fn advise(&self, _offset: u64, _len: u64, _advice: Advice) -> Result<(), Error> {
+        Err(ErrorKind::Spipe.into())
+    }
+
+    fn allocate(&self, _offset: u64, _len: u64) -> Result<(), Error> {
+        Err(ErrorKind::Spipe.into())
+    }
+
+    fn set_times(
+        &self,
+        _atime: Option<SystemTimeSpec>,
+        _mtime: Option<SystemTimeSpec>,
+    ) -> Result<(), Error> {
+        Err(ErrorKind::Badf.into())
+    }
+
+    fn read_vectored(&self, bufs: &mut [IoSliceMut]) -> Result<u64, Error> {
+        Ok(Read::read_vectored(&mut &*self, bufs)? as u64)
+    }
+
+    fn read_vectored_at(&self, _bufs: &mut [IoSliceMut], _offset: u64) -> Result<u64, Error> {
+        Err(ErrorKind::Spipe.into())
+    }
+
+    fn write_vectored(&self, bufs: &[IoSlice]) -> Result<u64, Error> {
+        Ok(Write::write_vectored(&mut &*self, bufs)? as u64)
+    }
+
+    fn write_vectored_at(&self, _bufs: &[IoSlice], _offset: u64) -> Result<u64, Error> {
+        Err(ErrorKind::Spipe.into())
+    }
+
+    fn seek(&self, _pos: SeekFrom) -> Result<u64, Error> {
+        Err(ErrorKind::Spipe.into())
+    }
+
+    fn peek(&self, buf: &mut [u8]) -> Result<u64, Error> {
+        Ok(self.socket.peek(buf)? as u64)
+    }
+
+    fn num_ready_bytes(&self) -> Result<u64, Error> {
+        Ok(0)
+    }
+}
@@ -0,0 +1,164 @@
+//! Redirection of the standard streams of the guest
+
+use anyhow::Context;
+use std::io::{Read, Write};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use wasi_cap_std_sync::WasiCtxBuilder;
+use wasi_common::pipe::{ReadPipe, WritePipe};
+use wasi_common::WasiFile;
+
+/// Output written by the guest, shared with the debugger
+#[derive(Clone, Default)]
+pub struct OutputBuffer(Arc<Mutex<Vec<u8>>>);
+
+impl OutputBuffer {
+    pub fn contents(&self) -> Vec<u8> {
+        self.0.lock().unwrap().clone()
+    }
+
+    pub fn clear(&self) {
+        self.0.lock().unwrap().clear();
+    }
+}
+
+/// Where an output stream of the guest goes
+#[derive(Clone)]
+pub enum OutputTarget {
+    Inherit,
+    File(PathBuf),
+    /// Keep the output in the buffer
+    Capture(OutputBuffer),
+    /// Pass the output to the function as soon as it's written
+    Forward(Arc<dyn Fn(&[u8]) + Send + Sync>),
+}
+
+impl Default for OutputTarget {
+    fn default() -> Self {
+        Self::Inherit
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct StdioOptions {
+    /// Read the standard input from the file instead of inheriting it
+    pub stdin: Option<PathBuf>,
+    /// Give the guest an empty standard input instead of inheriting it if `stdin` is not set
+    pub null_stdin: bool,
+    pub stdout: OutputTarget,
+    pub stderr: OutputTarget,
+}
+
+impl StdioOptions {
+    /// Returns the buffer capturing stdout or stderr
+    pub fn captured(&self) -> Option<&OutputBuffer> {
+        match (&self.stdout, &self.stderr) {
+            (OutputTarget::Capture(buffer), _) | (_, OutputTarget::Capture(buffer)) => Some(buffer),
+            _ => None,
+        }
+    }
+}
+
+struct CaptureWriter(OutputBuffer);
+
+impl Write for CaptureWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        (self.0).0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+struct ForwardWriter(Arc<dyn Fn(&[u8]) + Send + Sync>);
+
+impl Write for ForwardWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        (self.0)(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+pub(crate) enum Stream {
+    Stdout,
+    Stderr,
+}
+
+fn set_output(
+    builder: WasiCtxBuilder,
+    stream: Stream,
+    target: &OutputTarget,
+) -> anyhow::Result<WasiCtxBuilder> {
+    let pipe: Box<dyn WasiFile> = match target {
+        OutputTarget::Inherit => {
+            return Ok(match stream {
+                Stream::Stdout => builder.inherit_stdout(),
+                Stream::Stderr => builder.inherit_stderr(),
+            })
+        }
+        OutputTarget::File(path) => {
+            let file = std::fs::File::create(path)
+                .with_context(|| format!("failed to create {}", path.display()))?;
+            Box::new(WritePipe::new(file))
+        }
+        OutputTarget::Capture(buffer) => Box::new(WritePipe::new(CaptureWriter(buffer.clone()))),
+        OutputTarget::Forward(forward) => Box::new(WritePipe::new(ForwardWriter(forward.clone()))),
+    };
+    Ok(match stream {
+        Stream::Stdout => builder.stdout(pipe),
+        Stream::Stderr => builder.stderr(pipe),
+    })
+}
+
+/// Opens the output stream for the hosts writing it without wasi-common
+pub(crate) fn output_writer(
+    stream: Stream,
+    target: &OutputTarget,
+) -> anyhow::Result<Box<dyn Write>> {
+    Ok(match target {
+        OutputTarget::Inherit => match stream {
+            Stream::Stdout => Box::new(std::io::stdout()),
+            Stream::Stderr => Box::new(std::io::stderr()),
+        },
+        OutputTarget::File(path) => Box::new(
+            std::fs::File::create(path)
+                .with_context(|| format!("failed to create {}", path.display()))?,
+        ),
+        OutputTarget::Capture(buffer) => Box::new(CaptureWriter(buffer.clone())),
+        OutputTarget::Forward(forward) => Box::new(ForwardWriter(forward.clone())),
+    })
+}
+
+pub(crate) fn input_reader(options: &StdioOptions) -> anyhow::Result<Box<dyn Read>> {
+    Ok(match &options.stdin {
+        Some(path) => Box::new(
+            std::fs::File::open(path)
+                .with_context(|| format!("failed to open {}", path.display()))?,
+        ),
+        None if options.null_stdin => Box::new(std::io::empty()),
+        None => Box::new(std::io::stdin()),
+    })
+}
+
+pub(crate) fn set_stdio(
+    builder: WasiCtxBuilder,
+    options: &StdioOptions,
+) -> anyhow::Result<WasiCtxBuilder> {
+    let builder = match &options.stdin {
+        Some(path) => {
+            let file = std::fs::File::open(path)
+                .with_context(|| format!("failed to open {}", path.display()))?;
+            builder.stdin(Box::new(ReadPipe::new(file)))
+        }
+        None if options.null_stdin => builder.stdin(Box::new(ReadPipe::new(std::io::empty()))),
+        None => builder.inherit_stdin(),
+    };
+    let builder = set_output(builder, Stream::Stdout, &options.stdout)?;
+    set_output(builder, Stream::Stderr, &options.stderr)
+}
@@ -0,0 +1,268 @@
+//! Decoding of WASI calls for tracing.
+//!
+//! The signatures are generated from the witx definitions by
+//! `define_wasi_fn_for_wasminspect!`, and each call is logged to stderr with the names
+//! and types of the arguments, the decoded values in the linear memory and the errno.
+
+use std::fmt::Debug;
+use wasminspect_vm::WasmValue;
+
+/// Strings and buffers longer than this are truncated
+const MAX_BYTES: usize = 64;
+/// Iovecs after this are omitted
+const MAX_IOVECS: u32 = 16;
+
+pub enum Param {
+    Int,
+    /// Bitset with the names of bits from the lowest
+    Flags(&'static [&'static str]),
+    Enum(&'static [&'static str]),
+    /// Pointer and length of UTF-8 string
+    Str,
+    /// Pointer and length of iovec array
+    Iovecs {
+        is_const: bool,
+    },
+    /// Pointer and length of any other array
+    List,
+    Pointer,
+}
+
+impl Param {
+    /// Number of wasm values the parameter is lowered to
+    fn arity(&self) -> usize {
+        match self {
+            Self::Str | Self::Iovecs { .. } | Self::List => 2,
+            _ => 1,
+        }
+    }
+}
+
+/// Value written to the out pointer on success
+pub enum Out {
+    U32,
+    U64,
+    Pointer,
+}
+
+pub struct Signature {
+    pub name: &'static str,
+    /// Name, type name and how to decode each parameter
+    pub params: &'static [(&'static str, &'static str, Param)],
+    pub outs: &'static [Out],
+    pub errno_names: &'static [&'static str],
+}
+
+impl Signature {
+    pub fn format_call(&self, args: &[WasmValue], mem: &[u8]) -> String {
+        let mut values = args.iter();
+        let params = self
+            .params
+            .iter()
+            .map(|(name, ty, param)| {
+                let lowered = values.by_ref().take(param.arity()).collect::<Vec<_>>();
+                format!("{}: {} = {}", name, ty, format_param(param, &lowered, mem))
+            })
+            .collect::<Vec<_>>();
+        format!("{}({})", self.name, params.join(", "))
+    }
+
+    /// Returns the string parameters named `path` or `*_path` paired with the directory
+    /// descriptor named the same way, e.g. `old_fd` for `old_path`
+    pub fn paths(&self, args: &[WasmValue], mem: &[u8]) -> Vec<(Option<u32>, String)> {
+        let mut lowered = Vec::new();
+        let mut values = args.iter();
+        for (name, _, param) in self.params {
+            let param_values = values.by_ref().take(param.arity()).collect::<Vec<_>>();
+            lowered.push((*name, param, param_values));
+        }
+        lowered
+            .iter()
+            .filter(|(name, param, _)| matches!(param, Param::Str) && name.ends_with("path"))
+            .filter_map(|(name, _, values)| {
+                let bytes = read_bytes(mem, as_u32(values.first()?), as_u32(values.get(1)?))?;
+                let fd_name = format!("{}fd", name.trim_end_matches("path"));
+                let fd = lowered
+                    .iter()
+                    .find(|(name, _, _)| *name == fd_name)
+                    .and_then(|(_, _, values)| values.first())
+                    .map(|fd| as_u32(fd));
+                Some((fd, String::from_utf8_lossy(bytes).into_owned()))
+            })
+            .collect()
+    }
+
+    pub fn log_call(&self, call: &str) {
+        eprintln!("[wasi] {}", call);
+    }
+
+    pub fn log_return(&self, call: &str, errno: i32, args: &[WasmValue], mem: &[u8]) {
+        let errno_name = self
+            .errno_names
+            .get(errno as usize)
+            .map(|name| name.to_string())
+            .unwrap_or_else(|| errno.to_string());
+        if errno != 0 || self.outs.is_empty() {
+            eprintln!("[wasi] {} -> {}", call, errno_name);
+            return;
+        }
+        let lowered_len = self.params.iter().map(|(_, _, p)| p.arity()).sum::<usize>();
+        let outs = self
+            .outs
+            .iter()
+            .zip(args.iter().skip(lowered_len))
+            .map(|(out, ptr)| format_out(out, as_u32(ptr), mem))
+            .collect::<Vec<_>>();
+        eprintln!("[wasi] {} -> {}, {}", call, errno_name, outs.join(", "));
+    }
+
+    pub fn log_trap(&self, call: &str, err: &dyn Debug) {
+        eprintln!("[wasi] {} -> trap: {:?}", call, err);
+    }
+}
+
+fn as_u32(value: &WasmValue) -> u32 {
+    value.as_i32().unwrap_or(0) as u32
+}
+
+fn read_bytes(mem: &[u8], ptr: u32, len: u32) -> Option<&[u8]> {
+    let start = ptr as usize;
+    mem.get(start..start.checked_add(len as usize)?)
+}
+
+fn read_u32(mem: &[u8], ptr: u32) -> Option<u32> {
+    let bytes = read_bytes(mem, ptr, 4)?;
+    Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+fn read_u64(mem: &[u8], ptr: u32) -> Option<u64> {
+    let bytes = read_bytes(mem, ptr, 8)?;
+    let mut buf = [0; 8];
+    buf.copy_from_slice(bytes);
+    Some(u64::from_le_bytes(buf))
+}
+
+fn format_bytes(bytes: &[u8]) -> String {
+    let text = String::from_utf8_lossy(&bytes[..bytes.len().min(MAX_BYTES)]);
+    if bytes.len() > MAX_BYTES {
+        format!("{:?}...", text)
+    } else {
+        format!("{:?}", text)
+    }
+}
+
+fn format_flags(names: &[&str], bits: u64) -> String {
+    if bits == 0 {
+        return "0".to_string();
+    }
+    let mut flags = names
+        .iter()
+        .enumerate()
+        .filter(|(index, _)| bits & (1 << index) != 0)
+        .map(|(_, name)| name.to_string())
+        .collect::<Vec<_>>();
+    let unknown = bits & !((1u64 << names.len().min(63)) - 1);
+    if unknown != 0 {
+        flags.push(format!("{:#x}", unknown));
+    }
+    flags.join("|")
+}
+
+fn format_param(param: &Param, values: &[&WasmValue], mem: &[u8]) -> String {
+    let value = match values.first() {
+        Some(value) => value,
+        None => return "?".to_string(),
+    };
+    let len = values.get(1).map(|len| as_u32(len)).unwrap_or(0);
+    match param {
+        Param::Int => match value.as_i64() {
+            Some(v) => v.to_string(),
+            None => as_u32(value).to_string(),
+        },
+        Param::Flags(names) => {
+            let bits = value
+                .as_i64()
+                .map(|v| v as u64)
+                .unwrap_or(as_u32(value) as u64);
+            format_flags(names, bits)
+        }
+        Param::Enum(names) => {
+            let index = as_u32(value);
+            names
+                .get(index as usize)
+                .map(|name| name.to_string())
+                .unwrap_or_else(|| index.to_string())
+        }
+        Param::Str => match read_bytes(mem, as_u32(value), len) {
+            Some(bytes) => format_bytes(bytes),
+            None => format!("<invalid {:#x}>", as_u32(value)),
+        },
+        Param::Iovecs { is_const } => {
+            let ptr = as_u32(value);
+            let mut iovecs = (0..len.min(MAX_IOVECS))
+                .map(|index| {
+                    let iovec = ptr.wrapping_add(index * 8);
+                    let buf_len = read_u32(mem, iovec.wrapping_add(4));
+                    let (buf, buf_len) = match (read_u32(mem, iovec), buf_len) {
+                        (Some(buf), Some(buf_len)) => (buf, buf_len),
+                        _ => return format!("<invalid {:#x}>", iovec),
+                    };
+                    match read_bytes(mem, buf, buf_len) {
+                        Some(bytes) if *is_const => format_bytes(bytes),
+                        _ => format!("{{buf: {:#x}, len: {}}}", buf, buf_len),
+                    }
+                })
+                .collect::<Vec<_>>();
+            if len > MAX_IOVECS {
+                iovecs.push("...".to_string());
+            }
+            format!("[{}]", iovecs.join(", "))
+        }
+        Param::List => format!("[{:#x}; {}]", as_u32(value), len),
+        Param::Pointer => format!("{:#x}", as_u32(value)),
+    }
+}
+
+fn format_out(out: &Out, ptr: u32, mem: &[u8]) -> String {
+    let value = match out {
+        Out::U32 => read_u32(mem, ptr).map(|v| v.to_string()),
+        Out::U64 => read_u64(mem, ptr).map(|v| v.to_string()),
+        Out::Pointer => Some(format!("{:#x}", ptr)),
+    };
+    value.unwrap_or_else(|| format!("<invalid {:#x}>", ptr))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_call() {
+        const SIGNATURE: Signature = Signature {
+            name: "path_open",
+            params: &[
+                ("fd", "fd", Param::Int),
+                ("path", "string", Param::Str),
+                (
+                    "oflags",
+                    "oflags",
+                    Param::Flags(&["creat", "directory", "excl", "trunc"]),
+                ),
+                ("iovs", "ciovec_array", Param::Iovecs { is_const: true }),
+            ],
+            outs: &[Out::U32],
+            errno_names: &["success"],
+        };
+        let mut mem = vec![0; 32];
+        mem[0..5].copy_from_slice(b"a.txt");
+        mem[8..12].copy_from_slice(&16u32.to_le_bytes());
+        mem[12..16].copy_from_slice(&2u32.to_le_bytes());
+        mem[16..18].copy_from_slice(b"hi");
+        let args = [3, 0, 5, 9, 8, 1].map(WasmValue::I32);
+        assert_eq!(
+            SIGNATURE.format_call(&args, &mem),
+            "path_open(fd: fd = 3, path: string = \"a.txt\", oflags: oflags = creat|trunc, \
+             iovs: ciovec_array = [\"hi\"])"
+        );
+    }
+}
@@ -0,0 +1,671 @@
+//! In-memory filesystem preopened to the guest instead of host directories.
+//!
+//! Each mount is populated from a snapshot of a host directory, a tar archive or inline
+//! files, or overlays a host directory whose entries are copied into memory on first
+//! access. Writes of the guest never reach the host in any case.
+
+mod handle;
+
+use anyhow::{anyhow, Context};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+pub(crate) use handle::VirtualDir;
+
+type Ino = usize;
+
+const TAR_BLOCK_SIZE: usize = 512;
+
+#[derive(Clone, Debug)]
+pub enum VfsSource {
+    /// Copy the host directory into memory
+    Snapshot(PathBuf),
+    /// Extract the tar archive into memory
+    Tar(PathBuf),
+    /// Read the host directory lazily and keep writes in memory
+    Overlay(PathBuf),
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct VfsOptions {
+    /// Guest directories and their initial contents
+    pub mounts: Vec<(String, VfsSource)>,
+    /// Guest paths and contents of files added to the mounts
+    pub files: Vec<(String, Vec<u8>)>,
+}
+
+#[derive(Debug)]
+pub(crate) enum FsError {
+    NotFound,
+    Exists,
+    NotDir,
+    IsDir,
+    NotEmpty,
+    /// The path goes out of the mount
+    Escape,
+    Invalid,
+    Io(std::io::Error),
+}
+
+impl std::error::Error for FsError {}
+impl std::fmt::Display for FsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NotFound => write!(f, "No such file or directory"),
+            Self::Exists => write!(f, "File exists"),
+            Self::NotDir => write!(f, "Not a directory"),
+            Self::IsDir => write!(f, "Is a directory"),
+            Self::NotEmpty => write!(f, "Directory not empty"),
+            Self::Escape => write!(f, "Path is outside of the mount"),
+            Self::Invalid => write!(f, "Invalid path"),
+            Self::Io(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl From<std::io::Error> for FsError {
+    fn from(err: std::io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+enum Node {
+    File(Vec<u8>),
+    Dir {
+        parent: Ino,
+        entries: BTreeMap<String, Ino>,
+    },
+    /// Entry of the overlaid host directory not copied into memory yet
+    Lower {
+        parent: Ino,
+        path: PathBuf,
+        /// Canonical path of the host directory of the mount
+        root: Arc<PathBuf>,
+    },
+}
+
+/// Inodes of all mounts. The root directory of a mount is its own parent.
+struct Tree {
+    nodes: Vec<Node>,
+}
+
+impl Tree {
+    fn push(&mut self, node: Node) -> Ino {
+        self.nodes.push(node);
+        self.nodes.len() - 1
+    }
+
+    fn push_root(&mut self, lower: Option<PathBuf>) -> Ino {
+        let ino = self.nodes.len();
+        match lower {
+            Some(path) => self.push(Node::Lower {
+                parent: ino,
+                root: Arc::new(path.clone()),
+                path,
+            }),
+            None => self.push(Node::Dir {
+                parent: ino,
+                entries: BTreeMap::new(),
+            }),
+        }
+    }
+
+    /// Copies the entry of the host directory into memory. Symbolic links are followed
+    /// only if they point to regular files inside the host directory of the mount, and
+    /// other links and special files are skipped.
+    fn materialize(&mut self, ino: Ino) -> Result<(), FsError> {
+        let (parent, path, root) = match &self.nodes[ino] {
+            Node::Lower { parent, path, root } => (*parent, path.clone(), root.clone()),
+            _ => return Ok(()),
+        };
+        if std::fs::symlink_metadata(&path)?.is_dir() {
+            let mut entries = BTreeMap::new();
+            for entry in std::fs::read_dir(&path)? {
+                let entry = entry?;
+                let file_type = entry.file_type()?;
+                let path = if file_type.is_symlink() {
+                    match resolve_link(&entry.path(), &root) {
+                        Some(target) => target,
+                        None => continue,
+                    }
+                } else if file_type.is_dir() || file_type.is_file() {
+                    entry.path()
+                } else {
+                    continue;
+                };
+                let name = entry.file_name().to_string_lossy().to_string();
+                let child = self.push(Node::Lower {
+                    parent: ino,
+                    path,
+                    root: root.clone(),
+                });
+                entries.insert(name, child);
+            }
+            self.nodes[ino] = Node::Dir { parent, entries };
+        } else {
+            self.nodes[ino] = Node::File(std::fs::read(&path)?);
+        }
+        Ok(())
+    }
+
+    fn materialize_all(&mut self, root: Ino) -> Result<(), FsError> {
+        let mut stack = vec![root];
+        while let Some(ino) = stack.pop() {
+            self.materialize(ino)?;
+            if let Node::Dir { entries, .. } = &self.nodes[ino] {
+                stack.extend(entries.values());
+            }
+        }
+        Ok(())
+    }
+
+    fn is_dir(&self, ino: Ino) -> bool {
+        match &self.nodes[ino] {
+            Node::File(_) => false,
+            Node::Dir { .. } => true,
+            Node::Lower { path, .. } => std::fs::symlink_metadata(path)
+                .map(|meta| meta.is_dir())
+                .unwrap_or(false),
+        }
+    }
+
+    fn size(&self, ino: Ino) -> u64 {
+        match &self.nodes[ino] {
+            Node::File(data) => data.len() as u64,
+            Node::Dir { .. } => 0,
+            Node::Lower { path, .. } => std::fs::symlink_metadata(path)
+                .map(|meta| meta.len())
+                .unwrap_or(0),
+        }
+    }
+
+    fn entries(&mut self, dir: Ino) -> Result<&mut BTreeMap<String, Ino>, FsError> {
+        self.materialize(dir)?;
+        match &mut self.nodes[dir] {
+            Node::Dir { entries, .. } => Ok(entries),
+            _ => Err(FsError::NotDir),
+        }
+    }
+
+    fn parent(&self, dir: Ino) -> Option<Ino> {
+        match &self.nodes[dir] {
+            Node::Dir { parent, .. } | Node::Lower { parent, .. } => Some(*parent),
+            Node::File(_) => None,
+        }
+    }
+
+    fn set_parent(&mut self, dir: Ino, new_parent: Ino) {
+        match &mut self.nodes[dir] {
+            Node::Dir { parent, .. } | Node::Lower { parent, .. } => *parent = new_parent,
+            Node::File(_) => {}
+        }
+    }
+
+    fn lookup(&mut self, dir: Ino, name: &str) -> Result<Ino, FsError> {
+        match name {
+            "." => {
+                self.entries(dir)?;
+                Ok(dir)
+            }
+            ".." => match self.parent(dir) {
+                Some(parent) if parent == dir => Err(FsError::Escape),
+                Some(parent) => Ok(parent),
+                None => Err(FsError::NotDir),
+            },
+            _ => self
+                .entries(dir)?
+                .get(name)
+                .copied()
+                .ok_or(FsError::NotFound),
+        }
+    }
+
+    fn resolve(&mut self, dir: Ino, path: &str) -> Result<Ino, FsError> {
+        if path.starts_with('/') {
+            return Err(FsError::Escape);
+        }
+        path.split('/')
+            .filter(|name| !name.is_empty())
+            .try_fold(dir, |current, name| self.lookup(current, name))
+    }
+
+    /// Resolves the parent directory of the path and returns it with the last name
+    fn resolve_parent<'a>(&mut self, dir: Ino, path: &'a str) -> Result<(Ino, &'a str), FsError> {
+        let path = path.trim_end_matches('/');
+        let (parent, name) = match path.rfind('/') {
+            Some(index) => (&path[..index], &path[index + 1..]),
+            None => ("", path),
+        };
+        if name.is_empty() || name == "." || name == ".." {
+            return Err(FsError::Invalid);
+        }
+        let parent = self.resolve(dir, parent)?;
+        self.entries(parent)?;
+        Ok((parent, name))
+    }
+
+    fn create_file(&mut self, dir: Ino, path: &str, exclusive: bool) -> Result<Ino, FsError> {
+        let (parent, name) = self.resolve_parent(dir, path)?;
+        if let Some(ino) = self.entries(parent)?.get(name).copied() {
+            return if exclusive {
+                Err(FsError::Exists)
+            } else {
+                Ok(ino)
+            };
+        }
+        let ino = self.push(Node::File(vec![]));
+        self.entries(parent)?.insert(name.to_string(), ino);
+        Ok(ino)
+    }
+
+    fn create_dir(&mut self, dir: Ino, path: &str) -> Result<Ino, FsError> {
+        let (parent, name) = self.resolve_parent(dir, path)?;
+        if self.entries(parent)?.contains_key(name) {
+            return Err(FsError::Exists);
+        }
+        let ino = self.push(Node::Dir {
+            parent,
+            entries: BTreeMap::new(),
+        });
+        self.entries(parent)?.insert(name.to_string(), ino);
+        Ok(ino)
+    }
+
+    fn create_dir_all(&mut self, dir: Ino, path: &str) -> Result<Ino, FsError> {
+        let mut current = dir;
+        for name in path.split('/').filter(|name| !name.is_empty()) {
+            current = match self.lookup(current, name) {
+                Ok(ino) => ino,
+                Err(FsError::NotFound) => self.create_dir(current, name)?,
+                Err(err) => return Err(err),
+            };
+        }
+        Ok(current)
+    }
+
+    fn file_mut(&mut self, ino: Ino) -> Result<&mut Vec<u8>, FsError> {
+        self.materialize(ino)?;
+        match &mut self.nodes[ino] {
+            Node::File(data) => Ok(data),
+            _ => Err(FsError::IsDir),
+        }
+    }
+
+    fn remove(&mut self, dir: Ino, path: &str, is_dir: bool) -> Result<(), FsError> {
+        let (parent, name) = self.resolve_parent(dir, path)?;
+        let ino = self.lookup(parent, name)?;
+        match (is_dir, self.is_dir(ino)) {
+            (true, false) => return Err(FsError::NotDir),
+            (false, true) => return Err(FsError::IsDir),
+            (true, true) if !self.entries(ino)?.is_empty() => return Err(FsError::NotEmpty),
+            _ => {}
+        }
+        self.entries(parent)?.remove(name);
+        Ok(())
+    }
+
+    fn rename(
+        &mut self,
+        src_dir: Ino,
+        src_path: &str,
+        dst_dir: Ino,
+        dst_path: &str,
+    ) -> Result<(), FsError> {
+        let (src_parent, src_name) = self.resolve_parent(src_dir, src_path)?;
+        let ino = self.lookup(src_parent, src_name)?;
+        let (dst_parent, dst_name) = self.resolve_parent(dst_dir, dst_path)?;
+        if let Some(existing) = self.entries(dst_parent)?.get(dst_name).copied() {
+            match (self.is_dir(ino), self.is_dir(existing)) {
+                (true, false) => return Err(FsError::NotDir),
+                (false, true) => return Err(FsError::IsDir),
+                (true, true) if existing != ino && !self.entries(existing)?.is_empty() => {
+                    return Err(FsError::NotEmpty)
+                }
+                _ => {}
+            }
+        }
+        if self.is_dir(ino) {
+            // A directory can't be moved into itself
+            let mut ancestor = dst_parent;
+            loop {
+                if ancestor == ino {
+                    return Err(FsError::Invalid);
+                }
+                match self.parent(ancestor) {
+                    Some(parent) if parent != ancestor => ancestor = parent,
+                    _ => break,
+                }
+            }
+            self.set_parent(ino, dst_parent);
+        }
+        self.entries(src_parent)?.remove(src_name);
+        self.entries(dst_parent)?.insert(dst_name.to_string(), ino);
+        Ok(())
+    }
+
+    fn hard_link(
+        &mut self,
+        src_dir: Ino,
+        src_path: &str,
+        dst_dir: Ino,
+        dst_path: &str,
+    ) -> Result<(), FsError> {
+        let ino = self.resolve(src_dir, src_path)?;
+        if self.is_dir(ino) {
+            return Err(FsError::IsDir);
+        }
+        let (dst_parent, dst_name) = self.resolve_parent(dst_dir, dst_path)?;
+        let entries = self.entries(dst_parent)?;
+        if entries.contains_key(dst_name) {
+            return Err(FsError::Exists);
+        }
+        entries.insert(dst_name.to_string(), ino);
+        Ok(())
+    }
+
+    /// Extracts regular files and directories of the ustar archive
+    fn extract_tar(&mut self, root: Ino, archive: &[u8]) -> anyhow::Result<()> {
+        let mut offset = 0;
+        while let Some(header) = archive.get(offset..offset + TAR_BLOCK_SIZE) {
+            if header.iter().all(|byte| *byte == 0) {
+                break;
+            }
+            let name = tar_string(&header[0..100]);
+            let prefix = tar_string(&header[345..500]);
+            let path = if prefix.is_empty() {
+                name
+            } else {
+                format!("{}/{}", prefix, name)
+            };
+            let size = tar_octal(&header[124..136])
+                .ok_or_else(|| anyhow!("invalid size of '{}' in tar archive", path))?;
+            let data_start = offset + TAR_BLOCK_SIZE;
+            let data = archive
+                .get(data_start..data_start + size)
+                .ok_or_else(|| anyhow!("truncated tar archive at '{}'", path))?;
+            match header[156] {
+                b'5' => {
+                    self.create_dir_all(root, &path)?;
+                }
+                b'0' | b'\0' | b'7' => {
+                    let ino = self.create_file_all(root, &path)?;
+                    *self.file_mut(ino)? = data.to_vec();
+                }
+                // Links, devices and extended headers are not supported
+                _ => {}
+            }
+            offset = data_start + (size + TAR_BLOCK_SIZE - 1) / TAR_BLOCK_SIZE * TAR_BLOCK_SIZE;
+        }
+        Ok(())
+    }
+
+    /// Creates the file and its missing parent directories
+    fn create_file_all(&mut self, root: Ino, path: &str) -> Result<Ino, FsError> {
+        let path = path.trim_start_matches("./").trim_start_matches('/');
+        if let Some(index) = path.rfind('/') {
+            self.create_dir_all(root, &path[..index])?;
+        }
+        self.create_file(root, path, false)
+    }
+}
+
+fn tar_string(field: &[u8]) -> String {
+    let len = field.iter().position(|b| *b == 0).unwrap_or(field.len());
+    String::from_utf8_lossy(&field[..len]).to_string()
+}
+
+fn tar_octal(field: &[u8]) -> Option<usize> {
+    let text = tar_string(field);
+    let text = text.trim();
+    if text.is_empty() {
+        return Some(0);
+    }
+    usize::from_str_radix(text, 8).ok()
+}
+
+/// Entry listed by `VirtualFs::list`
+pub struct VfsEntry {
+    pub name: String,
+    pub is_dir: bool,
+    pub size: u64,
+}
+
+/// Handle of the in-memory filesystem shared by the WASI context and the debugger
+#[derive(Clone)]
+pub struct VirtualFs {
+    tree: Arc<Mutex<Tree>>,
+    /// Guest paths and root directories of the mounts
+    mounts: Vec<(String, Ino)>,
+}
+
+impl VirtualFs {
+    pub fn new(options: &VfsOptions) -> anyhow::Result<Self> {
+        let mut tree = Tree { nodes: vec![] };
+        let mut mounts = vec![];
+        for (guest, source) in &options.mounts {
+            let root = match source {
+                VfsSource::Snapshot(host) | VfsSource::Overlay(host) => {
+                    if !host.is_dir() {
+                        return Err(anyhow!("'{}' is not a directory", host.display()));
+                    }
+                    let host = host
+                        .canonicalize()
+                        .with_context(|| format!("failed to resolve {}", host.display()))?;
+                    let root = tree.push_root(Some(host.clone()));
+                    if let VfsSource::Snapshot(_) = source {
+                        tree.materialize_all(root)
+                            .with_context(|| format!("failed to copy {}", host.display()))?;
+                    }
+                    root
+                }
+                VfsSource::Tar(archive) => {
+                    let root = tree.push_root(None);
+                    let bytes = std::fs::read(archive)
+                        .with_context(|| format!("failed to read {}", archive.display()))?;
+                    tree.extract_tar(root, &bytes)
+                        .with_context(|| format!("failed to extract {}", archive.display()))?;
+                    root
+                }
+            };
+            mounts.push((normalize_mount(guest), root));
+        }
+        let mut vfs = Self {
+            tree: Arc::new(Mutex::new(tree)),
+            mounts,
+        };
+        for (path, contents) in &options.files {
+            if vfs.locate(path).is_none() {
+                let root = vfs.tree.lock().unwrap().push_root(None);
+                vfs.mounts.push(("/".to_string(), root));
+            }
+            let (root, path) = vfs.locate(path).unwrap();
+            let mut tree = vfs.tree.lock().unwrap();
+            let ino = tree.create_file_all(root, &path)?;
+            *tree.file_mut(ino)? = contents.clone();
+        }
+        Ok(vfs)
+    }
+
+    /// Guest paths of the mounts
+    pub fn mounts(&self) -> impl Iterator<Item = &str> {
+        self.mounts.iter().map(|(guest, _)| guest.as_str())
+    }
+
+    pub(crate) fn preopen_dirs(&self) -> impl Iterator<Item = (&str, VirtualDir)> {
+        self.mounts
+            .iter()
+            .map(move |(guest, root)| (guest.as_str(), VirtualDir::new(self.tree.clone(), *root)))
+    }
+
+    /// Finds the mount with the longest guest path containing the path
+    fn locate(&self, path: &str) -> Option<(Ino, String)> {
+        self.mounts
+            .iter()
+            .filter_map(|(guest, root)| {
+                let relative = match guest.as_str() {
+                    "." if !path.starts_with('/') => Some(path),
+                    "/" => path.strip_prefix('/'),
+                    guest => path
+                        .strip_prefix(guest)
+                        .filter(|rest| rest.is_empty() || rest.starts_with('/')),
+                }?;
+                Some((
+                    guest.len(),
+                    *root,
+                    relative.trim_start_matches('/').to_string(),
+                ))
+            })
+            .max_by_key(|(len, _, _)| *len)
+            .map(|(_, root, relative)| (root, relative))
+    }
+
+    fn locate_or_err(&self, path: &str) -> anyhow::Result<(Ino, String)> {
+        self.locate(path)
+            .ok_or_else(|| anyhow!("'{}' is not in any mount of the in-memory filesystem", path))
+    }
+
+    /// Lists the directory, or the file itself if the path is a file
+    pub fn list(&self, path: &str) -> anyhow::Result<Vec<VfsEntry>> {
+        let (root, relative) = self.locate_or_err(path)?;
+        let mut tree = self.tree.lock().unwrap();
+        let ino = tree.resolve(root, &relative)?;
+        if !tree.is_dir(ino) {
+            let name = path.rsplit('/').next().unwrap_or(path).to_string();
+            let size = tree.size(ino);
+            return Ok(vec![VfsEntry {
+                name,
+                is_dir: false,
+                size,
+            }]);
+        }
+        let entries = tree
+            .entries(ino)?
+            .iter()
+            .map(|(name, ino)| (name.clone(), *ino))
+            .collect::<Vec<_>>();
+        Ok(entries
+            .into_iter()
+            .map(|(name, ino)| VfsEntry {
+                name,
+                is_dir: tree.is_dir(ino),
+                size: tree.size(ino),
+            })
+            .collect())
+    }
+
+    pub fn read(&self, path: &str) -> anyhow::Result<Vec<u8>> {
+        let (root, relative) = self.locate_or_err(path)?;
+        let mut tree = self.tree.lock().unwrap();
+        let ino = tree.resolve(root, &relative)?;
+        Ok(tree.file_mut(ino)?.clone())
+    }
+
+    /// Replaces the contents of the file, creating it if it doesn't exist
+    pub fn write(&self, path: &str, contents: &[u8]) -> anyhow::Result<()> {
+        let (root, relative) = self.locate_or_err(path)?;
+        let mut tree = self.tree.lock().unwrap();
+        let ino = tree.create_file(root, &relative, false)?;
+        *tree.file_mut(ino)? = contents.to_vec();
+        Ok(())
+    }
+}
+
+/// Returns the target of the symbolic link if it is a regular file inside the root
+fn resolve_link(link: &Path, root: &Path) -> Option<PathBuf> {
+    let target = link.canonicalize().ok()?;
+    if target.starts_with(root) && target.is_file() {
+        Some(target)
+    } else {
+        None
+    }
+}
+
+fn normalize_mount(guest: &str) -> String {
+    match guest.trim_end_matches('/') {
+        "" => "/".to_string(),
+        guest => guest.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tar_header(path: &str, typeflag: u8, size: usize) -> Vec<u8> {
+        let mut header = vec![0; TAR_BLOCK_SIZE];
+        header[..path.len()].copy_from_slice(path.as_bytes());
+        let size = format!("{:011o}", size);
+        header[124..135].copy_from_slice(size.as_bytes());
+        header[156] = typeflag;
+        header
+    }
+
+    #[test]
+    fn test_tar_and_inline_files() {
+        let dir = std::env::temp_dir().join(format!("wasminspect-vfs-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut archive = tar_header("./etc/", b'5', 0);
+        archive.extend(tar_header("./etc/hosts", b'0', 5));
+        archive.extend(b"hosts");
+        archive.resize(archive.len() + TAR_BLOCK_SIZE - 5 + TAR_BLOCK_SIZE * 2, 0);
+        let tar = dir.join("root.tar");
+        std::fs::write(&tar, &archive).unwrap();
+
+        let options = VfsOptions {
+            mounts: vec![("/".to_string(), VfsSource::Tar(tar))],
+            files: vec![("/etc/motd".to_string(), b"hello".to_vec())],
+        };
+        let vfs = VirtualFs::new(&options).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(vfs.read("/etc/hosts").unwrap(), b"hosts");
+        assert_eq!(vfs.read("/etc/motd").unwrap(), b"hello");
+        vfs.write("/etc/hosts", b"changed").unwrap();
+        assert_eq!(vfs.read("/etc/../etc/hosts").unwrap(), b"changed");
+        let names = vfs
+            .list("/etc")
+            .unwrap()
+            .into_iter()
+            .map(|entry| entry.name)
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["hosts", "motd"]);
+        assert!(vfs.read("/../etc/hosts").is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_symlinks_of_host_directory() {
+        use std::os::unix::fs::symlink;
+        let dir = std::env::temp_dir().join(format!("wasminspect-vfs-link-{}", std::process::id()));
+        let root = dir.join("root");
+        std::fs::create_dir_all(&root).unwrap();
+        std::fs::write(root.join("file"), b"inside").unwrap();
+        std::fs::write(dir.join("secret"), b"outside").unwrap();
+        symlink(root.join("file"), root.join("inside")).unwrap();
+        symlink(dir.join("secret"), root.join("outside")).unwrap();
+        symlink(&dir, root.join("parent")).unwrap();
+
+        for source in vec![
+            VfsSource::Snapshot(root.clone()),
+            VfsSource::Overlay(root.clone()),
+        ] {
+            let options = VfsOptions {
+                mounts: vec![("/".to_string(), source)],
+                files: vec![],
+            };
+            let vfs = VirtualFs::new(&options).unwrap();
+            assert_eq!(vfs.read("/inside").unwrap(), b"inside");
+            assert!(vfs.read("/outside").is_err());
+            assert!(vfs.read("/parent/secret").is_err());
+            let names = vfs
+                .list("/")
+                .unwrap()
+                .into_iter()
+                .map(|entry| entry.name)
+                .collect::<Vec<_>>();
+            assert_eq!(names, ["file", "inside"]);
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,366 @@
+//! `WasiDir` and `WasiFile` backed by the in-memory tree
+
+use super::{FsError, Ino, Tree};
+use std::any::Any;
+use std::io::{self, IoSlice, IoSliceMut, SeekFrom};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use wasi_common::dir::{ReaddirCursor, ReaddirEntity};
+use wasi_common::file::{Advice, FdFlags, FileType, Filestat, OFlags};
+use wasi_common::{Error, ErrorKind, SystemTimeSpec, WasiDir, WasiFile};
+
+fn wasi_error(err: FsError) -> Error {
+    match err {
+        FsError::NotFound => io::Error::from(io::ErrorKind::NotFound).into(),
+        // POSIX allows EEXIST for removing a non-empty directory
+        FsError::Exists | FsError::NotEmpty => ErrorKind::Exist.into(),
+        FsError::NotDir => ErrorKind::Notdir.into(),
+        FsError::IsDir => ErrorKind::Badf.into(),
+        FsError::Escape => ErrorKind::NotCapable.into(),
+        FsError::Invalid => ErrorKind::Inval.into(),
+        FsError::Io(err) => err.into(),
+    }
+}
+
+fn filetype(tree: &Tree, ino: Ino) -> FileType {
+    if tree.is_dir(ino) {
+        FileType::Directory
+    } else {
+        FileType::RegularFile
+    }
+}
+
+fn filestat(tree: &Tree, ino: Ino) -> Filestat {
+    Filestat {
+        device_id: 0,
+        inode: ino as u64,
+        filetype: filetype(tree, ino),
+        nlink: 1,
+        size: tree.size(ino),
+        atim: None,
+        mtim: None,
+        ctim: None,
+    }
+}
+
+pub(crate) struct VirtualDir {
+    tree: Arc<Mutex<Tree>>,
+    ino: Ino,
+}
+
+impl VirtualDir {
+    pub(super) fn new(tree: Arc<Mutex<Tree>>, ino: Ino) -> Self {
+        Self { tree, ino }
+    }
+
+    /// Returns the directory if it belongs to the same tree
+    fn same_tree<'a>(&self, dir: &'a dyn WasiDir) -> Result<&'a VirtualDir, Error> {
+        match dir.as_any().downcast_ref::<VirtualDir>() {
+            Some(dir) if Arc::ptr_eq(&self.tree, &dir.tree) => Ok(dir),
+            _ => Err(ErrorKind::Notsup.into()),
+        }
+    }
+}
+
+impl WasiDir for VirtualDir {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn open_file(
+        &self,
+        _symlink_follow: bool,
+        path: &str,
+        oflags: OFlags,
+        read: bool,
+        write: bool,
+        fdflags: FdFlags,
+    ) -> Result<Box<dyn WasiFile>, Error> {
+        let mut tree = self.tree.lock().unwrap();
+        let ino = if oflags.contains(OFlags::CREATE) {
+            tree.create_file(self.ino, path, oflags.contains(OFlags::EXCLUSIVE))
+        } else {
+            tree.resolve(self.ino, path)
+        }
+        .map_err(wasi_error)?;
+        let data = tree.file_mut(ino).map_err(wasi_error)?;
+        if oflags.contains(OFlags::TRUNCATE) && write {
+            data.clear();
+        }
+        Ok(Box::new(VirtualFile {
+            tree: self.tree.clone(),
+            ino,
+            position: AtomicU64::new(0),
+            fdflags,
+            read,
+            write,
+        }))
+    }
+
+    fn open_dir(&self, _symlink_follow: bool, path: &str) -> Result<Box<dyn WasiDir>, Error> {
+        let mut tree = self.tree.lock().unwrap();
+        let ino = tree.resolve(self.ino, path).map_err(wasi_error)?;
+        tree.entries(ino).map_err(wasi_error)?;
+        Ok(Box::new(VirtualDir::new(self.tree.clone(), ino)))
+    }
+
+    fn create_dir(&self, path: &str) -> Result<(), Error> {
+        let mut tree = self.tree.lock().unwrap();
+        tree.create_dir(self.ino, path).map_err(wasi_error)?;
+        Ok(())
+    }
+
+    fn readdir(
+        &self,
+        cursor: ReaddirCursor,
+    ) -> Result<Box<dyn Iterator<Item = Result<(ReaddirEntity, String), Error>>>, Error> {
+        let mut tree = self.tree.lock().unwrap();
+        let parent = tree.parent(self.ino).unwrap_or(self.ino);
+        let mut entries = vec![(".".to_string(), self.ino), ("..".to_string(), parent)];
+        let children = tree.entries(self.ino).map_err(wasi_error)?;
+        entries.extend(children.iter().map(|(name, ino)| (name.clone(), *ino)));
+        let entries = entries
+            .into_iter()
+            .enumerate()
+            .skip(u64::from(cursor) as usize)
+            .map(|(index, (name, ino))| {
+                let entity = ReaddirEntity {
+                    next: ReaddirCursor::from(index as u64 + 1),
+                    inode: ino as u64,
+                    namelen: name.len() as u32,
+                    filetype: filetype(&tree, ino),
+                };
+                Ok((entity, name))
+            })
+            .collect::<Vec<_>>();
+        Ok(Box::new(entries.into_iter()))
+    }
+
+    fn symlink(&self, _old_path: &str, _new_path: &str) -> Result<(), Error> {
+        Err(ErrorKind::Notsup.into())
+    }
+
+    fn remove_dir(&self, path: &str) -> Result<(), Error> {
+        let mut tree = self.tree.lock().unwrap();
+        tree.remove(self.ino, path, true).map_err(wasi_error)
+    }
+
+    fn unlink_file(&self, path: &str) -> Result<(), Error> {
+        let mut tree = self.tree.lock().unwrap();
+        tree.remove(self.ino, path, false).map_err(wasi_error)
+    }
+
+    fn read_link(&self, path: &str) -> Result<std::path::PathBuf, Error> {
+        let mut tree = self.tree.lock().unwrap();
+        tree.resolve(self.ino, path).map_err(wasi_error)?;
+        // Symbolic links are not supported, so the path is never a link
+        Err(ErrorKind::Inval.into())
+    }
+
+    fn get_filestat(&self) -> Result<Filestat, Error> {
+        let tree = self.tree.lock().unwrap();
+        Ok(filestat(&tree, self.ino))
+    }
+
+    fn get_path_filestat(&self, path: &str, _follow_symlinks: bool) -> Result<Filestat, Error> {
+        let mut tree = self.tree.lock().unwrap();
+        let ino = tree.resolve(self.ino, path).map_err(wasi_error)?;
+        Ok(filestat(&tree, ino))
+    }
+
+    fn rename(&self, path: &str, dest_dir: &dyn WasiDir, dest_path: &str) -> Result<(), Error> {
+        let dest_dir = self.same_tree(dest_dir)?;
+        let mut tree = self.tree.lock().unwrap();
+        tree.rename(self.ino, path, dest_dir.ino, dest_path)
+            .map_err(wasi_error)
+    }
+
+    fn hard_link(
+        &self,
+        path: &str,
+        target_dir: &dyn WasiDir,
+        target_path: &str,
+    ) -> Result<(), Error> {
+        let target_dir = self.same_tree(target_dir)?;
+        let mut tree = self.tree.lock().unwrap();
+        tree.hard_link(self.ino, path, target_dir.ino, target_path)
+            .map_err(wasi_error)
+    }
+
+    fn set_times(
+        &self,
+        path: &str,
+        _atime: Option<SystemTimeSpec>,
+        _mtime: Option<SystemTimeSpec>,
+        _follow_symlinks: bool,
+    ) -> Result<(), Error> {
+        // Timestamps are not recorded
+        let mut tree = self.tree.lock().unwrap();
+        tree.resolve(self.ino, path).map_err(wasi_error)?;
+        Ok(())
+    }
+}
+
+struct VirtualFile {
+    tree: Arc<Mutex<Tree>>,
+    ino: Ino,
+    position: AtomicU64,
+    fdflags: FdFlags,
+    read: bool,
+    write: bool,
+}
+
+impl VirtualFile {
+    fn read_at(&self, bufs: &mut [IoSliceMut], offset: u64) -> Result<u64, Error> {
+        if !self.read {
+            return Err(ErrorKind::Badf.into());
+        }
+        let mut tree = self.tree.lock().unwrap();
+        let data = tree.file_mut(self.ino).map_err(wasi_error)?;
+        let mut offset = (offset as usize).min(data.len());
+        let mut total = 0;
+        for buf in bufs.iter_mut() {
+            let len = buf.len().min(data.len() - offset);
+            buf[..len].copy_from_slice(&data[offset..offset + len]);
+            offset += len;
+            total += len;
+        }
+        Ok(total as u64)
+    }
+
+    fn write_at(&self, bufs: &[IoSlice], offset: u64) -> Result<u64, Error> {
+        if !self.write {
+            return Err(ErrorKind::Badf.into());
+        }
+        let mut tree = self.tree.lock().unwrap();
+        let data = tree.file_mut(self.ino).map_err(wasi_error)?;
+        let mut offset = offset as usize;
+        let total = bufs.iter().map(|buf| buf.len()).sum::<usize>();
+        if data.len() < offset + total {
+            data.resize(offset + total, 0);
+        }
+        for buf in bufs {
+            data[offset..offset + buf.len()].copy_from_slice(buf);
+            offset += buf.len();
+        }
+        Ok(total as u64)
+    }
+
+    fn len(&self) -> Result<u64, Error> {
+        let mut tree = self.tree.lock().unwrap();
+        Ok(tree.file_mut(self.ino).map_err(wasi_error)?.len() as u64)
+    }
+}
+
+impl WasiFile for VirtualFile {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn datasync(&self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn sync(&self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn get_filetype(&self) -> Result<FileType, Error> {
+        Ok(FileType::RegularFile)
+    }
+
+    fn get_fdflags(&self) -> Result<FdFlags, Error> {
+        Ok(self.fdflags)
+    }
+
+    fn set_fdflags(&mut self, flags: FdFlags) -> Result<(), Error> {
+        self.fdflags = flags;
+        Ok(())
+    }
+
+    fn get_filestat(&self) -> Result<Filestat, Error> {
+        let tree = self.tree.lock().unwrap();
+        Ok(filestat(&tree, self.ino))
+    }
+
+    fn set_filestat_size(&self, size: u64) -> Result<(), Error> {
+        let mut tree = self.tree.lock().unwrap();
+        let data = tree.file_mut(self.ino).map_err(wasi_error)?;
+        data.resize(size as usize, 0);
+        Ok(())
+    }
+
+    fn advise(&self, _offset: u64, _len: u64, _advice: Advice) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn allocate(&self, offset: u64, len: u64) -> Result<(), Error> {
+        let mut tree = self.tree.lock().unwrap();
+        let data = tree.file_mut(self.ino).map_err(wasi_error)?;
+        let end = (offset + len) as usize;
+        if data.len() < end {
+            data.resize(end, 0);
+        }
+        Ok(())
+    }
+
+    fn set_times(
+        &self,
+        _atime: Option<SystemTimeSpec>,
+        _mtime: Option<SystemTimeSpec>,
+    ) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn read_vectored(&self, bufs: &mut [IoSliceMut]) -> Result<u64, Error> {
+        let position = self.position.load(Ordering::SeqCst);
+        let len = self.read_at(bufs, position)?;
+        self.position.store(position + len, Ordering::SeqCst);
+        Ok(len)
+    }
+
+    fn read_vectored_at(&self, bufs: &mut [IoSliceMut], offset: u64) -> Result<u64, Error> {
+        self.read_at(bufs, offset)
+    }
+
+    fn write_vectored(&self, bufs: &[IoSlice]) -> Result<u64, Error> {
+        let position = if self.fdflags.contains(FdFlags::APPEND) {
+            self.len()?
+        } else {
+            self.position.load(Ordering::SeqCst)
+        };
+        let len = self.write_at(bufs, position)?;
+        self.position.store(position + len, Ordering::SeqCst);
+        Ok(len)
+    }
+
+    fn write_vectored_at(&self, bufs: &[IoSlice], offset: u64) -> Result<u64, Error> {
+        self.write_at(bufs, offset)
+    }
+
+    fn seek(&self, pos: SeekFrom) -> Result<u64, Error> {
+        let (base, delta) = match pos {
+            SeekFrom::Start(offset) => (0, offset as i64),
+            SeekFrom::Current(delta) => (self.position.load(Ordering::SeqCst), delta),
+            SeekFrom::End(delta) => (self.len()?, delta),
+        };
+        let position = if delta < 0 {
+            base.checked_sub(delta.unsigned_abs())
+        } else {
+            base.checked_add(delta as u64)
+        }
+        .ok_or_else(|| Error::from(ErrorKind::Inval))?;
+        self.position.store(position, Ordering::SeqCst);
+        Ok(position)
+    }
+
+    fn peek(&self, buf: &mut [u8]) -> Result<u64, Error> {
+        let position = self.position.load(Ordering::SeqCst);
+        self.read_at(&mut [IoSliceMut::new(buf)], position)
+    }
+
+    fn num_ready_bytes(&self) -> Result<u64, Error> {
+        let position = self.position.load(Ordering::SeqCst);
+        Ok(self.len()?.saturating_sub(position))
+    }
+}
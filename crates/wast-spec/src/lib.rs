@@ -65,7 +65,7 @@ impl WastContext {
             .map_err(|e| anyhow!("Failed to instantiate: {}", e))?;
         if let Some(start_section) = start_section {
             let func_addr = FuncAddr::new_unsafe(module_index, start_section as usize);
-            invoke_func_ignoring_break(func_addr, vec![], &mut self.instance.store, &self.config)
+            invoke_func_ignoring_break(func_addr, vec![], &self.instance.store, &self.config)
                 .map_err(|e| anyhow!("Failed to exec start func: {}", e))?;
         }
         self.current = Some(module_index);
@@ -334,7 +334,7 @@ impl WastContext {
                     return Ok(invoke_func_ignoring_break(
                         func_addr,
                         vec![],
-                        &mut self.instance.store,
+                        &self.instance.store,
                         &self.config,
                     )
                     .map_err(|e| anyhow!("Failed to exec start func: {}", e)));
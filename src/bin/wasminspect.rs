@@ -1,24 +1,11 @@
 use anyhow::anyhow;
 
-use std::io::Read;
+use std::io::{Read, Write};
 use structopt::StructOpt;
-use wasminspect_debugger::{self, ModuleInput};
-
-fn parse_env_var(s: &str) -> anyhow::Result<(String, String)> {
-    let parts: Vec<_> = s.splitn(2, '=').collect();
-    if parts.len() != 2 {
-        return Err(anyhow!("must be of the form `key=value"));
-    }
-    Ok((parts[0].to_owned(), parts[1].to_owned()))
-}
-
-fn parse_map_dirs(s: &str) -> anyhow::Result<(String, String)> {
-    let parts: Vec<&str> = s.split("::").collect();
-    if parts.len() != 2 {
-        return Err(anyhow!("must contain exactly one double colon ('::')"));
-    }
-    Ok((parts[0].into(), parts[1].into()))
-}
+use wasminspect_debugger::{
+    self, parse_env_var, parse_map_dirs, DeterministicOptions, HostCallMode, ListenAddress,
+    ModuleInput, TraceOptions, VfsOptions, VfsSource,
+};
 
 #[derive(StructOpt)]
 struct Opts {
@@ -35,6 +22,167 @@ struct Opts {
     /// Pass an environment variable to the program
     #[structopt(long = "env", number_of_values = 1, value_name = "NAME=VAL", parse(try_from_str = parse_env_var))]
     envs: Vec<(String, String)>,
+
+    /// Load debug info from a separate debug file instead of the wasm binary file
+    #[structopt(long = "debug-file", parse(from_os_str))]
+    debug_file: Option<std::path::PathBuf>,
+
+    /// Load source map v3 file instead of DWARF
+    #[structopt(long = "source-map", parse(from_os_str))]
+    source_map: Option<std::path::PathBuf>,
+
+    /// Write a coredump to the file when the process traps
+    #[structopt(
+        long = "coredump-on-trap",
+        value_name = "CORE_FILE",
+        parse(from_os_str)
+    )]
+    coredump_on_trap: Option<std::path::PathBuf>,
+
+    /// Inspect the coredump of the wasm binary file instead of running it
+    #[structopt(
+        long = "core",
+        value_name = "CORE_FILE",
+        requires = "FILE",
+        parse(from_os_str)
+    )]
+    core: Option<std::path::PathBuf>,
+
+    /// Resume the process from the snapshot saved by `process save`
+    #[structopt(
+        long = "restore",
+        value_name = "SNAPSHOT_FILE",
+        requires = "FILE",
+        conflicts_with = "core",
+        parse(from_os_str)
+    )]
+    restore: Option<std::path::PathBuf>,
+
+    /// Log the calls of host functions to the file to replay them later
+    #[structopt(
+        long = "record-host-calls",
+        value_name = "LOG_FILE",
+        parse(from_os_str)
+    )]
+    record_host_calls: Option<std::path::PathBuf>,
+
+    /// Replay the calls of host functions logged by `--record-host-calls` instead of calling the host
+    #[structopt(
+        long = "replay-host-calls",
+        value_name = "LOG_FILE",
+        conflicts_with = "record-host-calls",
+        parse(from_os_str)
+    )]
+    replay_host_calls: Option<std::path::PathBuf>,
+
+    /// Run the wasm binary file without the console and write the line coverage in LCOV format
+    #[structopt(
+        long = "coverage",
+        value_name = "LCOV_FILE",
+        requires = "FILE",
+        parse(from_os_str)
+    )]
+    coverage: Option<std::path::PathBuf>,
+
+    /// Run the wasm binary file without the console, write the folded stacks of executed instructions
+    /// for flamegraph tools, and show the top functions and source lines
+    #[structopt(
+        long = "profile",
+        value_name = "FOLDED_FILE",
+        requires = "FILE",
+        conflicts_with = "coverage",
+        parse(from_os_str)
+    )]
+    profile: Option<std::path::PathBuf>,
+
+    /// Number of functions and source lines shown by `--profile`
+    #[structopt(long = "profile-top", value_name = "N", default_value = "20")]
+    profile_top: usize,
+
+    /// Arguments passed to the program run with `--coverage` or `--profile`
+    #[structopt(last = true)]
+    args: Vec<String>,
+
+    /// Log calls and returns of functions without stopping the process
+    #[structopt(long = "trace")]
+    trace: bool,
+
+    /// Only trace functions whose name matches the regex
+    #[structopt(long = "trace-filter", value_name = "REGEX", requires = "trace")]
+    trace_filter: Option<regex::Regex>,
+
+    /// Write the trace to the file instead of stdout
+    #[structopt(
+        long = "trace-output",
+        value_name = "FILE",
+        requires = "trace",
+        parse(from_os_str)
+    )]
+    trace_output: Option<std::path::PathBuf>,
+
+    /// Show the names and types of traced parameters from DWARF
+    #[structopt(long = "trace-dwarf", requires = "trace")]
+    trace_dwarf: bool,
+
+    /// Log every WASI call with the decoded arguments and errno to stderr
+    #[structopt(long = "trace-wasi")]
+    trace_wasi: bool,
+
+    /// Replace clocks, random_get, sched_yield and poll_oneoff of WASI with deterministic ones
+    #[structopt(long = "wasi-deterministic")]
+    wasi_deterministic: bool,
+
+    /// Initial time of the deterministic realtime clock in seconds since the Unix epoch
+    #[structopt(
+        long = "wasi-clock-start",
+        value_name = "SECONDS",
+        requires = "wasi-deterministic"
+    )]
+    wasi_clock_start: Option<u64>,
+
+    /// Seed of the deterministic random_get
+    #[structopt(
+        long = "wasi-random-seed",
+        value_name = "SEED",
+        requires = "wasi-deterministic"
+    )]
+    wasi_random_seed: Option<u64>,
+
+    /// Preopen an in-memory copy of the host directory
+    #[structopt(long = "vfs-dir", number_of_values = 1, value_name = "GUEST_DIR::HOST_DIR", parse(try_from_str = parse_map_dirs))]
+    vfs_dirs: Vec<(String, String)>,
+
+    /// Preopen an in-memory directory extracted from the tar archive
+    #[structopt(long = "vfs-tar", number_of_values = 1, value_name = "GUEST_DIR::TAR_FILE", parse(try_from_str = parse_map_dirs))]
+    vfs_tars: Vec<(String, String)>,
+
+    /// Preopen the host directory as copy-on-write, which reads the host files but keeps writes in memory
+    #[structopt(long = "vfs-overlay", number_of_values = 1, value_name = "GUEST_DIR::HOST_DIR", parse(try_from_str = parse_map_dirs))]
+    vfs_overlays: Vec<(String, String)>,
+
+    /// Add a file to the in-memory filesystem
+    #[structopt(long = "vfs-file", number_of_values = 1, value_name = "GUEST_PATH=CONTENTS", parse(try_from_str = parse_env_var))]
+    vfs_files: Vec<(String, String)>,
+
+    /// Listen on the TCP address and give the listener to the process as a socket file descriptor
+    #[structopt(long = "tcplisten", number_of_values = 1, value_name = "ADDR")]
+    tcplisten: Vec<String>,
+
+    /// Listen on the Unix socket and give the listener to the process as a socket file descriptor
+    #[structopt(long = "unixlisten", number_of_values = 1, value_name = "PATH")]
+    unixlisten: Vec<std::path::PathBuf>,
+
+    /// Make WASI calls fail, given in the same form as the arguments of `wasi fail`
+    #[structopt(long = "wasi-fail", number_of_values = 1, value_name = "FUNCTION --errno ERRNO [OPTIONS]", parse(try_from_str = wasminspect_debugger::parse_fault))]
+    wasi_faults: Vec<wasminspect_debugger::FaultOptions>,
+
+    /// Serve Debug Adapter Protocol on stdin and stdout instead of the interactive console
+    #[structopt(long = "dap")]
+    dap: bool,
+
+    /// Serve Debug Adapter Protocol over TCP on the given port of localhost
+    #[structopt(long = "dap-port", value_name = "PORT", conflicts_with = "dap")]
+    dap_port: Option<u16>,
 }
 
 fn main() -> anyhow::Result<()> {
@@ -56,14 +204,140 @@ fn main() -> anyhow::Result<()> {
             Some(ModuleInput {
                 bytes: buffer,
                 basename,
+                dirname: filepath.parent().map(|dir| dir.to_path_buf()),
+                debug_file: opts.debug_file,
+                source_map: opts.source_map,
             })
         }
         None => None,
     };
+    if let Some(coverage) = opts.coverage {
+        let module_input = module_input.ok_or_else(|| anyhow!("no wasm binary file"))?;
+        let report = wasminspect_debugger::collect_coverage(
+            module_input,
+            opts.map_dirs,
+            opts.envs,
+            &opts.args,
+        )?;
+        let mut file = std::fs::File::create(&coverage)?;
+        report.write(&mut file)?;
+        return Ok(());
+    }
+    if let Some(profile) = opts.profile {
+        let module_input = module_input.ok_or_else(|| anyhow!("no wasm binary file"))?;
+        let report = wasminspect_debugger::profile_module(
+            module_input,
+            opts.map_dirs,
+            opts.envs,
+            &opts.args,
+        )?;
+        let mut file = std::fs::File::create(&profile)?;
+        report.write_folded(&mut file, true)?;
+        let stdout = std::io::stdout();
+        let mut stdout = stdout.lock();
+        report.write_top(&mut stdout, opts.profile_top, true)?;
+        if !report.lines.is_empty() {
+            writeln!(stdout)?;
+            report.write_lines(&mut stdout, opts.profile_top)?;
+        }
+        return Ok(());
+    }
+    if opts.dap {
+        return wasminspect_debugger::serve_dap_stdio(module_input, opts.map_dirs, opts.envs);
+    }
+    if let Some(port) = opts.dap_port {
+        let addr = ("127.0.0.1", port);
+        return wasminspect_debugger::serve_dap_tcp(module_input, opts.map_dirs, opts.envs, addr);
+    }
+    let replay_host_calls = opts.replay_host_calls;
+    let mounts = opts
+        .vfs_dirs
+        .into_iter()
+        .map(|(guest, host)| (guest, VfsSource::Snapshot(host.into())))
+        .chain(
+            opts.vfs_tars
+                .into_iter()
+                .map(|(guest, tar)| (guest, VfsSource::Tar(tar.into()))),
+        )
+        .chain(
+            opts.vfs_overlays
+                .into_iter()
+                .map(|(guest, host)| (guest, VfsSource::Overlay(host.into()))),
+        )
+        .collect::<Vec<_>>();
+    let wasi_vfs = if mounts.is_empty() && opts.vfs_files.is_empty() {
+        None
+    } else {
+        Some(VfsOptions {
+            mounts,
+            files: opts
+                .vfs_files
+                .into_iter()
+                .map(|(path, contents)| (path, contents.into_bytes()))
+                .collect(),
+        })
+    };
+    let session = wasminspect_debugger::SessionOptions {
+        coredump_on_trap: opts.coredump_on_trap,
+        core: opts.core,
+        restore: opts.restore,
+        host_calls: opts
+            .record_host_calls
+            .map(HostCallMode::Record)
+            .or_else(|| replay_host_calls.map(HostCallMode::Replay)),
+        trace: if opts.trace {
+            Some(TraceOptions {
+                filter: opts.trace_filter,
+                output: opts.trace_output,
+            })
+        } else {
+            None
+        },
+        trace_dwarf: opts.trace_dwarf,
+        trace_wasi: opts.trace_wasi,
+        wasi_deterministic: if opts.wasi_deterministic {
+            Some(DeterministicOptions {
+                clock_start: std::time::Duration::from_secs(opts.wasi_clock_start.unwrap_or(0)),
+                random_seed: opts.wasi_random_seed.unwrap_or(0),
+            })
+        } else {
+            None
+        },
+        wasi_vfs,
+        wasi_listen: opts
+            .tcplisten
+            .into_iter()
+            .map(ListenAddress::Tcp)
+            .chain(opts.unixlisten.into_iter().map(ListenAddress::Unix))
+            .collect(),
+        wasi_faults: opts.wasi_faults,
+    };
     if let Err(err) =
-        wasminspect_debugger::run_loop(module_input, opts.source, opts.map_dirs, opts.envs)
+        wasminspect_debugger::run_loop(module_input, opts.source, opts.map_dirs, opts.envs, session)
     {
         println!("{:?}", err)
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::Opts;
+    use structopt::StructOpt;
+
+    #[test]
+    fn test_record_and_replay_host_calls_conflict() {
+        let opts = Opts::from_iter_safe(&[
+            "wasminspect",
+            "--record-host-calls",
+            "record.log",
+            "--replay-host-calls",
+            "replay.log",
+            "main.wasm",
+        ]);
+        assert!(opts.is_err());
+        assert!(
+            Opts::from_iter_safe(&["wasminspect", "--replay-host-calls", "replay.log"]).is_ok()
+        );
+    }
+}
@@ -1,19 +1,77 @@
-use std::net::SocketAddr;
+use std::net::{SocketAddr, TcpListener};
+use std::path::PathBuf;
 use std::str::FromStr;
 use structopt::StructOpt;
+use wasminspect_debugger::{parse_env_var, parse_map_dirs, ModuleInput};
 
 #[derive(StructOpt)]
 struct Opts {
     /// The listen address
     #[structopt(default_value = "127.0.0.1:4000")]
     listen_addr: String,
+
+    /// Serve LLDB over gdb-remote protocol on the given port of localhost instead of the remote API
+    #[structopt(long = "gdb-remote", value_name = "PORT", requires = "module")]
+    gdb_remote: Option<u16>,
+
+    /// The wasm binary file to debug over gdb-remote protocol
+    #[structopt(long = "module", parse(from_os_str))]
+    module: Option<PathBuf>,
+
+    /// Exported function to start instead of the start function or `_start`
+    #[structopt(long = "entry-point")]
+    entry_point: Option<String>,
+
+    /// Grant access to a guest directory mapped as a host directory
+    #[structopt(long = "mapdir", number_of_values = 1, value_name = "GUEST_DIR::HOST_DIR", parse(try_from_str = parse_map_dirs))]
+    map_dirs: Vec<(String, String)>,
+
+    /// Pass an environment variable to the program
+    #[structopt(long = "env", number_of_values = 1, value_name = "NAME=VAL", parse(try_from_str = parse_env_var))]
+    envs: Vec<(String, String)>,
+
+    /// Arguments passed to the program
+    #[structopt(last = true)]
+    args: Vec<String>,
+}
+
+fn serve_gdb_remote(opts: Opts, port: u16, module: PathBuf) -> anyhow::Result<()> {
+    let bytes = std::fs::read(&module)?;
+    let basename = module
+        .file_name()
+        .expect("invalid file path")
+        .to_string_lossy()
+        .to_string();
+    let module_input = ModuleInput {
+        bytes,
+        basename,
+        dirname: module.parent().map(|dir| dir.to_path_buf()),
+        debug_file: None,
+        source_map: None,
+    };
+    let listener = TcpListener::bind(("127.0.0.1", port))?;
+    eprintln!(
+        "Listening for gdb-remote client on {}",
+        listener.local_addr()?
+    );
+    wasminspect_debugger::serve_gdb_remote(
+        listener,
+        module_input,
+        opts.map_dirs,
+        opts.envs,
+        opts.args,
+        opts.entry_point,
+    )
 }
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     env_logger::init_from_env(env_logger::Env::default().default_filter_or("warn"));
 
-    let opts = Opts::from_args();
+    let mut opts = Opts::from_args();
+    if let (Some(port), Some(module)) = (opts.gdb_remote, opts.module.take()) {
+        return serve_gdb_remote(opts, port, module);
+    }
     let addr = SocketAddr::from_str(&opts.listen_addr)?;
     wasminspect_debugger_server::start(addr).await;
     Ok(())
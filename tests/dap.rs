@@ -0,0 +1,238 @@
+extern crate wasminspect_debugger;
+use serde_json::{json, Value};
+use std::collections::VecDeque;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+
+/// Scripted DAP client talking with the server over TCP
+struct Client {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+    seq: i64,
+    events: VecDeque<Value>,
+}
+
+impl Client {
+    fn connect() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let reader = BufReader::new(stream.try_clone().unwrap());
+            wasminspect_debugger::serve_dap(None, vec![], vec![], reader, stream).unwrap();
+        });
+        let stream = TcpStream::connect(addr).unwrap();
+        Self {
+            reader: BufReader::new(stream.try_clone().unwrap()),
+            writer: stream,
+            seq: 0,
+            events: VecDeque::new(),
+        }
+    }
+
+    fn read_message(&mut self) -> Value {
+        let mut content_length = 0;
+        loop {
+            let mut line = String::new();
+            self.reader.read_line(&mut line).unwrap();
+            let line = line.trim_end();
+            if line.is_empty() {
+                break;
+            }
+            if let Some(value) = line.strip_prefix("Content-Length:") {
+                content_length = value.trim().parse().unwrap();
+            }
+        }
+        let mut content = vec![0; content_length];
+        self.reader.read_exact(&mut content).unwrap();
+        serde_json::from_slice(&content).unwrap()
+    }
+
+    /// Sends a request and returns the response
+    fn response(&mut self, command: &str, arguments: Value) -> Value {
+        self.seq += 1;
+        let content = json!({
+            "seq": self.seq,
+            "type": "request",
+            "command": command,
+            "arguments": arguments,
+        })
+        .to_string();
+        write!(
+            self.writer,
+            "Content-Length: {}\r\n\r\n{}",
+            content.len(),
+            content
+        )
+        .unwrap();
+        loop {
+            let message = self.read_message();
+            if message["type"] == "event" {
+                self.events.push_back(message);
+                continue;
+            }
+            assert_eq!(message["request_seq"], self.seq);
+            return message;
+        }
+    }
+
+    /// Sends a request and returns the body of the successful response
+    fn request(&mut self, command: &str, arguments: Value) -> Value {
+        let response = self.response(command, arguments);
+        assert_eq!(response["success"], true, "{}", response);
+        response["body"].clone()
+    }
+
+    /// Waits for the event and returns its body
+    fn event(&mut self, event: &str) -> Value {
+        loop {
+            let message = match self.events.pop_front() {
+                Some(message) => message,
+                None => self.read_message(),
+            };
+            if message["event"] == event {
+                return message["body"].clone();
+            }
+        }
+    }
+
+    fn evaluate(&mut self, expression: &str) -> Value {
+        let body = self.request(
+            "evaluate",
+            json!({ "expression": expression, "frameId": 0, "context": "watch" }),
+        );
+        body["result"].clone()
+    }
+}
+
+fn example_path(path: &str) -> PathBuf {
+    Path::new(file!())
+        .parent()
+        .unwrap()
+        .join("simple-example")
+        .join(path)
+        .canonicalize()
+        .unwrap()
+}
+
+#[test]
+fn test_dap_function_breakpoint() {
+    let mut client = Client::connect();
+    let capabilities = client.request("initialize", json!({ "adapterID": "wasminspect" }));
+    assert_eq!(capabilities["supportsConfigurationDoneRequest"], true);
+    client.request(
+        "launch",
+        json!({ "program": example_path("counter.wasm"), "entryPoint": "count_up" }),
+    );
+    client.event("initialized");
+    let body = client.request(
+        "setFunctionBreakpoints",
+        json!({ "breakpoints": [{ "name": "increment" }] }),
+    );
+    assert_eq!(body["breakpoints"][0]["verified"], true);
+    client.request("configurationDone", json!({}));
+    assert_eq!(client.event("stopped")["reason"], "breakpoint");
+
+    let threads = client.request("threads", json!({}));
+    assert_eq!(threads["threads"].as_array().unwrap().len(), 1);
+    let trace = client.request("stackTrace", json!({ "threadId": 1 }));
+    assert_eq!(trace["totalFrames"], 2);
+    assert_eq!(trace["stackFrames"][0]["name"], "increment");
+    assert_eq!(trace["stackFrames"][1]["name"], "count_up");
+
+    let scopes = client.request("scopes", json!({ "frameId": 0 }));
+    let locals = scopes["scopes"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|scope| scope["name"] == "Wasm Locals")
+        .unwrap()["variablesReference"]
+        .clone();
+    let variables = client.request("variables", json!({ "variablesReference": locals }));
+    assert_eq!(variables["variables"][0]["name"], "local0");
+    assert_eq!(variables["variables"][0]["value"], "1");
+    assert_eq!(variables["variables"][0]["type"], "i32");
+    assert_eq!(client.evaluate("global0"), "0");
+
+    client.request("stepOut", json!({ "threadId": 1 }));
+    assert_eq!(client.event("stopped")["reason"], "step");
+    let trace = client.request("stackTrace", json!({ "threadId": 1 }));
+    assert_eq!(trace["totalFrames"], 1);
+    assert_eq!(client.evaluate("global0"), "1");
+
+    client.request("continue", json!({ "threadId": 1 }));
+    assert_eq!(client.event("stopped")["reason"], "breakpoint");
+    assert_eq!(client.evaluate("local0"), "2");
+
+    // Commands moving the process are rejected in the debug console
+    for expression in &["process launch", "thread step-in", "run"] {
+        let response = client.response(
+            "evaluate",
+            json!({ "expression": expression, "frameId": 0, "context": "repl" }),
+        );
+        assert_eq!(response["success"], false, "{}", response);
+    }
+    assert_eq!(client.evaluate("local0"), "2");
+
+    client.request("continue", json!({ "threadId": 1 }));
+    let output = client.event("output");
+    assert_eq!(output["output"], "Process finished with [3]\n");
+    assert_eq!(client.event("exited")["exitCode"], 0);
+    client.event("terminated");
+    client.request("disconnect", json!({}));
+}
+
+#[test]
+fn test_dap_source_breakpoint() {
+    let mut client = Client::connect();
+    client.request("initialize", json!({ "adapterID": "wasminspect" }));
+    client.request(
+        "launch",
+        json!({ "program": example_path("c-dwarf/main.wasm") }),
+    );
+    client.event("initialized");
+    let source = example_path("c-dwarf/main.c");
+    let body = client.request(
+        "setBreakpoints",
+        json!({ "source": { "path": source }, "breakpoints": [{ "line": 7 }] }),
+    );
+    assert_eq!(body["breakpoints"][0]["verified"], true);
+    assert_eq!(body["breakpoints"][0]["line"], 7);
+    client.request("configurationDone", json!({}));
+    assert_eq!(client.event("stopped")["reason"], "breakpoint");
+
+    let trace = client.request("stackTrace", json!({ "threadId": 1 }));
+    let frame = &trace["stackFrames"][0];
+    assert_eq!(frame["line"], 7);
+    assert!(frame["source"]["path"]
+        .as_str()
+        .unwrap()
+        .ends_with("main.c"));
+
+    let scopes = client.request("scopes", json!({ "frameId": 0 }));
+    let reference = scopes["scopes"][0]["variablesReference"].clone();
+    let variables = client.request("variables", json!({ "variablesReference": reference }));
+    let variables = variables["variables"].as_array().unwrap();
+    assert!(variables.iter().any(|variable| variable["name"] == "foo"));
+
+    client.request("next", json!({ "threadId": 1 }));
+    assert_eq!(client.event("stopped")["reason"], "step");
+    let trace = client.request("stackTrace", json!({ "threadId": 1 }));
+    assert_eq!(trace["stackFrames"][0]["line"], 8);
+    client.request("disconnect", json!({}));
+}
+
+#[test]
+fn test_dap_guest_output() {
+    let mut client = Client::connect();
+    client.request("initialize", json!({ "adapterID": "wasminspect" }));
+    client.request("launch", json!({ "program": example_path("hello.wasm") }));
+    client.event("initialized");
+    client.request("configurationDone", json!({}));
+    let output = client.event("output");
+    assert_eq!(output["category"], "stdout");
+    assert_eq!(output["output"], "Hello\n");
+    assert_eq!(client.event("exited")["exitCode"], 0);
+    client.request("disconnect", json!({}));
+}
@@ -1,8 +1,9 @@
 extern crate wasminspect_debugger;
 extern crate wasminspect_vm;
-use std::{collections::HashMap, io::Read};
+use std::{cell::RefCell, collections::HashMap, io::Read, rc::Rc};
 use wasminspect_debugger::*;
 use wasminspect_vm::*;
+use wasmparser::{FuncType, ValType};
 use wast_spec::instantiate_spectest;
 
 fn load_file(filename: &str) -> anyhow::Result<Vec<u8>> {
@@ -33,3 +34,968 @@ fn test_load_and_execute() -> anyhow::Result<()> {
         .run(Some("add"), vec![WasmValue::I32(1), WasmValue::I32(2)])?;
     Ok(())
 }
+
+#[test]
+fn test_load_dwarf5() -> anyhow::Result<()> {
+    let (_, mut context) = start_debugger(None, vec![], vec![])?;
+    let example_dir = std::path::Path::new(file!())
+        .parent()
+        .unwrap()
+        .join("simple-example")
+        .join("c-dwarf");
+    let bytes = load_file(example_dir.join("main-dwarf5.wasm").to_str().unwrap())?;
+    try_load_dwarf(&bytes, &mut context)?;
+
+    let offsets = 0..bytes.len();
+    let has_line_info = offsets.clone().any(|offset| {
+        context
+            .sourcemap
+            .find_line_info(offset)
+            .map(|info| info.filepath.ends_with("main.c"))
+            .unwrap_or(false)
+    });
+    assert!(has_line_info);
+    let has_variable = offsets.clone().any(|offset| {
+        context
+            .subroutine
+            .variable_name_list(offset, 0)
+            .map(|vars| vars.iter().any(|var| var.name == "foo"))
+            .unwrap_or(false)
+    });
+    assert!(has_variable);
+    Ok(())
+}
+
+fn append_custom_section(module: &mut Vec<u8>, name: &str, data: &[u8]) {
+    let mut payload = vec![name.len() as u8];
+    payload.extend_from_slice(name.as_bytes());
+    payload.extend_from_slice(data);
+    module.push(0);
+    let mut size = payload.len();
+    loop {
+        let byte = (size & 0x7f) as u8;
+        size >>= 7;
+        if size == 0 {
+            module.push(byte);
+            break;
+        }
+        module.push(byte | 0x80);
+    }
+    module.extend(payload);
+}
+
+/// Builds a module which only has DWARF 5 sections. The code of `main` is
+/// split into 0x10..0x20 and 0x40..0x50 by `DW_AT_ranges` in `.debug_rnglists`.
+fn dwarf5_module_with_ranges() -> anyhow::Result<Vec<u8>> {
+    append_dwarf5_ranges(b"\0asm\x01\0\0\0".to_vec())
+}
+
+fn append_dwarf5_ranges(mut module: Vec<u8>) -> anyhow::Result<Vec<u8>> {
+    use gimli::write::{
+        Address, AttributeValue, DwarfUnit, EndianVec, LineProgram, LineString, Range, RangeList,
+        Sections,
+    };
+    let encoding = gimli::Encoding {
+        format: gimli::Format::Dwarf32,
+        version: 5,
+        address_size: 4,
+    };
+    let mut dwarf = DwarfUnit::new(encoding);
+    let mut program = LineProgram::new(
+        encoding,
+        gimli::LineEncoding::default(),
+        LineString::String(b"/src".to_vec()),
+        LineString::String(b"main.c".to_vec()),
+        None,
+    );
+    let dir = program.default_directory();
+    let file = program.add_file(LineString::String(b"main.c".to_vec()), dir, None);
+    for (address, line) in [(0x10, 2), (0x40, 5)].iter() {
+        program.begin_sequence(Some(Address::Constant(*address)));
+        program.row().file = file;
+        program.row().line = *line;
+        program.generate_row();
+        program.end_sequence(0x10);
+    }
+    dwarf.unit.line_program = program;
+
+    let ranges = dwarf.unit.ranges.add(RangeList(vec![
+        Range::BaseAddress {
+            address: Address::Constant(0x10),
+        },
+        Range::OffsetPair {
+            begin: 0,
+            end: 0x10,
+        },
+        Range::StartLength {
+            begin: Address::Constant(0x40),
+            length: 0x10,
+        },
+    ]));
+    let root = dwarf.unit.root();
+    let main = dwarf.unit.add(root, gimli::DW_TAG_subprogram);
+    let entry = dwarf.unit.get_mut(main);
+    entry.set(gimli::DW_AT_name, AttributeValue::String(b"main".to_vec()));
+    entry.set(gimli::DW_AT_ranges, AttributeValue::RangeListRef(ranges));
+    let variable = dwarf.unit.add(main, gimli::DW_TAG_variable);
+    let entry = dwarf.unit.get_mut(variable);
+    entry.set(gimli::DW_AT_name, AttributeValue::String(b"foo".to_vec()));
+
+    let mut sections = Sections::new(EndianVec::new(gimli::LittleEndian));
+    dwarf.write(&mut sections)?;
+
+    sections.for_each(|id, section| -> anyhow::Result<()> {
+        append_custom_section(&mut module, id.name(), section.slice());
+        Ok(())
+    })?;
+    Ok(module)
+}
+
+#[test]
+fn test_load_dwarf5_ranges() -> anyhow::Result<()> {
+    let (_, mut context) = start_debugger(None, vec![], vec![])?;
+    let bytes = dwarf5_module_with_ranges()?;
+    try_load_dwarf(&bytes, &mut context)?;
+
+    let has_foo = |offset: usize| {
+        context
+            .subroutine
+            .variable_name_list(offset, 0)
+            .map(|vars| vars.iter().any(|var| var.name == "foo"))
+            .unwrap_or(false)
+    };
+    assert!(has_foo(0x10));
+    assert!(has_foo(0x1f));
+    assert!(has_foo(0x40));
+    assert!(!has_foo(0x30));
+    assert!(!has_foo(0x50));
+
+    let line = |offset: usize| {
+        context
+            .sourcemap
+            .find_line_info(offset)
+            .and_then(|info| info.line)
+    };
+    assert_eq!(line(0x10), Some(2));
+    assert_eq!(line(0x40), Some(5));
+    Ok(())
+}
+
+#[test]
+fn test_load_external_debug_info() -> anyhow::Result<()> {
+    let example_dir = std::path::Path::new(file!())
+        .parent()
+        .unwrap()
+        .join("simple-example");
+    let counter = load_file(example_dir.join("counter.wasm").to_str().unwrap())?;
+    let calc = load_file(example_dir.join("calc.wasm").to_str().unwrap())?;
+    let dirname = std::env::temp_dir().join(format!(
+        "wasminspect-external-debug-info-{}",
+        std::process::id()
+    ));
+    std::fs::create_dir_all(&dirname)?;
+
+    // The stripped module only records the relative path of its debug file
+    let debug_file_name = "counter.debug.wasm";
+    let mut stripped = counter.clone();
+    let mut path = vec![debug_file_name.len() as u8];
+    path.extend_from_slice(debug_file_name.as_bytes());
+    append_custom_section(&mut stripped, "external_debug_info", &path);
+
+    let line_at = |debug_file: Option<Vec<u8>>| -> anyhow::Result<Option<u64>> {
+        let debug_file_path = dirname.join(debug_file_name);
+        match debug_file {
+            Some(bytes) => std::fs::write(&debug_file_path, bytes)?,
+            None => {
+                let _ = std::fs::remove_file(&debug_file_path);
+            }
+        }
+        let (_, context) = start_debugger(
+            Some(ModuleInput {
+                bytes: stripped.clone(),
+                basename: String::from("counter.wasm"),
+                dirname: Some(dirname.clone()),
+                debug_file: None,
+                source_map: None,
+            }),
+            vec![],
+            vec![],
+        )?;
+        Ok(context
+            .sourcemap
+            .find_line_info(0x10)
+            .and_then(|info| info.line))
+    };
+
+    let debug_file = append_dwarf5_ranges(counter.clone())?;
+    assert_eq!(line_at(Some(debug_file))?, Some(2));
+
+    // Debug file built for another module is rejected, and the loading falls back
+    let mismatched = append_dwarf5_ranges(calc)?;
+    let (_, mut context) = start_debugger(None, vec![], vec![])?;
+    assert!(try_load_separate_dwarf(&counter, &mismatched, &mut context).is_err());
+    assert_eq!(line_at(Some(mismatched))?, None);
+
+    assert_eq!(line_at(None)?, None);
+    std::fs::remove_dir_all(&dirname)?;
+    Ok(())
+}
+
+#[test]
+fn test_load_source_map() -> anyhow::Result<()> {
+    let example_dir = std::path::Path::new(file!())
+        .parent()
+        .unwrap()
+        .join("simple-example");
+    let bytes = load_file(example_dir.join("source_map.wasm").to_str().unwrap())?;
+    let source = example_dir.join("add.ts").to_str().unwrap().to_string();
+    let location = |context: &CommandContext, offset: usize| {
+        context
+            .sourcemap
+            .find_line_info(offset)
+            .map(|info| (info.filepath, info.line, u64::from(info.column)))
+    };
+    let assert_locations = |context: &CommandContext| {
+        // Generated columns in the source map are offsets in the module,
+        // and are looked up relative to the code section
+        assert_eq!(location(context, 0x3), Some((source.clone(), Some(2), 3)));
+        assert_eq!(location(context, 0x4), Some((source.clone(), Some(2), 3)));
+        assert_eq!(location(context, 0x5), Some((source.clone(), Some(2), 16)));
+        assert_eq!(location(context, 0x7), Some((source.clone(), Some(3), 3)));
+        assert_eq!(location(context, 0x8), None);
+    };
+
+    let (_, mut context) = start_debugger(None, vec![], vec![])?;
+    try_load_source_map(
+        &bytes,
+        &example_dir.join("source_map.wasm.map"),
+        &mut context,
+    )?;
+    assert_locations(&context);
+
+    // Modules without DWARF fall back to the source map in sourceMappingURL
+    let (_, context) = start_debugger(
+        Some(ModuleInput {
+            bytes,
+            basename: String::from("source_map.wasm"),
+            dirname: Some(example_dir.clone()),
+            debug_file: None,
+            source_map: None,
+        }),
+        vec![],
+        vec![],
+    )?;
+    assert_locations(&context);
+    Ok(())
+}
+
+/// Collects the output of commands
+#[derive(Clone, Default)]
+struct BufferPrinter(Rc<RefCell<Vec<String>>>);
+
+impl BufferPrinter {
+    fn take(&self) -> Vec<String> {
+        std::mem::take(&mut *self.0.borrow_mut())
+    }
+}
+
+impl OutputPrinter for BufferPrinter {
+    fn println(&self, output: &str) {
+        self.0.borrow_mut().push(output.to_string());
+    }
+    fn eprintln(&self, output: &str) {
+        self.0.borrow_mut().push(output.to_string());
+    }
+    fn confirm(&self, _: &str) -> bool {
+        true
+    }
+}
+
+/// Starts the debugger with the example loaded
+fn load_example(
+    filename: &str,
+) -> anyhow::Result<(Process<MainDebugger>, CommandContext, BufferPrinter)> {
+    let example_dir = std::path::Path::new(file!())
+        .parent()
+        .unwrap()
+        .join("simple-example");
+    let bytes = load_file(example_dir.join(filename).to_str().unwrap())?;
+    let (process, mut context) = start_debugger(
+        Some(ModuleInput {
+            bytes,
+            basename: String::from(filename),
+            dirname: None,
+            debug_file: None,
+            source_map: None,
+        }),
+        vec![],
+        vec![],
+    )?;
+    let printer = BufferPrinter::default();
+    context.printer = Box::new(printer.clone());
+    Ok((process, context, printer))
+}
+
+/// Starts the example and runs it until the breakpoint
+fn run_example(
+    filename: &str,
+    breakpoint: Breakpoint,
+) -> anyhow::Result<(Process<MainDebugger>, CommandContext, BufferPrinter)> {
+    let (mut process, context, printer) = load_example(filename)?;
+    let debugger = &mut process.debugger;
+    debugger.set_breakpoint(breakpoint);
+    debugger.instantiate(HashMap::new(), Some(&[]))?;
+    assert!(matches!(debugger.run(None, vec![])?, RunResult::Breakpoint));
+    Ok((process, context, printer))
+}
+
+fn next_offset<D: Debugger>(debugger: &D) -> anyhow::Result<usize> {
+    let (insts, next_index) = debugger.selected_instructions()?;
+    Ok(insts[next_index].offset)
+}
+
+#[test]
+fn test_step_inlined_subroutine() -> anyhow::Result<()> {
+    // The DWARF of inline.wasm describes `inc` inlined into `main`
+    let main = || {
+        run_example(
+            "inline.wasm",
+            Breakpoint::Function {
+                name: "main".to_string(),
+            },
+        )
+    };
+
+    // Step in stops at the entry of the inlined subroutine, and leaves it at the next line
+    let (mut process, mut context, _) = main()?;
+    assert_eq!(next_offset(&process.debugger)?, 0x5);
+    process.dispatch_command("thread step-in", &mut context)?;
+    assert_eq!(next_offset(&process.debugger)?, 0x9);
+    process.dispatch_command("thread step-in", &mut context)?;
+    assert_eq!(next_offset(&process.debugger)?, 0xe);
+    assert_eq!(process.debugger.frame().len(), 2);
+
+    // Step over doesn't stop in the subroutine inlined into the current frame
+    let (mut process, mut context, _) = main()?;
+    process.dispatch_command("thread step-over", &mut context)?;
+    assert_eq!(next_offset(&process.debugger)?, 0xe);
+
+    // Step out of the inlined subroutine stays in the physical frame
+    let (mut process, mut context, _) = main()?;
+    process.dispatch_command("thread step-in", &mut context)?;
+    process.dispatch_command("thread step-out", &mut context)?;
+    assert_eq!(next_offset(&process.debugger)?, 0xe);
+    assert_eq!(process.debugger.frame().len(), 2);
+
+    // Step out of the physical frame returns to the caller
+    process.dispatch_command("thread step-out", &mut context)?;
+    assert_eq!(next_offset(&process.debugger)?, 0x17);
+    assert_eq!(process.debugger.frame().len(), 1);
+    Ok(())
+}
+
+#[test]
+fn test_inlined_frames() -> anyhow::Result<()> {
+    let (mut process, mut context, printer) =
+        run_example("inline.wasm", Breakpoint::Instruction { inst_offset: 0xb })?;
+
+    process.dispatch_command("thread backtrace", &mut context)?;
+    assert_eq!(
+        printer.take(),
+        vec![
+            "0: 0xb `inc at /src/main.c:2:12` [inlined]",
+            "1: 0xb `main at /src/main.c:6:9`",
+            "2: 0x15 `_start at /src/main.c:10:3`",
+        ]
+    );
+
+    // Variables are looked up in the scope of the selected virtual frame
+    process.dispatch_command("frame variable", &mut context)?;
+    assert_eq!(printer.take(), vec!["x: int"]);
+    process.dispatch_command("frame select 1", &mut context)?;
+    process.dispatch_command("frame variable", &mut context)?;
+    assert_eq!(printer.take(), vec!["foo: int"]);
+    process.dispatch_command("frame select 3", &mut context)?;
+    assert_eq!(printer.take(), vec!["Frame index 3 is out of range"]);
+    Ok(())
+}
+
+#[test]
+fn test_backtrace() -> anyhow::Result<()> {
+    let (mut process, mut context, printer) =
+        run_example("inline.wasm", Breakpoint::Instruction { inst_offset: 0x10 })?;
+    process.dispatch_command("thread backtrace --count 1", &mut context)?;
+    assert_eq!(printer.take(), vec!["0: 0x10 `main at /src/main.c:7:3`"]);
+
+    let add = "_ZN4demo3add17h0123456789abcdefE";
+    let (mut process, mut context, printer) = run_example(
+        "mangled.wasm",
+        Breakpoint::Function {
+            name: add.to_string(),
+        },
+    )?;
+    process.dispatch_command("thread backtrace", &mut context)?;
+    assert_eq!(
+        printer.take(),
+        vec![
+            "0: 0x3 `demo::add`",
+            "1: 0xf `demo::twice(int)`",
+            "2: 0x16 `_start`",
+        ]
+    );
+    process.dispatch_command("thread backtrace -c 2 --full --mangled", &mut context)?;
+    assert_eq!(
+        printer.take(),
+        vec![
+            "0: 0x3 `_ZN4demo3add17h0123456789abcdefE`",
+            "    arg0: Num(I32(21))",
+            "    arg1: Num(I32(21))",
+            "1: 0xf `_ZN4demo5twiceEi`",
+            "    arg0: Num(I32(21))",
+        ]
+    );
+    Ok(())
+}
+
+#[test]
+fn test_demangle_symbols() -> anyhow::Result<()> {
+    let add = "_ZN4demo3add17h0123456789abcdefE";
+    let (mut process, mut context, printer) = run_example(
+        "mangled.wasm",
+        Breakpoint::Function {
+            name: add.to_string(),
+        },
+    )?;
+    process.dispatch_command("thread info", &mut context)?;
+    process.dispatch_command("thread info --mangled", &mut context)?;
+    assert_eq!(
+        printer.take(),
+        vec!["0x3 `demo::add`", "0x3 `_ZN4demo3add17h0123456789abcdefE`"]
+    );
+
+    process.dispatch_command("settings set symbols.demangle false", &mut context)?;
+    process.dispatch_command("thread backtrace", &mut context)?;
+    assert_eq!(
+        printer.take(),
+        vec![
+            "0: 0x3 `_ZN4demo3add17h0123456789abcdefE`",
+            "1: 0xf `_ZN4demo5twiceEi`",
+            "2: 0x16 `_start`",
+        ]
+    );
+    process.dispatch_command("settings set symbols.demangle yes", &mut context)?;
+    assert_eq!(printer.take(), vec!["'yes' is not a boolean value"]);
+    process.dispatch_command("settings set symbols.demangle true", &mut context)?;
+    process.dispatch_command("thread info", &mut context)?;
+    assert_eq!(printer.take(), vec!["0x3 `demo::add`"]);
+    Ok(())
+}
+
+#[test]
+fn test_coredump_on_trap() -> anyhow::Result<()> {
+    let example_dir = std::path::Path::new(file!())
+        .parent()
+        .unwrap()
+        .join("simple-example");
+    let bytes = load_file(example_dir.join("trap.wasm").to_str().unwrap())?;
+    let core_path = std::env::temp_dir().join(format!("trap-{}.core", std::process::id()));
+
+    let (mut process, _) = start_debugger(None, vec![], vec![])?;
+    let debugger = &mut process.debugger;
+    debugger.load_main_module(&bytes, String::from("trap.wasm"))?;
+    debugger.set_coredump_path(Some(core_path.clone()));
+    debugger.instantiate(HashMap::new(), Some(&[]))?;
+    assert!(debugger.run(None, vec![]).is_err());
+
+    let (mut process, _) = start_debugger(None, vec![], vec![])?;
+    let debugger = &mut process.debugger;
+    debugger.load_main_module(&bytes, String::from("trap.wasm"))?;
+    let core = load_file(core_path.to_str().unwrap())?;
+    std::fs::remove_file(&core_path)?;
+    assert_eq!(debugger.load_coredump(&core)?, "trap.wasm");
+
+    let frames = debugger.frame();
+    let names = frames.iter().map(|f| f.name.as_str()).collect::<Vec<_>>();
+    assert_eq!(names, vec!["_start", "divide"]);
+    assert_eq!(
+        debugger.locals(),
+        vec![WasmValue::I32(42), WasmValue::I32(0)]
+    );
+    let (insts, next_index) = debugger.selected_instructions()?;
+    assert!(matches!(
+        insts[next_index - 1].kind,
+        InstructionKind::I32DivS
+    ));
+    assert_eq!(&debugger.memory()?[16..20], &[0x78, 0x56, 0x34, 0x12]);
+    debugger.select_frame(Some(1), 0)?;
+    assert!(debugger.locals().is_empty());
+    assert!(debugger.process().is_err());
+    Ok(())
+}
+
+#[test]
+fn test_reverse_execution() -> anyhow::Result<()> {
+    let example_dir = std::path::Path::new(file!())
+        .parent()
+        .unwrap()
+        .join("simple-example");
+    let bytes = load_file(example_dir.join("trap.wasm").to_str().unwrap())?;
+
+    let (mut process, _) = start_debugger(None, vec![], vec![])?;
+    let debugger = &mut process.debugger;
+    debugger.load_main_module(&bytes, String::from("trap.wasm"))?;
+    let mut opts = debugger.get_opts();
+    opts.record = true;
+    debugger.set_opts(opts);
+    debugger.set_watchpoint(Watchpoint {
+        address: 16,
+        size: 4,
+    });
+    debugger.instantiate(HashMap::new(), Some(&[]))?;
+    assert!(matches!(debugger.run(None, vec![])?, RunResult::Breakpoint));
+    assert!(debugger.process().is_err());
+
+    // Rewinds to the store hitting the watchpoint
+    assert!(matches!(debugger.reverse_process()?, Signal::Breakpoint));
+    let (insts, next_index) = debugger.selected_instructions()?;
+    assert!(matches!(
+        insts[next_index - 1].kind,
+        InstructionKind::I32Store { .. }
+    ));
+    assert_eq!(&debugger.memory()?[16..20], &[0x78, 0x56, 0x34, 0x12]);
+    assert!(matches!(
+        debugger.step_back(StepStyle::InstIn)?,
+        Signal::Next
+    ));
+    assert_eq!(&debugger.memory()?[16..20], &[0, 0, 0, 0]);
+
+    assert!(matches!(debugger.reverse_process()?, Signal::End));
+    let frames = debugger.frame();
+    let names = frames.iter().map(|f| f.name.as_str()).collect::<Vec<_>>();
+    assert_eq!(names, vec!["_start"]);
+
+    // Executes again from the beginning of the history
+    assert!(matches!(debugger.process()?, RunResult::Breakpoint));
+    assert_eq!(&debugger.memory()?[16..20], &[0x78, 0x56, 0x34, 0x12]);
+    Ok(())
+}
+
+#[test]
+fn test_snapshot_restore() -> anyhow::Result<()> {
+    let example_dir = std::path::Path::new(file!())
+        .parent()
+        .unwrap()
+        .join("simple-example");
+    let bytes = load_file(example_dir.join("counter.wasm").to_str().unwrap())?;
+
+    let (mut process, _) = start_debugger(None, vec![], vec![])?;
+    let debugger = &mut process.debugger;
+    debugger.load_main_module(&bytes, String::from("counter.wasm"))?;
+    debugger.instantiate(HashMap::new(), Some(&[]))?;
+    debugger.set_breakpoint(Breakpoint::Function {
+        name: "increment".to_string(),
+    });
+    assert!(matches!(
+        debugger.run(Some("count_up"), vec![])?,
+        RunResult::Breakpoint
+    ));
+    assert!(matches!(debugger.process()?, RunResult::Breakpoint));
+    let snapshot = debugger.snapshot()?;
+
+    let (mut process, _) = start_debugger(None, vec![], vec![])?;
+    let debugger = &mut process.debugger;
+    debugger.load_main_module(&bytes, String::from("counter.wasm"))?;
+    debugger.restore_snapshot(&snapshot)?;
+    let frames = debugger.frame();
+    let names = frames.iter().map(|f| f.name.as_str()).collect::<Vec<_>>();
+    assert_eq!(names, vec!["count_up", "increment"]);
+    assert_eq!(debugger.locals(), vec![WasmValue::I32(2)]);
+    match debugger.process()? {
+        RunResult::Finish(values) => assert_eq!(values, vec![WasmValue::I32(3)]),
+        RunResult::Breakpoint => panic!("unexpected breakpoint"),
+        RunResult::Exiting(status) | RunResult::Exit(status) => {
+            panic!("unexpected exit with status {}", status)
+        }
+    }
+
+    // Snapshots can't be restored with another module
+    let trap = load_file(example_dir.join("trap.wasm").to_str().unwrap())?;
+    let (mut process, _) = start_debugger(None, vec![], vec![])?;
+    let debugger = &mut process.debugger;
+    debugger.load_main_module(&trap, String::from("trap.wasm"))?;
+    assert!(debugger.restore_snapshot(&snapshot).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_host_call_replay() -> anyhow::Result<()> {
+    let example_dir = std::path::Path::new(file!())
+        .parent()
+        .unwrap()
+        .join("simple-example");
+    let bytes = load_file(example_dir.join("host_call.wasm").to_str().unwrap())?;
+    let log_path = std::env::temp_dir().join("wasminspect_test_host_calls.log");
+
+    // `put` writes the argument to the memory and returns twice of it
+    fn host_modules(factor: i32) -> HashMap<String, HashMap<String, HostValue>> {
+        let ty = FuncType::new(vec![ValType::I32], vec![ValType::I32]);
+        let put = HostFuncBody::new(ty, move |args, results, ctx, _| {
+            let n = args[0].as_i32().unwrap();
+            ctx.mem[0..4].copy_from_slice(&n.to_le_bytes());
+            results.push(WasmValue::I32(n * factor));
+            Ok(())
+        });
+        let mut env = HashMap::new();
+        env.insert("put".to_string(), HostValue::Func(put));
+        let mut modules = HashMap::new();
+        modules.insert("env".to_string(), env);
+        modules
+    }
+    fn run(bytes: &[u8], mode: HostCallMode, factor: i32, arg: i32) -> anyhow::Result<RunResult> {
+        let (mut process, _) = start_debugger(None, vec![], vec![])?;
+        let debugger = &mut process.debugger;
+        let mut opts = debugger.get_opts();
+        opts.host_calls = Some(mode);
+        debugger.set_opts(opts);
+        debugger.load_main_module(bytes, String::from("host_call.wasm"))?;
+        debugger.instantiate(host_modules(factor), None)?;
+        debugger.run(Some("run"), vec![WasmValue::I32(arg)])
+    }
+
+    let result = run(&bytes, HostCallMode::Record(log_path.clone()), 2, 5)?;
+    assert!(matches!(result, RunResult::Finish(values) if values == vec![WasmValue::I32(15)]));
+
+    // The host function isn't called while replaying, so the result doesn't change
+    let result = run(&bytes, HostCallMode::Replay(log_path.clone()), 3, 5)?;
+    assert!(matches!(result, RunResult::Finish(values) if values == vec![WasmValue::I32(15)]));
+
+    // Calls with other arguments diverge from the recording
+    let result = run(&bytes, HostCallMode::Replay(log_path.clone()), 2, 6);
+    let err = result.err().expect("replay should diverge");
+    assert!(err.to_string().contains("diverged"), "{}", err);
+    std::fs::remove_file(log_path)?;
+    Ok(())
+}
+
+#[test]
+fn test_host_call_replay_exit() -> anyhow::Result<()> {
+    let example_dir = std::path::Path::new(file!())
+        .parent()
+        .unwrap()
+        .join("simple-example");
+    let bytes = load_file(example_dir.join("exit.wasm").to_str().unwrap())?;
+    let log_path = std::env::temp_dir().join("wasminspect_test_host_call_exit.log");
+
+    fn host_modules(status: i32) -> HashMap<String, HashMap<String, HostValue>> {
+        let ty = FuncType::new(vec![ValType::I32], vec![]);
+        let exit = HostFuncBody::new(ty, move |_, _, _, _| Err(Trap::Exit(status)));
+        let mut env = HashMap::new();
+        env.insert("exit".to_string(), HostValue::Func(exit));
+        let mut modules = HashMap::new();
+        modules.insert("env".to_string(), env);
+        modules
+    }
+    fn run(bytes: &[u8], mode: HostCallMode, status: i32) -> anyhow::Result<RunResult> {
+        let (mut process, _) = start_debugger(None, vec![], vec![])?;
+        let debugger = &mut process.debugger;
+        let mut opts = debugger.get_opts();
+        opts.host_calls = Some(mode);
+        debugger.set_opts(opts);
+        debugger.load_main_module(bytes, String::from("exit.wasm"))?;
+        debugger.instantiate(host_modules(status), None)?;
+        debugger.run(Some("run"), vec![])
+    }
+
+    let result = run(&bytes, HostCallMode::Record(log_path.clone()), 3)?;
+    assert!(matches!(result, RunResult::Exit(3)));
+
+    // The recorded exit is raised again instead of calling the host function
+    let result = run(&bytes, HostCallMode::Replay(log_path.clone()), 4)?;
+    assert!(matches!(result, RunResult::Exit(3)));
+    std::fs::remove_file(log_path)?;
+    Ok(())
+}
+
+#[test]
+fn test_coverage_summary() -> anyhow::Result<()> {
+    let example_dir = std::path::Path::new(file!())
+        .parent()
+        .unwrap()
+        .join("simple-example");
+    let bytes = load_file(example_dir.join("counter.wasm").to_str().unwrap())?;
+
+    let (mut process, context) = start_debugger(None, vec![], vec![])?;
+    let debugger = &mut process.debugger;
+    debugger.load_main_module(&bytes, String::from("counter.wasm"))?;
+    debugger.enable_coverage();
+    debugger.instantiate(HashMap::new(), Some(&[]))?;
+    debugger.run(Some("count_up"), vec![])?;
+
+    let report = debugger.coverage_report(context.sourcemap.as_ref())?;
+    assert!(report.lines.is_empty());
+    let calls = report
+        .functions
+        .iter()
+        .map(|f| (f.name.as_str(), f.calls, f.executed == f.instructions))
+        .collect::<Vec<_>>();
+    assert_eq!(calls, vec![("increment", 2, true), ("count_up", 1, true)]);
+
+    let mut summary = vec![];
+    report.write(&mut summary)?;
+    assert!(String::from_utf8(summary)?.contains("increment: "));
+    Ok(())
+}
+
+#[test]
+fn test_profile() -> anyhow::Result<()> {
+    let example_dir = std::path::Path::new(file!())
+        .parent()
+        .unwrap()
+        .join("simple-example");
+    let bytes = load_file(example_dir.join("counter.wasm").to_str().unwrap())?;
+
+    let (mut process, context) = start_debugger(None, vec![], vec![])?;
+    let debugger = &mut process.debugger;
+    debugger.load_main_module(&bytes, String::from("counter.wasm"))?;
+    debugger.start_profiling();
+    debugger.instantiate(HashMap::new(), Some(&[]))?;
+    debugger.run(Some("count_up"), vec![])?;
+
+    let report = debugger.profile(context.sourcemap.as_ref())?;
+    let count_up = report
+        .functions
+        .iter()
+        .find(|f| f.name == "count_up")
+        .unwrap();
+    let increment = report
+        .functions
+        .iter()
+        .find(|f| f.name == "increment")
+        .unwrap();
+    assert_eq!(count_up.total, report.total);
+    assert_eq!(count_up.self_count + increment.self_count, report.total);
+    assert_eq!(increment.total, increment.self_count);
+
+    let mut folded = vec![];
+    report.write_folded(&mut folded, false)?;
+    let folded = String::from_utf8(folded)?;
+    let expected = format!("count_up;increment {}\n", increment.self_count);
+    assert!(folded.contains(&expected), "{}", folded);
+    Ok(())
+}
+
+#[test]
+fn test_trace() -> anyhow::Result<()> {
+    let example_dir = std::path::Path::new(file!())
+        .parent()
+        .unwrap()
+        .join("simple-example");
+    let bytes = load_file(example_dir.join("counter.wasm").to_str().unwrap())?;
+    let output = std::env::temp_dir().join("wasminspect-test-trace.log");
+
+    let (mut process, _) = start_debugger(None, vec![], vec![])?;
+    let debugger = &mut process.debugger;
+    debugger.load_main_module(&bytes, String::from("counter.wasm"))?;
+    let options = TraceOptions {
+        filter: None,
+        output: Some(output.clone()),
+    };
+    debugger.start_trace(options, None)?;
+    debugger.instantiate(HashMap::new(), Some(&[]))?;
+    debugger.run(Some("count_up"), vec![])?;
+    debugger.stop_trace();
+
+    let trace = std::fs::read_to_string(&output)?;
+    std::fs::remove_file(&output)?;
+    let expected = [
+        "-> count_up()",
+        "  -> increment(i32 1)",
+        "  <- increment = i32 1",
+        "  -> increment(i32 2)",
+        "  <- increment = i32 3",
+        "<- count_up = i32 3",
+    ];
+    assert_eq!(trace.lines().collect::<Vec<_>>(), expected);
+    Ok(())
+}
+
+#[test]
+fn test_exit() -> anyhow::Result<()> {
+    let example_dir = std::path::Path::new(file!())
+        .parent()
+        .unwrap()
+        .join("simple-example");
+    let bytes = load_file(example_dir.join("exit.wasm").to_str().unwrap())?;
+
+    fn host_modules() -> HashMap<String, HashMap<String, HostValue>> {
+        let ty = FuncType::new(vec![ValType::I32], vec![]);
+        let exit = HostFuncBody::new(ty, |args, _, _, _| {
+            Err(Trap::Exit(args[0].as_i32().unwrap()))
+        });
+        let mut env = HashMap::new();
+        env.insert("exit".to_string(), HostValue::Func(exit));
+        let mut modules = HashMap::new();
+        modules.insert("env".to_string(), env);
+        modules
+    }
+
+    let (mut process, _) = start_debugger(None, vec![], vec![])?;
+    let debugger = &mut process.debugger;
+    debugger.load_main_module(&bytes, String::from("exit.wasm"))?;
+    debugger.instantiate(host_modules(), None)?;
+    let result = debugger.run(Some("run"), vec![])?;
+    assert!(matches!(result, RunResult::Exit(3)));
+    assert!(!debugger.is_running());
+
+    // The exit breakpoint keeps the final state to inspect
+    debugger.set_breakpoint(Breakpoint::Exit);
+    debugger.instantiate(host_modules(), None)?;
+    let result = debugger.run(Some("run"), vec![])?;
+    assert!(matches!(result, RunResult::Exiting(3)));
+    assert_eq!(debugger.memory()?[0], 42);
+    assert_eq!(debugger.frame()[0].name, "run");
+    assert!(matches!(debugger.process()?, RunResult::Exit(3)));
+    assert!(!debugger.is_running());
+    Ok(())
+}
+
+#[test]
+fn test_wasi_preview0() -> anyhow::Result<()> {
+    let example_dir = std::path::Path::new(file!())
+        .parent()
+        .unwrap()
+        .join("simple-example");
+    let bytes = load_file(example_dir.join("preview0.wasm").to_str().unwrap())?;
+    let dir = std::env::temp_dir().join(format!("wasminspect-preview0-{}", std::process::id()));
+    std::fs::create_dir_all(&dir)?;
+    let contents = b"Hello, preview0!\n";
+    std::fs::write(dir.join("data.txt"), contents)?;
+
+    let preopen_dirs = vec![(".".to_string(), dir.to_str().unwrap().to_string())];
+    let (mut process, _) = start_debugger(None, preopen_dirs, vec![])?;
+    let debugger = &mut process.debugger;
+    debugger.load_main_module(&bytes, String::from("preview0.wasm"))?;
+    debugger.instantiate(HashMap::new(), Some(&[]))?;
+    let mut call = |name: &str, args: Vec<WasmValue>| -> anyhow::Result<Vec<u8>> {
+        match debugger.run(Some(name), args)? {
+            RunResult::Finish(values) => assert_eq!(values, vec![WasmValue::I32(0)]),
+            _ => panic!("{} didn't finish", name),
+        }
+        debugger.memory()
+    };
+    let read_u32 = |memory: &[u8], offset: usize| {
+        let mut bytes = [0; 4];
+        bytes.copy_from_slice(&memory[offset..offset + 4]);
+        u32::from_le_bytes(bytes)
+    };
+    let read_u64 = |memory: &[u8], offset: usize| {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(&memory[offset..offset + 8]);
+        u64::from_le_bytes(bytes)
+    };
+
+    let memory = call("open", vec![])?;
+    let fd = WasmValue::I32(read_u32(&memory, 16) as i32);
+
+    // whence 1 is END in preview0
+    let memory = call("seek_end", vec![fd])?;
+    assert_eq!(read_u64(&memory, 24), contents.len() as u64 - 2);
+
+    // Regular file, nlink and size in the layout of preview0
+    let memory = call("filestat", vec![fd])?;
+    assert_eq!(memory[32 + 16], 4);
+    assert_eq!(read_u32(&memory, 32 + 20), 1);
+    assert_eq!(read_u64(&memory, 32 + 24), contents.len() as u64);
+
+    // The clock event has the userdata of the subscription and no error
+    let memory = call("poll", vec![])?;
+    assert_eq!(read_u32(&memory, 224), 1);
+    assert_eq!(read_u64(&memory, 192), 0x1234);
+    assert_eq!(memory[200..203], [0, 0, 0]);
+
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[test]
+fn test_stdio_redirection() -> anyhow::Result<()> {
+    let dir = std::env::temp_dir().join(format!("wasminspect-stdio-{}", std::process::id()));
+    std::fs::create_dir_all(&dir)?;
+    let path = |name: &str| dir.join(name).to_str().unwrap().to_string();
+    std::fs::write(path("in.txt"), "Hello\n")?;
+
+    // echo.wasm writes the standard input to stdout and stderr
+    let (mut process, mut context, printer) = load_example("echo.wasm")?;
+    process.dispatch_command(
+        &format!(
+            "process launch --stdin {} --stdout {} --stderr {}",
+            path("in.txt"),
+            path("out.txt"),
+            path("err.txt")
+        ),
+        &mut context,
+    )?;
+    assert_eq!(printer.take(), vec!["[]"]);
+    assert_eq!(std::fs::read_to_string(path("out.txt"))?, "Hello\n");
+    assert_eq!(std::fs::read_to_string(path("err.txt"))?, "Hello\n");
+
+    process.dispatch_command("process output", &mut context)?;
+    assert_eq!(
+        printer.take(),
+        vec!["Output is not captured, launch with `process launch --capture`"]
+    );
+
+    process.dispatch_command(
+        &format!("process launch --stdin {} --capture", path("in.txt")),
+        &mut context,
+    )?;
+    process.dispatch_command("process output --clear", &mut context)?;
+    assert_eq!(printer.take(), vec!["[]", "Hello\nHello"]);
+    process.dispatch_command("process output", &mut context)?;
+    assert_eq!(printer.take(), vec![""]);
+
+    // The redirection is kept for later launches without stdio flags
+    process.dispatch_command("process launch", &mut context)?;
+    process.dispatch_command("process output", &mut context)?;
+    assert_eq!(printer.take(), vec!["[]", "Hello\nHello"]);
+
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[test]
+fn test_component() -> anyhow::Result<()> {
+    let example_dir = std::path::Path::new(file!())
+        .parent()
+        .unwrap()
+        .join("simple-example");
+    let bytes = load_file(example_dir.join("hello_component.wasm").to_str().unwrap())?;
+
+    let (mut process, _) = start_debugger(None, vec![], vec![])?;
+    let debugger = &mut process.debugger;
+    debugger.load_main_module(&bytes, String::from("hello_component.wasm"))?;
+    let mut opts = debugger.get_opts();
+    opts.stdio.stdout = OutputTarget::Capture(Default::default());
+    debugger.set_opts(opts);
+    debugger.instantiate(HashMap::new(), Some(&[]))?;
+
+    // The main module is the core module exporting `wasi:cli/run`
+    let main_module = debugger.main_module_bytes().unwrap();
+    assert!(!is_component(main_module));
+    debugger.start(None, vec![])?;
+    assert_eq!(debugger.frame()[0].name, "run");
+
+    let result = debugger.run(None, vec![])?;
+    assert!(matches!(result, RunResult::Finish(ref values) if values.len() == 1));
+    let stdout = debugger.get_opts().stdio.captured().unwrap().contents();
+    assert_eq!(stdout, b"Hello, component!\n");
+    Ok(())
+}
+
+#[test]
+fn test_parse_fault() {
+    let fault = parse_fault("fd_write --errno io --skip 1").unwrap();
+    assert_eq!(fault.function, "fd_write");
+    assert_eq!(fault.skip, 1);
+    assert!(parse_fault("fd_wrte --errno io").is_err());
+}
@@ -0,0 +1,160 @@
+extern crate wasminspect_debugger;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::Path;
+use wasminspect_debugger::ModuleInput;
+
+/// Scripted gdb-remote client in no-ack mode
+struct Client {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+}
+
+impl Client {
+    fn connect(module: &str, entry_point: &str) -> Self {
+        let path = Path::new(file!())
+            .parent()
+            .unwrap()
+            .join("simple-example")
+            .join(module);
+        let module_input = ModuleInput {
+            bytes: std::fs::read(&path).unwrap(),
+            basename: module.to_string(),
+            dirname: None,
+            debug_file: None,
+            source_map: None,
+        };
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let entry_point = entry_point.to_string();
+        std::thread::spawn(move || {
+            wasminspect_debugger::serve_gdb_remote(
+                listener,
+                module_input,
+                vec![],
+                vec![],
+                vec![],
+                Some(entry_point),
+            )
+            .unwrap();
+        });
+        let stream = TcpStream::connect(addr).unwrap();
+        let mut client = Self {
+            reader: BufReader::new(stream.try_clone().unwrap()),
+            writer: stream,
+        };
+        client.send("QStartNoAckMode");
+        let mut ack = [0; 1];
+        client.reader.read_exact(&mut ack).unwrap();
+        assert_eq!(&ack, b"+");
+        assert_eq!(client.receive(), "OK");
+        client
+    }
+
+    fn send(&mut self, packet: &str) {
+        let sum = packet.bytes().fold(0u8, |sum, b| sum.wrapping_add(b));
+        write!(self.writer, "${}#{:02x}", packet, sum).unwrap();
+    }
+
+    fn receive(&mut self) -> String {
+        let mut data = vec![];
+        self.reader.read_until(b'$', &mut data).unwrap();
+        data.clear();
+        self.reader.read_until(b'#', &mut data).unwrap();
+        data.pop();
+        let mut sum = [0; 2];
+        self.reader.read_exact(&mut sum).unwrap();
+        String::from_utf8(data).unwrap()
+    }
+
+    fn request(&mut self, packet: &str) -> String {
+        self.send(packet);
+        self.receive()
+    }
+
+    /// Returns PCs of the call stack, innermost first
+    fn call_stack(&mut self) -> Vec<u64> {
+        let reply = self.request("qWasmCallStack:1");
+        (0..reply.len())
+            .step_by(16)
+            .map(|i| {
+                let bytes = (0..8)
+                    .map(|j| u8::from_str_radix(&reply[i + j * 2..i + j * 2 + 2], 16).unwrap())
+                    .collect::<Vec<_>>();
+                let mut pc = [0; 8];
+                pc.copy_from_slice(&bytes);
+                u64::from_le_bytes(pc)
+            })
+            .collect()
+    }
+}
+
+#[test]
+fn test_gdb_remote_breakpoint() {
+    let mut client = Client::connect("counter.wasm", "count_up");
+    assert!(client
+        .request("qSupported:xmlRegisters=i386")
+        .contains("qXfer:libraries:read+"));
+    let libraries = client.request("qXfer:libraries:read::0,1000");
+    assert_eq!(
+        libraries,
+        "l<library-list><library name=\"counter.wasm\"><section address=\"0x4000000000000000\"/></library></library-list>"
+    );
+    // Module bytes are mapped in the object address space
+    assert_eq!(client.request("m4000000000000000,4"), "0061736d");
+
+    let entry = client.call_stack();
+    assert_eq!(entry.len(), 1);
+    assert_eq!(entry[0] >> 32, 0x4000_0000);
+
+    // Step into the first call of `increment`
+    let mut callee = None;
+    for _ in 0..10 {
+        assert!(client.request("s").starts_with("T05"));
+        let stack = client.call_stack();
+        if stack.len() == 2 {
+            callee = Some(stack[0]);
+            break;
+        }
+    }
+    let callee = callee.expect("increment should be called");
+    assert_eq!(client.request("qWasmLocal:0;0"), "01000000");
+    assert_eq!(client.request("qWasmGlobal:0;0"), "00000000");
+
+    assert_eq!(client.request(&format!("Z0,{:x},1", callee)), "OK");
+    let stop = client.request("c");
+    assert!(stop.starts_with("T05"), "{}", stop);
+    assert!(stop.contains("reason:breakpoint"), "{}", stop);
+    assert_eq!(client.call_stack()[0], callee);
+    assert_eq!(client.request("qWasmLocal:0;0"), "02000000");
+    assert_eq!(client.request("qWasmGlobal:0;0"), "01000000");
+
+    assert_eq!(client.request(&format!("z0,{:x},1", callee)), "OK");
+    assert_eq!(client.request("c"), "W00");
+    assert_eq!(client.request("k"), "OK");
+}
+
+#[test]
+fn test_gdb_remote_exit() {
+    let mut client = Client::connect("proc_exit.wasm", "_start");
+    assert_eq!(client.request("c"), "W03");
+    // The exited process keeps reporting the status
+    assert_eq!(client.request("c"), "W03");
+    assert_eq!(client.request("k"), "OK");
+}
+
+#[test]
+fn test_gdb_remote_overflowing_length() {
+    let mut client = Client::connect("counter.wasm", "count_up");
+    assert!(client.request("s").starts_with("T05"));
+    assert_eq!(client.request("m0,ffffffffffffffff"), "E01");
+    assert_eq!(client.request("qWasmMem:0;1;ffffffffffffffff"), "E01");
+    assert_eq!(client.request("m4000000000000001,ffffffffffffffff"), "E01");
+    assert_eq!(
+        client.request("qXfer:libraries:read::1,ffffffffffffffff"),
+        "E01"
+    );
+    // The stub keeps serving after the errors
+    assert_eq!(client.request("m4000000000000000,4"), "0061736d");
+    assert_eq!(client.request("k"), "OK");
+}
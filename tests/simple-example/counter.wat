@@ -0,0 +1,9 @@
+(module
+  (global $count (mut i32) (i32.const 0))
+  (func $increment (export "increment") (param $n i32) (result i32)
+    (set_global $count (i32.add (get_global $count) (get_local $n)))
+    (get_global $count))
+  (func $count_up (export "count_up") (result i32)
+    (drop (call $increment (i32.const 1)))
+    (call $increment (i32.const 2)))
+)
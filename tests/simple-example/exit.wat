@@ -0,0 +1,8 @@
+(module
+  (import "env" "exit" (func $exit (param i32)))
+  (memory (export "memory") 1)
+  (func $run (export "run")
+    (i32.store (i32.const 0) (i32.const 42))
+    (call $exit (i32.const 3))
+    (unreachable))
+)
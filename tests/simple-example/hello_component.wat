@@ -0,0 +1,58 @@
+(component
+  (import "wasi:io/error@0.2.0" (instance $error
+    (export "error" (type (sub resource)))
+  ))
+  (alias export $error "error" (type $error-type))
+  (import "wasi:io/streams@0.2.0" (instance $streams
+    (alias outer 1 $error-type (type $error-outer))
+    (export "error" (type $error (eq $error-outer)))
+    (export "output-stream" (type $output-stream (sub resource)))
+    (type $stream-error (variant (case "last-operation-failed" (own $error)) (case "closed")))
+    (export "stream-error" (type $stream-error-export (eq $stream-error)))
+    (export "[method]output-stream.blocking-write-and-flush"
+      (func (param "self" (borrow $output-stream)) (param "contents" (list u8))
+        (result (result (error $stream-error-export)))))
+  ))
+  (alias export $streams "output-stream" (type $output-stream))
+  (import "wasi:cli/stdout@0.2.0" (instance $stdout
+    (alias outer 1 $output-stream (type $output-stream-outer))
+    (export "output-stream" (type $output-stream (eq $output-stream-outer)))
+    (export "get-stdout" (func (result (own $output-stream))))
+  ))
+
+  (core module $Memory
+    (memory (export "memory") 1)
+  )
+  (core module $Main
+    (import "env" "memory" (memory 1))
+    (import "wasi:cli/stdout@0.2.0" "get-stdout" (func $get-stdout (result i32)))
+    (import "wasi:io/streams@0.2.0" "[method]output-stream.blocking-write-and-flush"
+      (func $write (param i32 i32 i32 i32)))
+    (import "wasi:io/streams@0.2.0" "[resource-drop]output-stream" (func $drop (param i32)))
+    (data (i32.const 16) "Hello, component!\n")
+    (func $run (export "wasi:cli/run@0.2.0#run") (result i32)
+      (local $stdout i32)
+      (local.set $stdout (call $get-stdout))
+      (call $write (local.get $stdout) (i32.const 16) (i32.const 18) (i32.const 0))
+      (call $drop (local.get $stdout))
+      ;; The discriminant of the result written by the write
+      (i32.load8_u (i32.const 0)))
+  )
+
+  (core instance $memory (instantiate $Memory))
+  (alias core export $memory "memory" (core memory $memory))
+  (core func $get-stdout (canon lower (func $stdout "get-stdout")))
+  (core func $write
+    (canon lower (func $streams "[method]output-stream.blocking-write-and-flush") (memory $memory)))
+  (core func $drop (canon resource.drop $output-stream))
+  (core instance $main (instantiate $Main
+    (with "env" (instance (export "memory" (memory $memory))))
+    (with "wasi:cli/stdout@0.2.0" (instance (export "get-stdout" (func $get-stdout))))
+    (with "wasi:io/streams@0.2.0" (instance
+      (export "[method]output-stream.blocking-write-and-flush" (func $write))
+      (export "[resource-drop]output-stream" (func $drop))))
+  ))
+  (func $run (result (result)) (canon lift (core func $main "wasi:cli/run@0.2.0#run")))
+  (instance $run (export "run" (func $run)))
+  (export "wasi:cli/run@0.2.0" (instance $run))
+)
@@ -0,0 +1,6 @@
+(module
+  (import "env" "put" (func $put (param i32) (result i32)))
+  (memory (export "memory") 1)
+  (func $run (export "run") (param $n i32) (result i32)
+    (i32.add (call $put (get_local $n)) (i32.load (i32.const 0))))
+)
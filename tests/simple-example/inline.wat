@@ -0,0 +1,78 @@
+;; main.c compiled with `inc` inlined into `main`:
+;;
+;;  1 static inline int inc(int x) {
+;;  2   return x + 1;
+;;  3 }
+;;  4 int main(void) {
+;;  5   int foo = 1;
+;;  6   foo = inc(foo);
+;;  7   return foo;
+;;  8 }
+;;  9 void _start(void) {
+;; 10   main();
+;; 11 }
+;;
+;; The DWARF sections below were written with gimli::write and describe:
+;;
+;;   DW_TAG_compile_unit "main.c"
+;;     DW_TAG_base_type "int"
+;;     DW_TAG_subprogram "inc" (DW_AT_inline)
+;;       DW_TAG_formal_parameter "x"
+;;     DW_TAG_subprogram "main" [0x2, 0x13)
+;;       DW_TAG_variable "foo"
+;;       DW_TAG_inlined_subroutine "inc" [0x9, 0xe), called at main.c:6:9
+;;         DW_TAG_formal_parameter "x"
+;;     DW_TAG_subprogram "_start" [0x14, 0x19)
+;;
+;; Code offsets are relative to the code section, and the line table maps
+;; 0x5 => 5, 0x9 => 2, 0xe => 6, 0x10 => 7, 0x15 => 10.
+(module
+  (func $main (export "main") (result i32)
+    (local $foo i32)
+    ;; 0x5: int foo = 1;
+    i32.const 1
+    local.set $foo
+    ;; 0x9: return x + 1; (inlined)
+    local.get $foo
+    i32.const 1
+    i32.add
+    ;; 0xe: foo = inc(foo);
+    local.set $foo
+    ;; 0x10: return foo;
+    local.get $foo)
+  (func $_start (export "_start")
+    ;; 0x15: main();
+    call $main
+    drop)
+  (@custom ".debug_abbrev"
+    "\01\11\01\03\0e\1b\0e\11\01\10\17\00\00\02\24\00"
+    "\03\0e\3e\0f\0b\0b\00\00\03\2e\01\03\0e\49\13\20"
+    "\0f\00\00\04\05\00\03\0e\49\13\00\00\05\2e\01\11"
+    "\01\12\0f\03\0e\49\13\00\00\06\34\00\03\0e\49\13"
+    "\00\00\07\1d\01\31\13\11\01\12\0f\58\0f\59\0f\57"
+    "\0f\00\00\08\05\00\31\13\00\00\09\2e\00\11\01\12"
+    "\0f\03\0e\00\00\00"
+  )
+  (@custom ".debug_str"
+    "\6d\61\69\6e\2e\63\00\2f\73\72\63\00\69\6e\74\00"
+    "\69\6e\63\00\78\00\6d\61\69\6e\00\66\6f\6f\00\5f"
+    "\73\74\61\72\74\00"
+  )
+  (@custom ".debug_line"
+    "\55\00\00\00\04\00\23\00\00\00\01\01\01\fb\0e\0d"
+    "\00\01\01\01\01\00\00\00\01\00\00\01\2f\73\72\63"
+    "\00\00\6d\61\69\6e\2e\63\00\01\00\00\00\00\05\02"
+    "\02\00\00\00\15\05\07\3d\05\0c\47\05\07\5c\05\03"
+    "\2f\02\03\00\01\01\00\05\02\14\00\00\00\1a\05\03"
+    "\21\05\01\3d\02\01\00\01\01"
+  )
+  (@custom ".debug_info"
+    "\69\00\00\00\04\00\00\00\00\00\04\01\00\00\00\00"
+    "\07\00\00\00\00\00\00\00\00\00\00\00\02\0c\00\00"
+    "\00\05\04\03\10\00\00\00\1c\00\00\00\01\04\14\00"
+    "\00\00\1c\00\00\00\00\05\02\00\00\00\11\16\00\00"
+    "\00\1c\00\00\00\06\1b\00\00\00\1c\00\00\00\07\23"
+    "\00\00\00\09\00\00\00\05\01\06\09\08\2d\00\00\00"
+    "\00\00\09\14\00\00\00\05\1f\00\00\00\00"
+  )
+)
@@ -0,0 +1,10 @@
+(module
+  ;; Rust legacy mangling of `demo::add`
+  (func $_ZN4demo3add17h0123456789abcdefE (param i32 i32) (result i32)
+    (i32.add (local.get 0) (local.get 1)))
+  ;; Itanium C++ mangling of `demo::twice(int)`
+  (func $_ZN4demo5twiceEi (param i32) (result i32)
+    (call $_ZN4demo3add17h0123456789abcdefE (local.get 0) (local.get 0)))
+  (func $_start (export "_start")
+    (drop (call $_ZN4demo5twiceEi (i32.const 21))))
+)
@@ -0,0 +1,47 @@
+;; Calls the functions of WASI preview0 (wasi_unstable) whose signatures or
+;; layouts differ from preview1. Each function returns the errno and stores
+;; the results in the memory.
+(module
+  (import "wasi_unstable" "path_open"
+    (func $path_open (param i32 i32 i32 i32 i32 i64 i64 i32 i32) (result i32)))
+  (import "wasi_unstable" "fd_seek"
+    (func $fd_seek (param i32 i64 i32 i32) (result i32)))
+  (import "wasi_unstable" "fd_filestat_get"
+    (func $fd_filestat_get (param i32 i32) (result i32)))
+  (import "wasi_unstable" "poll_oneoff"
+    (func $poll_oneoff (param i32 i32 i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (data (i32.const 0) "data.txt")
+
+  ;; Opens data.txt in the preopened directory with fd_read, fd_seek, fd_tell
+  ;; and fd_filestat_get rights, and stores the fd at 16
+  (func $open (export "open") (result i32)
+    (call $path_open
+      (i32.const 3) (i32.const 0) (i32.const 0) (i32.const 8) (i32.const 0)
+      (i64.const 0x200026) (i64.const 0) (i32.const 0) (i32.const 16)))
+
+  ;; Seeks to 2 bytes before the end, and stores the new offset at 24.
+  ;; The whence END is 1 in preview0, which is CUR in preview1.
+  (func $seek_end (export "seek_end") (param $fd i32) (result i32)
+    (call $fd_seek (local.get $fd) (i64.const -2) (i32.const 1) (i32.const 24)))
+
+  ;; Stores the filestat at 32. The filestat of preview0 has 32-bit nlink at
+  ;; offset 20 and size at offset 24.
+  (func $filestat (export "filestat") (param $fd i32) (result i32)
+    (call $fd_filestat_get (local.get $fd) (i32.const 32)))
+
+  ;; Polls a relative clock subscription at 128 with zero timeout, and stores
+  ;; the event at 192 and the number of events at 224. The subscription of
+  ;; preview0 has an identifier before the clock id.
+  (func $poll (export "poll") (result i32)
+    ;; userdata
+    (i64.store (i32.const 128) (i64.const 0x1234))
+    ;; tag: clock
+    (i32.store8 (i32.const 136) (i32.const 0))
+    ;; identifier, which is not a valid clock id
+    (i64.store (i32.const 144) (i64.const 5))
+    ;; clock id: monotonic
+    (i32.store (i32.const 152) (i32.const 1))
+    ;; timeout, precision and flags are zero
+    (call $poll_oneoff (i32.const 128) (i32.const 192) (i32.const 1) (i32.const 224)))
+)
@@ -0,0 +1,13 @@
+;; add.ts compiled to wasm with a source map:
+;;
+;; 1 export function add(a: i32, b: i32): i32 {
+;; 2   let sum = a + b;
+;; 3   return sum;
+;; 4 }
+(module
+  (func $add (export "add") (param i32 i32) (result i32)
+    local.get 0
+    local.get 1
+    i32.add)
+  (@custom "sourceMappingURL" "\13source_map.wasm.map")
+)
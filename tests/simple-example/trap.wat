@@ -0,0 +1,10 @@
+(module
+  (memory (export "memory") 1)
+  (global $status (mut i32) (i32.const 0))
+  (func $divide (param $lhs i32) (param $rhs i32) (result i32)
+    (i32.store (i32.const 16) (i32.const 0x12345678))
+    (set_global $status (i32.const 7))
+    (i32.div_s (get_local $lhs) (get_local $rhs)))
+  (func $_start (export "_start")
+    (drop (call $divide (i32.const 42) (i32.const 0))))
+)